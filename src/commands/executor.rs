@@ -4,6 +4,10 @@ use super::parser::{Command, SensorType};
 use super::response::{Response, SensorValue};
 use crate::hw::traits::{BackupRegisters, DeviceManagement};
 use crate::hw::{BackupRegister, BootTask};
+use crate::radio::link_quality::links_snapshot;
+use crate::radio::neighbor::neighbors_snapshot;
+use crate::radio::tx_queue::tx_queue_stats;
+use embassy_time::Instant;
 use heapless::String;
 
 /// Command executor that runs commands and generates responses
@@ -130,6 +134,19 @@ impl<D: for<'d> DeviceManagement<'d>> CommandExecutor<D> {
                     self.device_manager.jump_to_dfu_bootloader();
                 }
             }
+
+            Command::Neighbors => Response::Neighbors {
+                neighbors: neighbors_snapshot(Instant::now().as_millis()),
+            },
+
+            Command::TxQueue => Response::TxQueue {
+                stats: tx_queue_stats(),
+            },
+
+            Command::Links => Response::Links {
+                links: links_snapshot(Instant::now().as_millis()),
+            },
+
             Command::Unknown(cmd) => {
                 let mut message = String::new();
                 let _ = core::fmt::write(
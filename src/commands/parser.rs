@@ -25,6 +25,12 @@ pub enum Command {
     Reboot,
     /// Reboot the CPU to DFU mode
     RebootToDfu,
+    /// List radio neighbors in range
+    Neighbors,
+    /// Show transmit queue statistics
+    TxQueue,
+    /// Show link quality of radio neighbors
+    Links,
     /// Unknown/invalid command
     Unknown(String<64>),
 }
@@ -89,6 +95,12 @@ impl CommandParser {
             Command::Reboot
         } else if matches_command("dfu") || matches_command("reboot_dfu") {
             Command::RebootToDfu
+        } else if matches_command("neighbors") || matches_command("nbr") {
+            Command::Neighbors
+        } else if matches_command("txqueue") || matches_command("txq") {
+            Command::TxQueue
+        } else if matches_command("links") {
+            Command::Links
         } else {
             let mut unknown_cmd = String::new();
             let _ = unknown_cmd.push_str(command_str);
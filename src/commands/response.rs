@@ -2,8 +2,11 @@
 /// This module defines response types and their formatting for command execution
 use super::parser::SensorType;
 use crate::hw::traits::DeviceInfo;
+use crate::radio::link_quality::{LinkSummary, MAX_LINKS};
+use crate::radio::neighbor::{NeighborSummary, MAX_NEIGHBORS};
+use crate::radio::tx_queue::TxQueueStats;
 use core::fmt;
-use heapless::String;
+use heapless::{String, Vec};
 
 /// Response enum representing different types of command responses
 #[derive(Debug, Clone, PartialEq)]
@@ -56,6 +59,14 @@ pub enum Response {
     Reboot,
     /// DFU reboot confirmation
     RebootToDfu,
+    /// Radio neighbors in range
+    Neighbors {
+        neighbors: Vec<NeighborSummary, MAX_NEIGHBORS>,
+    },
+    /// Transmit queue statistics
+    TxQueue { stats: TxQueueStats },
+    /// Link quality of radio neighbors
+    Links { links: Vec<LinkSummary, MAX_LINKS> },
     /// Error for unknown commands
     Error { message: String<128> },
 }
@@ -111,6 +122,9 @@ impl fmt::Display for Response {
                 writeln!(f, "  status - Show device status")?;
                 writeln!(f, "  ping - Test connectivity")?;
                 writeln!(f, "  version - Show firmware version")?;
+                writeln!(f, "  neighbors - List radio neighbors")?;
+                writeln!(f, "  txqueue - Show transmit queue statistics")?;
+                writeln!(f, "  links - Show link quality (PRR, ETX)")?;
                 writeln!(f, "  reboot - Reboot the device")?;
                 write!(f, "  dfu - Reboot to DFU mode")
             }
@@ -211,6 +225,79 @@ impl fmt::Display for Response {
             Response::RebootToDfu => {
                 write!(f, "Rebooting to DFU mode...")
             }
+            Response::Neighbors { neighbors } => {
+                if neighbors.is_empty() {
+                    return write!(f, "No neighbors in range");
+                }
+                write!(f, "Neighbors ({}):", neighbors.len())?;
+                for neighbor in neighbors {
+                    let firmware = neighbor.firmware;
+                    write!(
+                        f,
+                        "\n  0x{:04X} {} v{}.{}.{}",
+                        neighbor.node_id,
+                        neighbor.role.as_str(),
+                        firmware.major,
+                        firmware.minor,
+                        firmware.patch
+                    )?;
+                    match neighbor.rssi_dbm {
+                        Some(rssi) => write!(f, " {rssi}dBm")?,
+                        None => write!(f, " ?dBm")?,
+                    }
+                    write!(
+                        f,
+                        " {}pkt {}s ago",
+                        neighbor.packet_count,
+                        neighbor.age_ms / 1000
+                    )?;
+                }
+                Ok(())
+            }
+            Response::TxQueue { stats } => {
+                writeln!(f, "TX queue:")?;
+                writeln!(f, "  Depth: {} (max {})", stats.depth, stats.max_depth)?;
+                writeln!(
+                    f,
+                    "  Sent: {} ({} emergency), failed: {}",
+                    stats.sent, stats.emergency_sent, stats.failed
+                )?;
+                writeln!(
+                    f,
+                    "  Dropped: {} full, {} stale, promoted: {}",
+                    stats.dropped_full, stats.dropped_stale, stats.promoted
+                )?;
+                write!(
+                    f,
+                    "  Wait: {} ms mean, {} ms max, {} ms max emergency",
+                    stats.mean_wait_ms(),
+                    stats.max_wait_ms,
+                    stats.max_emergency_wait_ms
+                )
+            }
+            Response::Links { links } => {
+                if links.is_empty() {
+                    return write!(f, "No links measured");
+                }
+                write!(f, "Links ({}):", links.len())?;
+                for link in links {
+                    write!(f, "\n  0x{:04X}", link.node_id)?;
+                    match link.reception_permille {
+                        Some(prr) => write!(f, " rx {}.{}%", prr / 10, prr % 10)?,
+                        None => write!(f, " rx ?")?,
+                    }
+                    match link.delivery_permille {
+                        Some(prr) => write!(f, " ack {}.{}%", prr / 10, prr % 10)?,
+                        None => write!(f, " ack ?")?,
+                    }
+                    match link.etx_centi {
+                        Some(etx) => write!(f, " etx {}.{:02}", etx / 100, etx % 100)?,
+                        None => write!(f, " etx ?")?,
+                    }
+                    write!(f, " {}s ago", link.age_ms / 1000)?;
+                }
+                Ok(())
+            }
             Response::Error { message } => {
                 write!(f, "{}", message.as_str())
             }
@@ -75,7 +75,7 @@ mod tests {
         let bytes = original_packet.to_bytes();
 
         // Deserialize back to packet
-        let deserialized_packet = Packet::from_bytes(&bytes);
+        let deserialized_packet = defmt::unwrap!(Packet::from_bytes(&bytes));
 
         // Verify all fields match
         defmt::assert!(deserialized_packet.header.sender_id == original_packet.header.sender_id);
@@ -90,6 +90,139 @@ mod tests {
         defmt::assert!(deserialized_packet == original_packet);
     }
 
+    #[test]
+    fn test_packet_wire_layout_is_little_endian() {
+        let mut packet = Packet::new(0x1234, 0x5678, 0x9ABC, &[0xAA, 0xBB]);
+        packet.header.control.set_emergency(true);
+
+        let bytes = packet.to_bytes();
+        let ttl = DEFAULT_TTL;
+
+        defmt::assert!(
+            bytes[..HEADER_SIZE]
+                == [
+                    0x34, 0x12, 0x78, 0x56, 0xBC, 0x9A, 0x04, ttl, 0x00, 0x34, 0x12, 0x00, 0x00,
+                    0x02
+                ]
+        );
+        defmt::assert!(bytes[HEADER_SIZE..HEADER_SIZE + 2] == [0xAA, 0xBB]);
+        defmt::assert!(bytes[HEADER_SIZE + 2..].iter().all(|&b| b == 0));
+        defmt::assert!(packet.encoded_len() == HEADER_SIZE + 2);
+
+        // Only the meaningful prefix is required to decode the packet
+        let decoded = defmt::unwrap!(Packet::from_bytes(&bytes[..packet.encoded_len()]));
+        defmt::assert!(decoded == packet);
+    }
+
+    #[test]
+    fn test_packet_decode_rejects_malformed_frames() {
+        let packet = Packet::new(1, 2, 3, b"abcd");
+        let bytes = packet.to_bytes();
+
+        defmt::assert!(
+            Packet::from_bytes(&bytes[..HEADER_SIZE - 1]) == Err(DecodeError::Truncated)
+        );
+        defmt::assert!(
+            Packet::from_bytes(&bytes[..packet.encoded_len() - 1]) == Err(DecodeError::Truncated)
+        );
+
+        let mut too_long = [0u8; PACKET_SIZE_BYTES + 1];
+        too_long[..PACKET_SIZE_BYTES].copy_from_slice(&bytes);
+        defmt::assert!(Packet::from_bytes(&too_long) == Err(DecodeError::TooLong));
+
+        let mut bad_len = bytes;
+        bad_len[HEADER_SIZE - 1] = MAX_PAYLOAD_SIZE as u8 + 1;
+        defmt::assert!(Packet::from_bytes(&bad_len) == Err(DecodeError::PayloadTooLarge));
+
+        let mut reserved = bytes;
+        reserved[6] |= 0x80;
+        defmt::assert!(Packet::from_bytes(&reserved) == Err(DecodeError::ReservedBitsSet));
+    }
+
+    #[test]
+    fn test_packet_header_options() {
+        let mut packet = Packet::new(1, 2, 3, b"data");
+        defmt::assert!(packet.protocol_version() == BASE_PROTOCOL_VERSION);
+        defmt::assert!(packet.options().next().is_none());
+
+        defmt::assert!(packet.push_option(OPTION_LIFETIME, &300u16.to_le_bytes()));
+        defmt::assert!(packet.push_option(OPTION_KEY_ID, &[7]));
+        defmt::assert!(packet.header.control.is_extended());
+        defmt::assert!(packet.protocol_version() == PROTOCOL_VERSION);
+        defmt::assert!(packet.payload_data() == b"data");
+        defmt::assert!(packet.extension_len() == EXTENSION_PREFIX_SIZE + 4 + 3);
+
+        let bytes = packet.to_bytes();
+        let decoded = defmt::unwrap!(Packet::from_bytes(&bytes[..packet.encoded_len()]));
+        defmt::assert!(decoded == packet);
+        defmt::assert!(decoded.option(OPTION_LIFETIME) == Some(&[0x2C, 0x01][..]));
+        defmt::assert!(decoded.option(OPTION_KEY_ID) == Some(&[7][..]));
+        defmt::assert!(decoded.option(OPTION_SOURCE_ROUTE).is_none());
+
+        // Options and payload share the payload space
+        let mut full = Packet::new(1, 2, 3, &[0u8; MAX_PAYLOAD_SIZE - 4]);
+        defmt::assert!(!full.push_option(OPTION_KEY_ID, &[1]));
+        defmt::assert!(full.push_option(OPTION_KEY_ID, &[]));
+        defmt::assert!(full.payload_data().len() == MAX_PAYLOAD_SIZE - 4);
+    }
+
+    #[test]
+    fn test_packet_version_negotiation() {
+        let mut packet = Packet::new(1, 2, 3, b"x");
+        defmt::assert!(packet.push_option(OPTION_KEY_ID, &[1]));
+        let bytes = packet.to_bytes();
+        let len = packet.encoded_len();
+
+        // Older frames and unknown non-critical options are accepted
+        let older = Packet::new(1, 2, 3, b"x");
+        let decoded = defmt::unwrap!(Packet::from_bytes(&older.to_bytes()[..older.encoded_len()]));
+        defmt::assert!(decoded.protocol_version() == BASE_PROTOCOL_VERSION);
+        let mut unknown = bytes;
+        unknown[HEADER_SIZE + EXTENSION_PREFIX_SIZE] = 0x7E;
+        let decoded = defmt::unwrap!(Packet::from_bytes(&unknown[..len]));
+        defmt::assert!(decoded.payload_data() == b"x");
+
+        let mut newer = bytes;
+        newer[HEADER_SIZE] = PROTOCOL_VERSION + 1;
+        defmt::assert!(
+            Packet::from_bytes(&newer[..len])
+                == Err(DecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        // Extension areas did not exist before EXTENDED_PROTOCOL_VERSION
+        let mut predates = bytes;
+        predates[HEADER_SIZE] = BASE_PROTOCOL_VERSION;
+        defmt::assert!(
+            Packet::from_bytes(&predates[..len])
+                == Err(DecodeError::UnsupportedVersion(BASE_PROTOCOL_VERSION))
+        );
+        predates[HEADER_SIZE] = 0;
+        defmt::assert!(
+            Packet::from_bytes(&predates[..len]) == Err(DecodeError::UnsupportedVersion(0))
+        );
+
+        let mut critical = bytes;
+        critical[HEADER_SIZE + EXTENSION_PREFIX_SIZE] = OPTION_CRITICAL | 0x7E;
+        defmt::assert!(
+            Packet::from_bytes(&critical[..len])
+                == Err(DecodeError::UnsupportedOption(OPTION_CRITICAL | 0x7E))
+        );
+
+        // Source routes are reserved, this firmware would forward them along its own routes
+        let mut routed = Packet::new(1, 2, 3, b"x");
+        defmt::assert!(routed.push_option(OPTION_SOURCE_ROUTE, &[7, 0, 9, 0]));
+        defmt::assert!(
+            Packet::from_bytes(&routed.to_bytes()[..routed.encoded_len()])
+                == Err(DecodeError::UnsupportedOption(OPTION_SOURCE_ROUTE))
+        );
+
+        let mut overlong = bytes;
+        overlong[HEADER_SIZE + 1] = 40;
+        defmt::assert!(
+            Packet::from_bytes(&overlong[..len]) == Err(DecodeError::MalformedExtension)
+        );
+    }
+
     // Tests from embedded modules - now hardware-agnostic using testing module
     #[test]
     fn test_boot_task_from_u32() {
@@ -1,5 +1,28 @@
 /// Radio communication module
 /// This module handles all radio-related functionality including protocol and traits
 
+pub mod csma;
+pub mod dedup;
+pub mod fec;
+pub mod flood;
+pub mod fragment;
+pub mod framing;
+pub mod hopping;
+pub mod join;
+pub mod link_quality;
+pub mod manchester;
+pub mod message;
+pub mod neighbor;
+pub mod node_id;
+pub mod power_control;
 pub mod protocol;
+pub mod random;
+pub mod reliable;
+pub mod report;
+pub mod routing;
+pub mod security;
+pub mod sync;
+pub mod tdma;
+pub mod timesync;
 pub mod traits;
+pub mod tx_queue;
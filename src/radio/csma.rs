@@ -0,0 +1,233 @@
+/// Listen-before-talk transmission (CSMA/CA)
+/// Nodes that report at the same moment collide and lose both frames. Before every
+/// transmission the node waits for a random number of backoff periods and then
+/// samples the channel with `RadioReceiver::is_channel_busy`. The initial backoff
+/// spreads nodes woken by the same event, which would otherwise all find the channel
+/// clear and transmit together. While the channel is busy the node backs off again,
+/// the backoff window doubles with every busy sample, as in IEEE 802.15.4 unslotted
+/// CSMA/CA. When the channel stays busy for all attempts the transmission is
+/// abandoned with `RadioError::Busy`.
+///
+/// `CsmaRadio` wraps a radio and implements the radio traits itself, so it can be
+/// used wherever a plain radio is expected (for example below `ReliableLink`).
+use super::protocol::Packet;
+use super::random::XorShift32;
+use super::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+use crate::terminal_log;
+use defmt::Format;
+use embassy_time::Timer;
+
+/// Channel access parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct CsmaConfig {
+    /// Number of channel samples before giving up, 0 is treated as 1
+    pub max_attempts: u8,
+    /// Backoff exponent used after the first busy sample
+    pub min_backoff_exponent: u8,
+    /// Largest backoff exponent
+    pub max_backoff_exponent: u8,
+    /// Length of one backoff period
+    pub backoff_unit_ms: u32,
+}
+
+impl Default for CsmaConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            min_backoff_exponent: 3,
+            max_backoff_exponent: 5,
+            backoff_unit_ms: 2,
+        }
+    }
+}
+
+impl CsmaConfig {
+    /// Backoff after busy sample number `busy_count`, 0 for the initial backoff
+    ///
+    /// A random number of backoff periods is drawn from `0..2^exponent`, where the
+    /// exponent starts at `min_backoff_exponent` and grows by one for every busy
+    /// sample after the first up to `max_backoff_exponent`.
+    pub fn backoff_ms(&self, busy_count: u8, rng: &mut XorShift32) -> u32 {
+        let exponent = self
+            .min_backoff_exponent
+            .saturating_add(busy_count.saturating_sub(1))
+            .min(self.max_backoff_exponent)
+            .min(31);
+        rng.below(1 << exponent)
+            .saturating_mul(self.backoff_unit_ms)
+    }
+}
+
+/// Channel access counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct CsmaStats {
+    /// Packets handed to the radio after a clear channel assessment
+    pub transmitted: u32,
+    /// Channel samples that found the channel busy
+    pub busy_samples: u32,
+    /// Transmissions abandoned because the channel never became clear
+    pub gave_up: u32,
+    /// Random backoffs waited, including the initial one before every transmission
+    pub backoffs: u32,
+    /// Total time spent in backoff
+    pub backoff_ms: u32,
+}
+
+/// Radio wrapper that listens before every transmission
+///
+/// # Type Parameters
+/// * `R` - Radio implementation providing carrier sense
+pub struct CsmaRadio<R> {
+    radio: R,
+    config: CsmaConfig,
+    rng: XorShift32,
+    stats: CsmaStats,
+}
+
+impl<R: RadioTransmitter + RadioReceiver> CsmaRadio<R> {
+    /// Wrap a radio, `seed` (usually the node ID) decorrelates the backoff of neighbors
+    pub fn new(radio: R, config: CsmaConfig, seed: u32) -> Self {
+        Self {
+            radio,
+            config,
+            rng: XorShift32::new(seed),
+            stats: CsmaStats::default(),
+        }
+    }
+
+    /// Active channel access parameters
+    pub fn config(&self) -> &CsmaConfig {
+        &self.config
+    }
+
+    /// Channel access counters
+    pub fn stats(&self) -> &CsmaStats {
+        &self.stats
+    }
+
+    /// Access the wrapped radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Unwrap the radio
+    pub fn into_inner(self) -> R {
+        self.radio
+    }
+
+    /// Wait until the channel is clear
+    ///
+    /// Every channel sample, the first one included, follows a random backoff. The
+    /// channel is always sampled at least once.
+    ///
+    /// # Returns
+    /// * `Ok(())` once the channel was found clear
+    /// * `Err(RadioError::Busy)` if it stayed busy for all attempts
+    pub async fn wait_for_clear_channel(&mut self) -> Result<(), RadioError> {
+        let max_attempts = self.config.max_attempts.max(1);
+        self.back_off(0).await;
+        for busy_count in 1..=max_attempts {
+            if !self.radio.is_channel_busy() {
+                return Ok(());
+            }
+            self.stats.busy_samples += 1;
+            if busy_count == max_attempts {
+                break;
+            }
+            self.back_off(busy_count).await;
+        }
+
+        self.stats.gave_up += 1;
+        terminal_log!(
+            warn,
+            "Channel busy after {} attempts, giving up",
+            max_attempts
+        );
+        Err(RadioError::Busy)
+    }
+
+    /// Wait for a random backoff after busy sample number `busy_count`
+    async fn back_off(&mut self, busy_count: u8) {
+        let backoff_ms = self.config.backoff_ms(busy_count, &mut self.rng);
+        self.stats.backoffs += 1;
+        self.stats.backoff_ms = self.stats.backoff_ms.saturating_add(backoff_ms);
+        terminal_log!(
+            trace,
+            "Backing off {} ms (busy samples: {})",
+            backoff_ms,
+            busy_count
+        );
+        if backoff_ms > 0 {
+            Timer::after_millis(backoff_ms as u64).await;
+        }
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send> RadioTransmitter for CsmaRadio<R> {
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        self.wait_for_clear_channel().await?;
+        self.radio.transmit(packet).await?;
+        self.stats.transmitted += 1;
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.radio.is_ready()
+    }
+
+    async fn set_power_level(&mut self, power_level: u8) -> Result<(), RadioError> {
+        self.radio.set_power_level(power_level).await
+    }
+
+    fn get_power_level(&self) -> u8 {
+        self.radio.get_power_level()
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send> RadioReceiver for CsmaRadio<R> {
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        self.radio.receive().await
+    }
+
+    fn packet_available(&self) -> bool {
+        self.radio.packet_available()
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.radio.set_enabled(enabled).await
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.radio.is_enabled()
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        self.radio.get_rssi()
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.radio.is_channel_busy()
+    }
+}
+
+impl<R: RadioTransceiver + Send> RadioTransceiver for CsmaRadio<R> {
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        self.radio.initialize().await
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.radio.sleep().await
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.radio.wake().await
+    }
+
+    fn get_frequency(&self) -> u32 {
+        self.radio.get_frequency()
+    }
+
+    async fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), RadioError> {
+        self.radio.set_frequency(frequency_hz).await
+    }
+}
@@ -0,0 +1,164 @@
+/// Duplicate packet suppression
+/// Retransmissions and multi-path reception deliver the same packet more than once.
+/// This module keeps a fixed-size cache of recently seen `(sender_id, sequence_number)`
+/// pairs. Each sender gets a sliding window anchored at its highest sequence number,
+/// compared with wrapping (serial number) arithmetic so 16-bit wraparound is handled.
+use super::protocol::Header;
+use defmt::Format;
+use heapless::Vec;
+
+/// Number of sequence numbers tracked behind the highest one for every sender
+pub const DEDUP_WINDOW: u16 = 32;
+
+/// Default time after which a silent sender is forgotten
+pub const DEFAULT_DEDUP_MAX_AGE_MS: u64 = 60_000;
+
+/// Result of checking a packet against the duplicate cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum DuplicateStatus {
+    /// First time this packet is seen, it should be processed
+    New,
+    /// Packet was already seen and must be dropped
+    Duplicate {
+        /// The sender is still asking for an acknowledgment (our previous ack
+        /// was probably lost), so it has to be acknowledged again
+        reack: bool,
+    },
+}
+
+/// Sliding sequence window for one sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+struct SenderWindow {
+    sender_id: u16,
+    /// Highest sequence number seen so far
+    highest: u16,
+    /// Bit `i` is set when sequence `highest - i` has been seen
+    seen: u32,
+    last_seen_ms: u64,
+}
+
+/// Fixed-capacity duplicate detection cache
+///
+/// # Type Parameters
+/// * `SENDERS` - Maximum number of senders tracked at the same time, the least
+///   recently heard sender is evicted when the cache is full
+pub struct DuplicateCache<const SENDERS: usize> {
+    windows: Vec<SenderWindow, SENDERS>,
+    max_age_ms: u64,
+    now_ms: u64,
+}
+
+impl<const SENDERS: usize> Default for DuplicateCache<SENDERS> {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_MAX_AGE_MS)
+    }
+}
+
+impl<const SENDERS: usize> DuplicateCache<SENDERS> {
+    /// Create a cache that forgets senders silent for longer than `max_age_ms`
+    pub fn new(max_age_ms: u64) -> Self {
+        Self {
+            windows: Vec::new(),
+            max_age_ms,
+            now_ms: 0,
+        }
+    }
+
+    /// Advance the cache clock and age out senders that have been silent too long
+    pub fn tick(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+        let max_age_ms = self.max_age_ms;
+        self.windows
+            .retain(|window| now_ms.saturating_sub(window.last_seen_ms) <= max_age_ms);
+    }
+
+    /// Number of senders currently tracked
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Check whether no sender is tracked
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Forget all senders
+    pub fn clear(&mut self) {
+        self.windows.clear();
+    }
+
+    /// Check whether the packet was already seen and record it
+    pub fn is_duplicate(&mut self, header: &Header) -> bool {
+        matches!(self.check(header), DuplicateStatus::Duplicate { .. })
+    }
+
+    /// Check a packet against the cache and record it as seen
+    pub fn check(&mut self, header: &Header) -> DuplicateStatus {
+        let sequence = header.sequence_number;
+        let now_ms = self.now_ms;
+
+        let Some(window) = self
+            .windows
+            .iter_mut()
+            .find(|window| window.sender_id == header.sender_id)
+        else {
+            self.insert(SenderWindow {
+                sender_id: header.sender_id,
+                highest: sequence,
+                seen: 1,
+                last_seen_ms: now_ms,
+            });
+            return DuplicateStatus::New;
+        };
+        window.last_seen_ms = now_ms;
+
+        // Serial number arithmetic: positive distance means newer than `highest`
+        let distance = sequence.wrapping_sub(window.highest) as i16;
+        if distance > 0 {
+            let shift = distance as u32;
+            window.seen = if shift >= u32::BITS {
+                0
+            } else {
+                window.seen << shift
+            };
+            window.seen |= 1;
+            window.highest = sequence;
+            return DuplicateStatus::New;
+        }
+
+        let age = distance.unsigned_abs();
+        if age >= DEDUP_WINDOW {
+            // Far behind the window: the sender most likely rebooted and restarted
+            // its sequence numbers, start a fresh window
+            window.highest = sequence;
+            window.seen = 1;
+            return DuplicateStatus::New;
+        }
+
+        let bit = 1u32 << age;
+        if window.seen & bit != 0 {
+            DuplicateStatus::Duplicate {
+                reack: header.control.is_ack_request(),
+            }
+        } else {
+            window.seen |= bit;
+            DuplicateStatus::New
+        }
+    }
+
+    fn insert(&mut self, window: SenderWindow) {
+        if self.windows.is_full() {
+            if let Some(oldest) = self
+                .windows
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, window)| window.last_seen_ms)
+                .map(|(index, _)| index)
+            {
+                self.windows.swap_remove(oldest);
+            }
+        }
+        // A zero-capacity cache simply tracks nothing
+        let _ = self.windows.push(window);
+    }
+}
@@ -0,0 +1,358 @@
+/// Reed-Solomon forward error correction for radio frames
+/// This module implements a systematic Reed-Solomon codec over GF(256)
+/// (primitive polynomial 0x11D, first consecutive root alpha^0).
+/// The codec is `no_std` and allocation-free, all working buffers live on the stack.
+/// Encoded frame layout: `crc frame (see radio::framing) | parity symbols`
+use super::framing::{self, MAX_FRAME_SIZE};
+use super::protocol::Packet;
+use super::traits::RadioError;
+use defmt::Format;
+use heapless::Vec;
+
+/// Maximum number of parity symbols supported by the codec
+/// Each pair of parity symbols allows correcting one corrupted byte.
+pub const MAX_PARITY_SYMBOLS: usize = 32;
+
+/// Maximum length of a Reed-Solomon codeword over GF(256)
+pub const MAX_CODEWORD_LEN: usize = 255;
+
+/// Largest possible FEC protected frame in bytes
+pub const MAX_FEC_FRAME_SIZE: usize = MAX_FRAME_SIZE + MAX_PARITY_SYMBOLS;
+
+/// Buffer holding one FEC protected frame
+pub type FecFrame = Vec<u8, MAX_FEC_FRAME_SIZE>;
+
+/// Errors reported by the Reed-Solomon codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum FecError {
+    /// Parity symbol count is zero or larger than `MAX_PARITY_SYMBOLS`
+    InvalidParity,
+    /// Message plus parity does not fit into a single codeword
+    MessageTooLong,
+    /// Output buffer cannot hold the encoded codeword
+    BufferTooSmall,
+    /// The codeword contains more errors than the code can correct
+    TooManyErrors,
+}
+
+impl From<FecError> for RadioError {
+    fn from(error: FecError) -> Self {
+        match error {
+            FecError::TooManyErrors => RadioError::Uncorrectable,
+            FecError::BufferTooSmall => RadioError::BufferError,
+            FecError::InvalidParity | FecError::MessageTooLong => RadioError::InvalidPacket,
+        }
+    }
+}
+
+/// Result of decoding a FEC protected frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum FecOutcome {
+    /// The frame arrived without any symbol errors
+    Clean,
+    /// The frame was repaired, `symbols` bytes were corrected
+    Repaired { symbols: usize },
+}
+
+/// Exponent and logarithm tables for GF(256) with primitive polynomial 0x11D
+/// The exponent table is doubled so products can be looked up without a modulo.
+const GF_TABLES: ([u8; 512], [u8; 256]) = build_gf_tables();
+
+const fn build_gf_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11D;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF_TABLES;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF_TABLES;
+    exp[log[a as usize] as usize + 255 - log[b as usize] as usize]
+}
+
+/// alpha^power for any (possibly negative) power
+fn gf_alpha_pow(power: i32) -> u8 {
+    GF_TABLES.0[power.rem_euclid(255) as usize]
+}
+
+/// Evaluate a polynomial stored lowest degree first at `x`
+fn poly_eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter()
+        .rev()
+        .fold(0, |acc, &coef| gf_mul(acc, x) ^ coef)
+}
+
+/// Reed-Solomon codec with a configurable number of parity symbols
+///
+/// The codec is systematic: the encoded codeword is the original data followed
+/// by `parity` symbols, so the data can be read directly from a clean frame.
+/// Up to `parity / 2` corrupted bytes anywhere in the codeword can be corrected.
+#[derive(Debug, Clone)]
+pub struct ReedSolomon {
+    parity: usize,
+    /// Generator polynomial, highest degree first, `parity + 1` coefficients
+    generator: [u8; MAX_PARITY_SYMBOLS + 1],
+}
+
+impl ReedSolomon {
+    /// Create a codec producing `parity` parity symbols per codeword
+    ///
+    /// # Returns
+    /// * `Ok(ReedSolomon)` for `1..=MAX_PARITY_SYMBOLS` parity symbols
+    /// * `Err(FecError::InvalidParity)` otherwise
+    pub fn new(parity: usize) -> Result<Self, FecError> {
+        if parity == 0 || parity > MAX_PARITY_SYMBOLS {
+            return Err(FecError::InvalidParity);
+        }
+
+        // g(x) = (x - a^0)(x - a^1)...(x - a^(parity-1)), built highest degree first
+        let mut generator = [0u8; MAX_PARITY_SYMBOLS + 1];
+        generator[0] = 1;
+        for i in 0..parity {
+            let root = gf_alpha_pow(i as i32);
+            for j in (1..=i + 1).rev() {
+                generator[j] ^= gf_mul(generator[j - 1], root);
+            }
+        }
+
+        Ok(Self { parity, generator })
+    }
+
+    /// Number of parity symbols appended to every codeword
+    pub fn parity_symbols(&self) -> usize {
+        self.parity
+    }
+
+    /// Maximum number of corrupted bytes that can be corrected per codeword
+    pub fn correction_capacity(&self) -> usize {
+        self.parity / 2
+    }
+
+    /// Encode `data` into `out` as `data | parity`
+    ///
+    /// # Returns
+    /// * `Ok(len)` with the total codeword length written to `out`
+    /// * `Err(FecError)` if the message or buffer sizes are not usable
+    pub fn encode(&self, data: &[u8], out: &mut [u8]) -> Result<usize, FecError> {
+        let total = data.len() + self.parity;
+        if total > MAX_CODEWORD_LEN {
+            return Err(FecError::MessageTooLong);
+        }
+        if out.len() < total {
+            return Err(FecError::BufferTooSmall);
+        }
+
+        // Polynomial long division of data(x) * x^parity by g(x) using an LFSR,
+        // the remainder ends up in `parity` with the highest degree first
+        let mut parity = [0u8; MAX_PARITY_SYMBOLS];
+        let parity = &mut parity[..self.parity];
+        for &byte in data {
+            let feedback = byte ^ parity[0];
+            parity.copy_within(1.., 0);
+            parity[self.parity - 1] = 0;
+            if feedback != 0 {
+                for (p, &g) in parity.iter_mut().zip(&self.generator[1..=self.parity]) {
+                    *p ^= gf_mul(g, feedback);
+                }
+            }
+        }
+
+        out[..data.len()].copy_from_slice(data);
+        out[data.len()..total].copy_from_slice(parity);
+        Ok(total)
+    }
+
+    /// Correct a received codeword in place
+    ///
+    /// On success the first `codeword.len() - parity_symbols()` bytes hold the
+    /// original data.
+    ///
+    /// # Returns
+    /// * `Ok(n)` with the number of corrected symbols (0 for a clean codeword)
+    /// * `Err(FecError::TooManyErrors)` if the codeword cannot be repaired
+    pub fn decode(&self, codeword: &mut [u8]) -> Result<usize, FecError> {
+        let len = codeword.len();
+        if len > MAX_CODEWORD_LEN {
+            return Err(FecError::MessageTooLong);
+        }
+        if len <= self.parity {
+            return Err(FecError::BufferTooSmall);
+        }
+
+        let mut syndromes = [0u8; MAX_PARITY_SYMBOLS];
+        if !self.compute_syndromes(codeword, &mut syndromes) {
+            return Ok(0);
+        }
+        let syndromes = &syndromes[..self.parity];
+
+        // Berlekamp-Massey: find the error locator polynomial (lowest degree first)
+        let mut locator = [0u8; MAX_PARITY_SYMBOLS + 1];
+        let mut previous = [0u8; MAX_PARITY_SYMBOLS + 1];
+        locator[0] = 1;
+        previous[0] = 1;
+        let mut errors = 0usize;
+        let mut shift = 1usize;
+        let mut previous_discrepancy = 1u8;
+
+        for n in 0..self.parity {
+            let mut discrepancy = syndromes[n];
+            for i in 1..=errors {
+                discrepancy ^= gf_mul(locator[i], syndromes[n - i]);
+            }
+
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+
+            let scale = gf_div(discrepancy, previous_discrepancy);
+            let snapshot = locator;
+            for i in shift..=self.parity {
+                locator[i] ^= gf_mul(scale, previous[i - shift]);
+            }
+
+            if 2 * errors <= n {
+                errors = n + 1 - errors;
+                previous = snapshot;
+                previous_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                shift += 1;
+            }
+        }
+
+        if errors == 0 || 2 * errors > self.parity {
+            return Err(FecError::TooManyErrors);
+        }
+        let locator = &locator[..=errors];
+
+        // Chien search: byte at index k carries power len - 1 - k, an error at
+        // power p is present when locator(alpha^-p) == 0
+        let mut positions = [0usize; MAX_PARITY_SYMBOLS / 2];
+        let mut found = 0;
+        for power in 0..len {
+            if poly_eval(locator, gf_alpha_pow(-(power as i32))) == 0 {
+                if found == errors {
+                    return Err(FecError::TooManyErrors);
+                }
+                positions[found] = power;
+                found += 1;
+            }
+        }
+        if found != errors {
+            return Err(FecError::TooManyErrors);
+        }
+
+        // Error evaluator omega(x) = S(x) * locator(x) mod x^parity
+        let mut evaluator = [0u8; MAX_PARITY_SYMBOLS];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate() {
+                if i + j < self.parity {
+                    evaluator[i + j] ^= gf_mul(s, l);
+                }
+            }
+        }
+        let evaluator = &evaluator[..self.parity];
+
+        // Formal derivative of the locator keeps only odd-degree terms
+        let mut derivative = [0u8; MAX_PARITY_SYMBOLS];
+        for i in (1..locator.len()).step_by(2) {
+            derivative[i - 1] = locator[i];
+        }
+        let derivative = &derivative[..errors];
+
+        // Forney algorithm (first root alpha^0): e = X * omega(X^-1) / locator'(X^-1)
+        for &power in &positions[..found] {
+            let x = gf_alpha_pow(power as i32);
+            let x_inv = gf_alpha_pow(-(power as i32));
+            let denominator = poly_eval(derivative, x_inv);
+            if denominator == 0 {
+                return Err(FecError::TooManyErrors);
+            }
+            let magnitude = gf_mul(x, gf_div(poly_eval(evaluator, x_inv), denominator));
+            codeword[len - 1 - power] ^= magnitude;
+        }
+
+        // A miscorrection leaves a non-zero syndrome behind, never report it as repaired
+        let mut check = [0u8; MAX_PARITY_SYMBOLS];
+        if self.compute_syndromes(codeword, &mut check) {
+            return Err(FecError::TooManyErrors);
+        }
+
+        Ok(found)
+    }
+
+    /// Compute syndromes S_j = c(alpha^j), returns `true` if any syndrome is non-zero
+    fn compute_syndromes(&self, codeword: &[u8], syndromes: &mut [u8; MAX_PARITY_SYMBOLS]) -> bool {
+        let mut corrupted = false;
+        for (j, syndrome) in syndromes.iter_mut().take(self.parity).enumerate() {
+            let root = gf_alpha_pow(j as i32);
+            *syndrome = codeword
+                .iter()
+                .fold(0, |acc, &byte| gf_mul(acc, root) ^ byte);
+            corrupted |= *syndrome != 0;
+        }
+        corrupted
+    }
+
+    /// Encode a packet into a CRC frame followed by Reed-Solomon parity
+    pub fn encode_packet(&self, packet: &Packet) -> Result<FecFrame, FecError> {
+        let frame = framing::encode(packet);
+
+        let mut buffer = [0u8; MAX_FEC_FRAME_SIZE];
+        let len = self.encode(&frame, &mut buffer)?;
+
+        let mut encoded = FecFrame::new();
+        encoded
+            .extend_from_slice(&buffer[..len])
+            .map_err(|_| FecError::BufferTooSmall)?;
+        Ok(encoded)
+    }
+
+    /// Repair a received FEC frame in place and decode the packet inside it
+    ///
+    /// The CRC of the inner frame is verified after correction, so a frame is
+    /// only accepted if both the Reed-Solomon decoder and the checksum agree.
+    ///
+    /// # Returns
+    /// * `Ok((packet, outcome))` telling whether the frame was clean or repaired
+    /// * `Err(RadioError::Uncorrectable)` if there were too many symbol errors
+    /// * `Err(RadioError::ChecksumMismatch)` if the repaired frame is still corrupt
+    pub fn decode_packet(&self, received: &mut [u8]) -> Result<(Packet, FecOutcome), RadioError> {
+        let corrected = self.decode(received)?;
+        let frame_len = received.len() - self.parity;
+        let packet = framing::decode(&received[..frame_len])?;
+
+        let outcome = if corrected == 0 {
+            FecOutcome::Clean
+        } else {
+            FecOutcome::Repaired { symbols: corrected }
+        };
+        Ok((packet, outcome))
+    }
+}
@@ -0,0 +1,199 @@
+/// Controlled flooding for multi-hop delivery
+/// Nodes out of direct range of their target are reached by letting every node
+/// rebroadcast packets that are not addressed to it. Flooding is kept under control
+/// by the hop limit (`ttl`) in the header, by remembering already seen
+/// `(sender_id, sequence_number)` pairs so each node relays a packet at most once,
+/// and by a random relay delay so neighbors hearing the same packet do not collide.
+///
+/// The relay is a pure state machine, the caller transmits the forwarded packets
+/// after the returned delay (see `relay`).
+use super::dedup::DuplicateCache;
+use super::protocol::{Packet, BROADCAST_ID};
+use super::random::XorShift32;
+use super::traits::{RadioError, RadioTransmitter};
+use crate::terminal_log;
+use defmt::Format;
+use embassy_time::Timer;
+
+/// Number of originators remembered by the relay's seen-packet history
+const FLOOD_HISTORY_SENDERS: usize = 16;
+
+/// Flooding relay parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FloodConfig {
+    /// Shortest delay before rebroadcasting a packet
+    pub min_relay_delay_ms: u32,
+    /// Longest delay before rebroadcasting a packet
+    pub max_relay_delay_ms: u32,
+    /// Time after which the seen-packet history forgets a silent originator
+    pub history_max_age_ms: u64,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            min_relay_delay_ms: 10,
+            max_relay_delay_ms: 120,
+            history_max_age_ms: 60_000,
+        }
+    }
+}
+
+/// Packet to rebroadcast after a delay
+#[derive(Debug, Clone, PartialEq, Eq, Format)]
+pub struct ScheduledForward {
+    /// Packet with decremented TTL and incremented hop count
+    pub packet: Packet,
+    /// Delay before transmission
+    pub delay_ms: u32,
+}
+
+/// What to do with a received packet
+#[derive(Debug, Clone, PartialEq, Eq, Format)]
+pub struct FloodDecision {
+    /// The packet is addressed to this node (or broadcast) and should be processed
+    pub deliver: bool,
+    /// The packet should be relayed
+    pub forward: Option<ScheduledForward>,
+}
+
+impl FloodDecision {
+    const DROP: Self = Self {
+        deliver: false,
+        forward: None,
+    };
+}
+
+/// Flooding relay counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct FloodStats {
+    /// Packets handed to this node
+    pub delivered: u32,
+    /// Packets scheduled for rebroadcast
+    pub forwarded: u32,
+    /// Packets dropped because they were already seen
+    pub duplicates: u32,
+    /// Packets not relayed because their hop limit was used up
+    pub ttl_expired: u32,
+}
+
+/// Controlled flooding relay
+pub struct FloodRelay {
+    node_id: u16,
+    config: FloodConfig,
+    seen: DuplicateCache<FLOOD_HISTORY_SENDERS>,
+    rng: XorShift32,
+    stats: FloodStats,
+}
+
+impl FloodRelay {
+    /// Create a relay for the node with the given ID
+    pub fn new(node_id: u16, config: FloodConfig) -> Self {
+        Self {
+            node_id,
+            config,
+            seen: DuplicateCache::new(config.history_max_age_ms),
+            rng: XorShift32::new(node_id as u32),
+            stats: FloodStats::default(),
+        }
+    }
+
+    /// Get the relay counters
+    pub fn stats(&self) -> &FloodStats {
+        &self.stats
+    }
+
+    /// Decide whether a received packet is delivered locally, relayed, or both
+    ///
+    /// Unicast packets for this node are delivered but not relayed. Broadcasts are
+    /// delivered and relayed. Unicast packets for other nodes are only relayed. Acks
+    /// answer a single hop (see `radio::reliable`) and are never relayed.
+    pub fn on_receive(&mut self, packet: &Packet, now_ms: u64) -> FloodDecision {
+        let header = &packet.header;
+
+        // Our own packets echoed back by a neighbor
+        if header.sender_id == self.node_id {
+            return FloodDecision::DROP;
+        }
+
+        // Acks echo the sequence number of the acknowledged packet, in the history
+        // they would shadow later packets the acking node originates
+        if header.control.is_ack() {
+            let deliver = header.target_id == self.node_id;
+            if deliver {
+                self.stats.delivered += 1;
+            }
+            return FloodDecision {
+                deliver,
+                forward: None,
+            };
+        }
+
+        self.seen.tick(now_ms);
+        if self.seen.is_duplicate(header) {
+            self.stats.duplicates += 1;
+            return FloodDecision::DROP;
+        }
+
+        let for_us = header.target_id == self.node_id;
+        let deliver = for_us || header.target_id == BROADCAST_ID;
+        if deliver {
+            self.stats.delivered += 1;
+        }
+        if for_us {
+            return FloodDecision {
+                deliver,
+                forward: None,
+            };
+        }
+
+        if header.ttl == 0 {
+            terminal_log!(
+                trace,
+                "Not relaying seq {} from {}, hop limit reached",
+                header.sequence_number,
+                header.sender_id
+            );
+            self.stats.ttl_expired += 1;
+            return FloodDecision {
+                deliver,
+                forward: None,
+            };
+        }
+
+        let mut forwarded = packet.clone();
+        forwarded.header.ttl -= 1;
+        forwarded.header.hop_count = forwarded.header.hop_count.saturating_add(1);
+        forwarded.header.relay_id = self.node_id;
+        let spread = self
+            .config
+            .max_relay_delay_ms
+            .saturating_sub(self.config.min_relay_delay_ms);
+        let delay_ms = self.config.min_relay_delay_ms + self.rng.below(spread.saturating_add(1));
+
+        self.stats.forwarded += 1;
+        FloodDecision {
+            deliver,
+            forward: Some(ScheduledForward {
+                packet: forwarded,
+                delay_ms,
+            }),
+        }
+    }
+}
+
+/// Transmit a scheduled forward after its relay delay
+pub async fn relay<T: RadioTransmitter>(
+    radio: &mut T,
+    forward: &ScheduledForward,
+) -> Result<(), RadioError> {
+    Timer::after_millis(forward.delay_ms as u64).await;
+    terminal_log!(
+        trace,
+        "Relaying seq {} from {} (hop {})",
+        forward.packet.header.sequence_number,
+        forward.packet.header.sender_id,
+        forward.packet.header.hop_count
+    );
+    radio.transmit(&forward.packet).await
+}
@@ -0,0 +1,398 @@
+/// Packet fragmentation and reassembly
+/// Messages larger than `MAX_DATA_SIZE` (calibration tables, log excerpts,
+/// configuration blobs) are split into numbered fragments. Every fragment payload
+/// starts with a small fragment header followed by up to `FRAGMENT_DATA_SIZE`
+/// bytes of message data. The receiver collects fragments per `(sender, message)`
+/// in bounded buffers, accepting them in any order. Fragment packets carry the
+/// `fragment` control flag so receivers can tell them from single-packet messages.
+/// Recently completed messages are remembered, so a retransmitted fragment arriving
+/// after its message was delivered is dropped instead of starting a new reassembly.
+///
+/// Fragment header layout (little-endian):
+/// `message_id: u16 | index: u8 | count: u8`
+use super::protocol::{Header, Packet, MAX_DATA_SIZE};
+use crate::terminal_log;
+use defmt::Format;
+use heapless::{Deque, Vec};
+
+/// Size of the fragment header at the start of every fragment payload
+pub const FRAGMENT_HEADER_SIZE: usize = 4;
+
+/// Message bytes carried by one fragment, leaving room for sealing the packet
+pub const FRAGMENT_DATA_SIZE: usize = MAX_DATA_SIZE - FRAGMENT_HEADER_SIZE;
+
+/// Largest message that can be fragmented
+pub const MAX_MESSAGE_SIZE: usize = 2048;
+
+/// Largest number of fragments for one message
+pub const MAX_FRAGMENTS: usize = MAX_MESSAGE_SIZE.div_ceil(FRAGMENT_DATA_SIZE);
+
+/// Default time allowed to receive all fragments of a message
+pub const DEFAULT_REASSEMBLY_TIMEOUT_MS: u64 = 5_000;
+
+/// Number of completed messages remembered to drop their late duplicate fragments
+pub const COMPLETED_HISTORY: usize = 8;
+
+/// Buffer holding a complete message
+pub type MessageBuffer = Vec<u8, MAX_MESSAGE_SIZE>;
+
+/// Errors produced while fragmenting or reassembling messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum FragmentError {
+    /// Message exceeds `MAX_MESSAGE_SIZE`
+    MessageTooLarge,
+    /// Packet is not flagged as a fragment, or its fragment header is truncated or
+    /// describes an impossible fragment
+    InvalidFragment,
+    /// Fragment does not match the message already being reassembled, only the
+    /// fragment is dropped
+    InconsistentFragment,
+    /// All reassembly slots are in use
+    TooManyReassemblies,
+}
+
+/// Header at the start of every fragment payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FragmentHeader {
+    /// Message identifier, unique per sender within the reassembly timeout
+    pub message_id: u16,
+    /// Position of this fragment within the message
+    pub index: u8,
+    /// Total number of fragments in the message
+    pub count: u8,
+}
+
+impl FragmentHeader {
+    /// Serialize the fragment header
+    pub fn to_bytes(&self) -> [u8; FRAGMENT_HEADER_SIZE] {
+        let id = self.message_id.to_le_bytes();
+        [id[0], id[1], self.index, self.count]
+    }
+
+    /// Parse and validate a fragment header from the start of a payload
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FragmentError> {
+        if bytes.len() < FRAGMENT_HEADER_SIZE {
+            return Err(FragmentError::InvalidFragment);
+        }
+        let header = Self {
+            message_id: u16::from_le_bytes([bytes[0], bytes[1]]),
+            index: bytes[2],
+            count: bytes[3],
+        };
+        if header.count == 0
+            || header.index >= header.count
+            || header.count as usize > MAX_FRAGMENTS
+        {
+            return Err(FragmentError::InvalidFragment);
+        }
+        Ok(header)
+    }
+}
+
+/// Number of fragments needed for a message of `len` bytes
+pub fn fragment_count(len: usize) -> usize {
+    len.div_ceil(FRAGMENT_DATA_SIZE).max(1)
+}
+
+/// Iterator producing the fragment packets of one message
+///
+/// Every packet copies the addressing, `ttl` and control flags of the template header
+/// and is marked with the `fragment` control flag. Header options are not carried, every
+/// fragment uses the whole payload for fragment data.
+/// Sequence numbers increase by one per fragment starting at the template's.
+pub struct Fragments<'a> {
+    template: Header,
+    message_id: u16,
+    data: &'a [u8],
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Fragments<'a> {
+    /// Split `data` into fragments
+    pub fn new(template: Header, message_id: u16, data: &'a [u8]) -> Result<Self, FragmentError> {
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(FragmentError::MessageTooLarge);
+        }
+        Ok(Self {
+            template,
+            message_id,
+            data,
+            index: 0,
+            count: fragment_count(data.len()),
+        })
+    }
+
+    /// Total number of fragments in the message
+    pub fn total(&self) -> usize {
+        self.count
+    }
+}
+
+impl Iterator for Fragments<'_> {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let start = self.index * FRAGMENT_DATA_SIZE;
+        let end = (start + FRAGMENT_DATA_SIZE).min(self.data.len());
+        let fragment_header = FragmentHeader {
+            message_id: self.message_id,
+            index: self.index as u8,
+            count: self.count as u8,
+        };
+
+        let mut payload = [0u8; MAX_DATA_SIZE];
+        payload[..FRAGMENT_HEADER_SIZE].copy_from_slice(&fragment_header.to_bytes());
+        let len = FRAGMENT_HEADER_SIZE + (end - start);
+        payload[FRAGMENT_HEADER_SIZE..len].copy_from_slice(&self.data[start..end]);
+
+        let mut packet = Packet::new(
+            self.template.sender_id,
+            self.template.target_id,
+            self.template
+                .sequence_number
+                .wrapping_add(self.index as u16),
+            &payload[..len],
+        );
+        packet.header.control = self
+            .template
+            .control
+            .with_fragment(true)
+            .with_extended(false);
+        packet.header.ttl = self.template.ttl;
+        packet.header.next_hop = self.template.next_hop;
+
+        self.index += 1;
+        Some(packet)
+    }
+}
+
+/// Message being reassembled
+struct Reassembly {
+    sender_id: u16,
+    message_id: u16,
+    count: u8,
+    /// Bit `i` is set once fragment `i` has arrived
+    received: u128,
+    /// Message length, known once the last fragment arrived
+    total_len: Option<usize>,
+    started_ms: u64,
+    buffer: [u8; MAX_MESSAGE_SIZE],
+}
+
+impl Reassembly {
+    fn is_complete(&self) -> bool {
+        let all = if self.count as u32 >= u128::BITS {
+            u128::MAX
+        } else {
+            (1u128 << self.count) - 1
+        };
+        self.received == all
+    }
+}
+
+/// Message delivered recently
+struct Completed {
+    sender_id: u16,
+    message_id: u16,
+    completed_ms: u64,
+}
+
+/// Reassembles fragmented messages
+///
+/// # Type Parameters
+/// * `SLOTS` - Maximum number of messages reassembled concurrently, each slot
+///   reserves `MAX_MESSAGE_SIZE` bytes
+pub struct Reassembler<const SLOTS: usize> {
+    slots: Vec<Reassembly, SLOTS>,
+    /// Oldest first, kept for `timeout_ms` after completion
+    completed: Deque<Completed, COMPLETED_HISTORY>,
+    timeout_ms: u64,
+}
+
+impl<const SLOTS: usize> Default for Reassembler<SLOTS> {
+    fn default() -> Self {
+        Self::new(DEFAULT_REASSEMBLY_TIMEOUT_MS)
+    }
+}
+
+impl<const SLOTS: usize> Reassembler<SLOTS> {
+    /// Create a reassembler that drops messages not completed within `timeout_ms`
+    pub fn new(timeout_ms: u64) -> Self {
+        Self {
+            slots: Vec::new(),
+            completed: Deque::new(),
+            timeout_ms,
+        }
+    }
+
+    /// Number of messages currently being reassembled
+    pub fn in_progress(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Drop reassemblies that exceeded the timeout
+    ///
+    /// Completed messages older than the timeout are forgotten as well.
+    ///
+    /// # Returns
+    /// Number of dropped messages
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let timeout_ms = self.timeout_ms;
+        while self
+            .completed
+            .front()
+            .is_some_and(|done| now_ms.saturating_sub(done.completed_ms) > timeout_ms)
+        {
+            self.completed.pop_front();
+        }
+
+        let before = self.slots.len();
+        self.slots
+            .retain(|slot| now_ms.saturating_sub(slot.started_ms) <= timeout_ms);
+        let dropped = before - self.slots.len();
+        if dropped > 0 {
+            terminal_log!(debug, "Dropped {} incomplete fragmented messages", dropped);
+        }
+        dropped
+    }
+
+    /// Process a received fragment packet
+    ///
+    /// # Returns
+    /// * `Ok(Some(message))` once all fragments of a message arrived
+    /// * `Ok(None)` if more fragments are needed or the fragment was a duplicate, also
+    ///   of a recently completed message
+    /// * `Err(FragmentError)` if the fragment was rejected
+    pub fn push(
+        &mut self,
+        packet: &Packet,
+        now_ms: u64,
+    ) -> Result<Option<MessageBuffer>, FragmentError> {
+        self.expire(now_ms);
+
+        if !packet.header.control.is_fragment() {
+            return Err(FragmentError::InvalidFragment);
+        }
+        let payload = packet.payload_data();
+        let header = FragmentHeader::from_bytes(payload)?;
+        let data = &payload[FRAGMENT_HEADER_SIZE..];
+
+        let is_last = header.index + 1 == header.count;
+        let valid_len = if is_last {
+            // Only a single-fragment message may be empty
+            !data.is_empty() || header.count == 1
+        } else {
+            data.len() == FRAGMENT_DATA_SIZE
+        };
+        if !valid_len {
+            return Err(FragmentError::InvalidFragment);
+        }
+
+        let sender_id = packet.header.sender_id;
+        if self.was_completed(sender_id, header.message_id) {
+            return Ok(None);
+        }
+
+        if header.count == 1 {
+            let message =
+                MessageBuffer::from_slice(data).map_err(|_| FragmentError::MessageTooLarge)?;
+            self.mark_completed(sender_id, header.message_id, now_ms);
+            return Ok(Some(message));
+        }
+
+        let position = self
+            .slots
+            .iter()
+            .position(|slot| slot.sender_id == sender_id && slot.message_id == header.message_id);
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let slot = Reassembly {
+                    sender_id,
+                    message_id: header.message_id,
+                    count: header.count,
+                    received: 0,
+                    total_len: None,
+                    started_ms: now_ms,
+                    buffer: [0; MAX_MESSAGE_SIZE],
+                };
+                if self.slots.push(slot).is_err() {
+                    terminal_log!(
+                        warn,
+                        "No reassembly slot for message {} from {}",
+                        header.message_id,
+                        sender_id
+                    );
+                    return Err(FragmentError::TooManyReassemblies);
+                }
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[position];
+        if slot.count != header.count {
+            // A stray fragment must not discard the partial message, it completes or
+            // times out on its own
+            terminal_log!(
+                warn,
+                "Fragment of message {} from {} claims {} fragments, expected {}",
+                header.message_id,
+                sender_id,
+                header.count,
+                slot.count
+            );
+            return Err(FragmentError::InconsistentFragment);
+        }
+
+        let bit = 1u128 << header.index;
+        if slot.received & bit != 0 {
+            return Ok(None);
+        }
+
+        let start = header.index as usize * FRAGMENT_DATA_SIZE;
+        let end = start + data.len();
+        if end > MAX_MESSAGE_SIZE {
+            self.slots.swap_remove(position);
+            return Err(FragmentError::MessageTooLarge);
+        }
+        slot.buffer[start..end].copy_from_slice(data);
+        slot.received |= bit;
+        if is_last {
+            slot.total_len = Some(end);
+        }
+
+        if !slot.is_complete() {
+            return Ok(None);
+        }
+
+        let slot = self.slots.swap_remove(position);
+        self.mark_completed(sender_id, header.message_id, now_ms);
+        let len = slot.total_len.unwrap_or(0);
+        MessageBuffer::from_slice(&slot.buffer[..len])
+            .map(Some)
+            .map_err(|_| FragmentError::MessageTooLarge)
+    }
+
+    fn was_completed(&self, sender_id: u16, message_id: u16) -> bool {
+        self.completed
+            .iter()
+            .any(|done| done.sender_id == sender_id && done.message_id == message_id)
+    }
+
+    fn mark_completed(&mut self, sender_id: u16, message_id: u16, now_ms: u64) {
+        if self.completed.is_full() {
+            self.completed.pop_front();
+        }
+        // Cannot fail, a slot was freed above
+        let _ = self.completed.push_back(Completed {
+            sender_id,
+            message_id,
+            completed_ms: now_ms,
+        });
+    }
+}
@@ -0,0 +1,67 @@
+/// Radio frame integrity layer
+/// This module wraps serialized packets into frames protected by a CRC-16 checksum.
+/// Frame layout: `header | payload (payload_len bytes) | crc16 (little-endian)`
+/// The checksum covers both header and payload bytes.
+use super::protocol::{Header, Packet, HEADER_SIZE, PACKET_SIZE_BYTES};
+use super::traits::RadioError;
+use crc::{Crc, CRC_16_IBM_3740};
+use heapless::Vec;
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF) used for frame integrity checks
+const FRAME_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+
+/// Size of the checksum appended to every frame in bytes
+pub const CRC_SIZE: usize = 2;
+
+/// Largest possible frame in bytes (full packet + checksum)
+pub const MAX_FRAME_SIZE: usize = PACKET_SIZE_BYTES + CRC_SIZE;
+
+/// Buffer holding one encoded frame
+pub type Frame = Vec<u8, MAX_FRAME_SIZE>;
+
+/// Compute the total frame length for a packet with the given header
+///
+/// Useful for receivers that need to know how many bytes to collect
+/// once the header has arrived.
+pub fn frame_len(header: &Header) -> usize {
+    HEADER_SIZE + header.payload_len as usize + CRC_SIZE
+}
+
+/// Encode a packet into a CRC-protected frame ready for transmission
+pub fn encode(packet: &Packet) -> Frame {
+    let bytes = packet.to_bytes();
+    let body = &bytes[..packet.encoded_len()];
+
+    let mut frame = Frame::new();
+    // Capacity is MAX_FRAME_SIZE, body + CRC always fits
+    let _ = frame.extend_from_slice(body);
+    let _ = frame.extend_from_slice(&FRAME_CRC.checksum(body).to_le_bytes());
+    frame
+}
+
+/// Verify the checksum of a received frame and decode the packet inside it
+///
+/// # Returns
+/// * `Ok(Packet)` if the checksum matches and the packet is well-formed
+/// * `Err(RadioError::ChecksumMismatch)` if the frame was corrupted on air
+/// * `Err(RadioError::InvalidPacket)` if the frame is too short or malformed
+pub fn decode(frame: &[u8]) -> Result<Packet, RadioError> {
+    if frame.len() < HEADER_SIZE + CRC_SIZE {
+        return Err(RadioError::InvalidPacket);
+    }
+
+    let (body, crc_bytes) = frame.split_at(frame.len() - CRC_SIZE);
+    let received_crc = u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]);
+    if FRAME_CRC.checksum(body) != received_crc {
+        return Err(RadioError::ChecksumMismatch);
+    }
+
+    let packet = Packet::from_bytes(body)?;
+
+    // Trailing bytes between payload and CRC mean the length field was not honest
+    if packet.encoded_len() != body.len() {
+        return Err(RadioError::InvalidPacket);
+    }
+
+    Ok(packet)
+}
@@ -0,0 +1,734 @@
+/// Frequency hopping and channel agility
+/// A single jammed or noisy channel must not take the whole swarm down, so nodes hop
+/// together over a `ChannelPlan` of up to `MAX_CHANNELS` frequencies. Network time
+/// (see `radio::timesync`) is divided into dwell intervals ("hops"). The channel of
+/// every hop follows from a hash of the hop number and a seed shared by the network,
+/// the network ID handed out on join by default, so all synchronized nodes tune to
+/// the same channel without exchanging anything per hop.
+///
+/// Every `HopConfig::rendezvous_interval`-th hop uses the rendezvous channel of the
+/// plan. Nodes without network time, joining nodes in particular, stay on that
+/// channel and meet the swarm there to receive time sync and join answers.
+///
+/// Each node rates the channels it sends on from ack feedback. Nodes hopping with
+/// different blacklists would lose each other, so only the coordinator turns its
+/// ratings into a `ChannelMap` and broadcasts it as `ChannelMapMessage` together
+/// with the hop from which it applies. Nodes only follow announcements sent by the
+/// coordinator, the gateway of their `radio::join::NetworkParams`
+/// (`ChannelHopper::set_coordinator`). Hops landing on a blocked channel are spread
+/// over the allowed ones. Blocked channels are tried again after
+/// `BlacklistConfig::retry_after_ms`, the rendezvous channel is never blocked.
+///
+/// A single announcement is easily missed, so the coordinator repeats the latest one
+/// on every rendezvous hop (`ChannelHopper::announcement`), where nodes that lost
+/// sync listen as well. Joining nodes get the active map with their network
+/// parameters (see `radio::join::NetworkParams`).
+///
+/// Channel map body: `allowed: u16 | from_hop: u64`
+use super::link_quality::{permille, RatioEstimator};
+use super::message::Message;
+use super::protocol::{Packet, BROADCAST_ID};
+use super::traits::{RadioError, RadioTransceiver};
+use crate::terminal_log;
+use crc::{Crc, CRC_32_ISCSI};
+use defmt::Format;
+use heapless::Vec;
+
+/// Largest number of channels in a plan
+pub const MAX_CHANNELS: usize = 16;
+
+/// Frequency of the first channel of the default plan
+pub const DEFAULT_BASE_FREQUENCY_HZ: u32 = 433_120_000;
+
+/// Distance between neighboring channels of the default plan
+pub const DEFAULT_CHANNEL_SPACING_HZ: u32 = 200_000;
+
+/// Number of channels in the default plan
+pub const DEFAULT_CHANNEL_COUNT: u8 = 8;
+
+/// Rendezvous channel of the default plan, 433.92 MHz
+pub const DEFAULT_RENDEZVOUS_CHANNEL: u8 = 4;
+
+/// Default time spent on one channel
+pub const DEFAULT_DWELL_MS: u32 = 1_000;
+
+/// Default number of hops between two visits of the rendezvous channel
+pub const DEFAULT_RENDEZVOUS_INTERVAL: u16 = 8;
+
+/// Size of an encoded channel map body
+pub const CHANNEL_MAP_SIZE: usize = 10;
+
+// The default plan must fit the channel map
+const _: () = assert!(
+    DEFAULT_CHANNEL_COUNT as usize <= MAX_CHANNELS
+        && DEFAULT_RENDEZVOUS_CHANNEL < DEFAULT_CHANNEL_COUNT
+);
+
+/// Hash selecting the channel of a hop
+const HOP_HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// Frequencies the swarm hops over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelPlan {
+    frequencies: Vec<u32, MAX_CHANNELS>,
+    rendezvous: u8,
+}
+
+impl Default for ChannelPlan {
+    fn default() -> Self {
+        Self {
+            frequencies: (0..DEFAULT_CHANNEL_COUNT as u32)
+                .map(|channel| DEFAULT_BASE_FREQUENCY_HZ + channel * DEFAULT_CHANNEL_SPACING_HZ)
+                .collect(),
+            rendezvous: DEFAULT_RENDEZVOUS_CHANNEL,
+        }
+    }
+}
+
+impl ChannelPlan {
+    /// Create a plan from a list of frequencies
+    ///
+    /// # Returns
+    /// `None` if the list is empty, longer than `MAX_CHANNELS` or the rendezvous
+    /// channel is not part of it
+    pub fn new(frequencies: &[u32], rendezvous: u8) -> Option<Self> {
+        if (rendezvous as usize) >= frequencies.len() {
+            return None;
+        }
+        Some(Self {
+            frequencies: Vec::from_slice(frequencies).ok()?,
+            rendezvous,
+        })
+    }
+
+    /// Create a plan of `count` equally spaced channels starting at `base_hz`
+    pub fn uniform(base_hz: u32, spacing_hz: u32, count: u8, rendezvous: u8) -> Option<Self> {
+        let mut frequencies: Vec<u32, MAX_CHANNELS> = Vec::new();
+        for channel in 0..count as u32 {
+            let frequency = spacing_hz
+                .checked_mul(channel)
+                .and_then(|offset| base_hz.checked_add(offset))?;
+            frequencies.push(frequency).ok()?;
+        }
+        Self::new(&frequencies, rendezvous)
+    }
+
+    /// Number of channels
+    pub fn len(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    /// Check whether the plan has no channels, never true for a constructed plan
+    pub fn is_empty(&self) -> bool {
+        self.frequencies.is_empty()
+    }
+
+    /// Frequency of a channel
+    pub fn frequency(&self, channel: u8) -> Option<u32> {
+        self.frequencies.get(channel as usize).copied()
+    }
+
+    /// Channel of a frequency
+    pub fn channel_of(&self, frequency_hz: u32) -> Option<u8> {
+        self.frequencies
+            .iter()
+            .position(|&frequency| frequency == frequency_hz)
+            .map(|channel| channel as u8)
+    }
+
+    /// Channel used by nodes without network time
+    pub fn rendezvous(&self) -> u8 {
+        self.rendezvous
+    }
+
+    /// Frequency of the rendezvous channel
+    pub fn rendezvous_frequency(&self) -> u32 {
+        self.frequencies[self.rendezvous as usize]
+    }
+}
+
+/// Set of channels the swarm may hop to, one bit per channel of the plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct ChannelMap {
+    allowed: u16,
+}
+
+impl ChannelMap {
+    /// Map allowing the first `channel_count` channels
+    pub const fn all(channel_count: usize) -> Self {
+        let allowed = if channel_count >= MAX_CHANNELS {
+            u16::MAX
+        } else {
+            (1 << channel_count) - 1
+        };
+        Self { allowed }
+    }
+
+    /// Create a map from its wire representation
+    pub const fn from_bits(allowed: u16) -> Self {
+        Self { allowed }
+    }
+
+    /// Wire representation, bit `n` set if channel `n` is allowed
+    pub const fn bits(&self) -> u16 {
+        self.allowed
+    }
+
+    /// Check whether a channel may be used
+    pub fn is_allowed(&self, channel: u8) -> bool {
+        (channel as usize) < MAX_CHANNELS && self.allowed & (1 << channel) != 0
+    }
+
+    /// Allow a channel
+    pub fn allow(&mut self, channel: u8) {
+        if (channel as usize) < MAX_CHANNELS {
+            self.allowed |= 1 << channel;
+        }
+    }
+
+    /// Block a channel
+    pub fn block(&mut self, channel: u8) {
+        if (channel as usize) < MAX_CHANNELS {
+            self.allowed &= !(1 << channel);
+        }
+    }
+
+    /// Number of allowed channels
+    pub fn allowed_count(&self) -> u32 {
+        self.allowed.count_ones()
+    }
+
+    /// Allowed channels in ascending order
+    pub fn channels(&self) -> impl Iterator<Item = u8> {
+        let map = *self;
+        (0..MAX_CHANNELS as u8).filter(move |&channel| map.is_allowed(channel))
+    }
+
+    /// Channels allowed in both maps
+    fn intersection(&self, other: &Self) -> Self {
+        Self {
+            allowed: self.allowed & other.allowed,
+        }
+    }
+}
+
+/// Channel map broadcast by the coordinator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct ChannelMapMessage {
+    /// Channels allowed from `from_hop` on
+    pub map: ChannelMap,
+    /// First hop using the map, far enough ahead for the broadcast to reach everyone
+    pub from_hop: u64,
+}
+
+impl ChannelMapMessage {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; CHANNEL_MAP_SIZE] {
+        let mut body = [0u8; CHANNEL_MAP_SIZE];
+        body[0..2].copy_from_slice(&self.map.bits().to_le_bytes());
+        body[2..10].copy_from_slice(&self.from_hop.to_le_bytes());
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..CHANNEL_MAP_SIZE)?;
+        let mut from_hop = [0u8; 8];
+        from_hop.copy_from_slice(&body[2..10]);
+        Some(Self {
+            map: ChannelMap::from_bits(u16::from_le_bytes([body[0], body[1]])),
+            from_hop: u64::from_le_bytes(from_hop),
+        })
+    }
+
+    /// Build the broadcast packet carrying this message
+    pub fn to_packet(&self, sender_id: u16, sequence_number: u16) -> Packet {
+        Message::ChannelMap(*self).to_packet(sender_id, BROADCAST_ID, sequence_number)
+    }
+
+    /// Extract a channel map message from a received packet
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        match Message::from_packet(packet) {
+            Ok(Message::ChannelMap(message)) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// Hopping parameters, identical on every node of the swarm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct HopConfig {
+    /// Seed of the hopping sequence
+    pub seed: u32,
+    /// Time spent on one channel
+    pub dwell_ms: u32,
+    /// Every this many hops the rendezvous channel is used, 0 disables rendezvous
+    /// hops
+    pub rendezvous_interval: u16,
+}
+
+impl Default for HopConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            dwell_ms: DEFAULT_DWELL_MS,
+            rendezvous_interval: DEFAULT_RENDEZVOUS_INTERVAL,
+        }
+    }
+}
+
+impl HopConfig {
+    /// Default parameters seeded with the network ID received on join
+    pub fn for_network(network_id: u16) -> Self {
+        Self {
+            seed: network_id as u32,
+            ..Self::default()
+        }
+    }
+}
+
+/// Channel sequence of the swarm
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoppingSchedule {
+    plan: ChannelPlan,
+    config: HopConfig,
+    map: ChannelMap,
+    /// Announced map waiting for its first hop
+    pending: Option<ChannelMapMessage>,
+}
+
+impl HoppingSchedule {
+    /// Create a schedule allowing every channel of the plan
+    pub fn new(plan: ChannelPlan, config: HopConfig) -> Self {
+        let config = HopConfig {
+            dwell_ms: config.dwell_ms.max(1),
+            ..config
+        };
+        Self {
+            map: ChannelMap::all(plan.len()),
+            plan,
+            config,
+            pending: None,
+        }
+    }
+
+    /// Channels hopped over
+    pub fn plan(&self) -> &ChannelPlan {
+        &self.plan
+    }
+
+    /// Hopping parameters
+    pub fn config(&self) -> &HopConfig {
+        &self.config
+    }
+
+    /// Channel map in use
+    pub fn map(&self) -> ChannelMap {
+        self.map
+    }
+
+    /// Announced channel map not in use yet
+    pub fn pending(&self) -> Option<&ChannelMapMessage> {
+        self.pending.as_ref()
+    }
+
+    /// Hop number at network time `network_ms`
+    pub fn hop_at(&self, network_ms: u64) -> u64 {
+        network_ms / self.config.dwell_ms as u64
+    }
+
+    /// Network time at which a hop starts
+    pub fn hop_start_ms(&self, hop: u64) -> u64 {
+        hop.saturating_mul(self.config.dwell_ms as u64)
+    }
+
+    /// Channel map valid during a hop
+    pub fn map_for_hop(&self, hop: u64) -> ChannelMap {
+        match self.pending {
+            Some(pending) if hop >= pending.from_hop => pending.map,
+            _ => self.map,
+        }
+    }
+
+    /// Channel used during a hop
+    pub fn channel_for_hop(&self, hop: u64) -> u8 {
+        let interval = self.config.rendezvous_interval as u64;
+        if interval != 0 && hop.is_multiple_of(interval) {
+            return self.plan.rendezvous;
+        }
+        let map = self
+            .map_for_hop(hop)
+            .intersection(&ChannelMap::all(self.plan.len()));
+        let allowed = map.allowed_count();
+        if allowed == 0 {
+            return self.plan.rendezvous;
+        }
+
+        let mut digest = HOP_HASH.digest();
+        digest.update(&self.config.seed.to_le_bytes());
+        digest.update(&hop.to_le_bytes());
+        // Multiply-shift reduction, see `XorShift32::below`
+        let pick = ((digest.finalize() as u64 * allowed as u64) >> 32) as usize;
+        map.channels().nth(pick).unwrap_or(self.plan.rendezvous)
+    }
+
+    /// Channel used at network time `network_ms`
+    pub fn channel_at(&self, network_ms: u64) -> u8 {
+        self.channel_for_hop(self.hop_at(network_ms))
+    }
+
+    /// Frequency used at network time `network_ms`
+    pub fn frequency_at(&self, network_ms: u64) -> u32 {
+        self.plan
+            .frequency(self.channel_at(network_ms))
+            .unwrap_or(self.plan.rendezvous_frequency())
+    }
+
+    /// Use an announced channel map from its first hop on
+    ///
+    /// A newer announcement replaces one that has not taken effect yet.
+    pub fn schedule_map(&mut self, message: ChannelMapMessage) {
+        self.pending = Some(message);
+    }
+
+    /// Make an announced map the active one once its first hop is reached
+    fn advance(&mut self, hop: u64) {
+        if let Some(pending) = self.pending {
+            if hop >= pending.from_hop {
+                self.map = pending.map;
+                self.pending = None;
+            }
+        }
+    }
+}
+
+/// Channel blacklisting parameters, used by the coordinator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct BlacklistConfig {
+    /// Channels delivering fewer packets are blocked
+    pub min_delivery_permille: u16,
+    /// Channels that stay allowed however poor they are
+    pub min_allowed: u8,
+    /// Time after which a blocked channel is tried again
+    pub retry_after_ms: u64,
+    /// Hops between announcing a map and using it
+    pub switch_delay_hops: u16,
+}
+
+impl Default for BlacklistConfig {
+    fn default() -> Self {
+        Self {
+            min_delivery_permille: 500,
+            min_allowed: 3,
+            retry_after_ms: 600_000,
+            switch_delay_hops: 4,
+        }
+    }
+}
+
+/// Delivery ratio of every channel of the plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelRatings {
+    delivery: [RatioEstimator; MAX_CHANNELS],
+    blocked_since_ms: [Option<u64>; MAX_CHANNELS],
+}
+
+impl Default for ChannelRatings {
+    fn default() -> Self {
+        Self {
+            delivery: [RatioEstimator::default(); MAX_CHANNELS],
+            blocked_since_ms: [None; MAX_CHANNELS],
+        }
+    }
+}
+
+impl ChannelRatings {
+    /// Record whether a packet sent on `channel` was acknowledged
+    pub fn record(&mut self, channel: u8, delivered: bool) {
+        if let Some(estimator) = self.delivery.get_mut(channel as usize) {
+            estimator.sample(delivered);
+        }
+    }
+
+    /// Share of acknowledged packets on a channel, in per mille
+    ///
+    /// # Returns
+    /// `None` until enough packets were sent on the channel
+    pub fn delivery_permille(&self, channel: u8) -> Option<u16> {
+        self.delivery
+            .get(channel as usize)
+            .and_then(RatioEstimator::estimate)
+            .map(permille)
+    }
+
+    /// Derive the channel map from the ratings
+    ///
+    /// Channels rated below the threshold are blocked, worst first, as long as
+    /// `min_allowed` channels remain. Channels blocked for `retry_after_ms` are
+    /// allowed again with a fresh rating.
+    pub fn evaluate(
+        &mut self,
+        plan: &ChannelPlan,
+        current: ChannelMap,
+        config: &BlacklistConfig,
+        now_ms: u64,
+    ) -> ChannelMap {
+        let mut map = current.intersection(&ChannelMap::all(plan.len()));
+        map.allow(plan.rendezvous());
+
+        for channel in 0..plan.len() as u8 {
+            let index = channel as usize;
+            match self.blocked_since_ms[index] {
+                Some(since) if now_ms.saturating_sub(since) >= config.retry_after_ms => {
+                    self.blocked_since_ms[index] = None;
+                    self.delivery[index] = RatioEstimator::default();
+                    map.allow(channel);
+                }
+                Some(_) => map.block(channel),
+                // Blocked before this node kept track, e.g. by a previous coordinator
+                None if !map.is_allowed(channel) => self.blocked_since_ms[index] = Some(now_ms),
+                None => {}
+            }
+        }
+
+        loop {
+            let worst = map
+                .channels()
+                .filter(|&channel| channel != plan.rendezvous())
+                .filter_map(|channel| Some((channel, self.delivery_permille(channel)?)))
+                .filter(|&(_, delivery)| delivery < config.min_delivery_permille)
+                .min_by_key(|&(_, delivery)| delivery);
+            let Some((channel, delivery)) = worst else {
+                break;
+            };
+            if map.allowed_count() <= config.min_allowed as u32 {
+                break;
+            }
+            terminal_log!(
+                warn,
+                "Blocking channel {} ({} permille delivered)",
+                channel,
+                delivery
+            );
+            map.block(channel);
+            self.blocked_since_ms[channel as usize] = Some(now_ms);
+        }
+        map
+    }
+}
+
+/// Hopping counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct HopStats {
+    /// Frequency changes
+    pub hops: u32,
+    /// Returns to the rendezvous channel because network time was lost
+    pub rendezvous_fallbacks: u32,
+    /// Channel maps received or announced
+    pub map_updates: u32,
+    /// Channel maps ignored because they were not sent by the coordinator
+    pub rejected_maps: u32,
+}
+
+/// Keeps a radio on the channel of the current hop
+pub struct ChannelHopper {
+    schedule: HoppingSchedule,
+    ratings: ChannelRatings,
+    blacklist: BlacklistConfig,
+    /// Channel the radio was last tuned to
+    channel: Option<u8>,
+    synchronized: bool,
+    /// Rendezvous hop the channel map was last repeated in
+    announced_hop: Option<u64>,
+    /// Node whose channel maps are followed
+    coordinator_id: Option<u16>,
+    stats: HopStats,
+}
+
+impl ChannelHopper {
+    /// Create a hopper, the blacklist parameters are only used by the coordinator
+    pub fn new(schedule: HoppingSchedule, blacklist: BlacklistConfig) -> Self {
+        Self {
+            schedule,
+            ratings: ChannelRatings::default(),
+            blacklist,
+            channel: None,
+            synchronized: false,
+            announced_hop: None,
+            coordinator_id: None,
+            stats: HopStats::default(),
+        }
+    }
+
+    /// Channel sequence followed
+    pub fn schedule(&self) -> &HoppingSchedule {
+        &self.schedule
+    }
+
+    /// Delivery ratio of the channels
+    pub fn ratings(&self) -> &ChannelRatings {
+        &self.ratings
+    }
+
+    /// Hopping counters
+    pub fn stats(&self) -> &HopStats {
+        &self.stats
+    }
+
+    /// Channel the radio was last tuned to
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    /// Set the node whose channel maps are followed
+    ///
+    /// This is the `gateway_id` of the network parameters received on join, the
+    /// coordinator passes its own ID. Without a coordinator, while joining in
+    /// particular, every announcement is ignored.
+    pub fn set_coordinator(&mut self, coordinator_id: Option<u16>) {
+        self.coordinator_id = coordinator_id;
+    }
+
+    /// Tune the radio to the channel of the current hop
+    ///
+    /// # Arguments
+    /// * `radio` - Radio to tune, the frequency is only set when it changes
+    /// * `network_ms` - Network time, `None` while unsynchronized or joining, which
+    ///   selects the rendezvous channel
+    ///
+    /// # Returns
+    /// Network time of the next hop, `None` on the rendezvous channel
+    pub async fn tune<R: RadioTransceiver>(
+        &mut self,
+        radio: &mut R,
+        network_ms: Option<u64>,
+    ) -> Result<Option<u64>, RadioError> {
+        let (channel, next_hop_ms) = match network_ms {
+            Some(now_ms) => {
+                let hop = self.schedule.hop_at(now_ms);
+                self.schedule.advance(hop);
+                let next = self.schedule.hop_start_ms(hop.saturating_add(1));
+                (self.schedule.channel_for_hop(hop), Some(next))
+            }
+            None => {
+                if self.synchronized {
+                    terminal_log!(
+                        warn,
+                        "Network time lost, falling back to rendezvous channel"
+                    );
+                    self.stats.rendezvous_fallbacks += 1;
+                }
+                (self.schedule.plan().rendezvous(), None)
+            }
+        };
+        self.synchronized = network_ms.is_some();
+
+        let plan = self.schedule.plan();
+        let frequency = plan
+            .frequency(channel)
+            .unwrap_or(plan.rendezvous_frequency());
+        if radio.get_frequency() != frequency {
+            radio.set_frequency(frequency).await?;
+            self.stats.hops += 1;
+            terminal_log!(trace, "Hopped to channel {} ({} Hz)", channel, frequency);
+        }
+        self.channel = Some(channel);
+        Ok(next_hop_ms)
+    }
+
+    /// Record whether a packet sent on the current channel was acknowledged
+    pub fn record_delivery(&mut self, acknowledged: bool) {
+        if let Some(channel) = self.channel {
+            self.ratings.record(channel, acknowledged);
+        }
+    }
+
+    /// Follow a channel map announced by the coordinator
+    ///
+    /// Maps sent by any other node and repeated announcements of the map already
+    /// followed are ignored.
+    ///
+    /// # Arguments
+    /// * `sender_id` - Originator of the announcement
+    /// * `message` - Announced channel map
+    pub fn on_channel_map(&mut self, sender_id: u16, message: &ChannelMapMessage) {
+        if self.coordinator_id != Some(sender_id) {
+            terminal_log!(
+                warn,
+                "Ignored channel map {:04x} from non-coordinator {}",
+                message.map.bits(),
+                sender_id
+            );
+            self.stats.rejected_maps += 1;
+            return;
+        }
+        self.follow_map(message);
+    }
+
+    /// Schedule a channel map unless it is already followed
+    fn follow_map(&mut self, message: &ChannelMapMessage) {
+        let known = match self.schedule.pending() {
+            Some(pending) => pending == message,
+            None => self.schedule.map() == message.map,
+        };
+        if known {
+            return;
+        }
+        terminal_log!(
+            info,
+            "Channel map {:04x} from hop {}",
+            message.map.bits(),
+            message.from_hop
+        );
+        self.schedule.schedule_map(*message);
+        self.stats.map_updates += 1;
+    }
+
+    /// Re-evaluate the channel map from the own ratings, used by the coordinator
+    ///
+    /// # Arguments
+    /// * `network_ms` - Current network time
+    ///
+    /// # Returns
+    /// The announcement to broadcast if the map changed, it is already scheduled
+    /// locally
+    pub fn evaluate(&mut self, network_ms: u64) -> Option<ChannelMapMessage> {
+        let hop = self.schedule.hop_at(network_ms);
+        let latest = self.schedule.map_for_hop(u64::MAX);
+        let map = self
+            .ratings
+            .evaluate(self.schedule.plan(), latest, &self.blacklist, network_ms);
+        if map == latest {
+            return None;
+        }
+        let message = ChannelMapMessage {
+            map,
+            from_hop: hop.saturating_add(self.blacklist.switch_delay_hops.max(1) as u64),
+        };
+        self.follow_map(&message);
+        Some(message)
+    }
+
+    /// The announcement to repeat, used by the coordinator
+    ///
+    /// # Arguments
+    /// * `network_ms` - Current network time
+    ///
+    /// # Returns
+    /// The latest channel map once per rendezvous hop, `None` during other hops
+    pub fn announcement(&mut self, network_ms: u64) -> Option<ChannelMapMessage> {
+        let hop = self.schedule.hop_at(network_ms);
+        let interval = self.schedule.config().rendezvous_interval as u64;
+        if interval == 0 || !hop.is_multiple_of(interval) || self.announced_hop == Some(hop) {
+            return None;
+        }
+        self.announced_hop = Some(hop);
+        Some(match self.schedule.pending() {
+            Some(pending) => *pending,
+            None => ChannelMapMessage {
+                map: self.schedule.map(),
+                from_hop: hop,
+            },
+        })
+    }
+}
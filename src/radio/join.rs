@@ -0,0 +1,681 @@
+/// Network join and node ID provisioning
+/// A node without an ID broadcasts join requests carrying its 12-byte MCU unique ID
+/// (`DeviceManagement::get_unique_id_bytes`) from `UNASSIGNED_NODE_ID`. The
+/// coordinator (usually the gateway) answers with a broadcast join accept holding the
+/// same unique ID, the assigned node ID and the network parameters. When the swarm
+/// uses per-join session keys, the accept announces one and the key follows in a
+/// separate message addressed to the new node ID. That message must be sealed with the
+/// key installed at flashing time (see `radio::security`), it is never sent in clear.
+///
+/// Over `SecureRadio` join requests are sealed like any other packet. All unjoined
+/// boards transmit as `UNASSIGNED_NODE_ID`, the UID tag in the security fields keeps
+/// their nonces and replay windows apart. Once joined, the node switches its security
+/// context to the assigned ID with `PacketSecurity::set_node_id`.
+///
+/// The node persists the resulting `Membership` and reuses it after a reboot. A
+/// re-joining node asks for its previous ID, which the coordinator grants unless it
+/// was given to another board in the meantime. A node that hears its own ID being
+/// granted to a different unique ID drops its membership and joins again.
+///
+/// Message bodies:
+/// - join request: `uid: [u8; 12] | previous_id: u16 | role: u8`
+/// - join accept: `uid: [u8; 12] | node_id: u16 | params | key_follows: u8`
+/// - session key: `key_id: u8 | key: [u8; 16]`
+///
+/// with `params` = `network_id: u16 | gateway_id: u16 | channel: u8 | report_interval_s: u16 |
+/// channel_map: u16`
+///
+/// The channel map in the parameters lets a joining node hop with the swarm right away
+/// (see `radio::hopping`). Membership records written before it was added fail their
+/// CRC check, those nodes join again.
+use super::hopping::ChannelMap;
+use super::message::Message;
+use super::neighbor::NodeRole;
+use super::protocol::{Header, Packet, BROADCAST_ID};
+use super::random::XorShift32;
+use super::security::{NetworkKey, NETWORK_KEY_SIZE};
+use crate::hw::traits::FlashStorage;
+use crate::terminal_log;
+use crc::{Crc, CRC_16_IBM_3740};
+use defmt::Format;
+use heapless::Vec;
+
+/// Size of the MCU unique ID
+pub const UID_SIZE: usize = 12;
+
+/// Sender ID used by nodes that have not joined yet
+pub const UNASSIGNED_NODE_ID: u16 = 0xFFFF;
+
+/// Lowest node ID handed out by the coordinator
+pub const FIRST_NODE_ID: u16 = 1;
+
+/// Size of encoded network parameters
+pub const NETWORK_PARAMS_SIZE: usize = 9;
+
+/// Size of an encoded join request body
+pub const JOIN_REQUEST_SIZE: usize = UID_SIZE + 3;
+
+/// Size of an encoded join accept body
+pub const JOIN_ACCEPT_SIZE: usize = UID_SIZE + 2 + NETWORK_PARAMS_SIZE + 1;
+
+/// Size of an encoded session key body
+pub const SESSION_KEY_SIZE: usize = 1 + NETWORK_KEY_SIZE;
+
+/// Size of the persisted membership record
+///
+/// Layout: `magic: u16 | node_id: u16 | params | has_key: u8 | session key | crc16`
+pub const MEMBERSHIP_RECORD_SIZE: usize = 2 + 2 + NETWORK_PARAMS_SIZE + 1 + SESSION_KEY_SIZE + 2;
+
+/// Delay before the first join request is repeated
+pub const DEFAULT_JOIN_RETRY_MS: u64 = 2_000;
+
+/// Upper bound of the doubling delay between join requests
+pub const MAX_JOIN_RETRY_MS: u64 = 60_000;
+
+/// Marker of a valid membership record
+const MEMBERSHIP_MAGIC: u16 = 0x4A4E;
+
+/// CRC-16/CCITT-FALSE protecting the membership record
+const RECORD_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+
+/// MCU unique ID
+pub type Uid = [u8; UID_SIZE];
+
+/// Network parameters handed out on join
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct NetworkParams {
+    /// Identifier of the swarm
+    pub network_id: u16,
+    /// Node collecting the swarm data
+    pub gateway_id: u16,
+    /// Radio channel used for normal traffic
+    pub channel: u8,
+    /// Interval between sensor reports
+    pub report_interval_s: u16,
+    /// Channels the swarm hops over when the parameters are handed out
+    pub channel_map: ChannelMap,
+}
+
+impl NetworkParams {
+    /// Encode the parameters
+    pub fn to_bytes(&self) -> [u8; NETWORK_PARAMS_SIZE] {
+        let mut bytes = [0u8; NETWORK_PARAMS_SIZE];
+        bytes[0..2].copy_from_slice(&self.network_id.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.gateway_id.to_le_bytes());
+        bytes[4] = self.channel;
+        bytes[5..7].copy_from_slice(&self.report_interval_s.to_le_bytes());
+        bytes[7..9].copy_from_slice(&self.channel_map.bits().to_le_bytes());
+        bytes
+    }
+
+    /// Decode parameters
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..NETWORK_PARAMS_SIZE)?;
+        Some(Self {
+            network_id: u16::from_le_bytes([bytes[0], bytes[1]]),
+            gateway_id: u16::from_le_bytes([bytes[2], bytes[3]]),
+            channel: bytes[4],
+            report_interval_s: u16::from_le_bytes([bytes[5], bytes[6]]),
+            channel_map: ChannelMap::from_bits(u16::from_le_bytes([bytes[7], bytes[8]])),
+        })
+    }
+}
+
+/// Request of a node to join the swarm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct JoinRequest {
+    /// MCU unique ID of the joining node
+    pub uid: Uid,
+    /// ID the node held before, `UNASSIGNED_NODE_ID` on the first join
+    pub previous_id: u16,
+    /// Role the node will play
+    pub role: NodeRole,
+}
+
+impl JoinRequest {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; JOIN_REQUEST_SIZE] {
+        let mut body = [0u8; JOIN_REQUEST_SIZE];
+        body[..UID_SIZE].copy_from_slice(&self.uid);
+        body[UID_SIZE..UID_SIZE + 2].copy_from_slice(&self.previous_id.to_le_bytes());
+        body[UID_SIZE + 2] = self.role as u8;
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..JOIN_REQUEST_SIZE)?;
+        let mut uid = [0u8; UID_SIZE];
+        uid.copy_from_slice(&body[..UID_SIZE]);
+        Some(Self {
+            uid,
+            previous_id: u16::from_le_bytes([body[UID_SIZE], body[UID_SIZE + 1]]),
+            role: NodeRole::from_u8(body[UID_SIZE + 2])?,
+        })
+    }
+
+    /// Build the broadcast packet carrying this request
+    pub fn to_packet(&self, sequence_number: u16) -> Packet {
+        Message::JoinRequest(*self).to_packet(UNASSIGNED_NODE_ID, BROADCAST_ID, sequence_number)
+    }
+}
+
+/// Answer of the coordinator to a join request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct JoinAccept {
+    /// MCU unique ID of the node the ID is granted to
+    pub uid: Uid,
+    /// Assigned node ID
+    pub node_id: u16,
+    /// Parameters of the swarm
+    pub params: NetworkParams,
+    /// A session key message addressed to `node_id` follows
+    pub key_follows: bool,
+}
+
+impl JoinAccept {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; JOIN_ACCEPT_SIZE] {
+        let mut body = [0u8; JOIN_ACCEPT_SIZE];
+        body[..UID_SIZE].copy_from_slice(&self.uid);
+        body[UID_SIZE..UID_SIZE + 2].copy_from_slice(&self.node_id.to_le_bytes());
+        body[UID_SIZE + 2..UID_SIZE + 2 + NETWORK_PARAMS_SIZE]
+            .copy_from_slice(&self.params.to_bytes());
+        body[JOIN_ACCEPT_SIZE - 1] = self.key_follows as u8;
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..JOIN_ACCEPT_SIZE)?;
+        let mut uid = [0u8; UID_SIZE];
+        uid.copy_from_slice(&body[..UID_SIZE]);
+        Some(Self {
+            uid,
+            node_id: u16::from_le_bytes([body[UID_SIZE], body[UID_SIZE + 1]]),
+            params: NetworkParams::from_bytes(&body[UID_SIZE + 2..])?,
+            key_follows: match body[JOIN_ACCEPT_SIZE - 1] {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+        })
+    }
+
+    /// Build the broadcast packet carrying this accept
+    ///
+    /// The joining node has no ID yet, so the accept is broadcast and matched by its
+    /// unique ID.
+    pub fn to_packet(&self, sender_id: u16, sequence_number: u16) -> Packet {
+        Message::JoinAccept(*self).to_packet(sender_id, BROADCAST_ID, sequence_number)
+    }
+}
+
+/// Session key handed to a newly joined node
+///
+/// Deliberately not `Format`, keys must never end up in logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionKey {
+    /// Identifier of the key, lets nodes tell a rotated key from the current one
+    pub key_id: u8,
+    /// Key material
+    pub key: NetworkKey,
+}
+
+impl SessionKey {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; SESSION_KEY_SIZE] {
+        let mut body = [0u8; SESSION_KEY_SIZE];
+        body[0] = self.key_id;
+        body[1..].copy_from_slice(&self.key);
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..SESSION_KEY_SIZE)?;
+        let mut key = [0u8; NETWORK_KEY_SIZE];
+        key.copy_from_slice(&body[1..]);
+        Some(Self {
+            key_id: body[0],
+            key,
+        })
+    }
+}
+
+/// Result of a join, persisted across reboots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Membership {
+    /// Assigned node ID
+    pub node_id: u16,
+    /// Parameters of the swarm
+    pub params: NetworkParams,
+    /// Session key received after the join, if the swarm uses one
+    pub session_key: Option<SessionKey>,
+}
+
+impl Membership {
+    /// Encode the persisted record
+    pub fn to_bytes(&self) -> [u8; MEMBERSHIP_RECORD_SIZE] {
+        let mut record = [0u8; MEMBERSHIP_RECORD_SIZE];
+        record[0..2].copy_from_slice(&MEMBERSHIP_MAGIC.to_le_bytes());
+        record[2..4].copy_from_slice(&self.node_id.to_le_bytes());
+        record[4..4 + NETWORK_PARAMS_SIZE].copy_from_slice(&self.params.to_bytes());
+        let key_start = 5 + NETWORK_PARAMS_SIZE;
+        if let Some(session_key) = &self.session_key {
+            record[key_start - 1] = 1;
+            record[key_start..key_start + SESSION_KEY_SIZE]
+                .copy_from_slice(&session_key.to_bytes());
+        }
+        let crc_start = MEMBERSHIP_RECORD_SIZE - 2;
+        let crc = RECORD_CRC.checksum(&record[..crc_start]);
+        record[crc_start..].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decode a persisted record
+    ///
+    /// # Returns
+    /// `None` for erased, corrupted or foreign records
+    pub fn from_bytes(record: &[u8]) -> Option<Self> {
+        let record = record.get(..MEMBERSHIP_RECORD_SIZE)?;
+        let crc_start = MEMBERSHIP_RECORD_SIZE - 2;
+        let crc = u16::from_le_bytes([record[crc_start], record[crc_start + 1]]);
+        if u16::from_le_bytes([record[0], record[1]]) != MEMBERSHIP_MAGIC
+            || RECORD_CRC.checksum(&record[..crc_start]) != crc
+        {
+            return None;
+        }
+
+        let node_id = u16::from_le_bytes([record[2], record[3]]);
+        if !is_assignable(node_id) {
+            return None;
+        }
+        let key_start = 5 + NETWORK_PARAMS_SIZE;
+        let session_key = match record[key_start - 1] {
+            0 => None,
+            1 => Some(SessionKey::from_bytes(&record[key_start..])?),
+            _ => return None,
+        };
+        Some(Self {
+            node_id,
+            params: NetworkParams::from_bytes(&record[4..])?,
+            session_key,
+        })
+    }
+
+    /// Read the membership from persistent storage
+    ///
+    /// # Returns
+    /// `None` if the node has not joined yet or the record is damaged
+    pub fn load<S: FlashStorage>(storage: &S, address: u32) -> Option<Self> {
+        let mut record = [0u8; MEMBERSHIP_RECORD_SIZE];
+        storage.read(address, &mut record).ok()?;
+        Self::from_bytes(&record)
+    }
+
+    /// Write the membership to persistent storage
+    ///
+    /// `address` must be the start of a sector reserved for the record, the sector
+    /// is erased before writing.
+    pub fn store<S: FlashStorage>(
+        &self,
+        storage: &mut S,
+        address: u32,
+    ) -> Result<(), &'static str> {
+        storage.erase_sector(address)?;
+        storage.write(address, &self.to_bytes())
+    }
+
+    /// Remove the membership from persistent storage
+    pub fn forget<S: FlashStorage>(storage: &mut S, address: u32) -> Result<(), &'static str> {
+        storage.erase_sector(address)
+    }
+}
+
+/// Check whether an ID may be assigned to a node
+pub(crate) fn is_assignable(node_id: u16) -> bool {
+    node_id != BROADCAST_ID && node_id != UNASSIGNED_NODE_ID
+}
+
+/// Outcome of handing a received message to `JoinClient`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum JoinEvent {
+    /// The message does not concern this node
+    Ignored,
+    /// The node joined (or re-joined), the membership must be persisted and the
+    /// security context switched to the assigned ID
+    Joined,
+    /// A session key was installed, the membership must be persisted
+    KeyReceived,
+    /// The node ID was granted to another board, the node joins again
+    Conflict,
+}
+
+/// Joining side of the handshake
+pub struct JoinClient {
+    uid: Uid,
+    role: NodeRole,
+    membership: Option<Membership>,
+    /// ID asked for in join requests
+    previous_id: u16,
+    awaiting_key: bool,
+    retry_ms: u64,
+    next_request_ms: u64,
+    sequence_number: u16,
+    rng: XorShift32,
+}
+
+impl JoinClient {
+    /// Create the client of a node
+    ///
+    /// # Arguments
+    /// * `uid` - MCU unique ID
+    /// * `role` - Role announced in join requests
+    /// * `stored` - Membership loaded from persistent storage, the node is joined
+    ///   right away when present
+    pub fn new(uid: Uid, role: NodeRole, stored: Option<Membership>) -> Self {
+        let seed = uid.chunks_exact(4).fold(0u32, |seed, word| {
+            seed ^ u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+        });
+        Self {
+            uid,
+            role,
+            membership: stored,
+            previous_id: stored.map_or(UNASSIGNED_NODE_ID, |membership| membership.node_id),
+            awaiting_key: false,
+            retry_ms: DEFAULT_JOIN_RETRY_MS,
+            next_request_ms: 0,
+            sequence_number: 0,
+            rng: XorShift32::new(seed),
+        }
+    }
+
+    /// MCU unique ID of this node
+    pub fn uid(&self) -> &Uid {
+        &self.uid
+    }
+
+    /// Current membership, `None` while joining
+    pub fn membership(&self) -> Option<&Membership> {
+        self.membership.as_ref()
+    }
+
+    /// Assigned node ID, `None` while joining
+    pub fn node_id(&self) -> Option<u16> {
+        self.membership.map(|membership| membership.node_id)
+    }
+
+    /// Check whether the node has joined the swarm
+    pub fn is_joined(&self) -> bool {
+        self.membership.is_some()
+    }
+
+    /// Check whether the coordinator announced a session key that has not arrived yet
+    pub fn awaiting_key(&self) -> bool {
+        self.awaiting_key
+    }
+
+    /// Drop the membership and start joining again
+    ///
+    /// The caller should also `Membership::forget` the persisted record.
+    pub fn rejoin(&mut self, now_ms: u64) {
+        if let Some(membership) = self.membership.take() {
+            self.previous_id = membership.node_id;
+        }
+        self.awaiting_key = false;
+        self.retry_ms = DEFAULT_JOIN_RETRY_MS;
+        self.next_request_ms = now_ms;
+    }
+
+    /// Produce the next join request when one is due
+    ///
+    /// Requests are repeated with a doubling, jittered delay until an accept arrives.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Packet> {
+        if self.is_joined() || now_ms < self.next_request_ms {
+            return None;
+        }
+        let jitter_ms = self.rng.below((self.retry_ms / 4) as u32 + 1) as u64;
+        self.next_request_ms = now_ms + self.retry_ms + jitter_ms;
+        self.retry_ms = (self.retry_ms * 2).min(MAX_JOIN_RETRY_MS);
+
+        let request = JoinRequest {
+            uid: self.uid,
+            previous_id: self.previous_id,
+            role: self.role,
+        };
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        terminal_log!(debug, "Sending join request");
+        Some(request.to_packet(self.sequence_number))
+    }
+
+    /// Handle a received message
+    ///
+    /// Session keys are only installed from packets that arrived sealed.
+    pub fn handle(&mut self, header: &Header, message: &Message, now_ms: u64) -> JoinEvent {
+        match message {
+            Message::JoinAccept(accept) if accept.uid == self.uid => {
+                let membership = Membership {
+                    node_id: accept.node_id,
+                    params: accept.params,
+                    session_key: None,
+                };
+                if self.membership.is_some_and(|current| {
+                    current.node_id == membership.node_id && current.params == membership.params
+                }) {
+                    // Repeated accept of a request sent before the first one arrived
+                    return JoinEvent::Ignored;
+                }
+                terminal_log!(info, "Joined network as node {}", accept.node_id);
+                self.membership = Some(membership);
+                self.previous_id = accept.node_id;
+                self.awaiting_key = accept.key_follows;
+                JoinEvent::Joined
+            }
+            Message::JoinAccept(accept) if Some(accept.node_id) == self.node_id() => {
+                terminal_log!(
+                    warn,
+                    "Node ID {} was granted to another board, joining again",
+                    accept.node_id
+                );
+                self.rejoin(now_ms);
+                // Do not ask for the contested ID again
+                self.previous_id = UNASSIGNED_NODE_ID;
+                JoinEvent::Conflict
+            }
+            Message::SessionKey(_) if !header.control.is_secured() => {
+                // Only possible while unsecured packets are accepted for migration
+                terminal_log!(
+                    warn,
+                    "Ignored unsecured session key from {}",
+                    header.sender_id
+                );
+                JoinEvent::Ignored
+            }
+            Message::SessionKey(session_key) => match self.membership.as_mut() {
+                Some(membership) if header.target_id == membership.node_id => {
+                    membership.session_key = Some(*session_key);
+                    self.awaiting_key = false;
+                    terminal_log!(info, "Installed session key {}", session_key.key_id);
+                    JoinEvent::KeyReceived
+                }
+                _ => JoinEvent::Ignored,
+            },
+            _ => JoinEvent::Ignored,
+        }
+    }
+}
+
+/// Coordinator counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct CoordinatorStats {
+    /// Join requests answered with an accept
+    pub accepted: u32,
+    /// Accepts for boards that were already known
+    pub rejoins: u32,
+    /// Requested IDs that were held by another board
+    pub conflicts: u32,
+    /// Requests dropped because the assignment table was full
+    pub table_full: u32,
+}
+
+/// Assigning side of the handshake, run by the gateway
+///
+/// # Type Parameters
+/// * `N` - Maximum number of nodes that can be assigned an ID
+pub struct JoinCoordinator<const N: usize> {
+    params: NetworkParams,
+    assignments: Vec<(Uid, u16), N>,
+    session_key: Option<SessionKey>,
+    stats: CoordinatorStats,
+}
+
+impl<const N: usize> JoinCoordinator<N> {
+    /// Create a coordinator handing out the given network parameters
+    pub fn new(params: NetworkParams) -> Self {
+        Self {
+            params,
+            assignments: Vec::new(),
+            session_key: None,
+            stats: CoordinatorStats::default(),
+        }
+    }
+
+    /// Network parameters handed out on join
+    pub fn params(&self) -> &NetworkParams {
+        &self.params
+    }
+
+    /// Coordinator counters
+    pub fn stats(&self) -> &CoordinatorStats {
+        &self.stats
+    }
+
+    /// Channel map handed to joining nodes, the active one of the hopping schedule
+    ///
+    /// Maps that are announced but not in use yet reach joined nodes through the
+    /// announcements repeated on the rendezvous channel.
+    pub fn set_channel_map(&mut self, channel_map: ChannelMap) {
+        self.params.channel_map = channel_map;
+    }
+
+    /// Session key handed to joining nodes, `None` disables key delivery
+    pub fn set_session_key(&mut self, session_key: Option<SessionKey>) {
+        self.session_key = session_key;
+    }
+
+    /// Number of assigned IDs
+    pub fn len(&self) -> usize {
+        self.assignments.len()
+    }
+
+    /// Check whether no ID has been assigned
+    pub fn is_empty(&self) -> bool {
+        self.assignments.is_empty()
+    }
+
+    /// Node ID assigned to a board
+    pub fn node_id_of(&self, uid: &Uid) -> Option<u16> {
+        self.assignments
+            .iter()
+            .find(|(assigned, _)| assigned == uid)
+            .map(|(_, node_id)| *node_id)
+    }
+
+    /// Restore an assignment, for example from the gateway's own storage
+    ///
+    /// # Returns
+    /// `false` if the ID is not assignable, already held by another board or the
+    /// table is full
+    pub fn assign(&mut self, uid: Uid, node_id: u16) -> bool {
+        if !self.is_free(node_id) && self.node_id_of(&uid) != Some(node_id) {
+            return false;
+        }
+        self.release_uid(&uid);
+        self.assignments.push((uid, node_id)).is_ok()
+    }
+
+    /// Forget the board holding `node_id`
+    ///
+    /// # Returns
+    /// `true` if the ID was assigned
+    pub fn release(&mut self, node_id: u16) -> bool {
+        let before = self.assignments.len();
+        self.assignments
+            .retain(|(_, assigned)| *assigned != node_id);
+        self.assignments.len() != before
+    }
+
+    /// Answer a join request
+    ///
+    /// A known board keeps its ID. A new board gets the ID it asks for when that ID
+    /// is free, otherwise the lowest free ID.
+    ///
+    /// # Returns
+    /// `None` if the assignment table is full
+    pub fn handle_request(&mut self, request: &JoinRequest) -> Option<JoinAccept> {
+        let node_id = if let Some(node_id) = self.node_id_of(&request.uid) {
+            self.stats.rejoins += 1;
+            node_id
+        } else {
+            let requested = request.previous_id;
+            let node_id = if self.is_free(requested) {
+                requested
+            } else {
+                if requested != UNASSIGNED_NODE_ID {
+                    self.stats.conflicts += 1;
+                    terminal_log!(
+                        warn,
+                        "Join request for node ID {} held by another board",
+                        requested
+                    );
+                }
+                self.lowest_free_id()?
+            };
+            if self.assignments.push((request.uid, node_id)).is_err() {
+                self.stats.table_full += 1;
+                terminal_log!(warn, "Join table full, ignoring join request");
+                return None;
+            }
+            node_id
+        };
+
+        self.stats.accepted += 1;
+        terminal_log!(info, "Assigned node ID {}", node_id);
+        Some(JoinAccept {
+            uid: request.uid,
+            node_id,
+            params: self.params,
+            key_follows: self.session_key.is_some(),
+        })
+    }
+
+    /// Build the packet delivering the session key to a joined node
+    ///
+    /// The packet must be sealed before transmission, see the module documentation.
+    pub fn session_key_packet(&self, node_id: u16, sequence_number: u16) -> Option<Packet> {
+        let session_key = self.session_key?;
+        Some(Message::SessionKey(session_key).to_packet(
+            self.params.gateway_id,
+            node_id,
+            sequence_number,
+        ))
+    }
+
+    fn is_free(&self, node_id: u16) -> bool {
+        is_assignable(node_id)
+            && node_id != self.params.gateway_id
+            && !self
+                .assignments
+                .iter()
+                .any(|(_, assigned)| *assigned == node_id)
+    }
+
+    fn lowest_free_id(&self) -> Option<u16> {
+        (FIRST_NODE_ID..UNASSIGNED_NODE_ID).find(|node_id| self.is_free(*node_id))
+    }
+
+    fn release_uid(&mut self, uid: &Uid) {
+        self.assignments.retain(|(assigned, _)| assigned != uid);
+    }
+}
@@ -0,0 +1,356 @@
+/// Per-neighbor link quality estimation
+/// RSSI is a poor predictor of delivery on OOK links, so every link is judged by
+/// how many packets actually get through:
+/// - reception ratio (PRR) from gaps in the sequence numbers of packets a neighbor
+///   originated itself, assuming every node numbers all its packets from one counter
+/// - delivery ratio from ack outcomes of our unicast packets (`radio::reliable`)
+///
+/// Both ratios are exponentially smoothed. The expected transmission count
+/// (ETX) follows from the ack-based delivery ratio, which covers both directions of
+/// the link. Links without ack history assume a symmetric link and use the squared
+/// reception ratio instead.
+///
+/// The table of this node (`LINKS`) is fed by `radio::reliable::ReliableLink`, which
+/// sees every received packet and the outcome of every reliable send. Routing
+/// (`radio::routing::Router`) and the command shell read it.
+use super::neighbor::{DEFAULT_NEIGHBOR_TIMEOUT_MS, MAX_NEIGHBORS};
+use super::protocol::Header;
+use super::reliable::DeliveryOutcome;
+use super::routing::PERFECT_LINK_COST;
+use crate::terminal_log;
+use core::cell::RefCell;
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use heapless::Vec;
+
+/// Capacity of the shared link table
+pub const MAX_LINKS: usize = MAX_NEIGHBORS;
+
+/// Default time after which a silent link is forgotten
+pub const DEFAULT_LINK_TIMEOUT_MS: u64 = DEFAULT_NEIGHBOR_TIMEOUT_MS;
+
+/// Samples needed before a ratio is trusted
+pub const MIN_LINK_SAMPLES: u16 = 4;
+
+/// Larger sequence number jumps are treated as a restart of the neighbor
+pub const MAX_SEQUENCE_GAP: u16 = 32;
+
+/// Worst ETX reported, in hundredths
+pub const MAX_ETX_CENTI: u16 = 1_000;
+
+/// Smoothing factor of the ratios as a power of two, alpha = 1/8
+const SMOOTHING_SHIFT: u16 = 3;
+
+/// A ratio of 1.0 in the Q16 fixed-point format of the estimators
+const RATIO_ONE: u32 = 1 << 16;
+
+/// Exponentially smoothed success ratio
+///
+/// The first samples are averaged with alpha = 1/n so the estimate settles quickly,
+/// later samples use the fixed smoothing factor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct RatioEstimator {
+    /// Smoothed ratio in Q16
+    value: u32,
+    samples: u16,
+}
+
+impl RatioEstimator {
+    /// Add one outcome
+    pub(crate) fn sample(&mut self, success: bool) {
+        self.samples = self.samples.saturating_add(1);
+        let target = if success { RATIO_ONE } else { 0 } as i64;
+        let weight = self.samples.min(1 << SMOOTHING_SHIFT) as i64;
+        let value = self.value as i64;
+        self.value = (value + (target - value) / weight) as u32;
+    }
+
+    /// Smoothed ratio in Q16, `None` until enough samples arrived
+    pub(crate) fn estimate(&self) -> Option<u32> {
+        (self.samples >= MIN_LINK_SAMPLES).then_some(self.value)
+    }
+}
+
+/// Convert a Q16 ratio to per mille
+pub(crate) fn permille(ratio: u32) -> u16 {
+    ((ratio as u64 * 1_000 + RATIO_ONE as u64 / 2) >> 16) as u16
+}
+
+/// Quality estimate of the link to one neighbor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkEstimate {
+    /// ID of the neighbor at the other end of the link
+    pub node_id: u16,
+    /// Time the neighbor was last heard or addressed
+    pub last_update_ms: u64,
+    /// Last sequence number originated by the neighbor
+    last_sequence: Option<u16>,
+    reception: RatioEstimator,
+    delivery: RatioEstimator,
+}
+
+impl LinkEstimate {
+    fn new(node_id: u16, now_ms: u64) -> Self {
+        Self {
+            node_id,
+            last_update_ms: now_ms,
+            last_sequence: None,
+            reception: RatioEstimator::default(),
+            delivery: RatioEstimator::default(),
+        }
+    }
+
+    /// Share of the neighbor's packets we receive, in per mille
+    pub fn reception_permille(&self) -> Option<u16> {
+        self.reception.estimate().map(permille)
+    }
+
+    /// Share of our unicast transmissions the neighbor acknowledges, in per mille
+    pub fn delivery_permille(&self) -> Option<u16> {
+        self.delivery.estimate().map(permille)
+    }
+
+    /// Expected transmissions per delivered packet, in hundredths
+    ///
+    /// # Returns
+    /// `None` until enough packets were exchanged with the neighbor
+    pub fn etx_centi(&self) -> Option<u16> {
+        let etx = match (self.delivery.estimate(), self.reception.estimate()) {
+            (Some(delivery), _) => (100 << 16) / (delivery as u64).max(1),
+            (None, Some(reception)) => (100 << 32) / (reception as u64 * reception as u64).max(1),
+            (None, None) => return None,
+        };
+        Some(etx.min(MAX_ETX_CENTI as u64) as u16)
+    }
+
+    /// ETX in routing cost units, `PERFECT_LINK_COST` for a lossless link
+    pub fn link_cost(&self) -> Option<u16> {
+        self.etx_centi()
+            .map(|etx| ((etx as u32 * PERFECT_LINK_COST as u32 + 50) / 100) as u16)
+    }
+
+    /// Summarize the estimate as seen at `now_ms`
+    pub fn summary(&self, now_ms: u64) -> LinkSummary {
+        LinkSummary {
+            node_id: self.node_id,
+            reception_permille: self.reception_permille(),
+            delivery_permille: self.delivery_permille(),
+            etx_centi: self.etx_centi(),
+            age_ms: now_ms.saturating_sub(self.last_update_ms),
+        }
+    }
+
+    fn observe_sequence(&mut self, sequence_number: u16) {
+        let Some(last) = self.last_sequence else {
+            self.last_sequence = Some(sequence_number);
+            self.reception.sample(true);
+            return;
+        };
+        let delta = sequence_number.wrapping_sub(last);
+        if delta == 0 || delta > u16::MAX / 2 {
+            // Repeated or reordered packet, nothing new about the link
+            return;
+        }
+        self.last_sequence = Some(sequence_number);
+        if delta > MAX_SEQUENCE_GAP {
+            terminal_log!(
+                debug,
+                "Sequence of {} jumped by {}, not counted as loss",
+                self.node_id,
+                delta
+            );
+        } else {
+            for _ in 1..delta {
+                self.reception.sample(false);
+            }
+        }
+        self.reception.sample(true);
+    }
+}
+
+/// Compact copy of a link estimate for reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct LinkSummary {
+    /// ID of the neighbor at the other end of the link
+    pub node_id: u16,
+    /// Share of the neighbor's packets we receive, in per mille
+    pub reception_permille: Option<u16>,
+    /// Share of our transmissions the neighbor acknowledges, in per mille
+    pub delivery_permille: Option<u16>,
+    /// Expected transmissions per delivered packet, in hundredths
+    pub etx_centi: Option<u16>,
+    /// Time since the link was last updated
+    pub age_ms: u64,
+}
+
+/// Fixed-capacity table of link estimates
+///
+/// # Type Parameters
+/// * `N` - Maximum number of links, the least recently updated one is replaced
+///   when a new neighbor appears in a full table
+pub struct LinkTable<const N: usize> {
+    links: Vec<LinkEstimate, N>,
+    timeout_ms: u64,
+}
+
+impl<const N: usize> Default for LinkTable<N> {
+    fn default() -> Self {
+        Self::new(DEFAULT_LINK_TIMEOUT_MS)
+    }
+}
+
+impl<const N: usize> LinkTable<N> {
+    /// Create an empty table forgetting links not updated for longer than `timeout_ms`
+    pub const fn new(timeout_ms: u64) -> Self {
+        Self {
+            links: Vec::new(),
+            timeout_ms,
+        }
+    }
+
+    /// Number of tracked links
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Check whether no link is tracked
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Look up the link to a neighbor
+    pub fn get(&self, node_id: u16) -> Option<&LinkEstimate> {
+        self.links.iter().find(|link| link.node_id == node_id)
+    }
+
+    /// Iterate over all tracked links
+    pub fn iter(&self) -> impl Iterator<Item = &LinkEstimate> {
+        self.links.iter()
+    }
+
+    /// Routing cost of the link to a neighbor, `None` while the link is unknown
+    pub fn link_cost(&self, node_id: u16) -> Option<u16> {
+        self.get(node_id).and_then(LinkEstimate::link_cost)
+    }
+
+    /// Update the reception ratio from the header of a received packet
+    ///
+    /// Only packets the transmitting neighbor originated carry its own sequence
+    /// numbers. Acks echo the sequence number of the acknowledged packet and are
+    /// skipped as well.
+    pub fn observe(&mut self, header: &Header, now_ms: u64) {
+        let neighbor = header.relay_id;
+        let Some(link) = self.entry(neighbor, now_ms) else {
+            return;
+        };
+        link.last_update_ms = now_ms;
+        if header.sender_id == neighbor && !header.control.is_ack() {
+            link.observe_sequence(header.sequence_number);
+        }
+    }
+
+    /// Record whether a unicast transmission to a neighbor was acknowledged
+    pub fn record_ack(&mut self, neighbor: u16, acknowledged: bool, now_ms: u64) {
+        if let Some(link) = self.entry(neighbor, now_ms) {
+            link.last_update_ms = now_ms;
+            link.delivery.sample(acknowledged);
+        }
+    }
+
+    /// Update the delivery ratio from the outcome of a reliable send
+    ///
+    /// Every unanswered attempt counts as a failure. Radio errors say nothing about
+    /// the link and are ignored.
+    pub fn record_delivery(&mut self, neighbor: u16, outcome: &DeliveryOutcome, now_ms: u64) {
+        let (failures, delivered) = match *outcome {
+            DeliveryOutcome::Delivered { attempts } => (attempts.saturating_sub(1), true),
+            DeliveryOutcome::TimedOut { attempts } => (attempts, false),
+            DeliveryOutcome::Failed(_) => return,
+        };
+        for _ in 0..failures {
+            self.record_ack(neighbor, false, now_ms);
+        }
+        if delivered {
+            self.record_ack(neighbor, true, now_ms);
+        }
+    }
+
+    /// Remove links that have not been updated within the timeout
+    ///
+    /// # Returns
+    /// Number of removed links
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let before = self.links.len();
+        let timeout_ms = self.timeout_ms;
+        self.links
+            .retain(|link| now_ms.saturating_sub(link.last_update_ms) <= timeout_ms);
+        before - self.links.len()
+    }
+
+    /// Find the link to a neighbor, adding it if unknown
+    ///
+    /// # Returns
+    /// `None` only for a zero-capacity table
+    fn entry(&mut self, node_id: u16, now_ms: u64) -> Option<&mut LinkEstimate> {
+        if let Some(index) = self.links.iter().position(|link| link.node_id == node_id) {
+            return self.links.get_mut(index);
+        }
+        if self.links.is_full() {
+            if let Some(oldest) = self
+                .links
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, link)| link.last_update_ms)
+                .map(|(index, _)| index)
+            {
+                self.links.swap_remove(oldest);
+            }
+        }
+        self.links.push(LinkEstimate::new(node_id, now_ms)).ok()?;
+        self.links.last_mut()
+    }
+}
+
+/// Link table shared between the radio task, routing, power control and the shell
+pub type SharedLinkTable = Mutex<CriticalSectionRawMutex, RefCell<LinkTable<MAX_LINKS>>>;
+
+/// Link table of this node
+pub static LINKS: SharedLinkTable =
+    Mutex::new(RefCell::new(LinkTable::new(DEFAULT_LINK_TIMEOUT_MS)));
+
+/// Take a snapshot of the current links after removing stale entries
+pub fn links_snapshot(now_ms: u64) -> Vec<LinkSummary, MAX_LINKS> {
+    LINKS.lock(|table| {
+        let mut table = table.borrow_mut();
+        table.expire(now_ms);
+        table.iter().map(|link| link.summary(now_ms)).collect()
+    })
+}
+
+/// Update a shared link table from the header of a received packet
+pub fn observe_link(links: &SharedLinkTable, header: &Header, now_ms: u64) {
+    links.lock(|table| table.borrow_mut().observe(header, now_ms));
+}
+
+/// Update a shared link table from the outcome of a reliable send to `neighbor`
+pub fn record_link_delivery(
+    links: &SharedLinkTable,
+    neighbor: u16,
+    outcome: &DeliveryOutcome,
+    now_ms: u64,
+) {
+    links.lock(|table| {
+        table
+            .borrow_mut()
+            .record_delivery(neighbor, outcome, now_ms)
+    });
+}
+
+/// Routing cost of the link to `neighbor` from a shared table
+///
+/// # Returns
+/// `None` while too few packets were exchanged with the neighbor
+pub fn link_cost(links: &SharedLinkTable, neighbor: u16) -> Option<u16> {
+    links.lock(|table| table.borrow().link_cost(neighbor))
+}
@@ -0,0 +1,255 @@
+/// Manchester line coding for 433MHz OOK bitstreams
+/// Every data bit is sent as two chips with a transition in the middle of the bit,
+/// which keeps the OOK receiver's slicer balanced and lets the decoder recover
+/// the transmitter clock. Data bits and chips are handled MSB first.
+/// The module is hardware-agnostic so any GPIO- or PIO-based OOK driver can reuse it.
+use defmt::Format;
+
+/// Number of chips produced for every data bit
+pub const CHIPS_PER_BIT: usize = 2;
+
+/// Errors reported by the Manchester codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ManchesterError {
+    /// A chip pair without a mid-bit transition (`00` or `11`) was found
+    InvalidSymbol {
+        /// Index of the offending data bit in the decoded stream
+        bit_index: usize,
+    },
+    /// The sampled signal does not follow the expected chip timing
+    ClockRecovery {
+        /// Index of the first sample of the run that could not be timed
+        sample_index: usize,
+    },
+    /// The chip stream ends in the middle of a data byte
+    Truncated,
+    /// The output buffer cannot hold the result
+    BufferTooSmall,
+}
+
+/// Chip ordering used to represent a data bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ManchesterConvention {
+    /// IEEE 802.3: `0` is sent as high-low (`10`), `1` as low-high (`01`)
+    Ieee8023,
+    /// G.E. Thomas: `0` is sent as low-high (`01`), `1` as high-low (`10`)
+    Thomas,
+}
+
+/// Iterate over the bits of a byte buffer, MSB first
+pub fn bits_msb_first(bytes: &[u8]) -> impl Iterator<Item = bool> + '_ {
+    bytes
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |bit| byte & (1 << bit) != 0))
+}
+
+/// Manchester encoder/decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct ManchesterCodec {
+    convention: ManchesterConvention,
+}
+
+impl Default for ManchesterCodec {
+    fn default() -> Self {
+        Self::new(ManchesterConvention::Ieee8023)
+    }
+}
+
+impl ManchesterCodec {
+    /// Create a codec using the given chip convention
+    pub const fn new(convention: ManchesterConvention) -> Self {
+        Self { convention }
+    }
+
+    /// Chip pair (first, second) used to send a data bit
+    fn chips_for(&self, bit: bool) -> (bool, bool) {
+        let first = match self.convention {
+            ManchesterConvention::Ieee8023 => !bit,
+            ManchesterConvention::Thomas => bit,
+        };
+        (first, !first)
+    }
+
+    /// Data bit represented by a chip pair, `None` if the pair has no transition
+    fn bit_for(&self, first: bool, second: bool) -> Option<bool> {
+        if first == second {
+            return None;
+        }
+        Some(match self.convention {
+            ManchesterConvention::Ieee8023 => !first,
+            ManchesterConvention::Thomas => first,
+        })
+    }
+
+    /// Encode `data` into a packed chip stream (MSB first, two output bytes per input byte)
+    ///
+    /// # Returns
+    /// * `Ok(len)` with the number of chip bytes written to `chips`
+    /// * `Err(ManchesterError::BufferTooSmall)` if `chips` is shorter than `2 * data.len()`
+    pub fn encode(&self, data: &[u8], chips: &mut [u8]) -> Result<usize, ManchesterError> {
+        let len = data.len() * CHIPS_PER_BIT;
+        if chips.len() < len {
+            return Err(ManchesterError::BufferTooSmall);
+        }
+
+        for (&byte, out) in data.iter().zip(chips.chunks_exact_mut(CHIPS_PER_BIT)) {
+            let mut word: u16 = 0;
+            for bit in (0..8).rev() {
+                let (first, second) = self.chips_for(byte & (1 << bit) != 0);
+                word = (word << 2) | ((first as u16) << 1) | second as u16;
+            }
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+
+        Ok(len)
+    }
+
+    /// Decode a packed chip stream (MSB first) aligned to the first chip of a byte
+    ///
+    /// # Returns
+    /// * `Ok(len)` with the number of data bytes written to `out`
+    /// * `Err(ManchesterError)` on invalid chip pairs or incomplete input
+    pub fn decode(&self, chips: &[u8], out: &mut [u8]) -> Result<usize, ManchesterError> {
+        if !chips.len().is_multiple_of(CHIPS_PER_BIT) {
+            return Err(ManchesterError::Truncated);
+        }
+
+        let mut decoder = BitAssembler::new(out);
+        let mut chip_stream = bits_msb_first(chips);
+        while let (Some(first), Some(second)) = (chip_stream.next(), chip_stream.next()) {
+            let bit = self
+                .bit_for(first, second)
+                .ok_or(ManchesterError::InvalidSymbol {
+                    bit_index: decoder.bit_count(),
+                })?;
+            decoder.push(bit)?;
+        }
+
+        Ok(decoder.finish())
+    }
+
+    /// Decode an oversampled signal with `samples_per_chip` samples for every chip
+    ///
+    /// The sample stream must start at the first chip of a byte (for example right
+    /// after a sync word). Timing is recovered from the signal edges, so each run of
+    /// equal samples may deviate by up to half a chip period from its nominal length.
+    /// A trailing partial byte (e.g. idle line after the frame) is ignored.
+    ///
+    /// # Returns
+    /// * `Ok(len)` with the number of complete data bytes written to `out`
+    /// * `Err(ManchesterError)` on timing violations or invalid chip pairs
+    pub fn decode_samples<I>(
+        &self,
+        samples: I,
+        samples_per_chip: usize,
+        out: &mut [u8],
+    ) -> Result<usize, ManchesterError>
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        if samples_per_chip == 0 {
+            return Err(ManchesterError::ClockRecovery { sample_index: 0 });
+        }
+
+        let mut decoder = BitAssembler::new(out);
+        let mut pending_chip: Option<bool> = None;
+        // Current run of equal samples: (level, index of first sample, length)
+        let mut run: Option<(bool, usize, usize)> = None;
+
+        for (index, sample) in samples.into_iter().enumerate() {
+            match run {
+                Some((level, start, len)) if level == sample => run = Some((level, start, len + 1)),
+                Some((level, start, len)) => {
+                    let chips = run_to_chips(len, samples_per_chip).ok_or(
+                        ManchesterError::ClockRecovery {
+                            sample_index: start,
+                        },
+                    )?;
+                    self.push_chips(level, chips, &mut pending_chip, &mut decoder)?;
+                    run = Some((sample, index, 1));
+                }
+                None => run = Some((sample, index, 1)),
+            }
+        }
+
+        let (level, _, len) = run.ok_or(ManchesterError::ClockRecovery { sample_index: 0 })?;
+        // The last run may merge with the idle line, it can only complete the final bit
+        let chips = ((2 * len + samples_per_chip) / (2 * samples_per_chip)).clamp(1, 2);
+        self.push_chips(level, chips, &mut pending_chip, &mut decoder)?;
+
+        Ok(decoder.finish())
+    }
+
+    /// Feed `count` chips of the same level into the chip pair decoder
+    fn push_chips(
+        &self,
+        level: bool,
+        count: usize,
+        pending_chip: &mut Option<bool>,
+        decoder: &mut BitAssembler,
+    ) -> Result<(), ManchesterError> {
+        for _ in 0..count {
+            match pending_chip.take() {
+                None => *pending_chip = Some(level),
+                Some(first) => {
+                    let bit = self
+                        .bit_for(first, level)
+                        .ok_or(ManchesterError::InvalidSymbol {
+                            bit_index: decoder.bit_count(),
+                        })?;
+                    decoder.push(bit)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert a run of equal samples into a chip count
+///
+/// Manchester only ever produces runs of one or two chip periods, anything else
+/// (glitches or missing transitions) means the clock could not be recovered.
+fn run_to_chips(run_len: usize, samples_per_chip: usize) -> Option<usize> {
+    let chips = (2 * run_len + samples_per_chip) / (2 * samples_per_chip);
+    (1..=2).contains(&chips).then_some(chips)
+}
+
+/// Collects decoded bits MSB first into an output byte buffer
+struct BitAssembler<'a> {
+    out: &'a mut [u8],
+    bits: usize,
+    current: u8,
+}
+
+impl<'a> BitAssembler<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Self {
+            out,
+            bits: 0,
+            current: 0,
+        }
+    }
+
+    fn bit_count(&self) -> usize {
+        self.bits
+    }
+
+    fn push(&mut self, bit: bool) -> Result<(), ManchesterError> {
+        self.current = (self.current << 1) | bit as u8;
+        self.bits += 1;
+        if self.bits.is_multiple_of(8) {
+            let slot = self
+                .out
+                .get_mut(self.bits / 8 - 1)
+                .ok_or(ManchesterError::BufferTooSmall)?;
+            *slot = self.current;
+            self.current = 0;
+        }
+        Ok(())
+    }
+
+    /// Number of complete bytes written, trailing partial bits are dropped
+    fn finish(self) -> usize {
+        self.bits / 8
+    }
+}
@@ -0,0 +1,488 @@
+/// Typed application messages carried in packet payloads
+/// Every single-packet payload starts with the message format version and a type
+/// tag, followed by the type-specific body:
+/// `version: u8 | type: u8 | body`
+///
+/// Receivers skip messages with a newer format version or an unknown type instead
+/// of misinterpreting them, so new message types can be rolled out node by node.
+/// For the same reason decoders ignore trailing bytes of fixed-layout bodies, later
+/// versions may append fields to existing messages. Fragments of larger messages (see
+/// `radio::fragment`) are flagged in the header and decoded after reassembly.
+use super::hopping::{ChannelMapMessage, CHANNEL_MAP_SIZE};
+use super::join::{
+    JoinAccept, JoinRequest, SessionKey, JOIN_ACCEPT_SIZE, JOIN_REQUEST_SIZE, SESSION_KEY_SIZE,
+};
+use super::neighbor::{Beacon, BEACON_SIZE};
+use super::node_id::{IdConflict, ID_CONFLICT_SIZE};
+use super::protocol::{Header, Packet, MAX_DATA_SIZE};
+use super::report::SensorReport;
+use super::timesync::{TimeSyncMessage, TIMESYNC_SIZE};
+use crate::terminal_log;
+use defmt::Format;
+use heapless::{String, Vec};
+
+/// Message format version written by this firmware
+pub const MESSAGE_VERSION: u8 = 1;
+
+/// Size of the version and type prefix
+pub const MESSAGE_PREFIX_SIZE: usize = 2;
+
+/// Largest message body fitting into one packet, sealed or not
+pub const MAX_BODY_SIZE: usize = MAX_DATA_SIZE - MESSAGE_PREFIX_SIZE;
+
+/// Longest text carried by command and log messages
+pub const MAX_TEXT_SIZE: usize = MAX_BODY_SIZE - 1;
+
+// Fixed-size bodies must fit into a single packet
+const _: () = assert!(
+    BEACON_SIZE <= MAX_BODY_SIZE
+        && TIMESYNC_SIZE <= MAX_BODY_SIZE
+        && JOIN_REQUEST_SIZE <= MAX_BODY_SIZE
+        && JOIN_ACCEPT_SIZE <= MAX_BODY_SIZE
+        && SESSION_KEY_SIZE <= MAX_BODY_SIZE
+        && CHANNEL_MAP_SIZE <= MAX_BODY_SIZE
+        && ID_CONFLICT_SIZE <= MAX_BODY_SIZE
+);
+
+/// Encoded message ready to be used as a packet payload
+pub type MessagePayload = Vec<u8, MAX_DATA_SIZE>;
+
+/// Text carried by command and log messages
+pub type MessageText = String<MAX_TEXT_SIZE>;
+
+/// Type tag of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+#[repr(u8)]
+pub enum MessageType {
+    /// Tag of `Message::SensorReport`
+    SensorReport = 1,
+    /// Tag of `Message::Beacon`
+    Beacon = 2,
+    /// Tag of `Message::TimeSync`
+    TimeSync = 3,
+    /// Tag of `Message::CommandRequest`
+    CommandRequest = 4,
+    /// Tag of `Message::CommandResponse`
+    CommandResponse = 5,
+    /// Tag of `Message::Ack`
+    Ack = 6,
+    /// Tag of `Message::ConfigUpdate`
+    ConfigUpdate = 7,
+    /// Tag of `Message::LogLine`
+    LogLine = 8,
+    /// Tag of `Message::JoinRequest`
+    JoinRequest = 9,
+    /// Tag of `Message::JoinAccept`
+    JoinAccept = 10,
+    /// Tag of `Message::SessionKey`
+    SessionKey = 11,
+    /// Tag of `Message::ChannelMap`
+    ChannelMap = 12,
+    /// Tag of `Message::IdConflict`
+    IdConflict = 13,
+}
+
+impl MessageType {
+    /// Convert a wire tag into a message type
+    pub fn from_u8(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Self::SensorReport),
+            2 => Some(Self::Beacon),
+            3 => Some(Self::TimeSync),
+            4 => Some(Self::CommandRequest),
+            5 => Some(Self::CommandResponse),
+            6 => Some(Self::Ack),
+            7 => Some(Self::ConfigUpdate),
+            8 => Some(Self::LogLine),
+            9 => Some(Self::JoinRequest),
+            10 => Some(Self::JoinAccept),
+            11 => Some(Self::SessionKey),
+            12 => Some(Self::ChannelMap),
+            13 => Some(Self::IdConflict),
+            _ => None,
+        }
+    }
+}
+
+/// Severity of a forwarded log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+#[repr(u8)]
+pub enum LogLevel {
+    /// Unhandled or unrecoverable condition
+    Error = 0,
+    /// Recoverable condition or issue
+    Warn = 1,
+    /// What the node is doing, suitable for production
+    Info = 2,
+    /// Verbose diagnostics
+    Debug = 3,
+    /// Step by step trace of the node's activity
+    Trace = 4,
+}
+
+impl LogLevel {
+    /// Convert a wire value into a log level
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Error),
+            1 => Some(Self::Warn),
+            2 => Some(Self::Info),
+            3 => Some(Self::Debug),
+            4 => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Errors produced while decoding a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum MessageError {
+    /// Payload is shorter than the version and type prefix
+    Truncated,
+    /// Message was written by a newer, unsupported format version
+    UnsupportedVersion(u8),
+    /// Type tag is not known to this firmware
+    UnknownType(u8),
+    /// Body does not match the layout of its message type
+    Malformed(MessageType),
+}
+
+/// Application message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// Batch of environmental readings of the sending node (see `radio::report`)
+    SensorReport(SensorReport),
+    /// Neighbor discovery beacon (see `radio::neighbor`)
+    Beacon(Beacon),
+    /// Time synchronization broadcast (see `radio::timesync`)
+    TimeSync(TimeSyncMessage),
+    /// Shell command to execute on the target node
+    CommandRequest {
+        /// Identifier echoed in the response
+        request_id: u8,
+        /// Command line as typed in the shell
+        command: MessageText,
+    },
+    /// Output of a remotely executed command
+    CommandResponse {
+        /// Identifier of the answered request
+        request_id: u8,
+        /// Command output, at most `MAX_TEXT_SIZE` bytes
+        output: MessageText,
+    },
+    /// Application-level confirmation of the message with this sequence number
+    Ack {
+        /// Sequence number of the confirmed message
+        sequence_number: u16,
+    },
+    /// Change of a configuration value on the target node
+    ConfigUpdate {
+        /// Identifier of the configuration value
+        key: u8,
+        /// New value
+        value: u32,
+    },
+    /// Log line forwarded to a gateway
+    LogLine {
+        /// Severity of the line
+        level: LogLevel,
+        /// Log text, at most `MAX_TEXT_SIZE` bytes
+        text: MessageText,
+    },
+    /// Request of a node to join the swarm (see `radio::join`)
+    JoinRequest(JoinRequest),
+    /// Node ID assignment answering a join request
+    JoinAccept(JoinAccept),
+    /// Session key for a newly joined node, only ever sent sealed
+    SessionKey(SessionKey),
+    /// Channels the swarm hops over from a given hop on (see `radio::hopping`)
+    ChannelMap(ChannelMapMessage),
+    /// Two boards beacon with the same node ID (see `radio::node_id`)
+    IdConflict(IdConflict),
+}
+
+impl Message {
+    /// Type tag of this message
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            Self::SensorReport(_) => MessageType::SensorReport,
+            Self::Beacon(_) => MessageType::Beacon,
+            Self::TimeSync(_) => MessageType::TimeSync,
+            Self::CommandRequest { .. } => MessageType::CommandRequest,
+            Self::CommandResponse { .. } => MessageType::CommandResponse,
+            Self::Ack { .. } => MessageType::Ack,
+            Self::ConfigUpdate { .. } => MessageType::ConfigUpdate,
+            Self::LogLine { .. } => MessageType::LogLine,
+            Self::JoinRequest(_) => MessageType::JoinRequest,
+            Self::JoinAccept(_) => MessageType::JoinAccept,
+            Self::SessionKey(_) => MessageType::SessionKey,
+            Self::ChannelMap(_) => MessageType::ChannelMap,
+            Self::IdConflict(_) => MessageType::IdConflict,
+        }
+    }
+
+    /// Encode the message into a packet payload
+    pub fn encode(&self) -> MessagePayload {
+        let mut payload = MessagePayload::new();
+        // Every body is bounded by `MAX_BODY_SIZE`, so none of the writes can overflow
+        let _ = payload.extend_from_slice(&[MESSAGE_VERSION, self.message_type() as u8]);
+        let _ = match self {
+            Self::SensorReport(report) => payload.extend_from_slice(report.as_bytes()),
+            Self::Beacon(beacon) => payload.extend_from_slice(&beacon.to_bytes()),
+            Self::TimeSync(sync) => payload.extend_from_slice(&sync.to_bytes()),
+            Self::CommandRequest {
+                request_id,
+                command: text,
+            }
+            | Self::CommandResponse {
+                request_id,
+                output: text,
+            } => payload
+                .extend_from_slice(&[*request_id])
+                .and_then(|_| payload.extend_from_slice(text.as_bytes())),
+            Self::Ack { sequence_number } => {
+                payload.extend_from_slice(&sequence_number.to_le_bytes())
+            }
+            Self::ConfigUpdate { key, value } => payload
+                .extend_from_slice(&[*key])
+                .and_then(|_| payload.extend_from_slice(&value.to_le_bytes())),
+            Self::LogLine { level, text } => payload
+                .extend_from_slice(&[*level as u8])
+                .and_then(|_| payload.extend_from_slice(text.as_bytes())),
+            Self::JoinRequest(request) => payload.extend_from_slice(&request.to_bytes()),
+            Self::JoinAccept(accept) => payload.extend_from_slice(&accept.to_bytes()),
+            Self::SessionKey(session_key) => payload.extend_from_slice(&session_key.to_bytes()),
+            Self::ChannelMap(channel_map) => payload.extend_from_slice(&channel_map.to_bytes()),
+            Self::IdConflict(conflict) => payload.extend_from_slice(&conflict.to_bytes()),
+        };
+        payload
+    }
+
+    /// Decode a message
+    ///
+    /// # Arguments
+    /// * `sender_id` - Node that sent the message, taken from the packet header
+    /// * `payload` - Packet payload or reassembled fragment data
+    pub fn decode(sender_id: u16, payload: &[u8]) -> Result<Self, MessageError> {
+        let message_type = peek_type(payload)?;
+        let body = &payload[MESSAGE_PREFIX_SIZE..];
+        let malformed = MessageError::Malformed(message_type);
+
+        let message = match message_type {
+            MessageType::SensorReport => {
+                Self::SensorReport(SensorReport::from_bytes(body).map_err(|_| malformed)?)
+            }
+            MessageType::Beacon => {
+                Self::Beacon(Beacon::from_bytes(sender_id, body).ok_or(malformed)?)
+            }
+            MessageType::TimeSync => {
+                Self::TimeSync(TimeSyncMessage::from_bytes(body).ok_or(malformed)?)
+            }
+            MessageType::CommandRequest => {
+                let (&request_id, text) = body.split_first().ok_or(malformed)?;
+                Self::CommandRequest {
+                    request_id,
+                    command: decode_text(text).ok_or(malformed)?,
+                }
+            }
+            MessageType::CommandResponse => {
+                let (&request_id, text) = body.split_first().ok_or(malformed)?;
+                Self::CommandResponse {
+                    request_id,
+                    output: decode_text(text).ok_or(malformed)?,
+                }
+            }
+            MessageType::Ack => {
+                let bytes = body.get(..2).ok_or(malformed)?;
+                Self::Ack {
+                    sequence_number: u16::from_le_bytes([bytes[0], bytes[1]]),
+                }
+            }
+            MessageType::ConfigUpdate => {
+                let bytes = body.get(..5).ok_or(malformed)?;
+                Self::ConfigUpdate {
+                    key: bytes[0],
+                    value: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+                }
+            }
+            MessageType::LogLine => {
+                let (&level, text) = body.split_first().ok_or(malformed)?;
+                Self::LogLine {
+                    level: LogLevel::from_u8(level).ok_or(malformed)?,
+                    text: decode_text(text).ok_or(malformed)?,
+                }
+            }
+            MessageType::JoinRequest => {
+                Self::JoinRequest(JoinRequest::from_bytes(body).ok_or(malformed)?)
+            }
+            MessageType::JoinAccept => {
+                Self::JoinAccept(JoinAccept::from_bytes(body).ok_or(malformed)?)
+            }
+            MessageType::SessionKey => {
+                Self::SessionKey(SessionKey::from_bytes(body).ok_or(malformed)?)
+            }
+            MessageType::ChannelMap => {
+                Self::ChannelMap(ChannelMapMessage::from_bytes(body).ok_or(malformed)?)
+            }
+            MessageType::IdConflict => {
+                Self::IdConflict(IdConflict::from_bytes(body).ok_or(malformed)?)
+            }
+        };
+        Ok(message)
+    }
+
+    /// Build a packet carrying this message
+    pub fn to_packet(&self, sender_id: u16, target_id: u16, sequence_number: u16) -> Packet {
+        Packet::new(sender_id, target_id, sequence_number, &self.encode())
+    }
+
+    /// Decode the message carried by a single (unfragmented) packet
+    pub fn from_packet(packet: &Packet) -> Result<Self, MessageError> {
+        Self::decode(packet.header.sender_id, packet.payload_data())
+    }
+}
+
+/// Read the type of an encoded message without decoding its body
+///
+/// # Returns
+/// * `Ok(message_type)` if this firmware can decode the message
+/// * `Err(MessageError)` if the message is truncated, newer or of an unknown type
+pub fn peek_type(payload: &[u8]) -> Result<MessageType, MessageError> {
+    let [version, tag, ..] = *payload else {
+        return Err(MessageError::Truncated);
+    };
+    if version > MESSAGE_VERSION {
+        return Err(MessageError::UnsupportedVersion(version));
+    }
+    MessageType::from_u8(tag).ok_or(MessageError::UnknownType(tag))
+}
+
+fn decode_text(bytes: &[u8]) -> Option<MessageText> {
+    let text = core::str::from_utf8(bytes).ok()?;
+    MessageText::try_from(text).ok()
+}
+
+/// Receiver of decoded messages
+pub trait MessageHandler {
+    /// Handle a message
+    ///
+    /// # Arguments
+    /// * `header` - Header of the packet that carried the message
+    /// * `message` - Decoded message
+    fn handle(&mut self, header: &Header, message: &Message);
+}
+
+impl<F: FnMut(&Header, &Message)> MessageHandler for F {
+    fn handle(&mut self, header: &Header, message: &Message) {
+        self(header, message)
+    }
+}
+
+/// Result of dispatching a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum DispatchOutcome {
+    /// The message was passed to this many handlers
+    Handled(usize),
+    /// The message was decoded but no handler is registered for its type
+    Unhandled(MessageType),
+    /// The packet is a fragment and must go through `radio::fragment::Reassembler`
+    Fragment,
+    /// The payload could not be decoded and was skipped
+    Skipped(MessageError),
+}
+
+/// Dispatcher counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct DispatchStats {
+    /// Messages passed to at least one handler
+    pub handled: u32,
+    /// Messages without a registered handler
+    pub unhandled: u32,
+    /// Payloads skipped because they could not be decoded
+    pub skipped: u32,
+}
+
+/// Routes decoded messages to the handlers registered for their type
+///
+/// # Type Parameters
+/// * `N` - Maximum number of registered handlers
+pub struct Dispatcher<'a, const N: usize> {
+    handlers: Vec<(MessageType, &'a mut dyn MessageHandler), N>,
+    stats: DispatchStats,
+}
+
+impl<const N: usize> Default for Dispatcher<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N: usize> Dispatcher<'a, N> {
+    /// Create a dispatcher without handlers
+    pub fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+            stats: DispatchStats::default(),
+        }
+    }
+
+    /// Register a handler for one message type
+    ///
+    /// Several handlers may be registered for the same type, they are called in
+    /// registration order.
+    ///
+    /// # Returns
+    /// `false` if the handler table is full
+    pub fn register(
+        &mut self,
+        message_type: MessageType,
+        handler: &'a mut dyn MessageHandler,
+    ) -> bool {
+        self.handlers.push((message_type, handler)).is_ok()
+    }
+
+    /// Dispatcher counters
+    pub fn stats(&self) -> &DispatchStats {
+        &self.stats
+    }
+
+    /// Decode the message carried by a received packet and pass it to its handlers
+    pub fn dispatch(&mut self, packet: &Packet) -> DispatchOutcome {
+        if packet.header.control.is_fragment() {
+            return DispatchOutcome::Fragment;
+        }
+        self.dispatch_payload(&packet.header, packet.payload_data())
+    }
+
+    /// Decode a message and pass it to its handlers
+    ///
+    /// Used for reassembled fragmented messages, `header` is the header of any of
+    /// their fragments.
+    pub fn dispatch_payload(&mut self, header: &Header, payload: &[u8]) -> DispatchOutcome {
+        let message = match Message::decode(header.sender_id, payload) {
+            Ok(message) => message,
+            Err(e) => {
+                terminal_log!(debug, "Skipping message from {}: {:?}", header.sender_id, e);
+                self.stats.skipped += 1;
+                return DispatchOutcome::Skipped(e);
+            }
+        };
+
+        let message_type = message.message_type();
+        let mut calls = 0;
+        for (registered, handler) in self.handlers.iter_mut() {
+            if *registered == message_type {
+                handler.handle(header, &message);
+                calls += 1;
+            }
+        }
+
+        if calls == 0 {
+            self.stats.unhandled += 1;
+            DispatchOutcome::Unhandled(message_type)
+        } else {
+            self.stats.handled += 1;
+            DispatchOutcome::Handled(calls)
+        }
+    }
+}
@@ -0,0 +1,558 @@
+/// Neighbor discovery
+/// Nodes periodically broadcast beacons carrying their ID, role and firmware version.
+/// Received beacons populate a fixed-capacity neighbor table that tracks when each
+/// neighbor was last heard, how many packets it sent and a smoothed RSSI with a short
+/// history. Entries of neighbors that stay silent age out.
+///
+/// Beacons are sent as `radio::message::Message::Beacon`, body layout:
+/// `role: u8 | major: u8 | minor: u8 | patch: u8 | gateway_id: u16 | gateway_cost: u16 |
+/// uid_tag: u32 | gateway_via: u16`
+///
+/// The UID tag is a hash of the sender's MCU unique ID (see `radio::node_id`), it tells
+/// apart two boards beaconing with the same node ID. The table reports such collisions
+/// so they can be passed on to both boards. Zero means no tag, beacons of
+/// older firmware end after the route fields. `gateway_via` is the next hop of the
+/// advertised route (see `radio::routing::RouteAdvert`), older beacons end before it.
+///
+/// The table of this node (`NEIGHBORS`) is fed by `radio::reliable::ReliableLink`,
+/// which observes every packet it receives.
+use super::message::Message;
+use super::node_id::IdConflict;
+use super::protocol::{Packet, BROADCAST_ID};
+use super::random::XorShift32;
+use super::routing::{RouteAdvert, INFINITE_COST};
+use crate::terminal_log;
+use core::cell::RefCell;
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use heapless::{HistoryBuffer, Vec};
+
+/// Size of an encoded beacon body
+pub const BEACON_SIZE: usize = 14;
+
+/// Size of a beacon body without the UID tag
+const BEACON_BASE_SIZE: usize = 8;
+
+/// Size of a beacon body without the next hop of the route
+const BEACON_TAGGED_SIZE: usize = 12;
+
+/// Capacity of the shared neighbor table
+pub const MAX_NEIGHBORS: usize = 8;
+
+/// Number of raw RSSI samples kept per neighbor
+pub const RSSI_HISTORY: usize = 8;
+
+/// Default interval between beacons
+pub const DEFAULT_BEACON_INTERVAL_MS: u64 = 30_000;
+
+/// Default random jitter added to the beacon interval
+pub const DEFAULT_BEACON_JITTER_MS: u32 = 3_000;
+
+/// Default time after which a silent neighbor is removed
+pub const DEFAULT_NEIGHBOR_TIMEOUT_MS: u64 =
+    3 * (DEFAULT_BEACON_INTERVAL_MS + DEFAULT_BEACON_JITTER_MS as u64);
+
+/// Role a node plays in the swarm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+#[repr(u8)]
+pub enum NodeRole {
+    /// Battery powered node that only reports measurements
+    Sensor = 0,
+    /// Node that forwards traffic for others
+    Relay = 1,
+    /// Node connected to the outside world collecting swarm data
+    Gateway = 2,
+}
+
+impl NodeRole {
+    /// Decode a role from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(NodeRole::Sensor),
+            1 => Some(NodeRole::Relay),
+            2 => Some(NodeRole::Gateway),
+            _ => None,
+        }
+    }
+
+    /// Human readable role name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeRole::Sensor => "sensor",
+            NodeRole::Relay => "relay",
+            NodeRole::Gateway => "gateway",
+        }
+    }
+}
+
+/// Firmware version advertised in beacons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FirmwareVersion {
+    /// Incremented on incompatible changes
+    pub major: u8,
+    /// Incremented on backwards compatible feature additions
+    pub minor: u8,
+    /// Incremented on bug fixes
+    pub patch: u8,
+}
+
+impl FirmwareVersion {
+    /// Version of the running firmware, taken from the crate version
+    pub const fn current() -> Self {
+        Self {
+            major: parse_version_component(env!("CARGO_PKG_VERSION_MAJOR")),
+            minor: parse_version_component(env!("CARGO_PKG_VERSION_MINOR")),
+            patch: parse_version_component(env!("CARGO_PKG_VERSION_PATCH")),
+        }
+    }
+}
+
+/// Parse a decimal version component at compile time
+const fn parse_version_component(text: &str) -> u8 {
+    let bytes = text.as_bytes();
+    let mut value: u8 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0');
+        i += 1;
+    }
+    value
+}
+
+/// Neighbor discovery beacon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct Beacon {
+    /// ID of the node sending the beacon (carried in the packet header)
+    pub node_id: u16,
+    /// Role the sender plays in the swarm
+    pub role: NodeRole,
+    /// Firmware the sender runs
+    pub firmware: FirmwareVersion,
+    /// Best route to a gateway known by the sender, used by `radio::routing`
+    pub route: Option<RouteAdvert>,
+    /// Hash of the sender's MCU unique ID, `None` if the sender does not announce it
+    pub uid_tag: Option<u32>,
+}
+
+impl Beacon {
+    /// Create a beacon for this node running the current firmware
+    pub fn new(node_id: u16, role: NodeRole) -> Self {
+        Self {
+            node_id,
+            role,
+            firmware: FirmwareVersion::current(),
+            route: None,
+            uid_tag: None,
+        }
+    }
+
+    /// Advertise a route to a gateway in this beacon
+    pub fn with_route(mut self, route: Option<RouteAdvert>) -> Self {
+        self.route = route;
+        self
+    }
+
+    /// Announce the hash of this node's MCU unique ID in this beacon
+    pub fn with_uid_tag(mut self, uid_tag: Option<u32>) -> Self {
+        self.uid_tag = uid_tag.filter(|&tag| tag != 0);
+        self
+    }
+
+    /// Encode the beacon body
+    pub fn to_bytes(&self) -> [u8; BEACON_SIZE] {
+        let route = self.route.unwrap_or(RouteAdvert {
+            gateway_id: BROADCAST_ID,
+            cost: INFINITE_COST,
+            via: BROADCAST_ID,
+        });
+        let gateway_id = route.gateway_id.to_le_bytes();
+        let cost = route.cost.to_le_bytes();
+        let uid_tag = self.uid_tag.unwrap_or(0).to_le_bytes();
+        let via = route.via.to_le_bytes();
+        [
+            self.role as u8,
+            self.firmware.major,
+            self.firmware.minor,
+            self.firmware.patch,
+            gateway_id[0],
+            gateway_id[1],
+            cost[0],
+            cost[1],
+            uid_tag[0],
+            uid_tag[1],
+            uid_tag[2],
+            uid_tag[3],
+            via[0],
+            via[1],
+        ]
+    }
+
+    /// Decode a beacon body sent by `node_id`
+    pub fn from_bytes(node_id: u16, body: &[u8]) -> Option<Self> {
+        let uid_tag = body
+            .get(BEACON_BASE_SIZE..BEACON_TAGGED_SIZE)
+            .map(|tag| u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]))
+            .filter(|&tag| tag != 0);
+        let via = body
+            .get(BEACON_TAGGED_SIZE..BEACON_SIZE)
+            .map_or(BROADCAST_ID, |via| u16::from_le_bytes([via[0], via[1]]));
+        let body = body.get(..BEACON_BASE_SIZE)?;
+        Some(Self {
+            node_id,
+            role: NodeRole::from_u8(body[0])?,
+            firmware: FirmwareVersion {
+                major: body[1],
+                minor: body[2],
+                patch: body[3],
+            },
+            route: RouteAdvert::from_parts(
+                u16::from_le_bytes([body[4], body[5]]),
+                u16::from_le_bytes([body[6], body[7]]),
+                via,
+            ),
+            uid_tag,
+        })
+    }
+
+    /// Build the broadcast packet carrying this beacon
+    ///
+    /// Beacons describe the direct neighborhood, so they are never relayed.
+    pub fn to_packet(&self, sequence_number: u16) -> Packet {
+        let mut packet =
+            Message::Beacon(*self).to_packet(self.node_id, BROADCAST_ID, sequence_number);
+        packet.header.ttl = 0;
+        packet
+    }
+
+    /// Extract a beacon from a received packet
+    ///
+    /// # Returns
+    /// * `Some(beacon)` if the packet is a well-formed beacon
+    /// * `None` for any other packet
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        match Message::from_packet(packet) {
+            Ok(Message::Beacon(beacon)) => Some(beacon),
+            _ => None,
+        }
+    }
+}
+
+/// Decides when the next beacon is due
+///
+/// Random jitter keeps neighbors that booted together from beaconing in lockstep.
+pub struct BeaconScheduler {
+    interval_ms: u64,
+    jitter_ms: u32,
+    next_due_ms: u64,
+    rng: XorShift32,
+}
+
+impl BeaconScheduler {
+    /// Create a scheduler, the first beacon is due immediately
+    pub fn new(interval_ms: u64, jitter_ms: u32, seed: u32) -> Self {
+        Self {
+            interval_ms,
+            jitter_ms,
+            next_due_ms: 0,
+            rng: XorShift32::new(seed),
+        }
+    }
+
+    /// Time at which the next beacon is due
+    pub fn next_due_ms(&self) -> u64 {
+        self.next_due_ms
+    }
+
+    /// Check whether a beacon is due and schedule the following one if so
+    pub fn poll(&mut self, now_ms: u64) -> bool {
+        if now_ms < self.next_due_ms {
+            return false;
+        }
+        let jitter = self.rng.below(self.jitter_ms.saturating_add(1));
+        self.next_due_ms = now_ms + self.interval_ms + jitter as u64;
+        true
+    }
+}
+
+/// Information about one neighbor
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighborEntry {
+    /// ID of the neighbor
+    pub node_id: u16,
+    /// Role announced in the neighbor's last beacon
+    pub role: NodeRole,
+    /// Firmware announced in the neighbor's last beacon
+    pub firmware: FirmwareVersion,
+    /// Hash of the neighbor's MCU unique ID from its last beacon
+    pub uid_tag: Option<u32>,
+    /// Time the neighbor was last heard
+    pub last_seen_ms: u64,
+    /// Packets received from the neighbor, beacons included
+    pub packet_count: u32,
+    /// Smoothed RSSI in 1/16 dBm, `None` until a sample arrived
+    rssi_smoothed: Option<i32>,
+    /// Most recent raw RSSI samples in dBm
+    rssi_history: HistoryBuffer<i16, RSSI_HISTORY>,
+}
+
+/// Compact copy of a neighbor entry for reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct NeighborSummary {
+    /// ID of the neighbor
+    pub node_id: u16,
+    /// Role announced in the neighbor's last beacon
+    pub role: NodeRole,
+    /// Firmware announced in the neighbor's last beacon
+    pub firmware: FirmwareVersion,
+    /// Time since the neighbor was last heard
+    pub age_ms: u64,
+    /// Packets received from the neighbor, beacons included
+    pub packet_count: u32,
+    /// Smoothed signal strength in dBm
+    pub rssi_dbm: Option<i16>,
+}
+
+impl NeighborEntry {
+    /// Summarize the entry as seen at `now_ms`
+    pub fn summary(&self, now_ms: u64) -> NeighborSummary {
+        NeighborSummary {
+            node_id: self.node_id,
+            role: self.role,
+            firmware: self.firmware,
+            age_ms: now_ms.saturating_sub(self.last_seen_ms),
+            packet_count: self.packet_count,
+            rssi_dbm: self.rssi_dbm(),
+        }
+    }
+
+    fn new(beacon: &Beacon, now_ms: u64) -> Self {
+        Self {
+            node_id: beacon.node_id,
+            role: beacon.role,
+            firmware: beacon.firmware,
+            uid_tag: beacon.uid_tag,
+            last_seen_ms: now_ms,
+            packet_count: 0,
+            rssi_smoothed: None,
+            rssi_history: HistoryBuffer::new(),
+        }
+    }
+
+    /// Smoothed signal strength in dBm
+    pub fn rssi_dbm(&self) -> Option<i16> {
+        self.rssi_smoothed.map(|value| (value / 16) as i16)
+    }
+
+    /// Raw RSSI samples, oldest first
+    pub fn rssi_history(&self) -> impl Iterator<Item = i16> + '_ {
+        self.rssi_history.oldest_ordered().copied()
+    }
+
+    fn record(&mut self, rssi: Option<i16>, now_ms: u64) {
+        self.last_seen_ms = now_ms;
+        self.packet_count = self.packet_count.saturating_add(1);
+
+        let Some(rssi) = rssi else {
+            return;
+        };
+        self.rssi_history.write(rssi);
+        let sample = rssi as i32 * 16;
+        // Exponentially weighted moving average with alpha = 1/4
+        self.rssi_smoothed = Some(match self.rssi_smoothed {
+            Some(smoothed) => smoothed + (sample - smoothed) / 4,
+            None => sample,
+        });
+    }
+}
+
+/// Fixed-capacity table of neighbors in radio range
+///
+/// # Type Parameters
+/// * `N` - Maximum number of neighbors, the least recently heard one is replaced
+///   when a new neighbor appears in a full table
+pub struct NeighborTable<const N: usize> {
+    entries: Vec<NeighborEntry, N>,
+    timeout_ms: u64,
+}
+
+impl<const N: usize> Default for NeighborTable<N> {
+    fn default() -> Self {
+        Self::new(DEFAULT_NEIGHBOR_TIMEOUT_MS)
+    }
+}
+
+impl<const N: usize> NeighborTable<N> {
+    /// Create an empty table removing neighbors silent for longer than `timeout_ms`
+    pub const fn new(timeout_ms: u64) -> Self {
+        Self {
+            entries: Vec::new(),
+            timeout_ms,
+        }
+    }
+
+    /// Number of known neighbors
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether no neighbor is known
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a neighbor
+    pub fn get(&self, node_id: u16) -> Option<&NeighborEntry> {
+        self.entries.iter().find(|entry| entry.node_id == node_id)
+    }
+
+    /// Iterate over all known neighbors
+    pub fn iter(&self) -> impl Iterator<Item = &NeighborEntry> {
+        self.entries.iter()
+    }
+
+    /// Update the table from a received packet
+    ///
+    /// Beacons add or refresh neighbors, any other packet only refreshes
+    /// neighbors that are already known. The neighbor is the node that transmitted
+    /// the packet (`relay_id`), which differs from the originator of relayed packets.
+    ///
+    /// # Arguments
+    /// * `packet` - Received packet
+    /// * `rssi` - Signal strength reported by `RadioReceiver::get_rssi`
+    /// * `now_ms` - Current time
+    ///
+    /// # Returns
+    /// ID collision detected from a beacon, see `update_from_beacon`
+    pub fn observe(
+        &mut self,
+        packet: &Packet,
+        rssi: Option<i16>,
+        now_ms: u64,
+    ) -> Option<IdConflict> {
+        match Beacon::from_packet(packet) {
+            Some(beacon) => self.update_from_beacon(&beacon, rssi, now_ms),
+            None => {
+                if let Some(entry) = self
+                    .entries
+                    .iter_mut()
+                    .find(|entry| entry.node_id == packet.header.relay_id)
+                {
+                    entry.record(rssi, now_ms);
+                }
+                None
+            }
+        }
+    }
+
+    /// Add or refresh a neighbor from its beacon
+    ///
+    /// # Returns
+    /// `Some(conflict)` if the beacon carries another UID tag than the one known for
+    /// its node ID. The boards may not hear each other, so the conflict should be
+    /// sent to them with `IdConflict::to_packet`.
+    pub fn update_from_beacon(
+        &mut self,
+        beacon: &Beacon,
+        rssi: Option<i16>,
+        now_ms: u64,
+    ) -> Option<IdConflict> {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.node_id == beacon.node_id)
+        {
+            let conflict = match (entry.uid_tag, beacon.uid_tag) {
+                (Some(known), Some(heard)) if known != heard => {
+                    terminal_log!(
+                        warn,
+                        "Node ID {} is used by two boards ({:08x} and {:08x})",
+                        beacon.node_id,
+                        known,
+                        heard
+                    );
+                    Some(IdConflict {
+                        node_id: beacon.node_id,
+                        uid_tags: [known, heard],
+                    })
+                }
+                _ => None,
+            };
+            entry.role = beacon.role;
+            entry.firmware = beacon.firmware;
+            entry.uid_tag = beacon.uid_tag.or(entry.uid_tag);
+            entry.record(rssi, now_ms);
+            return conflict;
+        }
+
+        terminal_log!(
+            debug,
+            "Discovered neighbor {} ({:?})",
+            beacon.node_id,
+            beacon.role
+        );
+        if self.entries.is_full() {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_seen_ms)
+                .map(|(index, _)| index)
+            {
+                self.entries.swap_remove(oldest);
+            }
+        }
+        let mut entry = NeighborEntry::new(beacon, now_ms);
+        entry.record(rssi, now_ms);
+        // A zero-capacity table simply tracks nothing
+        let _ = self.entries.push(entry);
+        None
+    }
+
+    /// Remove neighbors that have been silent longer than the timeout
+    ///
+    /// # Returns
+    /// Number of removed neighbors
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let before = self.entries.len();
+        let timeout_ms = self.timeout_ms;
+        self.entries
+            .retain(|entry| now_ms.saturating_sub(entry.last_seen_ms) <= timeout_ms);
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            terminal_log!(debug, "{} neighbors aged out", removed);
+        }
+        removed
+    }
+}
+
+/// Neighbor table shared between the radio task, the command shell and the application
+pub type SharedNeighborTable =
+    Mutex<CriticalSectionRawMutex, RefCell<NeighborTable<MAX_NEIGHBORS>>>;
+
+/// Neighbor table of this node
+pub static NEIGHBORS: SharedNeighborTable = Mutex::new(RefCell::new(NeighborTable::new(
+    DEFAULT_NEIGHBOR_TIMEOUT_MS,
+)));
+
+/// Take a snapshot of the current neighbors after removing stale entries
+pub fn neighbors_snapshot(now_ms: u64) -> Vec<NeighborSummary, MAX_NEIGHBORS> {
+    NEIGHBORS.lock(|table| {
+        let mut table = table.borrow_mut();
+        table.expire(now_ms);
+        table.iter().map(|entry| entry.summary(now_ms)).collect()
+    })
+}
+
+/// Update a shared neighbor table from a received packet
+///
+/// # Returns
+/// ID collision to report, see `NeighborTable::update_from_beacon`
+pub fn observe_neighbor(
+    neighbors: &SharedNeighborTable,
+    packet: &Packet,
+    rssi: Option<i16>,
+    now_ms: u64,
+) -> Option<IdConflict> {
+    neighbors.lock(|table| table.borrow_mut().observe(packet, rssi, now_ms))
+}
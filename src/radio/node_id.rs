@@ -0,0 +1,433 @@
+/// Node ID derived from the MCU unique ID
+/// Without a configured or assigned ID, a node derives a default 16-bit node ID from
+/// its 12-byte MCU unique ID (`DeviceManagement::get_unique_id_bytes`) with a stable
+/// hash, so every board gets the same ID on every boot without manual setup.
+///
+/// A 16-bit hash collides now and then, so beacons also carry a 32-bit hash of the
+/// unique ID (the UID tag, see `radio::neighbor::Beacon`). Hearing a beacon with our
+/// node ID but another UID tag means two boards share the ID. The node then derives
+/// the next candidate ID, or drops an ID assigned by the join handshake
+/// (`radio::join`) so it can ask for a new one. An ID configured by the operator and
+/// stored as `NodeIdOverride` always takes precedence and is never changed.
+///
+/// Two boards out of range of each other never hear each other's beacons. A third
+/// node hearing both reports the collision to them as `IdConflict` (see
+/// `radio::neighbor::NeighborTable::update_from_beacon`), then the board with the
+/// higher UID tag gives way. The number of collisions selecting the derived
+/// candidate is persisted as `DerivedIdRecord`, so a rederived ID survives reboots.
+///
+/// Persisted override record layout: `magic: u16 | node_id: u16 | crc16`
+///
+/// Persisted derived ID record layout: `magic: u16 | attempt: u8 | crc16`
+///
+/// Conflict body: `node_id: u16 | uid_tag_a: u32 | uid_tag_b: u32`
+use super::join::{is_assignable, Uid};
+use super::message::Message;
+use super::neighbor::{Beacon, NodeRole};
+use super::protocol::Packet;
+use crate::hw::traits::FlashStorage;
+use crate::terminal_log;
+use crc::{Crc, CRC_16_IBM_3740, CRC_32_ISCSI, CRC_32_ISO_HDLC};
+use defmt::Format;
+
+/// Size of the persisted node ID override record
+pub const NODE_ID_OVERRIDE_RECORD_SIZE: usize = 6;
+
+/// Size of the persisted derived ID record
+pub const DERIVED_ID_RECORD_SIZE: usize = 5;
+
+/// Size of an encoded ID conflict body
+pub const ID_CONFLICT_SIZE: usize = 10;
+
+/// Marker of a valid override record
+const OVERRIDE_MAGIC: u16 = 0x4E49;
+
+/// Marker of a valid derived ID record
+const DERIVED_MAGIC: u16 = 0x4E44;
+
+/// CRC-16/CCITT-FALSE protecting the override record
+const RECORD_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+
+/// Hash the node ID candidates are derived from
+const NODE_ID_HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// Hash of the UID announced in beacons, independent of `NODE_ID_HASH`
+const UID_TAG_HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Derive a node ID candidate from the MCU unique ID
+///
+/// The result only depends on the arguments, every `attempt` yields another
+/// candidate. Reserved IDs (broadcast, unassigned) are never returned.
+///
+/// # Arguments
+/// * `uid` - MCU unique ID
+/// * `attempt` - Number of collisions seen so far, 0 for the default ID
+pub fn derive_node_id(uid: &Uid, attempt: u8) -> u16 {
+    let mut salt = attempt;
+    loop {
+        let mut digest = NODE_ID_HASH.digest();
+        digest.update(&[salt]);
+        digest.update(uid);
+        let hash = digest.finalize();
+        let node_id = (hash ^ (hash >> 16)) as u16;
+        if is_assignable(node_id) {
+            return node_id;
+        }
+        salt = salt.wrapping_add(1);
+    }
+}
+
+/// Hash of the MCU unique ID announced in beacons, never zero
+pub fn uid_tag(uid: &Uid) -> u32 {
+    UID_TAG_HASH.checksum(uid).max(1)
+}
+
+/// Node ID configured by the operator, persisted across reboots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct NodeIdOverride {
+    /// Configured node ID
+    pub node_id: u16,
+}
+
+impl NodeIdOverride {
+    /// Encode the persisted record
+    pub fn to_bytes(&self) -> [u8; NODE_ID_OVERRIDE_RECORD_SIZE] {
+        let mut record = [0u8; NODE_ID_OVERRIDE_RECORD_SIZE];
+        record[0..2].copy_from_slice(&OVERRIDE_MAGIC.to_le_bytes());
+        record[2..4].copy_from_slice(&self.node_id.to_le_bytes());
+        let crc = RECORD_CRC.checksum(&record[..4]);
+        record[4..6].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decode a persisted record
+    ///
+    /// # Returns
+    /// `None` for erased, corrupted or foreign records and reserved IDs
+    pub fn from_bytes(record: &[u8]) -> Option<Self> {
+        let record = record.get(..NODE_ID_OVERRIDE_RECORD_SIZE)?;
+        let crc = u16::from_le_bytes([record[4], record[5]]);
+        if u16::from_le_bytes([record[0], record[1]]) != OVERRIDE_MAGIC
+            || RECORD_CRC.checksum(&record[..4]) != crc
+        {
+            return None;
+        }
+        let node_id = u16::from_le_bytes([record[2], record[3]]);
+        is_assignable(node_id).then_some(Self { node_id })
+    }
+
+    /// Read the override from persistent storage
+    ///
+    /// # Returns
+    /// `None` if no override is configured or the record is damaged
+    pub fn load<S: FlashStorage>(storage: &S, address: u32) -> Option<Self> {
+        let mut record = [0u8; NODE_ID_OVERRIDE_RECORD_SIZE];
+        storage.read(address, &mut record).ok()?;
+        Self::from_bytes(&record)
+    }
+
+    /// Write the override to persistent storage
+    ///
+    /// `address` must be the start of a sector reserved for the record, the sector
+    /// is erased before writing.
+    pub fn store<S: FlashStorage>(
+        &self,
+        storage: &mut S,
+        address: u32,
+    ) -> Result<(), &'static str> {
+        storage.erase_sector(address)?;
+        storage.write(address, &self.to_bytes())
+    }
+
+    /// Remove the override from persistent storage
+    pub fn clear<S: FlashStorage>(storage: &mut S, address: u32) -> Result<(), &'static str> {
+        storage.erase_sector(address)
+    }
+}
+
+/// Collisions of the derived node ID, persisted across reboots
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct DerivedIdRecord {
+    /// Number of collisions seen so far, selects the derived candidate
+    pub attempt: u8,
+}
+
+impl DerivedIdRecord {
+    /// Encode the persisted record
+    pub fn to_bytes(&self) -> [u8; DERIVED_ID_RECORD_SIZE] {
+        let mut record = [0u8; DERIVED_ID_RECORD_SIZE];
+        record[0..2].copy_from_slice(&DERIVED_MAGIC.to_le_bytes());
+        record[2] = self.attempt;
+        let crc = RECORD_CRC.checksum(&record[..3]);
+        record[3..5].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decode a persisted record
+    ///
+    /// # Returns
+    /// `None` for erased, corrupted or foreign records
+    pub fn from_bytes(record: &[u8]) -> Option<Self> {
+        let record = record.get(..DERIVED_ID_RECORD_SIZE)?;
+        let crc = u16::from_le_bytes([record[3], record[4]]);
+        if u16::from_le_bytes([record[0], record[1]]) != DERIVED_MAGIC
+            || RECORD_CRC.checksum(&record[..3]) != crc
+        {
+            return None;
+        }
+        Some(Self { attempt: record[2] })
+    }
+
+    /// Read the record from persistent storage
+    ///
+    /// # Returns
+    /// `None` if no collision was recorded or the record is damaged
+    pub fn load<S: FlashStorage>(storage: &S, address: u32) -> Option<Self> {
+        let mut record = [0u8; DERIVED_ID_RECORD_SIZE];
+        storage.read(address, &mut record).ok()?;
+        Self::from_bytes(&record)
+    }
+
+    /// Write the record to persistent storage
+    ///
+    /// `address` must be the start of a sector reserved for the record, the sector
+    /// is erased before writing.
+    pub fn store<S: FlashStorage>(
+        &self,
+        storage: &mut S,
+        address: u32,
+    ) -> Result<(), &'static str> {
+        storage.erase_sector(address)?;
+        storage.write(address, &self.to_bytes())
+    }
+}
+
+/// Report of two boards beaconing with the same node ID, sent by a third node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct IdConflict {
+    /// Node ID used by both boards
+    pub node_id: u16,
+    /// UID tags announced with it
+    pub uid_tags: [u32; 2],
+}
+
+impl IdConflict {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; ID_CONFLICT_SIZE] {
+        let mut body = [0u8; ID_CONFLICT_SIZE];
+        body[0..2].copy_from_slice(&self.node_id.to_le_bytes());
+        body[2..6].copy_from_slice(&self.uid_tags[0].to_le_bytes());
+        body[6..10].copy_from_slice(&self.uid_tags[1].to_le_bytes());
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..ID_CONFLICT_SIZE)?;
+        Some(Self {
+            node_id: u16::from_le_bytes([body[0], body[1]]),
+            uid_tags: [
+                u32::from_le_bytes([body[2], body[3], body[4], body[5]]),
+                u32::from_le_bytes([body[6], body[7], body[8], body[9]]),
+            ],
+        })
+    }
+
+    /// Build the packet reporting the conflict to both boards
+    ///
+    /// The reporter heard both boards, so the packet is addressed to their shared
+    /// ID and never relayed.
+    pub fn to_packet(&self, sender_id: u16, sequence_number: u16) -> Packet {
+        let mut packet =
+            Message::IdConflict(*self).to_packet(sender_id, self.node_id, sequence_number);
+        packet.header.ttl = 0;
+        packet
+    }
+
+    /// Extract an ID conflict from a received packet
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        match Message::from_packet(packet) {
+            Ok(Message::IdConflict(conflict)) => Some(conflict),
+            _ => None,
+        }
+    }
+}
+
+/// Where the current node ID comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum NodeIdSource {
+    /// Persisted operator override, never changed automatically
+    Configured,
+    /// Handed out by the join coordinator
+    Assigned,
+    /// Derived from the MCU unique ID
+    Derived,
+}
+
+/// Outcome of checking a received beacon for an ID collision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum IdentityEvent {
+    /// No collision
+    Ignored,
+    /// The derived ID is used by another board, a new one was derived and
+    /// `NodeIdentity::derived_record` must be stored
+    Rederived,
+    /// The assigned ID is used by another board, a derived ID is used until the node
+    /// joined again with `JoinClient::rejoin`
+    RejoinRequired,
+    /// The configured ID is used by another board, it is kept
+    ConfiguredConflict,
+}
+
+/// Node ID of this node and the collision detection guarding it
+pub struct NodeIdentity {
+    uid: Uid,
+    uid_tag: u32,
+    node_id: u16,
+    source: NodeIdSource,
+    /// Collisions of derived IDs so far, selects the derived candidate
+    attempt: u8,
+    conflicts: u32,
+}
+
+impl NodeIdentity {
+    /// Create the identity of a node
+    ///
+    /// # Arguments
+    /// * `uid` - MCU unique ID
+    /// * `configured` - Override loaded from persistent storage, it takes precedence
+    ///   over the derived ID
+    /// * `derived` - Derived ID record loaded from persistent storage
+    pub fn new(
+        uid: Uid,
+        configured: Option<NodeIdOverride>,
+        derived: Option<DerivedIdRecord>,
+    ) -> Self {
+        let attempt = derived.unwrap_or_default().attempt;
+        let (node_id, source) = match configured {
+            Some(configured) => (configured.node_id, NodeIdSource::Configured),
+            None => (derive_node_id(&uid, attempt), NodeIdSource::Derived),
+        };
+        terminal_log!(info, "Node ID {} ({:?})", node_id, source);
+        Self {
+            uid,
+            uid_tag: uid_tag(&uid),
+            node_id,
+            source,
+            attempt,
+            conflicts: 0,
+        }
+    }
+
+    /// MCU unique ID of this node
+    pub fn uid(&self) -> &Uid {
+        &self.uid
+    }
+
+    /// Hash of the MCU unique ID announced in beacons
+    pub fn uid_tag(&self) -> u32 {
+        self.uid_tag
+    }
+
+    /// Current node ID
+    pub fn node_id(&self) -> u16 {
+        self.node_id
+    }
+
+    /// Where the current node ID comes from
+    pub fn source(&self) -> NodeIdSource {
+        self.source
+    }
+
+    /// Number of ID collisions detected so far
+    pub fn conflicts(&self) -> u32 {
+        self.conflicts
+    }
+
+    /// Record to persist after the derived ID changed
+    pub fn derived_record(&self) -> DerivedIdRecord {
+        DerivedIdRecord {
+            attempt: self.attempt,
+        }
+    }
+
+    /// Use the ID handed out by the join coordinator
+    ///
+    /// # Returns
+    /// `false` if a configured override keeps precedence or the ID is reserved
+    pub fn assign(&mut self, node_id: u16) -> bool {
+        if self.source == NodeIdSource::Configured || !is_assignable(node_id) {
+            return false;
+        }
+        self.node_id = node_id;
+        self.source = NodeIdSource::Assigned;
+        true
+    }
+
+    /// Build the beacon of this node announcing its UID tag
+    pub fn beacon(&self, role: NodeRole) -> Beacon {
+        Beacon::new(self.node_id, role).with_uid_tag(Some(self.uid_tag))
+    }
+
+    /// Check a received beacon for another board using our node ID
+    ///
+    /// Both boards hear each other, so as for reported conflicts only the one with
+    /// the higher UID tag gives way. Beacons without a UID tag cannot be told apart
+    /// and are ignored.
+    pub fn observe_beacon(&mut self, beacon: &Beacon) -> IdentityEvent {
+        if beacon.node_id != self.node_id || beacon.uid_tag.is_none_or(|tag| tag >= self.uid_tag) {
+            return IdentityEvent::Ignored;
+        }
+        self.resolve_collision()
+    }
+
+    /// Handle a collision reported by a node that heard both boards
+    ///
+    /// If this board is one of the two, only the one with the higher UID tag gives
+    /// way. Reports naming two other boards mean at least two others use our ID.
+    pub fn observe_conflict(&mut self, conflict: &IdConflict) -> IdentityEvent {
+        let [a, b] = conflict.uid_tags;
+        let other = match (a == self.uid_tag, b == self.uid_tag) {
+            (true, false) => Some(b),
+            (false, true) => Some(a),
+            (false, false) => None,
+            (true, true) => return IdentityEvent::Ignored,
+        };
+        if conflict.node_id != self.node_id || other.is_some_and(|tag| tag > self.uid_tag) {
+            return IdentityEvent::Ignored;
+        }
+        self.resolve_collision()
+    }
+
+    /// Move away from a node ID another board uses as well
+    fn resolve_collision(&mut self) -> IdentityEvent {
+        self.conflicts = self.conflicts.saturating_add(1);
+
+        if self.source == NodeIdSource::Configured {
+            terminal_log!(
+                error,
+                "Configured node ID {} is also used by another board",
+                self.node_id
+            );
+            return IdentityEvent::ConfiguredConflict;
+        }
+
+        let previous = self.node_id;
+        let event = match self.source {
+            NodeIdSource::Assigned => IdentityEvent::RejoinRequired,
+            _ => {
+                self.attempt = self.attempt.wrapping_add(1);
+                IdentityEvent::Rederived
+            }
+        };
+        self.node_id = derive_node_id(&self.uid, self.attempt);
+        self.source = NodeIdSource::Derived;
+        terminal_log!(
+            warn,
+            "Node ID {} is also used by another board, switching to {}",
+            previous,
+            self.node_id
+        );
+        event
+    }
+}
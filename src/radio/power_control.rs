@@ -0,0 +1,429 @@
+/// Acknowledgment-driven transmit power control
+/// Sending every packet at full power wastes energy and disturbs nodes further away
+/// than needed, which hurts in dense deployments. The controller keeps a transmit
+/// power level per neighbor a packet is handed to (`next_hop`, the target for packets
+/// without next hop): it lowers the level by a small step after a streak
+/// of acknowledged packets and raises it by a larger step after consecutive losses,
+/// always within the configured bounds. Broadcasts are sent at the upper bound so
+/// every neighbor hears them.
+///
+/// Feedback comes from the hop-by-hop acks of `radio::reliable`. An ack request this
+/// node transmitted, originated or relayed, that is followed by another one over the
+/// same neighbor before the neighbor's ack arrived (a retransmission or the next
+/// packet) counts as lost, a matching ack counts as delivered. Packets without ack
+/// request give no feedback.
+///
+/// `PowerControlledRadio` wraps a radio and implements the radio traits itself, so
+/// it can be used below `ReliableLink`. Every power decision is logged.
+use super::protocol::{Header, Packet, BROADCAST_ID};
+use super::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+use crate::terminal_log;
+use defmt::Format;
+use embassy_time::Instant;
+use heapless::Vec;
+
+/// Default number of destinations with their own power level
+pub const MAX_POWER_DESTINATIONS: usize = 8;
+
+/// Transmit power control parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct PowerConfig {
+    /// Lowest level the controller selects
+    pub min_level: u8,
+    /// Highest level the controller selects, also used for broadcasts
+    pub max_level: u8,
+    /// Level used for a destination without history
+    pub initial_level: u8,
+    /// Level decrease after a streak of acknowledged packets
+    pub step_down: u8,
+    /// Level increase after consecutive losses
+    pub step_up: u8,
+    /// Acknowledged packets in a row before the level is lowered
+    pub acks_to_lower: u8,
+    /// Lost packets in a row before the level is raised
+    pub losses_to_raise: u8,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            min_level: 16,
+            max_level: 255,
+            initial_level: 255,
+            step_down: 16,
+            step_up: 64,
+            acks_to_lower: 8,
+            losses_to_raise: 2,
+        }
+    }
+}
+
+/// Direction of a power change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum PowerChange {
+    /// Acks kept arriving, the level was lowered
+    Lowered,
+    /// Packets were lost, the level was raised
+    Raised,
+}
+
+/// Power level change for one destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct PowerDecision {
+    /// Neighbor the level is kept for (the next hop of the packets)
+    pub destination: u16,
+    /// Level before the change
+    pub from: u8,
+    /// Level after the change
+    pub to: u8,
+    /// Direction of the change
+    pub change: PowerChange,
+}
+
+/// Power control counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct PowerStats {
+    /// Acknowledged packets seen
+    pub acks: u32,
+    /// Packets counted as lost
+    pub losses: u32,
+    /// Decisions lowering a level
+    pub lowered: u32,
+    /// Decisions raising a level
+    pub raised: u32,
+}
+
+/// Power state of one destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DestinationPower {
+    destination: u16,
+    level: u8,
+    ack_streak: u8,
+    loss_streak: u8,
+    /// Sequence number of the ack request still waiting for the neighbor's ack
+    pending: Option<u16>,
+    last_used_ms: u64,
+}
+
+/// Per-destination transmit power controller
+///
+/// # Type Parameters
+/// * `N` - Number of destinations tracked, the least recently used one is
+///   forgotten when a new destination appears in a full table
+pub struct PowerController<const N: usize> {
+    node_id: u16,
+    config: PowerConfig,
+    destinations: Vec<DestinationPower, N>,
+    stats: PowerStats,
+}
+
+impl<const N: usize> PowerController<N> {
+    /// Create a controller for the node with the given ID, the initial level is
+    /// clamped into the bounds
+    pub fn new(node_id: u16, config: PowerConfig) -> Self {
+        let max_level = config.max_level.max(config.min_level);
+        Self {
+            node_id,
+            config: PowerConfig {
+                max_level,
+                initial_level: config.initial_level.clamp(config.min_level, max_level),
+                ..config
+            },
+            destinations: Vec::new(),
+            stats: PowerStats::default(),
+        }
+    }
+
+    /// Active parameters
+    pub fn config(&self) -> &PowerConfig {
+        &self.config
+    }
+
+    /// Power control counters
+    pub fn stats(&self) -> &PowerStats {
+        &self.stats
+    }
+
+    /// Change the upper bound, levels above it are lowered to it
+    pub fn set_max_level(&mut self, max_level: u8) {
+        self.config.max_level = max_level.max(self.config.min_level);
+        self.config.initial_level = self.config.initial_level.min(self.config.max_level);
+        let max_level = self.config.max_level;
+        for entry in self.destinations.iter_mut() {
+            entry.level = entry.level.min(max_level);
+        }
+    }
+
+    /// Level used for packets to `destination`
+    pub fn level_for(&self, destination: u16) -> u8 {
+        if destination == BROADCAST_ID {
+            return self.config.max_level;
+        }
+        self.destinations
+            .iter()
+            .find(|entry| entry.destination == destination)
+            .map_or(self.config.initial_level, |entry| entry.level)
+    }
+
+    /// Select the level for an outgoing packet
+    ///
+    /// The level belongs to the neighbor the packet is handed to. An ack request over
+    /// a neighbor that still owes the ack of the previous one counts that one as lost,
+    /// so a retransmission already uses the raised level.
+    pub fn on_transmit(&mut self, header: &Header, now_ms: u64) -> u8 {
+        let destination = header.hop_target();
+        if destination == BROADCAST_ID {
+            return self.config.max_level;
+        }
+        if header.control.is_ack_request() && header.relay_id == self.node_id {
+            let unanswered = self
+                .entry(destination, now_ms)
+                .is_some_and(|entry| entry.pending.is_some());
+            if unanswered {
+                self.record_ack(destination, false, now_ms);
+            }
+            if let Some(entry) = self.entry(destination, now_ms) {
+                entry.pending = Some(header.sequence_number);
+            }
+        }
+        self.level_for(destination)
+    }
+
+    /// Take delivery feedback from a received packet
+    ///
+    /// Only the ack of the neighbor a packet was handed to counts.
+    pub fn on_receive(&mut self, header: &Header, now_ms: u64) {
+        if !header.control.is_ack() || header.target_id != self.node_id {
+            return;
+        }
+        let acknowledged = self.destinations.iter_mut().find(|entry| {
+            entry.destination == header.sender_id && entry.pending == Some(header.sequence_number)
+        });
+        if let Some(entry) = acknowledged {
+            entry.pending = None;
+            let destination = entry.destination;
+            self.record_ack(destination, true, now_ms);
+        }
+    }
+
+    /// Record whether a packet to `destination` was acknowledged
+    ///
+    /// # Returns
+    /// The power change this caused, if any
+    pub fn record_ack(
+        &mut self,
+        destination: u16,
+        acknowledged: bool,
+        now_ms: u64,
+    ) -> Option<PowerDecision> {
+        let config = self.config;
+        if acknowledged {
+            self.stats.acks += 1;
+        } else {
+            self.stats.losses += 1;
+        }
+
+        let entry = self.entry(destination, now_ms)?;
+        let from = entry.level;
+        let change = if acknowledged {
+            entry.loss_streak = 0;
+            entry.ack_streak = entry.ack_streak.saturating_add(1);
+            if entry.ack_streak < config.acks_to_lower || from <= config.min_level {
+                return None;
+            }
+            entry.ack_streak = 0;
+            entry.level = from.saturating_sub(config.step_down).max(config.min_level);
+            PowerChange::Lowered
+        } else {
+            entry.ack_streak = 0;
+            entry.loss_streak = entry.loss_streak.saturating_add(1);
+            if entry.loss_streak < config.losses_to_raise || from >= config.max_level {
+                return None;
+            }
+            entry.loss_streak = 0;
+            entry.level = from.saturating_add(config.step_up).min(config.max_level);
+            PowerChange::Raised
+        };
+
+        let decision = PowerDecision {
+            destination,
+            from,
+            to: entry.level,
+            change,
+        };
+        match change {
+            PowerChange::Lowered => self.stats.lowered += 1,
+            PowerChange::Raised => self.stats.raised += 1,
+        }
+        terminal_log!(
+            info,
+            "TX power for {}: {} -> {} ({:?})",
+            destination,
+            decision.from,
+            decision.to,
+            change
+        );
+        Some(decision)
+    }
+
+    /// Find the state of a destination, adding it if unknown
+    ///
+    /// # Returns
+    /// `None` only for a zero-capacity controller
+    fn entry(&mut self, destination: u16, now_ms: u64) -> Option<&mut DestinationPower> {
+        let known = self
+            .destinations
+            .iter()
+            .position(|entry| entry.destination == destination);
+        let index = match known {
+            Some(index) => index,
+            None => {
+                if self.destinations.is_full() {
+                    if let Some(oldest) = self
+                        .destinations
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, entry)| entry.last_used_ms)
+                        .map(|(index, _)| index)
+                    {
+                        self.destinations.swap_remove(oldest);
+                    }
+                }
+                let entry = DestinationPower {
+                    destination,
+                    level: self.config.initial_level,
+                    ack_streak: 0,
+                    loss_streak: 0,
+                    pending: None,
+                    last_used_ms: now_ms,
+                };
+                self.destinations.push(entry).ok()?;
+                self.destinations.len() - 1
+            }
+        };
+        let entry = &mut self.destinations[index];
+        entry.last_used_ms = now_ms;
+        Some(entry)
+    }
+}
+
+/// Radio wrapper applying the controller's power level to every transmission
+///
+/// # Type Parameters
+/// * `R` - Wrapped radio
+/// * `N` - Number of destinations with their own power level
+pub struct PowerControlledRadio<R, const N: usize> {
+    radio: R,
+    controller: PowerController<N>,
+}
+
+impl<R: RadioTransmitter + RadioReceiver, const N: usize> PowerControlledRadio<R, N> {
+    /// Wrap an initialized radio of the node with the given ID
+    pub fn new(radio: R, node_id: u16, config: PowerConfig) -> Self {
+        Self {
+            radio,
+            controller: PowerController::new(node_id, config),
+        }
+    }
+
+    /// Access the power controller
+    pub fn controller(&self) -> &PowerController<N> {
+        &self.controller
+    }
+
+    /// Power control counters
+    pub fn stats(&self) -> &PowerStats {
+        self.controller.stats()
+    }
+
+    /// Access the wrapped radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Unwrap the radio
+    pub fn into_inner(self) -> R {
+        self.radio
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send, const N: usize> RadioTransmitter
+    for PowerControlledRadio<R, N>
+{
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        let level = self
+            .controller
+            .on_transmit(&packet.header, Instant::now().as_millis());
+        if self.radio.get_power_level() != level {
+            self.radio.set_power_level(level).await?;
+        }
+        self.radio.transmit(packet).await
+    }
+
+    fn is_ready(&self) -> bool {
+        self.radio.is_ready()
+    }
+
+    /// Set the upper bound of the controlled levels
+    async fn set_power_level(&mut self, power_level: u8) -> Result<(), RadioError> {
+        self.controller.set_max_level(power_level);
+        Ok(())
+    }
+
+    fn get_power_level(&self) -> u8 {
+        self.radio.get_power_level()
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send, const N: usize> RadioReceiver
+    for PowerControlledRadio<R, N>
+{
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        let packet = self.radio.receive().await?;
+        self.controller
+            .on_receive(&packet.header, Instant::now().as_millis());
+        Ok(packet)
+    }
+
+    fn packet_available(&self) -> bool {
+        self.radio.packet_available()
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.radio.set_enabled(enabled).await
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.radio.is_enabled()
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        self.radio.get_rssi()
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.radio.is_channel_busy()
+    }
+}
+
+impl<R: RadioTransceiver + Send, const N: usize> RadioTransceiver for PowerControlledRadio<R, N> {
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        self.radio.initialize().await
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.radio.sleep().await
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.radio.wake().await
+    }
+
+    fn get_frequency(&self) -> u32 {
+        self.radio.get_frequency()
+    }
+
+    async fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), RadioError> {
+        self.radio.set_frequency(frequency_hz).await
+    }
+}
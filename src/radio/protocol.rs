@@ -1,27 +1,124 @@
 /// Radio protocol definitions
 /// This module defines the data structures for our custom radio packet format
-
+///
+/// The fixed header carries no version. Frames that need more than the fixed header
+/// set the `extended` control flag and start their payload with an extension area
+/// holding the protocol version and TLV header options:
+/// `version: u8 | options_len: u8 | options | payload`
+/// with every option encoded as `type: u8 | len: u8 | value`.
+///
+/// Frames without extension area are version 1 (`BASE_PROTOCOL_VERSION`) and are
+/// accepted by every node. Extension areas were introduced with version 2
+/// (`EXTENDED_PROTOCOL_VERSION`). Extended frames claiming an older version or a newer
+/// version than `PROTOCOL_VERSION`, or with a critical option this firmware does not
+/// understand, are rejected with a specific `DecodeError`. `payload_len` covers the
+/// extension area, so framing and frame synchronization work on the length in the
+/// fixed header alone.
 use bitfield_struct::bitfield;
 use defmt::Format;
 
+/// Target identifier addressing every node in range
+pub const BROADCAST_ID: u16 = 0;
+
+/// Hop limit given to newly created packets
+pub const DEFAULT_TTL: u8 = 3;
+
+/// Largest application data carried by one packet: single-packet messages and
+/// fragments are sized to it
+pub const MAX_DATA_SIZE: usize = 32;
+
+/// Payload bytes reserved for the frame counter, UID tag and MIC of sealed packets (see
+/// `radio::security`), so application data fits whether a packet is sealed or not
+pub const SECURITY_RESERVE: usize = 16;
+
 /// Maximum size of the packet payload in bytes
-pub const MAX_PAYLOAD_SIZE: usize = 32;
+pub const MAX_PAYLOAD_SIZE: usize = MAX_DATA_SIZE + SECURITY_RESERVE;
+
+/// Size of the encoded header on the wire in bytes
+///
+/// Wire layout (all multi-byte fields little-endian):
+/// `sender_id:u16 | target_id:u16 | sequence_number:u16 | control:u8 | ttl:u8 |
+/// hop_count:u8 | relay_id:u16 | next_hop:u16 | payload_len:u8`
+pub const HEADER_SIZE: usize = 14;
 
 /// Total packet size in bytes (header + payload)
-pub const PACKET_SIZE_BYTES: usize = core::mem::size_of::<Header>() + MAX_PAYLOAD_SIZE;
+pub const PACKET_SIZE_BYTES: usize = HEADER_SIZE + MAX_PAYLOAD_SIZE;
+
+/// Protocol version written by this firmware
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Version of frames without extension area (the original fixed header)
+pub const BASE_PROTOCOL_VERSION: u8 = 1;
+
+/// First version with an extension area, extended frames carry at least this version
+pub const EXTENDED_PROTOCOL_VERSION: u8 = 2;
+
+/// Size of the extension area prefix: `version: u8 | options_len: u8`
+pub const EXTENSION_PREFIX_SIZE: usize = 2;
+
+/// Size of the type and length fields of a header option
+pub const OPTION_PREFIX_SIZE: usize = 2;
+
+/// Option types with this bit set must be understood by the receiver, frames with an
+/// unknown critical option are rejected instead of being misinterpreted
+pub const OPTION_CRITICAL: u8 = 0x80;
+
+/// Header option: remaining lifetime of the packet in seconds (`u16`)
+pub const OPTION_LIFETIME: u8 = 0x01;
+
+/// Header option: identifier of the key the payload is secured with (`u8`)
+pub const OPTION_KEY_ID: u8 = 0x02;
+
+/// Header option: source route, the node IDs (`u16` each) still to be visited
+///
+/// Reserved for a later firmware. Forwarding ignores it, so this firmware does not
+/// list it as understood and rejects frames carrying it.
+pub const OPTION_SOURCE_ROUTE: u8 = OPTION_CRITICAL | 0x03;
+
+/// Critical options understood by this firmware
+const KNOWN_CRITICAL_OPTIONS: [u8; 0] = [];
+
+/// Mask of the `PacketControl` bits that are reserved and must be zero on the wire
+const RESERVED_CONTROL_MASK: u8 = 0x80;
+
+/// Errors that can occur while decoding a packet from its wire format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum DecodeError {
+    /// The frame ended before the header or the announced payload was complete
+    Truncated,
+    /// The frame is longer than the largest possible packet
+    TooLong,
+    /// The header announces a payload larger than `MAX_PAYLOAD_SIZE`
+    PayloadTooLarge,
+    /// Reserved bits in the control field are set
+    ReservedBitsSet,
+    /// The extended frame claims a protocol version this firmware does not support
+    UnsupportedVersion(u8),
+    /// The frame carries a critical header option of this unknown type
+    UnsupportedOption(u8),
+    /// The extension area does not fit into the payload or its options are malformed
+    MalformedExtension,
+}
 
 /// Packet header containing routing and control information
 #[derive(Debug, Clone, PartialEq, Eq, Format)]
-#[repr(C)]
 pub struct Header {
-    /// Unique identifier of the sender node
+    /// Unique identifier of the node that originated the packet
     pub sender_id: u16,
-    /// Target node identifier (0 for broadcast)
+    /// Target node identifier (`BROADCAST_ID` for broadcast)
     pub target_id: u16,
     /// Sequence number for packet ordering and duplicate detection
     pub sequence_number: u16,
     /// Control flags and packet type information
     pub control: PacketControl,
+    /// Remaining number of times the packet may be relayed
+    pub ttl: u8,
+    /// Number of times the packet has been relayed so far
+    pub hop_count: u8,
+    /// Node that transmitted this copy of the packet (the originator or the last relay)
+    pub relay_id: u16,
+    /// Node asked to forward the packet, `BROADCAST_ID` lets any node relay it (flooding)
+    pub next_hop: u16,
     /// Length of the actual payload data
     pub payload_len: u8,
 }
@@ -38,11 +135,75 @@ pub struct PacketControl {
     pub emergency: bool,
     /// Retransmission flag
     pub retransmit: bool,
+    /// The payload is a fragment of a larger message (see `radio::fragment`)
+    pub fragment: bool,
+    /// The payload is encrypted and authenticated (see `radio::security`)
+    pub secured: bool,
+    /// The payload starts with an extension area (version and header options)
+    pub extended: bool,
     /// Reserved bits (unused)
-    #[bits(4)]
+    #[bits(1)]
     _reserved: u8,
 }
 
+impl Header {
+    /// Neighbor this copy of the packet is transmitted to: the next hop, or the
+    /// target when the packet is sent directly
+    pub fn hop_target(&self) -> u16 {
+        match self.next_hop {
+            BROADCAST_ID => self.target_id,
+            next_hop => next_hop,
+        }
+    }
+
+    /// Encode the header into its fixed-size little-endian wire format
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..2].copy_from_slice(&self.sender_id.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.target_id.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.sequence_number.to_le_bytes());
+        bytes[6] = self.control.into_bits();
+        bytes[7] = self.ttl;
+        bytes[8] = self.hop_count;
+        bytes[9..11].copy_from_slice(&self.relay_id.to_le_bytes());
+        bytes[11..13].copy_from_slice(&self.next_hop.to_le_bytes());
+        bytes[13] = self.payload_len;
+        bytes
+    }
+
+    /// Decode a header from its wire format
+    ///
+    /// Only the header itself is validated here, the payload length is checked
+    /// against `MAX_PAYLOAD_SIZE` but not against the number of bytes that follow.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(DecodeError::Truncated);
+        }
+
+        let control = bytes[6];
+        if control & RESERVED_CONTROL_MASK != 0 {
+            return Err(DecodeError::ReservedBitsSet);
+        }
+
+        let payload_len = bytes[13];
+        if payload_len as usize > MAX_PAYLOAD_SIZE {
+            return Err(DecodeError::PayloadTooLarge);
+        }
+
+        Ok(Self {
+            sender_id: u16::from_le_bytes([bytes[0], bytes[1]]),
+            target_id: u16::from_le_bytes([bytes[2], bytes[3]]),
+            sequence_number: u16::from_le_bytes([bytes[4], bytes[5]]),
+            control: PacketControl::from_bits(control),
+            ttl: bytes[7],
+            hop_count: bytes[8],
+            relay_id: u16::from_le_bytes([bytes[9], bytes[10]]),
+            next_hop: u16::from_le_bytes([bytes[11], bytes[12]]),
+            payload_len,
+        })
+    }
+}
+
 impl PacketControl {
     /// Check if acknowledgment is requested
     pub fn is_ack_request(&self) -> bool {
@@ -63,6 +224,77 @@ impl PacketControl {
     pub fn is_retransmit(&self) -> bool {
         self.retransmit()
     }
+
+    /// Check if the payload is a message fragment
+    pub fn is_fragment(&self) -> bool {
+        self.fragment()
+    }
+
+    /// Check if the payload is protected by the network key
+    pub fn is_secured(&self) -> bool {
+        self.secured()
+    }
+
+    /// Check if the payload starts with an extension area
+    pub fn is_extended(&self) -> bool {
+        self.extended()
+    }
+}
+
+/// Header option carried in the extension area
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct HeaderOption<'a> {
+    /// Option type, `OPTION_CRITICAL` marks options that must be understood
+    pub kind: u8,
+    /// Option value
+    pub value: &'a [u8],
+}
+
+/// Iterator over the header options of a packet
+pub struct HeaderOptions<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for HeaderOptions<'a> {
+    type Item = HeaderOption<'a>;
+
+    fn next(&mut self) -> Option<HeaderOption<'a>> {
+        // Options are validated when the packet is decoded or built
+        let [kind, len, ref rest @ ..] = *self.bytes else {
+            return None;
+        };
+        let value = rest.get(..len as usize)?;
+        self.bytes = &rest[len as usize..];
+        Some(HeaderOption { kind, value })
+    }
+}
+
+/// Validate an extension area
+///
+/// # Returns
+/// Length of the extension area
+fn validate_extension(payload: &[u8]) -> Result<usize, DecodeError> {
+    let [version, options_len, ref rest @ ..] = *payload else {
+        return Err(DecodeError::MalformedExtension);
+    };
+    if !(EXTENDED_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let mut options = rest
+        .get(..options_len as usize)
+        .ok_or(DecodeError::MalformedExtension)?;
+    while let [kind, len, ref rest @ ..] = *options {
+        options = rest
+            .get(len as usize..)
+            .ok_or(DecodeError::MalformedExtension)?;
+        if kind & OPTION_CRITICAL != 0 && !KNOWN_CRITICAL_OPTIONS.contains(&kind) {
+            return Err(DecodeError::UnsupportedOption(kind));
+        }
+    }
+    if !options.is_empty() {
+        return Err(DecodeError::MalformedExtension);
+    }
+    Ok(EXTENSION_PREFIX_SIZE + options_len as usize)
 }
 
 /// Complete radio packet structure
@@ -70,12 +302,15 @@ impl PacketControl {
 pub struct Packet {
     /// Packet header with routing and control information
     pub header: Header,
-    /// Fixed-size payload array
+    /// Fixed-size payload array, starting with the extension area of extended packets
     pub payload: [u8; MAX_PAYLOAD_SIZE],
 }
 
 impl Packet {
     /// Create a new packet with the given parameters
+    ///
+    /// Payloads longer than `MAX_PAYLOAD_SIZE` are truncated, use
+    /// `radio::fragment` to send larger messages.
     pub fn new(sender_id: u16, target_id: u16, sequence_number: u16, payload: &[u8]) -> Self {
         let mut packet = Self {
             header: Header {
@@ -83,6 +318,10 @@ impl Packet {
                 target_id,
                 sequence_number,
                 control: PacketControl::new(),
+                ttl: DEFAULT_TTL,
+                hop_count: 0,
+                relay_id: sender_id,
+                next_hop: BROADCAST_ID,
                 payload_len: payload.len().min(MAX_PAYLOAD_SIZE) as u8,
             },
             payload: [0; MAX_PAYLOAD_SIZE],
@@ -95,49 +334,140 @@ impl Packet {
         packet
     }
 
-    /// Get the actual payload data (excluding padding)
+    /// Get the actual payload data (excluding padding and the extension area)
     pub fn payload_data(&self) -> &[u8] {
-        &self.payload[..self.header.payload_len as usize]
+        let end = (self.header.payload_len as usize).min(MAX_PAYLOAD_SIZE);
+        &self.payload[self.extension_len().min(end)..end]
+    }
+
+    /// Raw extension area, empty for packets that are not extended
+    pub fn extension_data(&self) -> &[u8] {
+        &self.payload[..self.extension_len()]
+    }
+
+    /// Size of the extension area, 0 for packets that are not extended
+    pub fn extension_len(&self) -> usize {
+        if !self.header.control.is_extended() {
+            return 0;
+        }
+        let end = (self.header.payload_len as usize).min(MAX_PAYLOAD_SIZE);
+        (EXTENSION_PREFIX_SIZE + self.payload[1] as usize).min(end)
+    }
+
+    /// Protocol version the packet was written with
+    pub fn protocol_version(&self) -> u8 {
+        if self.header.control.is_extended() {
+            self.payload[0]
+        } else {
+            BASE_PROTOCOL_VERSION
+        }
+    }
+
+    /// Iterate over the header options
+    pub fn options(&self) -> HeaderOptions<'_> {
+        HeaderOptions {
+            bytes: self
+                .extension_data()
+                .get(EXTENSION_PREFIX_SIZE..)
+                .unwrap_or(&[]),
+        }
+    }
+
+    /// Value of the first header option of the given type
+    pub fn option(&self, kind: u8) -> Option<&[u8]> {
+        self.options()
+            .find(|option| option.kind == kind)
+            .map(|option| option.value)
+    }
+
+    /// Append a header option, adding the extension area if needed
+    ///
+    /// The payload moves behind the grown extension area.
+    ///
+    /// # Returns
+    /// `false` if the option does not fit next to the payload
+    pub fn push_option(&mut self, kind: u8, value: &[u8]) -> bool {
+        let prefix = if self.header.control.is_extended() {
+            0
+        } else {
+            EXTENSION_PREFIX_SIZE
+        };
+        let added = prefix + OPTION_PREFIX_SIZE + value.len();
+        let extension_len = self.extension_len();
+        let options_len = extension_len.saturating_sub(EXTENSION_PREFIX_SIZE) + added - prefix;
+        let total = self.header.payload_len as usize + added;
+        if total > MAX_PAYLOAD_SIZE || options_len > u8::MAX as usize {
+            return false;
+        }
+
+        // Make room right behind the existing options
+        let end = self.header.payload_len as usize;
+        self.payload
+            .copy_within(extension_len..end, extension_len + added);
+        let mut at = extension_len;
+        if prefix > 0 {
+            self.payload[0] = PROTOCOL_VERSION;
+            at += EXTENSION_PREFIX_SIZE;
+        }
+        self.payload[1] = options_len as u8;
+        self.payload[at] = kind;
+        self.payload[at + 1] = value.len() as u8;
+        self.payload[at + OPTION_PREFIX_SIZE..at + OPTION_PREFIX_SIZE + value.len()]
+            .copy_from_slice(value);
+
+        self.header.control.set_extended(true);
+        self.header.payload_len = total as u8;
+        true
+    }
+
+    /// Number of meaningful bytes in the encoded packet (header + actual payload)
+    ///
+    /// Transports may send only this prefix of `to_bytes()`, the remaining
+    /// bytes are zero padding.
+    pub fn encoded_len(&self) -> usize {
+        HEADER_SIZE + (self.header.payload_len as usize).min(MAX_PAYLOAD_SIZE)
     }
 
     /// Convert packet to byte array for transmission
-    /// TODO: Add Reed-Solomon error correction encoding as per project requirements
-    /// TODO: Add packet integrity checks (CRC/checksum) for production reliability
+    ///
+    /// The header is written field by field in little-endian order, followed by
+    /// the payload. Bytes after the actual payload are always zero.
+    /// Integrity protection is added on top of this by `radio::framing`
+    /// and error correction by `radio::fec`.
     pub fn to_bytes(&self) -> [u8; PACKET_SIZE_BYTES] {
         let mut bytes = [0u8; PACKET_SIZE_BYTES];
+        bytes[..HEADER_SIZE].copy_from_slice(&self.header.to_bytes());
 
-        // FIXME: Replace unsafe pointer operations with safe serialization
-        // This unsafe code should be replaced with safer alternatives for production
-        // Serialize header
-        let header_bytes = unsafe {
-            core::slice::from_raw_parts(
-                &self.header as *const Header as *const u8,
-                core::mem::size_of::<Header>(),
-            )
-        };
-
-        let header_size = core::mem::size_of::<Header>();
-        bytes[..header_size].copy_from_slice(header_bytes);
-        bytes[header_size..].copy_from_slice(&self.payload);
+        let len = (self.header.payload_len as usize).min(MAX_PAYLOAD_SIZE);
+        bytes[HEADER_SIZE..HEADER_SIZE + len].copy_from_slice(&self.payload[..len]);
 
         bytes
     }
 
-    /// Create packet from byte array received from radio
-    /// TODO: Add Reed-Solomon error correction decoding as per project requirements
-    /// TODO: Add packet validation and integrity checks for production reliability
-    /// TODO: Add error handling for malformed or corrupted packets
-    pub fn from_bytes(bytes: &[u8; PACKET_SIZE_BYTES]) -> Self {
-        let header_size = core::mem::size_of::<Header>();
+    /// Create packet from bytes received from radio
+    ///
+    /// Accepts anything from `encoded_len()` bytes up to the full `PACKET_SIZE_BYTES`
+    /// frame. Trailing padding is ignored and the returned payload is zero-filled
+    /// past `payload_len`. The extension area of extended frames is validated.
+    /// Frames received over the air should be repaired and verified with
+    /// `radio::fec` and `radio::framing` before they are decoded here.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() > PACKET_SIZE_BYTES {
+            return Err(DecodeError::TooLong);
+        }
 
-        // FIXME: Replace unsafe unaligned read with safe deserialization
-        // This unsafe code should be replaced with safer alternatives for production
-        // Deserialize header
-        let header = unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Header) };
+        let header = Header::from_bytes(bytes)?;
+        let payload_len = header.payload_len as usize;
+        let payload_bytes = bytes
+            .get(HEADER_SIZE..HEADER_SIZE + payload_len)
+            .ok_or(DecodeError::Truncated)?;
+        if header.control.is_extended() {
+            validate_extension(payload_bytes)?;
+        }
 
         let mut payload = [0u8; MAX_PAYLOAD_SIZE];
-        payload.copy_from_slice(&bytes[header_size..]);
+        payload[..payload_len].copy_from_slice(payload_bytes);
 
-        Self { header, payload }
+        Ok(Self { header, payload })
     }
 }
@@ -0,0 +1,39 @@
+/// Lightweight pseudo-random number generation for radio protocols
+/// Backoff jitter, relay delays and hopping sequences only need fast, reproducible
+/// randomness, not cryptographic quality. Nodes seed the generator from their
+/// node ID or unique hardware ID so neighbours do not pick identical delays.
+use defmt::Format;
+
+/// Xorshift32 pseudo-random number generator
+#[derive(Debug, Clone, PartialEq, Eq, Format)]
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    /// Create a generator from a seed, a zero seed is replaced by a fixed constant
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    /// Produce the next 32-bit value
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Produce a value in `0..bound`, returns 0 when `bound` is 0
+    pub fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        // Multiply-shift reduction avoids the modulo bias of `% bound` for small bounds
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+}
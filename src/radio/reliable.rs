@@ -0,0 +1,325 @@
+/// Reliable packet delivery with acknowledgments and retransmission
+/// This module implements stop-and-wait delivery on top of any `RadioTransceiver`.
+/// Outgoing unicast packets request an acknowledgment and are retransmitted with
+/// exponential backoff and random jitter until the matching ack arrives or the
+/// retry limit is reached. Incoming ack requests addressed to this node are
+/// answered automatically, and duplicates caused by lost acks are suppressed.
+///
+/// Acknowledgments are hop-by-hop: the neighbor a packet is handed to (its next hop,
+/// or its target when sent directly) acks the node that transmitted the copy. A
+/// delivered packet has therefore only reached the next hop, confirming that a routed
+/// packet arrived at its target is left to the application (for example a
+/// `CommandResponse`).
+///
+/// Every received packet and the outcome of every acknowledged send update the
+/// link table handed to the link (see `radio::link_quality`). Received packets also
+/// update the neighbor table (see `radio::neighbor`).
+use super::dedup::DuplicateCache;
+use super::link_quality::{observe_link, record_link_delivery, SharedLinkTable};
+use super::neighbor::{observe_neighbor, SharedNeighborTable};
+use super::node_id::IdConflict;
+use super::protocol::{Header, Packet, BROADCAST_ID};
+use super::random::XorShift32;
+use super::traits::{RadioError, RadioTransceiver};
+use crate::terminal_log;
+use defmt::Format;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Deque;
+
+/// Interval between receive polls while waiting for an acknowledgment
+const ACK_POLL_INTERVAL_MS: u64 = 5;
+
+/// Number of senders tracked by the duplicate suppression cache
+const DEDUP_SENDERS: usize = 16;
+
+/// Retransmission timing parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct RetryPolicy {
+    /// Number of retransmissions after the first attempt
+    pub max_retries: u8,
+    /// Time to wait for an acknowledgment after each transmission
+    pub ack_timeout_ms: u32,
+    /// Backoff before the first retransmission, doubled for every further one
+    pub initial_backoff_ms: u32,
+    /// Upper bound for the exponential backoff (jitter is added on top)
+    pub max_backoff_ms: u32,
+    /// Maximum random jitter added to every backoff
+    pub jitter_ms: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            ack_timeout_ms: 200,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 2_000,
+            jitter_ms: 50,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before retransmission number `retry` (starting at 1)
+    pub fn backoff_ms(&self, retry: u8, rng: &mut XorShift32) -> u32 {
+        let exponent = retry.saturating_sub(1).min(31) as u32;
+        let base = self
+            .initial_backoff_ms
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_backoff_ms);
+        base.saturating_add(rng.below(self.jitter_ms.saturating_add(1)))
+    }
+}
+
+/// Result of a reliable send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum DeliveryOutcome {
+    /// The next hop acknowledged the packet after `attempts` transmissions
+    Delivered { attempts: u8 },
+    /// No acknowledgment arrived within the retry limit
+    TimedOut { attempts: u8 },
+    /// The radio reported an error while transmitting
+    Failed(RadioError),
+}
+
+/// Check whether `ack` acknowledges the packet described by `sent`
+///
+/// The ack must come from the neighbor the packet was handed to, be addressed to
+/// the node that transmitted it and carry the same sequence number.
+pub fn is_matching_ack(sent: &Header, ack: &Header) -> bool {
+    ack.control.is_ack()
+        && ack.sender_id == sent.hop_target()
+        && ack.target_id == sent.relay_id
+        && ack.sequence_number == sent.sequence_number
+}
+
+/// Build the acknowledgment for a received packet, addressed to its transmitter
+pub fn ack_for(received: &Header, own_id: u16) -> Packet {
+    let mut ack = Packet::new(own_id, received.relay_id, received.sequence_number, &[]);
+    ack.header.control.set_ack_response(true);
+    ack
+}
+
+/// Reliable delivery service wrapping a radio transceiver
+///
+/// # Type Parameters
+/// * `R` - Radio transceiver implementation
+/// * `INBOX` - Number of data packets buffered while waiting for acknowledgments
+pub struct ReliableLink<'a, R: RadioTransceiver, const INBOX: usize> {
+    radio: R,
+    node_id: u16,
+    policy: RetryPolicy,
+    links: &'a SharedLinkTable,
+    neighbors: &'a SharedNeighborTable,
+    id_conflict: Option<IdConflict>,
+    rng: XorShift32,
+    inbox: Deque<Packet, INBOX>,
+    dedup: DuplicateCache<DEDUP_SENDERS>,
+}
+
+impl<'a, R: RadioTransceiver, const INBOX: usize> ReliableLink<'a, R, INBOX> {
+    /// Create a reliable link for the node with the given ID
+    ///
+    /// # Arguments
+    /// * `links` - Link table to feed, `link_quality::LINKS` on a node
+    /// * `neighbors` - Neighbor table to feed, `neighbor::NEIGHBORS` on a node
+    pub fn new(
+        radio: R,
+        node_id: u16,
+        policy: RetryPolicy,
+        links: &'a SharedLinkTable,
+        neighbors: &'a SharedNeighborTable,
+    ) -> Self {
+        Self {
+            radio,
+            node_id,
+            policy,
+            links,
+            neighbors,
+            id_conflict: None,
+            rng: XorShift32::new(node_id as u32),
+            inbox: Deque::new(),
+            dedup: DuplicateCache::default(),
+        }
+    }
+
+    /// Get the active retry policy
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Access the wrapped radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Take the latest ID collision detected from received beacons
+    ///
+    /// The colliding boards may not hear each other, report the conflict to them
+    /// with `IdConflict::to_packet`.
+    pub fn take_id_conflict(&mut self) -> Option<IdConflict> {
+        self.id_conflict.take()
+    }
+
+    /// Send a packet and wait for its acknowledgment
+    ///
+    /// Broadcast packets cannot be acknowledged, they are transmitted once and
+    /// reported as delivered after a successful transmission. The outcome of a
+    /// unicast send is credited to the link to its next hop.
+    pub async fn send(&mut self, packet: Packet) -> DeliveryOutcome {
+        if packet.header.target_id == BROADCAST_ID {
+            return match self.radio.transmit(&packet).await {
+                Ok(()) => DeliveryOutcome::Delivered { attempts: 1 },
+                Err(e) => DeliveryOutcome::Failed(e),
+            };
+        }
+
+        let neighbor = packet.header.hop_target();
+        let outcome = self.send_unicast(packet).await;
+        record_link_delivery(self.links, neighbor, &outcome, Instant::now().as_millis());
+        outcome
+    }
+
+    /// Transmit a unicast packet until it is acknowledged or the retries run out
+    async fn send_unicast(&mut self, mut packet: Packet) -> DeliveryOutcome {
+        packet.header.control.set_ack_request(true);
+        let total_attempts = self.policy.max_retries.saturating_add(1);
+
+        for attempt in 1..=total_attempts {
+            if attempt > 1 {
+                packet.header.control.set_retransmit(true);
+            }
+
+            terminal_log!(
+                trace,
+                "Sending seq {} to {} (attempt {})",
+                packet.header.sequence_number,
+                packet.header.target_id,
+                attempt
+            );
+            if let Err(e) = self.radio.transmit(&packet).await {
+                terminal_log!(warn, "Transmission failed: {:?}", e);
+                return DeliveryOutcome::Failed(e);
+            }
+
+            if self.wait_for_ack(&packet.header).await {
+                terminal_log!(debug, "Seq {} acknowledged", packet.header.sequence_number);
+                return DeliveryOutcome::Delivered { attempts: attempt };
+            }
+
+            if attempt < total_attempts {
+                let backoff = self.policy.backoff_ms(attempt, &mut self.rng);
+                Timer::after(Duration::from_millis(backoff as u64)).await;
+            }
+        }
+
+        terminal_log!(
+            warn,
+            "No ack for seq {} from {}",
+            packet.header.sequence_number,
+            packet.header.target_id
+        );
+        DeliveryOutcome::TimedOut {
+            attempts: total_attempts,
+        }
+    }
+
+    /// Receive the next data packet
+    ///
+    /// Packets buffered while waiting for acknowledgments are returned first.
+    /// Ack requests are answered automatically before the packet is handed out.
+    ///
+    /// # Returns
+    /// * `Some(packet)` if a data packet is available
+    /// * `None` if nothing is pending right now
+    pub async fn receive(&mut self) -> Option<Packet> {
+        if let Some(packet) = self.inbox.pop_front() {
+            return Some(packet);
+        }
+
+        if !self.radio.packet_available() {
+            return None;
+        }
+
+        match self.radio.receive().await {
+            Ok(packet) => {
+                self.observe(&packet);
+                self.handle_incoming(packet).await
+            }
+            Err(e) => {
+                terminal_log!(debug, "Receive failed: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Poll the radio until an ack for `sent` arrives or the ack timeout expires
+    async fn wait_for_ack(&mut self, sent: &Header) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(self.policy.ack_timeout_ms as u64);
+
+        while Instant::now() < deadline {
+            if !self.radio.packet_available() {
+                Timer::after(Duration::from_millis(ACK_POLL_INTERVAL_MS)).await;
+                continue;
+            }
+
+            let Ok(packet) = self.radio.receive().await else {
+                continue;
+            };
+            self.observe(&packet);
+            if is_matching_ack(sent, &packet.header) {
+                return true;
+            }
+
+            // Unrelated traffic is kept for the application
+            if let Some(data) = self.handle_incoming(packet).await {
+                if self.inbox.push_back(data).is_err() {
+                    terminal_log!(warn, "Reliable link inbox full, dropping packet");
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Credit a received packet to the link and neighbor tables
+    fn observe(&mut self, packet: &Packet) {
+        let now_ms = Instant::now().as_millis();
+        observe_link(self.links, &packet.header, now_ms);
+        let rssi = self.radio.get_rssi();
+        if let Some(conflict) = observe_neighbor(self.neighbors, packet, rssi, now_ms) {
+            self.id_conflict = Some(conflict);
+        }
+    }
+
+    /// Answer ack requests and filter out stray acknowledgments and duplicates
+    ///
+    /// Ack requests are answered before the duplicate check, so a sender whose
+    /// ack was lost gets re-acknowledged when its retransmission arrives.
+    async fn handle_incoming(&mut self, packet: Packet) -> Option<Packet> {
+        if packet.header.control.is_ack() {
+            terminal_log!(trace, "Ignoring stray ack from {}", packet.header.sender_id);
+            return None;
+        }
+
+        if packet.header.control.is_ack_request() && packet.header.hop_target() == self.node_id {
+            let ack = ack_for(&packet.header, self.node_id);
+            if let Err(e) = self.radio.transmit(&ack).await {
+                terminal_log!(warn, "Failed to send ack: {:?}", e);
+            }
+        }
+
+        self.dedup.tick(Instant::now().as_millis());
+        if self.dedup.is_duplicate(&packet.header) {
+            terminal_log!(
+                trace,
+                "Dropping duplicate seq {} from {}",
+                packet.header.sequence_number,
+                packet.header.sender_id
+            );
+            return None;
+        }
+
+        Some(packet)
+    }
+}
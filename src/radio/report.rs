@@ -0,0 +1,355 @@
+/// Compact encoding of sensor readings for radio reports
+/// A raw `EnvironmentalData` fills almost a whole packet payload, so reports use a
+/// compact TLV encoding that batches several timestamped readings into one message.
+/// Every field is a tag byte, carrying the field type in the high nibble and the
+/// value length in the low nibble, followed by the value:
+/// `type: u4 | len: u4 | value`
+///
+/// - Only channels marked valid in `DataValidity` are sent
+/// - Values are varints, signed differences are zigzag encoded first
+/// - Each reading starts with a timestamp field holding the unsigned time since the
+///   previous reading (or the absolute timestamp for the first one)
+/// - Channel values are sent as the difference to the last value of the same channel
+///   earlier in the batch (or to zero)
+///
+/// Every batch starts from zero, so a lost packet never affects the next one.
+/// Decoders skip fields of unknown type using the length nibble.
+use super::message::MAX_BODY_SIZE;
+use crate::sensors::traits::EnvironmentalData;
+use defmt::Format;
+use heapless::Vec;
+
+/// Largest encoded batch, one message body
+pub const MAX_REPORT_SIZE: usize = MAX_BODY_SIZE;
+
+/// Longest varint (a 64-bit value)
+pub const MAX_VARINT_SIZE: usize = 10;
+
+/// Largest encoded reading: timestamp, four channels and the sync flag
+const MAX_READING_SIZE: usize = 5 * (1 + MAX_VARINT_SIZE) + 1;
+
+/// Errors produced while building or decoding a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ReportError {
+    /// The reading does not fit into the remaining space of the batch
+    Full,
+    /// Readings must be added in timestamp order
+    OutOfOrder,
+    /// The encoded batch is malformed
+    Malformed,
+}
+
+/// Field types of the TLV encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+#[repr(u8)]
+enum Field {
+    /// Unsigned time since the previous reading, starts a new reading
+    Timestamp = 0,
+    Temperature = 1,
+    Humidity = 2,
+    Pressure = 3,
+    Light = 4,
+    /// Zero-length flag: the timestamp is swarm network time
+    Synced = 5,
+}
+
+/// Channels carried as deltas, in field order
+const CHANNELS: [Field; 4] = [
+    Field::Temperature,
+    Field::Humidity,
+    Field::Pressure,
+    Field::Light,
+];
+
+/// Map a signed value to an unsigned one so small magnitudes stay small
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Inverse of `zigzag_encode`
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Write a LEB128 varint
+///
+/// # Returns
+/// Number of bytes written to `out`
+pub fn encode_varint(mut value: u64, out: &mut [u8; MAX_VARINT_SIZE]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out[len] = byte;
+            return len + 1;
+        }
+        out[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+/// Read a LEB128 varint that must span exactly `bytes`
+pub fn decode_varint(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > MAX_VARINT_SIZE {
+        return None;
+    }
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let last = i + 1 == bytes.len();
+        if (byte & 0x80 == 0) != last {
+            return None;
+        }
+        let bits = (byte & 0x7F) as u64;
+        value |= bits
+            .checked_shl(7 * i as u32)
+            .filter(|v| v >> (7 * i) == bits)?;
+    }
+    Some(value)
+}
+
+/// Values the next reading is encoded against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct DeltaState {
+    timestamp_ms: u64,
+    channels: [i64; CHANNELS.len()],
+}
+
+fn channel_value(reading: &EnvironmentalData, field: Field) -> Option<i64> {
+    let validity = reading.validity;
+    match field {
+        Field::Temperature if validity.temperature_valid() => {
+            Some(reading.temperature_celsius_x100 as i64)
+        }
+        Field::Humidity if validity.humidity_valid() => Some(reading.humidity_percent_x100 as i64),
+        Field::Pressure if validity.pressure_valid() => Some(reading.pressure_pa as i64),
+        Field::Light if validity.light_valid() => Some(reading.light_lux_x10 as i64),
+        _ => None,
+    }
+}
+
+fn set_channel(reading: &mut EnvironmentalData, field: Field, value: i64) -> Option<()> {
+    let validity = reading.validity;
+    match field {
+        Field::Temperature => {
+            reading.temperature_celsius_x100 = i32::try_from(value).ok()?;
+            reading.validity = validity.with_temperature_valid(true);
+        }
+        Field::Humidity => {
+            reading.humidity_percent_x100 = u32::try_from(value).ok()?;
+            reading.validity = validity.with_humidity_valid(true);
+        }
+        Field::Pressure => {
+            reading.pressure_pa = u32::try_from(value).ok()?;
+            reading.validity = validity.with_pressure_valid(true);
+        }
+        Field::Light => {
+            reading.light_lux_x10 = u32::try_from(value).ok()?;
+            reading.validity = validity.with_light_valid(true);
+        }
+        Field::Timestamp | Field::Synced => return None,
+    }
+    Some(())
+}
+
+/// Batch of compactly encoded readings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorReport {
+    bytes: Vec<u8, MAX_REPORT_SIZE>,
+    count: u8,
+    state: DeltaState,
+}
+
+impl Default for SensorReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SensorReport {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            count: 0,
+            state: DeltaState::default(),
+        }
+    }
+
+    /// Create a batch holding a single reading
+    pub fn single(reading: &EnvironmentalData) -> Result<Self, ReportError> {
+        let mut report = Self::new();
+        report.push(reading)?;
+        Ok(report)
+    }
+
+    /// Number of readings in the batch
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Check whether the batch holds no reading
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Encoded batch
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Add a reading to the batch
+    ///
+    /// Invalid channels are not encoded and decode as zero.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the reading was added
+    /// * `Err(ReportError::Full)` if it does not fit, the batch is unchanged
+    /// * `Err(ReportError::OutOfOrder)` if it is older than the previous reading
+    pub fn push(&mut self, reading: &EnvironmentalData) -> Result<(), ReportError> {
+        if self.count == u8::MAX {
+            return Err(ReportError::Full);
+        }
+        if reading.timestamp_ms < self.state.timestamp_ms {
+            return Err(ReportError::OutOfOrder);
+        }
+
+        let mut record: Vec<u8, MAX_READING_SIZE> = Vec::new();
+        let mut state = self.state;
+        let mut write = |field: Field, value: Option<u64>| {
+            let mut varint = [0u8; MAX_VARINT_SIZE];
+            let len = value.map_or(0, |value| encode_varint(value, &mut varint));
+            // The record buffer is sized for every field, so this cannot overflow
+            let _ = record.push(((field as u8) << 4) | len as u8);
+            let _ = record.extend_from_slice(&varint[..len]);
+        };
+
+        write(
+            Field::Timestamp,
+            Some(reading.timestamp_ms - state.timestamp_ms),
+        );
+        state.timestamp_ms = reading.timestamp_ms;
+        for (field, previous) in CHANNELS.iter().zip(state.channels.iter_mut()) {
+            if let Some(value) = channel_value(reading, *field) {
+                write(*field, Some(zigzag_encode(value - *previous)));
+                *previous = value;
+            }
+        }
+        if reading.validity.timestamp_synced() {
+            write(Field::Synced, None);
+        }
+
+        self.bytes
+            .extend_from_slice(&record)
+            .map_err(|_| ReportError::Full)?;
+        self.state = state;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Decode and validate an encoded batch
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReportError> {
+        let stored = Vec::from_slice(bytes).map_err(|_| ReportError::Malformed)?;
+        let mut decoder = Decoder::new(bytes);
+        let mut count = 0u8;
+        while decoder.next_reading()?.is_some() {
+            count = count.checked_add(1).ok_or(ReportError::Malformed)?;
+        }
+        Ok(Self {
+            bytes: stored,
+            count,
+            state: decoder.state,
+        })
+    }
+
+    /// Iterate over the readings of the batch
+    pub fn readings(&self) -> Readings<'_> {
+        Readings {
+            decoder: Decoder::new(&self.bytes),
+        }
+    }
+}
+
+/// TLV decoder state
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    state: DeltaState,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            state: DeltaState::default(),
+        }
+    }
+
+    /// Field type tag and value of the field at the current position
+    fn peek_field(&self) -> Result<Option<(u8, &'a [u8])>, ReportError> {
+        let Some(&tag) = self.bytes.get(self.pos) else {
+            return Ok(None);
+        };
+        let start = self.pos + 1;
+        let value = self
+            .bytes
+            .get(start..start + (tag & 0x0F) as usize)
+            .ok_or(ReportError::Malformed)?;
+        Ok(Some((tag >> 4, value)))
+    }
+
+    fn next_reading(&mut self) -> Result<Option<EnvironmentalData>, ReportError> {
+        let Some((tag, value)) = self.peek_field()? else {
+            return Ok(None);
+        };
+        if tag != Field::Timestamp as u8 {
+            return Err(ReportError::Malformed);
+        }
+        let delta = decode_varint(value).ok_or(ReportError::Malformed)?;
+        self.pos += 1 + value.len();
+
+        let mut reading = EnvironmentalData::new();
+        reading.timestamp_ms = self
+            .state
+            .timestamp_ms
+            .checked_add(delta)
+            .ok_or(ReportError::Malformed)?;
+        self.state.timestamp_ms = reading.timestamp_ms;
+
+        while let Some((tag, value)) = self.peek_field()? {
+            if tag == Field::Timestamp as u8 {
+                break;
+            }
+            self.pos += 1 + value.len();
+
+            if tag == Field::Synced as u8 {
+                reading.validity = reading.validity.with_timestamp_synced(true);
+                continue;
+            }
+            let Some(index) = CHANNELS.iter().position(|field| *field as u8 == tag) else {
+                // Field added by a newer encoder
+                continue;
+            };
+            let delta = zigzag_decode(decode_varint(value).ok_or(ReportError::Malformed)?);
+            let channel = &mut self.state.channels[index];
+            *channel = channel.checked_add(delta).ok_or(ReportError::Malformed)?;
+            set_channel(&mut reading, CHANNELS[index], *channel).ok_or(ReportError::Malformed)?;
+        }
+        Ok(Some(reading))
+    }
+}
+
+/// Iterator over the readings of a `SensorReport`
+pub struct Readings<'a> {
+    decoder: Decoder<'a>,
+}
+
+impl Iterator for Readings<'_> {
+    type Item = EnvironmentalData;
+
+    fn next(&mut self) -> Option<EnvironmentalData> {
+        // Batches are validated when built or decoded, errors cannot occur here
+        self.decoder.next_reading().ok().flatten()
+    }
+}
@@ -0,0 +1,438 @@
+/// Distance-vector routing
+/// Flooding does not scale once the swarm grows, so unicast packets are forwarded
+/// hop by hop along learned routes instead. Every node keeps a bounded table with
+/// the next hop and cost per destination. Routes are learned from:
+/// - beacons, which advertise the sender's best route to a gateway
+/// - any received packet, which proves a link to the transmitting node (`relay_id`)
+///   and a reverse path to the originator (`sender_id`)
+///
+/// Route costs add up per-link costs derived from link quality, so a path over two
+/// strong links can win over a single weak one. Links are judged by their measured
+/// ETX from the link table handed to the router (see `radio::link_quality`), signal
+/// strength only stands in for links that carried too few packets yet. Routes that
+/// are not refreshed expire.
+///
+/// A node that loses its gateway route keeps advertising the gateway with
+/// `INFINITE_COST`, so nodes routing through it drop their routes at the next beacon
+/// instead of waiting for them to expire. Adverts name their next hop (poison
+/// reverse): a node never takes a route that leads back through itself, and routes
+/// costing more than `MAX_ROUTE_COST` count as unreachable. Together this stops
+/// two nodes from counting to infinity over each other when a gateway disappears.
+use super::link_quality::{self, SharedLinkTable};
+use super::neighbor::{Beacon, NodeRole, DEFAULT_NEIGHBOR_TIMEOUT_MS};
+use super::protocol::{Packet, BROADCAST_ID};
+use crate::terminal_log;
+use defmt::Format;
+use heapless::Vec;
+
+/// Cost marking an unreachable destination
+pub const INFINITE_COST: u16 = u16::MAX;
+
+/// Cost of one hop over an excellent link
+pub const PERFECT_LINK_COST: u16 = 16;
+
+/// Routes that cost more are treated as unreachable
+pub const MAX_ROUTE_COST: u16 = 64 * PERFECT_LINK_COST;
+
+/// Default number of routes kept by a node
+pub const MAX_ROUTES: usize = 16;
+
+/// Default time after which a route that was not refreshed expires
+pub const DEFAULT_ROUTE_TIMEOUT_MS: u64 = DEFAULT_NEIGHBOR_TIMEOUT_MS;
+
+/// Route to a gateway advertised in beacons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct RouteAdvert {
+    /// Gateway the route leads to
+    pub gateway_id: u16,
+    /// Cost from the advertising node to the gateway, `INFINITE_COST` once the
+    /// route was lost
+    pub cost: u16,
+    /// Next hop of the advertising node towards the gateway, `BROADCAST_ID` if the
+    /// advertising node is the gateway or does not tell
+    pub via: u16,
+}
+
+impl RouteAdvert {
+    /// Build an advertisement from its wire fields, `None` if it names no gateway
+    pub fn from_parts(gateway_id: u16, cost: u16, via: u16) -> Option<Self> {
+        if gateway_id == BROADCAST_ID {
+            return None;
+        }
+        Some(Self {
+            gateway_id,
+            cost,
+            via,
+        })
+    }
+
+    /// Check whether the advertising node can reach the gateway
+    pub fn is_reachable(&self) -> bool {
+        self.cost != INFINITE_COST
+    }
+}
+
+/// Estimate the cost of a link from the signal strength of a received packet
+pub fn link_cost_from_rssi(rssi: Option<i16>) -> u16 {
+    match rssi {
+        Some(rssi) if rssi >= -70 => PERFECT_LINK_COST,
+        Some(rssi) if rssi >= -85 => PERFECT_LINK_COST * 3 / 2,
+        Some(rssi) if rssi >= -95 => PERFECT_LINK_COST * 5 / 2,
+        Some(_) => PERFECT_LINK_COST * 4,
+        None => PERFECT_LINK_COST * 2,
+    }
+}
+
+/// Cost of the link to a neighbor
+///
+/// Uses the ETX from the link table once enough packets were exchanged with the
+/// neighbor, the signal strength of the received packet until then.
+pub fn link_cost(links: &SharedLinkTable, neighbor: u16, rssi: Option<i16>) -> u16 {
+    link_quality::link_cost(links, neighbor).unwrap_or_else(|| link_cost_from_rssi(rssi))
+}
+
+/// One entry of the routing table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct Route {
+    /// Node the route leads to
+    pub destination: u16,
+    /// Neighbor the packets for `destination` are handed to
+    pub next_hop: u16,
+    /// Total path cost to the destination
+    pub cost: u16,
+    /// The destination is a gateway
+    pub is_gateway: bool,
+    /// Time the route was last learned or refreshed
+    pub updated_ms: u64,
+}
+
+/// Bounded routing table
+///
+/// # Type Parameters
+/// * `N` - Maximum number of destinations
+pub struct RoutingTable<const N: usize> {
+    routes: Vec<Route, N>,
+    timeout_ms: u64,
+}
+
+impl<const N: usize> Default for RoutingTable<N> {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROUTE_TIMEOUT_MS)
+    }
+}
+
+impl<const N: usize> RoutingTable<N> {
+    /// Create an empty table whose routes expire after `timeout_ms`
+    pub const fn new(timeout_ms: u64) -> Self {
+        Self {
+            routes: Vec::new(),
+            timeout_ms,
+        }
+    }
+
+    /// Number of known destinations
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Check whether no route is known
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Look up the route to a destination
+    pub fn get(&self, destination: u16) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|route| route.destination == destination)
+    }
+
+    /// Iterate over all routes
+    pub fn iter(&self) -> impl Iterator<Item = &Route> {
+        self.routes.iter()
+    }
+
+    /// Cheapest route to any gateway
+    pub fn best_gateway(&self) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|route| route.is_gateway)
+            .min_by_key(|route| route.cost)
+    }
+
+    /// Offer a route to the table
+    ///
+    /// A known destination switches to the candidate if it is cheaper or comes
+    /// from the current next hop (which may report a changed cost). A full table
+    /// replaces its most expensive route if the candidate is cheaper.
+    ///
+    /// # Returns
+    /// `true` if the table changed
+    pub fn update(&mut self, candidate: Route) -> bool {
+        if let Some(index) = self
+            .routes
+            .iter()
+            .position(|route| route.destination == candidate.destination)
+        {
+            let current = self.routes[index];
+            if candidate.next_hop == current.next_hop && candidate.cost == INFINITE_COST {
+                // The next hop lost its own route
+                self.routes.swap_remove(index);
+                return true;
+            }
+            if candidate.next_hop != current.next_hop && candidate.cost >= current.cost {
+                return false;
+            }
+            self.routes[index] = Route {
+                is_gateway: candidate.is_gateway || current.is_gateway,
+                ..candidate
+            };
+            return true;
+        }
+
+        if candidate.cost == INFINITE_COST {
+            return false;
+        }
+
+        if self.routes.is_full() {
+            let Some((worst, worst_cost)) = self
+                .routes
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, route)| route.cost)
+                .map(|(index, route)| (index, route.cost))
+            else {
+                return false;
+            };
+            if worst_cost <= candidate.cost {
+                return false;
+            }
+            self.routes.swap_remove(worst);
+        }
+
+        terminal_log!(
+            debug,
+            "New route to {} via {} (cost {})",
+            candidate.destination,
+            candidate.next_hop,
+            candidate.cost
+        );
+        self.routes.push(candidate).is_ok()
+    }
+
+    /// Remove routes that were not refreshed within the timeout
+    ///
+    /// # Returns
+    /// Number of removed routes
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let before = self.routes.len();
+        let timeout_ms = self.timeout_ms;
+        self.routes
+            .retain(|route| now_ms.saturating_sub(route.updated_ms) <= timeout_ms);
+        before - self.routes.len()
+    }
+}
+
+/// What to do with a received packet
+#[derive(Debug, Clone, PartialEq, Eq, Format)]
+pub enum RouteDecision {
+    /// The packet is addressed to this node
+    Deliver,
+    /// Transmit this packet to its next hop
+    Forward(Packet),
+    /// The packet is flooded (no next hop selected), hand it to `radio::flood`
+    Flood,
+    /// The packet is routed by other nodes or its hop limit is used up
+    Drop,
+}
+
+/// Distance-vector router
+///
+/// The router only reads link costs, the link table is fed by whoever owns the
+/// radio (usually `radio::reliable::ReliableLink`).
+///
+/// # Type Parameters
+/// * `N` - Maximum number of destinations in the routing table
+pub struct Router<'a, const N: usize> {
+    node_id: u16,
+    role: NodeRole,
+    table: RoutingTable<N>,
+    links: &'a SharedLinkTable,
+    /// Gateway of the last advertised route, poisoned once the route is lost
+    last_gateway: Option<u16>,
+}
+
+impl<'a, const N: usize> Router<'a, N> {
+    /// Create a router for the node with the given ID and role
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - Time after which routes that were not refreshed expire
+    /// * `links` - Link table providing the link costs, `link_quality::LINKS` on a node
+    pub fn new(node_id: u16, role: NodeRole, timeout_ms: u64, links: &'a SharedLinkTable) -> Self {
+        Self {
+            node_id,
+            role,
+            table: RoutingTable::new(timeout_ms),
+            links,
+            last_gateway: None,
+        }
+    }
+
+    /// Access the routing table
+    pub fn table(&self) -> &RoutingTable<N> {
+        &self.table
+    }
+
+    /// Route to advertise in this node's beacons
+    ///
+    /// After the route to a gateway was lost, the gateway is advertised with
+    /// `INFINITE_COST` until another route is found.
+    pub fn advertisement(&self) -> Option<RouteAdvert> {
+        if self.role == NodeRole::Gateway {
+            return Some(RouteAdvert {
+                gateway_id: self.node_id,
+                cost: 0,
+                via: BROADCAST_ID,
+            });
+        }
+        match self.table.best_gateway() {
+            Some(route) => Some(RouteAdvert {
+                gateway_id: route.destination,
+                cost: route.cost,
+                via: route.next_hop,
+            }),
+            None => self.last_gateway.map(|gateway_id| RouteAdvert {
+                gateway_id,
+                cost: INFINITE_COST,
+                via: BROADCAST_ID,
+            }),
+        }
+    }
+
+    /// Select the next hop for a packet originated by this node
+    ///
+    /// # Returns
+    /// `true` if a route was found, otherwise the packet is left to be flooded
+    pub fn prepare_outgoing(&self, packet: &mut Packet) -> bool {
+        packet.header.relay_id = self.node_id;
+        packet.header.next_hop = BROADCAST_ID;
+        if packet.header.target_id == BROADCAST_ID {
+            return false;
+        }
+        match self.table.get(packet.header.target_id) {
+            Some(route) => {
+                packet.header.next_hop = route.next_hop;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Learn routes from a received packet
+    ///
+    /// # Arguments
+    /// * `packet` - Received packet
+    /// * `rssi` - Signal strength reported by `RadioReceiver::get_rssi`, used while
+    ///   the link to the transmitting node has no ETX estimate
+    /// * `now_ms` - Current time
+    pub fn learn(&mut self, packet: &Packet, rssi: Option<i16>, now_ms: u64) {
+        self.table.expire(now_ms);
+
+        let header = &packet.header;
+        let relay = header.relay_id;
+        if relay == self.node_id || header.sender_id == self.node_id {
+            return;
+        }
+
+        let link_cost = link_cost(self.links, relay, rssi);
+        let beacon = Beacon::from_packet(packet);
+        self.table.update(Route {
+            destination: relay,
+            next_hop: relay,
+            cost: link_cost,
+            is_gateway: beacon.is_some_and(|beacon| beacon.role == NodeRole::Gateway),
+            updated_ms: now_ms,
+        });
+
+        match beacon.and_then(|beacon| beacon.route) {
+            Some(advert) if advert.gateway_id != self.node_id && advert.gateway_id != relay => {
+                let cost = advert.cost.saturating_add(link_cost);
+                // Poison reverse: a route back through this node is no route
+                let cost = if !advert.is_reachable()
+                    || advert.via == self.node_id
+                    || cost > MAX_ROUTE_COST
+                {
+                    INFINITE_COST
+                } else {
+                    cost
+                };
+                if self.table.update(Route {
+                    destination: advert.gateway_id,
+                    next_hop: relay,
+                    cost,
+                    is_gateway: true,
+                    updated_ms: now_ms,
+                }) && cost == INFINITE_COST
+                {
+                    terminal_log!(
+                        info,
+                        "Route to gateway {} lost via {}",
+                        advert.gateway_id,
+                        relay
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(route) = self.table.best_gateway() {
+            self.last_gateway = Some(route.destination);
+        }
+
+        if header.sender_id != relay {
+            // Reverse path: the originator is `hop_count` links behind the relay
+            let behind = (header.hop_count as u16).saturating_mul(PERFECT_LINK_COST);
+            self.table.update(Route {
+                destination: header.sender_id,
+                next_hop: relay,
+                cost: link_cost.saturating_add(behind),
+                is_gateway: false,
+                updated_ms: now_ms,
+            });
+        }
+    }
+
+    /// Learn from a received packet and decide how to handle it
+    pub fn on_receive(&mut self, packet: &Packet, rssi: Option<i16>, now_ms: u64) -> RouteDecision {
+        self.learn(packet, rssi, now_ms);
+
+        let header = &packet.header;
+        if header.next_hop == BROADCAST_ID {
+            return RouteDecision::Flood;
+        }
+        if header.target_id == self.node_id {
+            return RouteDecision::Deliver;
+        }
+        if header.next_hop != self.node_id || header.ttl == 0 {
+            return RouteDecision::Drop;
+        }
+
+        let mut forwarded = packet.clone();
+        forwarded.header.ttl -= 1;
+        forwarded.header.hop_count = forwarded.header.hop_count.saturating_add(1);
+        forwarded.header.relay_id = self.node_id;
+        forwarded.header.next_hop = match self.table.get(header.target_id) {
+            Some(route) => route.next_hop,
+            None => {
+                terminal_log!(
+                    debug,
+                    "No route to {}, flooding seq {}",
+                    header.target_id,
+                    header.sequence_number
+                );
+                BROADCAST_ID
+            }
+        };
+        RouteDecision::Forward(forwarded)
+    }
+}
@@ -0,0 +1,736 @@
+/// Authenticated encryption of radio packets
+/// Anyone with a cheap 433 MHz transmitter can inject packets into the swarm. Nodes
+/// sharing a network key protect their packets with AES-128-CCM: the payload is
+/// encrypted, and the header together with the payload is authenticated by a short
+/// message integrity code (MIC). Secured packets set `PacketControl::secured`,
+/// payload layout:
+/// `[extension area] | frame_counter: u32 | uid_tag: u32 | ciphertext | mic: [u8; MIC_SIZE]`
+///
+/// The extension area with the header options (see `radio::protocol`) stays readable
+/// for relays but is authenticated together with the header.
+///
+/// Protection is hop-by-hop. Relays rewrite the hop fields of the header, so every
+/// transmitting node (`relay_id`) seals with its own frame counter and receivers
+/// verify the copy they heard. The nonce is built from the transmitting node ID, the
+/// UID tag of the transmitting board (`radio::node_id::uid_tag`) and its frame counter.
+/// Two boards sharing a node ID, after an ID collision or while both are still
+/// joining, therefore never produce the same nonce. Receivers keep a sliding window of
+/// accepted frame counters per transmitting board and drop frames that were already
+/// accepted or are too old.
+///
+/// Frame counters survive reboots. Before a counter is used, a block of
+/// `FRAME_COUNTER_RESERVATION` counters is reserved in flash, and a restarted node
+/// continues after the last reserved block, so no counter is used twice even when the
+/// node loses power between two reservations.
+///
+/// Reservations are appended to a log spanning two flash sectors. A sector is only
+/// erased once the other one is full, so the newest record survives a power loss
+/// during the erase, and a sector is erased once per sector of records instead of
+/// once per reservation.
+///
+/// Persisted frame counter record layout: `magic: u16 | next: u32 | crc16`
+use super::join::Uid;
+use super::node_id::uid_tag;
+use super::protocol::{
+    Packet, HEADER_SIZE, MAX_DATA_SIZE, MAX_PAYLOAD_SIZE, PACKET_SIZE_BYTES, SECURITY_RESERVE,
+};
+use super::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+use crate::hw::traits::FlashStorage;
+use crate::terminal_log;
+use aes::Aes128;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{AeadInPlace, KeyInit};
+use ccm::consts::{U13, U8};
+use ccm::Ccm;
+use crc::{Crc, CRC_16_IBM_3740};
+use defmt::Format;
+use heapless::Vec;
+
+/// Size of the network key in bytes
+pub const NETWORK_KEY_SIZE: usize = 16;
+
+/// Size of the frame counter sent in front of the ciphertext
+pub const FRAME_COUNTER_SIZE: usize = 4;
+
+/// Size of the UID tag of the transmitting board sent after the frame counter
+pub const UID_TAG_SIZE: usize = 4;
+
+/// Size of the security fields in front of the ciphertext
+pub const SECURITY_HEADER_SIZE: usize = FRAME_COUNTER_SIZE + UID_TAG_SIZE;
+
+/// Size of the message integrity code appended to the ciphertext
+pub const MIC_SIZE: usize = 8;
+
+/// Payload bytes taken by the security fields
+pub const SECURITY_OVERHEAD: usize = SECURITY_HEADER_SIZE + MIC_SIZE;
+
+/// Largest payload that can be sealed into a packet without header options
+pub const MAX_SECURED_PAYLOAD: usize = MAX_PAYLOAD_SIZE - SECURITY_OVERHEAD;
+
+// Every message and fragment must still fit once sealed
+const _: () =
+    assert!(SECURITY_OVERHEAD == SECURITY_RESERVE && MAX_SECURED_PAYLOAD >= MAX_DATA_SIZE);
+
+/// Number of frame counters tracked behind the highest one for every transmitter
+pub const REPLAY_WINDOW: u32 = 32;
+
+/// Number of frame counters reserved in flash at once
+///
+/// Every reservation appends a record to flash, a restarted node skips the unused
+/// rest of the block.
+pub const FRAME_COUNTER_RESERVATION: u32 = 256;
+
+/// Size of the persisted frame counter record
+pub const FRAME_COUNTER_RECORD_SIZE: usize = 8;
+
+/// Size of the CCM nonce:
+/// `transmitter_id: u16 | uid_tag: u32 | frame_counter: u32 | zero padding`
+const NONCE_SIZE: usize = 13;
+
+/// Marker of a valid frame counter record
+const FRAME_COUNTER_MAGIC: u16 = 0x4643;
+
+/// Value of erased flash bytes
+const ERASED_BYTE: u8 = 0xFF;
+
+/// CRC-16/CCITT-FALSE protecting the frame counter record
+const RECORD_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+
+/// Shared secret of the swarm
+pub type NetworkKey = [u8; NETWORK_KEY_SIZE];
+
+/// AES-128-CCM with an 8-byte MIC and a 13-byte nonce
+type NetworkCipher = Ccm<Aes128, U8, U13>;
+
+/// Reasons a packet cannot be sealed or opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum SecurityError {
+    /// The payload is longer than `MAX_SECURED_PAYLOAD`
+    PayloadTooLarge,
+    /// Every frame counter has been used, the network key must be replaced
+    CounterExhausted,
+    /// The frame counter reservation could not be persisted
+    StorageFailed,
+    /// The packet is not secured and unsecured packets are not accepted
+    NotSecured,
+    /// The secured payload is too short to hold the security fields
+    Malformed,
+    /// The MIC does not match: wrong key, corrupted or forged frame
+    AuthenticationFailed,
+    /// The frame counter was already accepted or is too old
+    Replay,
+}
+
+impl From<SecurityError> for RadioError {
+    fn from(error: SecurityError) -> Self {
+        match error {
+            SecurityError::PayloadTooLarge => RadioError::InvalidPacket,
+            SecurityError::CounterExhausted => RadioError::NotReady,
+            SecurityError::StorageFailed => RadioError::HardwareError,
+            _ => RadioError::AuthenticationFailed,
+        }
+    }
+}
+
+/// Security counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct SecurityStats {
+    /// Packets sealed for transmission
+    pub sealed: u32,
+    /// Received packets that passed authentication
+    pub opened: u32,
+    /// Received packets dropped because the MIC did not match
+    pub auth_failures: u32,
+    /// Received packets dropped by the replay window
+    pub replays: u32,
+    /// Received packets without protection (dropped unless accepted by policy)
+    pub unsecured: u32,
+    /// Secured packets too short to hold the security fields
+    pub malformed: u32,
+}
+
+/// End of the frame counters reserved for sealing, persisted across reboots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FrameCounterRecord {
+    /// First frame counter that is not reserved yet, a restarted node continues here
+    pub next: u32,
+}
+
+impl FrameCounterRecord {
+    /// Encode the persisted record
+    pub fn to_bytes(&self) -> [u8; FRAME_COUNTER_RECORD_SIZE] {
+        let mut record = [0u8; FRAME_COUNTER_RECORD_SIZE];
+        record[0..2].copy_from_slice(&FRAME_COUNTER_MAGIC.to_le_bytes());
+        record[2..6].copy_from_slice(&self.next.to_le_bytes());
+        let crc = RECORD_CRC.checksum(&record[..6]);
+        record[6..8].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decode a persisted record
+    ///
+    /// # Returns
+    /// `None` for erased, corrupted or foreign records
+    pub fn from_bytes(record: &[u8]) -> Option<Self> {
+        let record = record.get(..FRAME_COUNTER_RECORD_SIZE)?;
+        let crc = u16::from_le_bytes([record[6], record[7]]);
+        if u16::from_le_bytes([record[0], record[1]]) != FRAME_COUNTER_MAGIC
+            || RECORD_CRC.checksum(&record[..6]) != crc
+        {
+            return None;
+        }
+        Some(Self {
+            next: u32::from_le_bytes([record[2], record[3], record[4], record[5]]),
+        })
+    }
+
+    /// Read the newest record of the `FrameCounterLog` at `address`
+    ///
+    /// # Returns
+    /// `None` if no counter was reserved yet or every record is damaged
+    pub fn load<S: FlashStorage>(storage: &S, address: u32) -> Option<Self> {
+        FrameCounterLog::open(storage, address).1
+    }
+}
+
+/// Append-only log of `FrameCounterRecord`s in two flash sectors
+///
+/// Records are appended to the current sector. When it is full the other sector is
+/// erased and becomes the current one, the newest record stays readable until its
+/// successor has been written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FrameCounterLog {
+    /// Start of the first of the two sectors
+    address: u32,
+    /// Start of the sector records are appended to
+    current: u32,
+    /// Offset of the next free slot in the current sector
+    offset: u32,
+}
+
+impl FrameCounterLog {
+    /// Find the newest record and the end of the log
+    ///
+    /// # Arguments
+    /// * `storage` - Storage holding the log
+    /// * `address` - Start of the two consecutive sectors reserved for the log
+    ///
+    /// # Returns
+    /// The log, positioned behind its newest record, and that record
+    pub fn open<S: FlashStorage>(storage: &S, address: u32) -> (Self, Option<FrameCounterRecord>) {
+        let second_sector = address + storage.sector_size();
+        let (first_newest, first_end) = scan_sector(storage, address);
+        let (second_newest, second_end) = scan_sector(storage, second_sector);
+        let use_second = match (first_newest, second_newest) {
+            (Some(first), Some(second)) => second.next > first.next,
+            (first, second) => first.is_none() && second.is_some(),
+        };
+        if use_second {
+            let log = Self {
+                address,
+                current: second_sector,
+                offset: second_end,
+            };
+            (log, second_newest)
+        } else {
+            let log = Self {
+                address,
+                current: address,
+                offset: first_end,
+            };
+            (log, first_newest)
+        }
+    }
+
+    /// Append a record, switching to the other sector when the current one is full
+    pub fn append<S: FlashStorage>(
+        &mut self,
+        storage: &mut S,
+        record: &FrameCounterRecord,
+    ) -> Result<(), &'static str> {
+        let sector_size = storage.sector_size();
+        if self.offset + FRAME_COUNTER_RECORD_SIZE as u32 > sector_size {
+            let other = if self.current == self.address {
+                self.address + sector_size
+            } else {
+                self.address
+            };
+            storage.erase_sector(other)?;
+            self.current = other;
+            self.offset = 0;
+        }
+        // A failed write may leave a torn record behind, the slot is not reused
+        let offset = self.offset;
+        self.offset += FRAME_COUNTER_RECORD_SIZE as u32;
+        storage.write(self.current + offset, &record.to_bytes())
+    }
+}
+
+/// Newest valid record of a log sector and the offset behind the last written slot
+fn scan_sector<S: FlashStorage>(storage: &S, sector: u32) -> (Option<FrameCounterRecord>, u32) {
+    let mut newest: Option<FrameCounterRecord> = None;
+    let mut offset = 0;
+    let mut slot = [0u8; FRAME_COUNTER_RECORD_SIZE];
+    while offset + FRAME_COUNTER_RECORD_SIZE as u32 <= storage.sector_size() {
+        if storage.read(sector + offset, &mut slot).is_err()
+            || slot.iter().all(|&byte| byte == ERASED_BYTE)
+        {
+            break;
+        }
+        if let Some(record) = FrameCounterRecord::from_bytes(&slot) {
+            if newest.is_none_or(|newest| record.next > newest.next) {
+                newest = Some(record);
+            }
+        }
+        offset += FRAME_COUNTER_RECORD_SIZE as u32;
+    }
+    (newest, offset)
+}
+
+/// Replay window for one transmitting board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+struct ReplayWindow {
+    transmitter_id: u16,
+    uid_tag: u32,
+    /// Highest frame counter accepted so far
+    highest: u32,
+    /// Bit `i` is set when frame counter `highest - i` has been accepted
+    seen: u32,
+    /// Value of the use counter when the transmitter was last heard
+    last_used: u32,
+}
+
+impl ReplayWindow {
+    /// Check whether `counter` may be accepted, without recording it
+    fn allows(&self, counter: u32) -> bool {
+        if counter > self.highest {
+            return true;
+        }
+        let age = self.highest - counter;
+        age < REPLAY_WINDOW && self.seen & (1 << age) == 0
+    }
+
+    /// Record an accepted frame counter
+    fn accept(&mut self, counter: u32) {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.seen = if shift >= u32::BITS {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = counter;
+        } else {
+            self.seen |= 1 << (self.highest - counter);
+        }
+    }
+}
+
+/// Sealing and opening of packets with the network key
+///
+/// # Type Parameters
+/// * `S` - Storage the frame counter reservations are persisted in
+/// * `SENDERS` - Number of transmitters whose replay windows are kept, the least
+///   recently heard one is forgotten when the table is full. A forgotten transmitter
+///   starts a fresh window, so the table should cover the whole neighborhood.
+pub struct PacketSecurity<S, const SENDERS: usize> {
+    cipher: NetworkCipher,
+    node_id: u16,
+    uid_tag: u32,
+    frame_counter: u32,
+    /// Frame counters below this value are reserved in storage
+    reserved_until: u32,
+    storage: S,
+    log: FrameCounterLog,
+    accept_unsecured: bool,
+    windows: Vec<ReplayWindow, SENDERS>,
+    use_counter: u32,
+    stats: SecurityStats,
+}
+
+impl<S: FlashStorage, const SENDERS: usize> PacketSecurity<S, SENDERS> {
+    /// Create the security context of a node for the given network key
+    ///
+    /// The frame counter continues after the block last reserved in `storage`.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID the node transmits with
+    /// * `uid` - MCU unique ID of the board, keeps nonces apart when IDs collide
+    /// * `key` - Network key
+    /// * `storage` - Storage holding the `FrameCounterLog`
+    /// * `address` - Start of the two consecutive sectors reserved for the log
+    pub fn new(node_id: u16, uid: &Uid, key: &NetworkKey, storage: S, address: u32) -> Self {
+        let (log, newest) = FrameCounterLog::open(&storage, address);
+        let frame_counter = newest.map_or(0, |record| {
+            terminal_log!(debug, "Frame counter restored at {}", record.next);
+            record.next
+        });
+        Self {
+            cipher: NetworkCipher::new(GenericArray::from_slice(key)),
+            node_id,
+            uid_tag: uid_tag(uid),
+            frame_counter,
+            reserved_until: frame_counter,
+            storage,
+            log,
+            accept_unsecured: false,
+            windows: Vec::new(),
+            use_counter: 0,
+            stats: SecurityStats::default(),
+        }
+    }
+
+    /// Security counters
+    pub fn stats(&self) -> &SecurityStats {
+        &self.stats
+    }
+
+    /// Node ID sealed packets are sent with
+    pub fn node_id(&self) -> u16 {
+        self.node_id
+    }
+
+    /// Change the node ID sealed packets are sent with, for example after joining
+    ///
+    /// The frame counter keeps counting, so nonces stay unique across the change.
+    pub fn set_node_id(&mut self, node_id: u16) {
+        self.node_id = node_id;
+    }
+
+    /// Frame counter the next sealed packet will use
+    pub fn frame_counter(&self) -> u32 {
+        self.frame_counter
+    }
+
+    /// Move the frame counter forward
+    ///
+    /// Counters only move forward, a value below the current one is ignored.
+    pub fn set_frame_counter(&mut self, frame_counter: u32) {
+        self.frame_counter = self.frame_counter.max(frame_counter);
+    }
+
+    /// Storage holding the frame counter log
+    pub fn storage(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Pass unsecured packets through instead of dropping them
+    ///
+    /// Meant for migrating a swarm to a network key node by node. Unsecured packets
+    /// are counted either way.
+    pub fn set_accept_unsecured(&mut self, accept: bool) {
+        self.accept_unsecured = accept;
+    }
+
+    /// Encrypt and authenticate a packet for transmission
+    ///
+    /// The relay ID of the sealed packet is set to this node, its frame counter
+    /// makes the nonce unique. The next block of frame counters is reserved in storage
+    /// when the current one is used up.
+    ///
+    /// # Returns
+    /// * `Ok(packet)` with the secured payload
+    /// * `Err(SecurityError::PayloadTooLarge)` if the payload and header options do not
+    ///   fit next to the security fields
+    /// * `Err(SecurityError::CounterExhausted)` if no frame counter is left
+    /// * `Err(SecurityError::StorageFailed)` if the reservation could not be persisted
+    pub fn seal(&mut self, packet: &Packet) -> Result<Packet, SecurityError> {
+        let extension_len = packet.extension_len();
+        let plaintext = packet.payload_data();
+        if extension_len + plaintext.len() > MAX_SECURED_PAYLOAD {
+            return Err(SecurityError::PayloadTooLarge);
+        }
+        let counter = self.frame_counter;
+        let next = counter
+            .checked_add(1)
+            .ok_or(SecurityError::CounterExhausted)?;
+        if counter >= self.reserved_until {
+            self.reserve()?;
+        }
+
+        let mut sealed = packet.clone();
+        sealed.header.relay_id = self.node_id;
+        sealed.header.control.set_secured(true);
+        sealed.header.payload_len = (extension_len + plaintext.len() + SECURITY_OVERHEAD) as u8;
+        let (aad, aad_len) = associated_data(&sealed);
+
+        let tag_start = extension_len + FRAME_COUNTER_SIZE;
+        let start = extension_len + SECURITY_HEADER_SIZE;
+        let end = start + plaintext.len();
+        sealed.payload[extension_len..].fill(0);
+        sealed.payload[extension_len..tag_start].copy_from_slice(&counter.to_le_bytes());
+        sealed.payload[tag_start..start].copy_from_slice(&self.uid_tag.to_le_bytes());
+        sealed.payload[start..end].copy_from_slice(plaintext);
+        let mic = self
+            .cipher
+            .encrypt_in_place_detached(
+                &nonce(self.node_id, self.uid_tag, counter),
+                &aad[..aad_len],
+                &mut sealed.payload[start..end],
+            )
+            .map_err(|_| SecurityError::PayloadTooLarge)?;
+        sealed.payload[end..end + MIC_SIZE].copy_from_slice(&mic);
+
+        self.frame_counter = next;
+        self.stats.sealed += 1;
+        Ok(sealed)
+    }
+
+    /// Verify and decrypt a received packet
+    ///
+    /// The replay window of the transmitter only advances for packets that pass
+    /// authentication, so forged frames cannot lock out genuine ones.
+    ///
+    /// # Returns
+    /// * `Ok(packet)` with the plaintext payload and the `secured` flag still set
+    /// * `Err(SecurityError)` if the packet must be dropped
+    pub fn open(&mut self, packet: &Packet) -> Result<Packet, SecurityError> {
+        let header = &packet.header;
+        if !header.control.is_secured() {
+            self.stats.unsecured += 1;
+            return if self.accept_unsecured {
+                Ok(packet.clone())
+            } else {
+                terminal_log!(debug, "Dropped unsecured packet from {}", header.relay_id);
+                Err(SecurityError::NotSecured)
+            };
+        }
+
+        let extension_len = packet.extension_len();
+        let payload = packet.payload_data();
+        if payload.len() < SECURITY_OVERHEAD {
+            self.stats.malformed += 1;
+            return Err(SecurityError::Malformed);
+        }
+        let transmitter_id = header.relay_id;
+        let counter = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let uid_tag = u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+        let window = self.windows.iter().position(|window| {
+            window.transmitter_id == transmitter_id && window.uid_tag == uid_tag
+        });
+        if let Some(index) = window {
+            if !self.windows[index].allows(counter) {
+                self.stats.replays += 1;
+                terminal_log!(
+                    warn,
+                    "Dropped replayed frame {} from {}",
+                    counter,
+                    transmitter_id
+                );
+                return Err(SecurityError::Replay);
+            }
+        }
+
+        let (aad, aad_len) = associated_data(packet);
+        let start = extension_len + SECURITY_HEADER_SIZE;
+        let end = extension_len + payload.len() - MIC_SIZE;
+        let mut opened = packet.clone();
+        let (ciphertext, mic) = opened.payload[..end + MIC_SIZE].split_at_mut(end);
+        let verified = self.cipher.decrypt_in_place_detached(
+            &nonce(transmitter_id, uid_tag, counter),
+            &aad[..aad_len],
+            &mut ciphertext[start..],
+            GenericArray::from_slice(mic),
+        );
+        if verified.is_err() {
+            self.stats.auth_failures += 1;
+            terminal_log!(
+                warn,
+                "Dropped packet from {}: authentication failed",
+                transmitter_id
+            );
+            return Err(SecurityError::AuthenticationFailed);
+        }
+
+        let plaintext_end = end - SECURITY_HEADER_SIZE;
+        opened.payload.copy_within(start..end, extension_len);
+        opened.payload[plaintext_end..].fill(0);
+        opened.header.payload_len = plaintext_end as u8;
+
+        self.use_counter = self.use_counter.wrapping_add(1);
+        let last_used = self.use_counter;
+        match window {
+            Some(index) => {
+                self.windows[index].accept(counter);
+                self.windows[index].last_used = last_used;
+            }
+            None => self.insert(ReplayWindow {
+                transmitter_id,
+                uid_tag,
+                highest: counter,
+                seen: 1,
+                last_used,
+            }),
+        }
+        self.stats.opened += 1;
+        Ok(opened)
+    }
+
+    /// Persist the next block of frame counters before any of them is used
+    fn reserve(&mut self) -> Result<(), SecurityError> {
+        let record = FrameCounterRecord {
+            next: self.frame_counter.saturating_add(FRAME_COUNTER_RESERVATION),
+        };
+        self.log
+            .append(&mut self.storage, &record)
+            .map_err(|error| {
+                terminal_log!(error, "Frame counter reservation failed: {}", error);
+                SecurityError::StorageFailed
+            })?;
+        self.reserved_until = record.next;
+        Ok(())
+    }
+
+    fn insert(&mut self, window: ReplayWindow) {
+        if self.windows.is_full() {
+            let use_counter = self.use_counter;
+            if let Some(oldest) = self
+                .windows
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, window)| use_counter.wrapping_sub(window.last_used))
+                .map(|(index, _)| index)
+            {
+                self.windows.swap_remove(oldest);
+            }
+        }
+        // Capacity was freed above
+        let _ = self.windows.push(window);
+    }
+}
+
+/// Authenticated data of a secured packet: the header and the extension area
+fn associated_data(packet: &Packet) -> ([u8; PACKET_SIZE_BYTES], usize) {
+    let extension = packet.extension_data();
+    let mut aad = [0u8; PACKET_SIZE_BYTES];
+    aad[..HEADER_SIZE].copy_from_slice(&packet.header.to_bytes());
+    aad[HEADER_SIZE..HEADER_SIZE + extension.len()].copy_from_slice(extension);
+    (aad, HEADER_SIZE + extension.len())
+}
+
+/// Nonce of the frame sent by the board with `uid_tag` as `transmitter_id`
+fn nonce(transmitter_id: u16, uid_tag: u32, frame_counter: u32) -> GenericArray<u8, U13> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce[0..2].copy_from_slice(&transmitter_id.to_le_bytes());
+    nonce[2..6].copy_from_slice(&uid_tag.to_le_bytes());
+    nonce[6..10].copy_from_slice(&frame_counter.to_le_bytes());
+    GenericArray::from(nonce)
+}
+
+/// Radio wrapper that seals every transmitted packet and opens every received one
+///
+/// Received packets that fail any check are counted and reported as
+/// `RadioError::AuthenticationFailed`, they never reach the layers above.
+///
+/// # Type Parameters
+/// * `R` - Radio implementation
+/// * `S` - Storage the frame counter reservations are persisted in
+/// * `SENDERS` - Number of transmitters whose replay windows are kept
+pub struct SecureRadio<R, S, const SENDERS: usize> {
+    radio: R,
+    security: PacketSecurity<S, SENDERS>,
+}
+
+impl<R: RadioTransmitter + RadioReceiver, S: FlashStorage, const SENDERS: usize>
+    SecureRadio<R, S, SENDERS>
+{
+    /// Wrap a radio with the security context of this node
+    pub fn new(radio: R, security: PacketSecurity<S, SENDERS>) -> Self {
+        Self { radio, security }
+    }
+
+    /// Security context (counters, frame counter)
+    pub fn security(&mut self) -> &mut PacketSecurity<S, SENDERS> {
+        &mut self.security
+    }
+
+    /// Security counters
+    pub fn stats(&self) -> &SecurityStats {
+        self.security.stats()
+    }
+
+    /// Access the wrapped radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Unwrap the radio
+    pub fn into_inner(self) -> R {
+        self.radio
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send, S: FlashStorage + Send, const SENDERS: usize>
+    RadioTransmitter for SecureRadio<R, S, SENDERS>
+{
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        let sealed = self.security.seal(packet)?;
+        self.radio.transmit(&sealed).await
+    }
+
+    fn is_ready(&self) -> bool {
+        self.radio.is_ready()
+    }
+
+    async fn set_power_level(&mut self, power_level: u8) -> Result<(), RadioError> {
+        self.radio.set_power_level(power_level).await
+    }
+
+    fn get_power_level(&self) -> u8 {
+        self.radio.get_power_level()
+    }
+}
+
+impl<R: RadioTransmitter + RadioReceiver + Send, S: FlashStorage + Send, const SENDERS: usize>
+    RadioReceiver for SecureRadio<R, S, SENDERS>
+{
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        let packet = self.radio.receive().await?;
+        Ok(self.security.open(&packet)?)
+    }
+
+    fn packet_available(&self) -> bool {
+        self.radio.packet_available()
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.radio.set_enabled(enabled).await
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.radio.is_enabled()
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        self.radio.get_rssi()
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.radio.is_channel_busy()
+    }
+}
+
+impl<R: RadioTransceiver + Send, S: FlashStorage + Send, const SENDERS: usize> RadioTransceiver
+    for SecureRadio<R, S, SENDERS>
+{
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        self.radio.initialize().await
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.radio.sleep().await
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.radio.wake().await
+    }
+
+    fn get_frequency(&self) -> u32 {
+        self.radio.get_frequency()
+    }
+
+    async fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), RadioError> {
+        self.radio.set_frequency(frequency_hz).await
+    }
+}
@@ -0,0 +1,287 @@
+/// Preamble and sync-word frame synchronizer for raw OOK receivers
+/// Cheap ASK receivers output noise until a transmission starts. This module
+/// consumes demodulated bits one at a time, hunts for the configured preamble
+/// followed by the sync word (allowing a number of bit errors) and then collects
+/// exactly the bytes of one CRC frame (see `radio::framing`).
+/// The state machine is pure logic, so recorded bit captures give identical
+/// results on the host and on target.
+///
+/// The frame length follows from the `payload_len` byte of the header. With FEC
+/// (see `radio::fec`) the header is only corrected once the whole frame arrived, too
+/// late for a corrupted length byte. Such links set `SyncConfig::protected_length`:
+/// the transmitter then sends `LENGTH_COPIES` copies of the payload length between
+/// sync word and frame (`length_prefix`), and the receiver takes a bitwise majority
+/// vote, which corrects any single corrupted copy.
+use super::fec::MAX_FEC_FRAME_SIZE;
+use super::framing::CRC_SIZE;
+use super::protocol::{HEADER_SIZE, MAX_PAYLOAD_SIZE};
+use defmt::Format;
+use heapless::Vec;
+
+/// Offset of the `payload_len` field inside the encoded header
+const PAYLOAD_LEN_OFFSET: usize = HEADER_SIZE - 1;
+
+/// Copies of the payload length sent ahead of the frame when it is protected
+pub const LENGTH_COPIES: usize = 3;
+
+// The majority vote needs an odd number of copies to never tie
+const _: () = assert!(LENGTH_COPIES % 2 == 1);
+
+/// Buffer holding one synchronized frame
+pub type SyncedFrame = Vec<u8, MAX_FEC_FRAME_SIZE>;
+
+/// Bytes to send between sync word and frame when the length is protected
+pub fn length_prefix(payload_len: u8) -> [u8; LENGTH_COPIES] {
+    [payload_len; LENGTH_COPIES]
+}
+
+/// Bitwise majority vote over the received copies of the payload length
+fn majority(copies: &[u8]) -> u8 {
+    (0..8).fold(0, |byte, bit| {
+        let ones = copies
+            .iter()
+            .filter(|&&copy| copy & (1 << bit) != 0)
+            .count();
+        if 2 * ones > copies.len() {
+            byte | (1 << bit)
+        } else {
+            byte
+        }
+    })
+}
+
+/// Synchronizer configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SyncConfig {
+    /// Preamble pattern, only the lowest `preamble_bits` bits are matched
+    pub preamble: u32,
+    /// Number of trailing preamble bits that must be present before the sync word
+    /// (0..=32, larger values are clamped)
+    pub preamble_bits: u8,
+    /// Sync word marking the start of the frame, lowest `sync_bits` bits are used
+    pub sync_word: u32,
+    /// Length of the sync word in bits (1..=32, other values are clamped)
+    pub sync_bits: u8,
+    /// Maximum number of mismatching bits tolerated in preamble + sync word
+    pub max_bit_errors: u8,
+    /// Extra bytes following the CRC frame (e.g. Reed-Solomon parity symbols)
+    pub trailer_bytes: usize,
+    /// The frame is preceded by `LENGTH_COPIES` copies of the payload length
+    pub protected_length: bool,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            preamble: 0xAAAA,
+            preamble_bits: 16,
+            sync_word: 0x2DD4,
+            sync_bits: 16,
+            max_bit_errors: 2,
+            trailer_bytes: 0,
+            protected_length: false,
+        }
+    }
+}
+
+impl SyncConfig {
+    /// Total length of the matched pattern (preamble tail followed by sync word)
+    fn pattern_bits(&self) -> u32 {
+        self.preamble_bits as u32 + self.sync_bits as u32
+    }
+
+    /// Preamble tail and sync word combined into a single pattern
+    fn pattern(&self) -> u64 {
+        ((self.preamble as u64 & bit_mask(self.preamble_bits as u32)) << self.sync_bits)
+            | (self.sync_word as u64 & bit_mask(self.sync_bits as u32))
+    }
+}
+
+/// Mask selecting the lowest `bits` bits of a u64
+fn bit_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    }
+}
+
+/// Counters describing the synchronizer activity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct SyncStats {
+    /// Number of times preamble + sync word were detected
+    pub sync_detections: u32,
+    /// Number of complete frames delivered
+    pub frames: u32,
+    /// Detections abandoned because the header announced an impossible length
+    pub false_syncs: u32,
+}
+
+/// Internal synchronizer state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+enum SyncState {
+    /// Searching the bitstream for preamble + sync word
+    Hunting,
+    /// Collecting frame bytes after a successful sync
+    Collecting,
+}
+
+/// Streaming preamble/sync-word detector and frame collector
+pub struct FrameSynchronizer {
+    config: SyncConfig,
+    state: SyncState,
+    shift: u64,
+    bits_seen: u32,
+    current_byte: u8,
+    current_bits: u8,
+    expected_len: Option<usize>,
+    buffer: SyncedFrame,
+    stats: SyncStats,
+}
+
+impl FrameSynchronizer {
+    /// Create a synchronizer with the given configuration
+    ///
+    /// Preamble and sync word lengths outside their ranges are clamped.
+    pub fn new(config: SyncConfig) -> Self {
+        let config = SyncConfig {
+            preamble_bits: config.preamble_bits.min(32),
+            sync_bits: config.sync_bits.clamp(1, 32),
+            ..config
+        };
+        Self {
+            config,
+            state: SyncState::Hunting,
+            shift: 0,
+            bits_seen: 0,
+            current_byte: 0,
+            current_bits: 0,
+            expected_len: None,
+            buffer: Vec::new(),
+            stats: SyncStats::default(),
+        }
+    }
+
+    /// Get the active configuration
+    pub fn config(&self) -> &SyncConfig {
+        &self.config
+    }
+
+    /// Get activity counters
+    pub fn stats(&self) -> SyncStats {
+        self.stats
+    }
+
+    /// Check whether the synchronizer is currently collecting a frame
+    pub fn is_locked(&self) -> bool {
+        self.state == SyncState::Collecting
+    }
+
+    /// Drop any partially received frame and start hunting again
+    pub fn reset(&mut self) {
+        self.state = SyncState::Hunting;
+        self.shift = 0;
+        self.bits_seen = 0;
+        self.current_byte = 0;
+        self.current_bits = 0;
+        self.expected_len = None;
+        self.buffer.clear();
+    }
+
+    /// Feed one demodulated bit into the synchronizer
+    ///
+    /// # Returns
+    /// * `Some(frame)` when the last byte of a frame has been collected
+    /// * `None` while hunting or collecting
+    pub fn push_bit(&mut self, bit: bool) -> Option<SyncedFrame> {
+        match self.state {
+            SyncState::Hunting => {
+                self.hunt(bit);
+                None
+            }
+            SyncState::Collecting => self.collect(bit),
+        }
+    }
+
+    /// Feed a sequence of bits, returning the first complete frame
+    ///
+    /// Bits after the completed frame are not consumed, so callers processing
+    /// long captures should feed bits one by one with `push_bit` instead.
+    pub fn push_bits<I>(&mut self, bits: I) -> Option<SyncedFrame>
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        bits.into_iter().find_map(|bit| self.push_bit(bit))
+    }
+
+    fn hunt(&mut self, bit: bool) {
+        let pattern_bits = self.config.pattern_bits();
+        self.shift = (self.shift << 1) | bit as u64;
+        self.bits_seen = self.bits_seen.saturating_add(1);
+        if self.bits_seen < pattern_bits {
+            return;
+        }
+
+        let errors = ((self.shift ^ self.config.pattern()) & bit_mask(pattern_bits)).count_ones();
+        if errors <= self.config.max_bit_errors as u32 {
+            self.stats.sync_detections += 1;
+            self.state = SyncState::Collecting;
+            self.current_byte = 0;
+            self.current_bits = 0;
+            self.expected_len = None;
+            self.buffer.clear();
+        }
+    }
+
+    fn collect(&mut self, bit: bool) -> Option<SyncedFrame> {
+        self.current_byte = (self.current_byte << 1) | bit as u8;
+        self.current_bits += 1;
+        if self.current_bits < 8 {
+            return None;
+        }
+
+        let byte = self.current_byte;
+        self.current_byte = 0;
+        self.current_bits = 0;
+        if self.buffer.push(byte).is_err() {
+            self.stats.false_syncs += 1;
+            self.reset();
+            return None;
+        }
+
+        if self.expected_len.is_none() {
+            // The frame length is only known once the length copies or the header
+            // have arrived
+            let payload_len = if self.config.protected_length {
+                if self.buffer.len() < LENGTH_COPIES {
+                    return None;
+                }
+                let payload_len = majority(&self.buffer[..LENGTH_COPIES]);
+                self.buffer.clear();
+                payload_len
+            } else {
+                if self.buffer.len() < HEADER_SIZE {
+                    return None;
+                }
+                self.buffer[PAYLOAD_LEN_OFFSET]
+            } as usize;
+            let frame_len = HEADER_SIZE + payload_len + CRC_SIZE + self.config.trailer_bytes;
+            if payload_len > MAX_PAYLOAD_SIZE || frame_len > MAX_FEC_FRAME_SIZE {
+                self.stats.false_syncs += 1;
+                self.reset();
+                return None;
+            }
+            self.expected_len = Some(frame_len);
+        }
+
+        match self.expected_len {
+            Some(len) if self.buffer.len() == len => {
+                let frame = self.buffer.clone();
+                self.stats.frames += 1;
+                self.reset();
+                Some(frame)
+            }
+            _ => None,
+        }
+    }
+}
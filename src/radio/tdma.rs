@@ -0,0 +1,529 @@
+/// Slotted, duty-cycled MAC layer
+/// Time is divided into repeating superframes of equally long slots. Every node owns
+/// a transmit slot and listens in a configurable set of slots, the radio is put to
+/// sleep for the rest of the superframe. Outgoing unicast packets are queued until the
+/// next owned slot. Unicast packets are sent in the sender's transmit slot, so a node
+/// only receives them after opting in to the transmit slots of its parent and the
+/// neighbors it expects traffic from (`SlotAssignment::with_neighbor`).
+///
+/// Every node listens in `SHARED_SLOT`, so broadcasts, beacons and time sync among
+/// them, are sent there instead of in the owned slot. Nodes contend for the shared
+/// slot: each picks a random point in its send window, senses the channel and draws
+/// a new point while it is busy.
+///
+/// Slot boundaries are computed from network time (see `radio::timesync`) so all
+/// nodes agree on them. Guard intervals at both ends of a transmit slot absorb the
+/// remaining clock error.
+use super::protocol::{Packet, BROADCAST_ID};
+use super::random::XorShift32;
+use super::traits::{RadioError, RadioTransceiver};
+use crate::terminal_log;
+use defmt::Format;
+use embassy_time::Timer;
+use heapless::Deque;
+
+/// Largest supported number of slots per superframe
+pub const MAX_SLOTS: u16 = 64;
+
+/// Smallest number of slots per superframe: the shared slot and one transmit slot
+pub const MIN_SLOTS: u16 = 2;
+
+/// Slot every node listens in by default, used for beacons and time sync
+pub const SHARED_SLOT: u16 = 0;
+
+/// Interval between receive polls while listening
+const LISTEN_POLL_INTERVAL_MS: u64 = 5;
+
+/// Superframe timing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SuperframeConfig {
+    /// Number of slots in a superframe, `MIN_SLOTS..=MAX_SLOTS`
+    pub slot_count: u16,
+    /// Length of one slot, at least 1 ms
+    pub slot_duration_ms: u32,
+    /// Time at the start and end of a transmit slot in which nothing is sent
+    pub guard_ms: u32,
+    /// Largest number of queued packets sent in one transmit slot, also bounds the
+    /// broadcasts sent in one shared slot
+    pub max_packets_per_slot: u8,
+}
+
+impl Default for SuperframeConfig {
+    fn default() -> Self {
+        Self {
+            slot_count: 32,
+            slot_duration_ms: 100,
+            guard_ms: 10,
+            max_packets_per_slot: 4,
+        }
+    }
+}
+
+impl SuperframeConfig {
+    /// Length of a whole superframe
+    pub fn superframe_ms(&self) -> u64 {
+        self.slot_count as u64 * self.slot_duration_ms as u64
+    }
+
+    /// Guard interval, at most half a slot
+    fn guard(&self) -> u64 {
+        (self.guard_ms as u64).min(self.slot_duration_ms as u64 / 2)
+    }
+}
+
+/// Slots used by one node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SlotAssignment {
+    /// Slot in which this node transmits
+    pub tx_slot: u16,
+    /// Bit `i` is set if the node listens in slot `i`
+    pub listen_mask: u64,
+}
+
+impl SlotAssignment {
+    /// Default assignment derived from the node ID
+    ///
+    /// The transmit slot is spread over all slots except `SHARED_SLOT`, and the node
+    /// listens in `SHARED_SLOT` only. Unicast packets from other nodes are missed
+    /// until their transmit slots are added with `with_neighbor` or `with_listen_slot`.
+    pub fn from_node_id(node_id: u16, config: &SuperframeConfig) -> Self {
+        Self {
+            tx_slot: Self::default_tx_slot(node_id, config),
+            listen_mask: 1 << SHARED_SLOT,
+        }
+    }
+
+    /// Transmit slot of the default assignment of `node_id`
+    pub fn default_tx_slot(node_id: u16, config: &SuperframeConfig) -> u16 {
+        let usable = config.slot_count.clamp(MIN_SLOTS, MAX_SLOTS) - 1;
+        SHARED_SLOT + 1 + node_id % usable
+    }
+
+    /// Also listen in `slot`, slots outside the superframe are ignored
+    pub fn with_listen_slot(mut self, slot: u16, config: &SuperframeConfig) -> Self {
+        if slot < config.slot_count.clamp(MIN_SLOTS, MAX_SLOTS) {
+            self.listen_mask |= 1 << slot;
+        }
+        self
+    }
+
+    /// Also listen in the default transmit slot of a neighbor, the parent node in
+    /// particular, to receive its unicast packets
+    pub fn with_neighbor(self, node_id: u16, config: &SuperframeConfig) -> Self {
+        self.with_listen_slot(Self::default_tx_slot(node_id, config), config)
+    }
+
+    /// Check whether the node listens in `slot`
+    pub fn listens_in(&self, slot: u16) -> bool {
+        slot < MAX_SLOTS && self.listen_mask & (1 << slot) != 0
+    }
+}
+
+/// What the radio should do during a part of a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum SlotActivity {
+    /// Send queued packets
+    Transmit,
+    /// Send queued broadcasts in the shared slot once the channel is clear, listen
+    /// otherwise
+    Contend,
+    /// Keep the receiver enabled
+    Listen,
+    /// Put the radio to sleep
+    Sleep,
+}
+
+/// Activity at a point in time and when it ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SlotAction {
+    /// What the radio should do
+    pub activity: SlotActivity,
+    /// Slot the point in time belongs to
+    pub slot: u16,
+    /// Network time at which the activity ends
+    pub until_ms: u64,
+}
+
+/// Superframe schedule of one node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct TdmaSchedule {
+    config: SuperframeConfig,
+    assignment: SlotAssignment,
+}
+
+impl TdmaSchedule {
+    /// Create a schedule, slot counts outside `MIN_SLOTS..=MAX_SLOTS` and zero slot
+    /// durations are clamped
+    pub fn new(config: SuperframeConfig, assignment: SlotAssignment) -> Self {
+        let config = SuperframeConfig {
+            slot_count: config.slot_count.clamp(MIN_SLOTS, MAX_SLOTS),
+            slot_duration_ms: config.slot_duration_ms.max(1),
+            ..config
+        };
+        Self { config, assignment }
+    }
+
+    /// Create a schedule with the default slot assignment for `node_id`
+    pub fn for_node(node_id: u16, config: SuperframeConfig) -> Self {
+        Self::new(config, SlotAssignment::from_node_id(node_id, &config))
+    }
+
+    /// Superframe timing
+    pub fn config(&self) -> &SuperframeConfig {
+        &self.config
+    }
+
+    /// Slots used by this node
+    pub fn assignment(&self) -> &SlotAssignment {
+        &self.assignment
+    }
+
+    /// Decide what the radio should do at network time `now_ms`
+    pub fn action_at(&self, now_ms: u64) -> SlotAction {
+        let slot_duration = self.config.slot_duration_ms as u64;
+        let guard = self.config.guard();
+        let in_superframe = now_ms % self.config.superframe_ms();
+        let slot = (in_superframe / slot_duration) as u16;
+        let slot_start = now_ms - in_superframe % slot_duration;
+        let slot_end = slot_start + slot_duration;
+
+        if slot == self.assignment.tx_slot {
+            // Awake for the whole owned slot, receiving acks outside the send window
+            let send_start = slot_start + guard;
+            let send_end = slot_end - guard;
+            let (activity, until_ms) = if now_ms < send_start {
+                (SlotActivity::Listen, send_start)
+            } else if now_ms < send_end {
+                (SlotActivity::Transmit, send_end)
+            } else {
+                (SlotActivity::Listen, slot_end)
+            };
+            return SlotAction {
+                activity,
+                slot,
+                until_ms,
+            };
+        }
+
+        if slot == SHARED_SLOT {
+            let send_start = slot_start + guard;
+            let send_end = slot_end - guard;
+            let (activity, until_ms) = if now_ms < send_start {
+                (SlotActivity::Listen, send_start)
+            } else if now_ms < send_end {
+                (SlotActivity::Contend, send_end)
+            } else {
+                (SlotActivity::Listen, slot_end)
+            };
+            return SlotAction {
+                activity,
+                slot,
+                until_ms,
+            };
+        }
+
+        let activity = if self.assignment.listens_in(slot) {
+            SlotActivity::Listen
+        } else {
+            SlotActivity::Sleep
+        };
+        SlotAction {
+            activity,
+            slot,
+            until_ms: slot_end,
+        }
+    }
+
+    /// Start of the next transmit window at or after `now_ms`
+    pub fn next_transmit_ms(&self, now_ms: u64) -> u64 {
+        let superframe = self.config.superframe_ms();
+        let guard = self.config.guard();
+        let offset = self.assignment.tx_slot as u64 * self.config.slot_duration_ms as u64 + guard;
+        let frame_start = now_ms - now_ms % superframe;
+        if self.action_at(now_ms).activity == SlotActivity::Transmit {
+            now_ms
+        } else if frame_start + offset > now_ms {
+            frame_start + offset
+        } else {
+            frame_start + superframe + offset
+        }
+    }
+}
+
+/// Radio power state managed by the MAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+enum RadioState {
+    Asleep,
+    Awake,
+}
+
+/// MAC counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct TdmaStats {
+    /// Packets sent in owned slots
+    pub transmitted: u32,
+    /// Broadcasts sent in the shared slot
+    pub broadcasts: u32,
+    /// Shared slot send attempts deferred because the channel was busy
+    pub busy_deferrals: u32,
+    /// Packets received while listening
+    pub received: u32,
+    /// Packets rejected because the transmit queue was full
+    pub queue_overflows: u32,
+    /// Radio wake-ups
+    pub wakeups: u32,
+}
+
+/// Duty-cycled MAC driving a radio transceiver
+///
+/// # Type Parameters
+/// * `R` - Radio transceiver implementation
+/// * `QUEUE` - Capacity of the unicast and broadcast transmit queues and the receive
+///   inbox
+pub struct TdmaMac<R: RadioTransceiver, const QUEUE: usize> {
+    radio: R,
+    schedule: TdmaSchedule,
+    tx_queue: Deque<Packet, QUEUE>,
+    broadcast_queue: Deque<Packet, QUEUE>,
+    inbox: Deque<Packet, QUEUE>,
+    state: RadioState,
+    rng: XorShift32,
+    /// Next send attempt in the shared slot and the end of its send window
+    contention: Option<Contention>,
+    stats: TdmaStats,
+}
+
+/// Progress of this node in the current shared slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Contention {
+    /// End of the send window of the shared slot
+    window_end_ms: u64,
+    /// Time of the next send attempt
+    attempt_ms: u64,
+    /// Broadcasts sent in this shared slot
+    sent: u8,
+}
+
+impl<R: RadioTransceiver, const QUEUE: usize> TdmaMac<R, QUEUE> {
+    /// Create a MAC for an initialized, awake radio
+    ///
+    /// `seed` (usually the node ID) decorrelates the shared slot access of neighbors.
+    pub fn new(radio: R, schedule: TdmaSchedule, seed: u32) -> Self {
+        Self {
+            radio,
+            schedule,
+            tx_queue: Deque::new(),
+            broadcast_queue: Deque::new(),
+            inbox: Deque::new(),
+            state: RadioState::Awake,
+            rng: XorShift32::new(seed),
+            contention: None,
+            stats: TdmaStats::default(),
+        }
+    }
+
+    /// Access the wrapped radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Active schedule
+    pub fn schedule(&self) -> &TdmaSchedule {
+        &self.schedule
+    }
+
+    /// MAC counters
+    pub fn stats(&self) -> &TdmaStats {
+        &self.stats
+    }
+
+    /// Number of unicast packets waiting for a transmit slot
+    pub fn queued(&self) -> usize {
+        self.tx_queue.len()
+    }
+
+    /// Number of broadcasts waiting for the shared slot
+    pub fn queued_broadcasts(&self) -> usize {
+        self.broadcast_queue.len()
+    }
+
+    /// Queue a packet for the next owned slot, or the next shared slot if it is a
+    /// broadcast
+    ///
+    /// # Returns
+    /// * `Ok(())` if the packet was queued
+    /// * `Err(RadioError::BufferError)` if the queue is full
+    pub fn enqueue(&mut self, packet: Packet) -> Result<(), RadioError> {
+        let queue = if packet.header.target_id == BROADCAST_ID {
+            &mut self.broadcast_queue
+        } else {
+            &mut self.tx_queue
+        };
+        queue.push_back(packet).map_err(|_| {
+            self.stats.queue_overflows += 1;
+            RadioError::BufferError
+        })
+    }
+
+    /// Take the next packet received while listening
+    pub fn receive(&mut self) -> Option<Packet> {
+        self.inbox.pop_front()
+    }
+
+    /// Perform the radio work for network time `now_ms`
+    ///
+    /// # Returns
+    /// Network time at which `step` should be called again
+    pub async fn step(&mut self, now_ms: u64) -> Result<u64, RadioError> {
+        let action = self.schedule.action_at(now_ms);
+        match action.activity {
+            SlotActivity::Sleep => {
+                if self.state == RadioState::Awake {
+                    terminal_log!(trace, "TDMA sleeping until {}", action.until_ms);
+                    self.radio.set_enabled(false).await?;
+                    self.radio.sleep().await?;
+                    self.state = RadioState::Asleep;
+                }
+                Ok(action.until_ms)
+            }
+            SlotActivity::Listen => {
+                self.ensure_listening().await?;
+                self.drain_receiver().await;
+                Ok(action.until_ms.min(now_ms + LISTEN_POLL_INTERVAL_MS))
+            }
+            SlotActivity::Contend => {
+                self.ensure_listening().await?;
+                self.drain_receiver().await;
+                let poll_ms = action.until_ms.min(now_ms + LISTEN_POLL_INTERVAL_MS);
+                Ok(match self.contend(now_ms, action.until_ms).await? {
+                    Some(attempt_ms) => poll_ms.min(attempt_ms.max(now_ms + 1)),
+                    None => poll_ms,
+                })
+            }
+            SlotActivity::Transmit => {
+                self.ensure_listening().await?;
+                let budget = self.schedule.config().max_packets_per_slot;
+                for _ in 0..budget {
+                    let Some(packet) = self.tx_queue.pop_front() else {
+                        break;
+                    };
+                    if let Err(e) = self.radio.transmit(&packet).await {
+                        terminal_log!(warn, "TDMA transmission failed: {:?}", e);
+                        // Keep the packet for the next slot
+                        let _ = self.tx_queue.push_front(packet);
+                        return Err(e);
+                    }
+                    self.stats.transmitted += 1;
+                }
+                self.drain_receiver().await;
+                // Nothing left to send in this slot, listen for acks until it ends
+                Ok(action.until_ms)
+            }
+        }
+    }
+
+    /// Run the MAC forever
+    ///
+    /// # Arguments
+    /// * `network_time_ms` - Source of the current network time
+    pub async fn run(&mut self, network_time_ms: impl Fn() -> u64) -> ! {
+        loop {
+            let now_ms = network_time_ms();
+            let next_ms = match self.step(now_ms).await {
+                Ok(next_ms) => next_ms,
+                Err(e) => {
+                    terminal_log!(warn, "TDMA step failed: {:?}", e);
+                    now_ms + LISTEN_POLL_INTERVAL_MS
+                }
+            };
+            Timer::after_millis(next_ms.saturating_sub(now_ms)).await;
+        }
+    }
+
+    /// Send a queued broadcast if the random send time in the shared slot has come
+    ///
+    /// # Returns
+    /// Time of the next send attempt, `None` if nothing more is sent in this slot
+    async fn contend(
+        &mut self,
+        now_ms: u64,
+        window_end_ms: u64,
+    ) -> Result<Option<u64>, RadioError> {
+        let contention = match self.contention {
+            Some(contention) if contention.window_end_ms == window_end_ms => contention,
+            _ => Contention {
+                window_end_ms,
+                attempt_ms: self.draw_attempt(now_ms, window_end_ms),
+                sent: 0,
+            },
+        };
+        self.contention = Some(contention);
+        if self.broadcast_queue.is_empty()
+            || contention.sent >= self.schedule.config().max_packets_per_slot
+        {
+            return Ok(None);
+        }
+        if now_ms < contention.attempt_ms {
+            return Ok(Some(contention.attempt_ms));
+        }
+
+        let attempt_ms = self.draw_attempt(now_ms, window_end_ms);
+        let mut next = Contention {
+            attempt_ms,
+            ..contention
+        };
+        if self.radio.is_channel_busy() {
+            self.stats.busy_deferrals += 1;
+            self.contention = Some(next);
+            return Ok(Some(attempt_ms));
+        }
+        if let Some(packet) = self.broadcast_queue.pop_front() {
+            if let Err(e) = self.radio.transmit(&packet).await {
+                terminal_log!(warn, "TDMA broadcast failed: {:?}", e);
+                // Keep the packet for the next shared slot
+                let _ = self.broadcast_queue.push_front(packet);
+                self.contention = Some(next);
+                return Err(e);
+            }
+            self.stats.broadcasts += 1;
+            next.sent += 1;
+        }
+        self.contention = Some(next);
+        Ok(Some(attempt_ms))
+    }
+
+    /// Random point in time between `now_ms` and the end of the send window
+    fn draw_attempt(&mut self, now_ms: u64, window_end_ms: u64) -> u64 {
+        let remaining = window_end_ms.saturating_sub(now_ms).min(u32::MAX as u64) as u32;
+        now_ms + self.rng.below(remaining) as u64
+    }
+
+    async fn ensure_listening(&mut self) -> Result<(), RadioError> {
+        if self.state == RadioState::Asleep {
+            self.radio.wake().await?;
+            self.state = RadioState::Awake;
+            self.stats.wakeups += 1;
+        }
+        if !self.radio.is_enabled() {
+            self.radio.set_enabled(true).await?;
+        }
+        Ok(())
+    }
+
+    async fn drain_receiver(&mut self) {
+        while self.radio.packet_available() {
+            match self.radio.receive().await {
+                Ok(packet) => {
+                    self.stats.received += 1;
+                    if self.inbox.push_back(packet).is_err() {
+                        terminal_log!(warn, "TDMA inbox full, dropping packet");
+                    }
+                }
+                Err(e) => {
+                    terminal_log!(debug, "TDMA receive failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
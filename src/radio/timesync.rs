@@ -0,0 +1,448 @@
+/// Swarm-wide time synchronization
+/// An FTSP-style protocol: the node with the lowest ID acts as time reference (root)
+/// and periodically broadcasts its clock. A node elects itself root once it heard no
+/// node with a lower ID for `ROOT_TIMEOUT_ROUNDS` intervals, until then and until it
+/// synchronized to a lower-ID root it has no network time. Every synchronized node rebroadcasts its
+/// own estimate of the root clock once per round, so the reference floods through
+/// the swarm hop by hop. Each node timestamps received sync messages with its local
+/// clock and fits a linear regression over the recent `(local time, offset)` pairs,
+/// estimating both the offset to the root and the relative clock drift.
+///
+/// Sync messages are sent as `radio::message::Message::TimeSync`, body layout
+/// (little-endian): `root_id: u16 | round: u16 | depth: u8 | global_time_ms: u64`
+///
+/// The instance driving the radio is created `with_published_clock`, so every change
+/// of its estimate reaches `NETWORK_CLOCK` and with it `stamp_reading`.
+use super::message::Message;
+use super::protocol::{Packet, BROADCAST_ID};
+use crate::sensors::traits::EnvironmentalData;
+use crate::terminal_log;
+use core::cell::Cell;
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Instant;
+use heapless::HistoryBuffer;
+
+/// Size of an encoded time sync body
+pub const TIMESYNC_SIZE: usize = 13;
+
+/// Default interval between sync broadcasts
+pub const DEFAULT_SYNC_INTERVAL_MS: u64 = 10_000;
+
+/// Timestamping uncertainty added by every hop
+///
+/// Packets are stamped in software right before transmission and after reception,
+/// so interrupt and driver latency end up in the estimate.
+pub const PER_HOP_ERROR_MS: u32 = 2;
+
+/// Worst-case crystal drift assumed between two regression updates
+pub const MAX_DRIFT_PPM: u32 = 100;
+
+/// Number of sync points kept for the regression
+const REGRESSION_POINTS: usize = 8;
+
+/// Sync points needed before a non-root node considers itself synchronized
+const MIN_SYNC_POINTS: usize = 3;
+
+/// Rounds without news from the root after which a node takes over as root
+pub const ROOT_TIMEOUT_ROUNDS: u64 = 6;
+
+/// Time sync broadcast
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct TimeSyncMessage {
+    /// Node acting as time reference
+    pub root_id: u16,
+    /// Sync round started by the root
+    pub round: u16,
+    /// Hops between the sender and the root
+    pub depth: u8,
+    /// Sender's estimate of the root clock at transmission
+    pub global_time_ms: u64,
+}
+
+impl TimeSyncMessage {
+    /// Encode the message body
+    pub fn to_bytes(&self) -> [u8; TIMESYNC_SIZE] {
+        let mut body = [0u8; TIMESYNC_SIZE];
+        body[0..2].copy_from_slice(&self.root_id.to_le_bytes());
+        body[2..4].copy_from_slice(&self.round.to_le_bytes());
+        body[4] = self.depth;
+        body[5..13].copy_from_slice(&self.global_time_ms.to_le_bytes());
+        body
+    }
+
+    /// Decode a message body
+    pub fn from_bytes(body: &[u8]) -> Option<Self> {
+        let body = body.get(..TIMESYNC_SIZE)?;
+        let mut global_time = [0u8; 8];
+        global_time.copy_from_slice(&body[5..13]);
+        Some(Self {
+            root_id: u16::from_le_bytes([body[0], body[1]]),
+            round: u16::from_le_bytes([body[2], body[3]]),
+            depth: body[4],
+            global_time_ms: u64::from_le_bytes(global_time),
+        })
+    }
+
+    /// Build the broadcast packet carrying this message
+    ///
+    /// Sync messages are re-originated by every node, so they are never relayed.
+    pub fn to_packet(&self, sender_id: u16, sequence_number: u16) -> Packet {
+        let mut packet =
+            Message::TimeSync(*self).to_packet(sender_id, BROADCAST_ID, sequence_number);
+        packet.header.ttl = 0;
+        packet
+    }
+
+    /// Extract a time sync message from a received packet
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        match Message::from_packet(packet) {
+            Ok(Message::TimeSync(message)) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// Mapping from the local clock to network time
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct NetworkClock {
+    /// Local time the regression is centered on
+    local_ref_ms: u64,
+    /// Network time minus local time at `local_ref_ms`
+    offset_ms: f64,
+    /// Drift of the root clock relative to the local clock
+    skew: f64,
+    /// Error of the estimate right after the last update
+    base_error_ms: f64,
+    /// Drift assumed for the time since the last update
+    drift_ppm: u32,
+    /// Local time of the last update
+    updated_ms: u64,
+}
+
+impl NetworkClock {
+    /// Clock of the root node, network time is its local time
+    const fn reference() -> Self {
+        Self {
+            local_ref_ms: 0,
+            offset_ms: 0.0,
+            skew: 0.0,
+            base_error_ms: 0.0,
+            drift_ppm: 0,
+            updated_ms: 0,
+        }
+    }
+
+    /// Convert a local timestamp to network time
+    pub fn network_time_ms(&self, local_ms: u64) -> u64 {
+        let elapsed = local_ms as f64 - self.local_ref_ms as f64;
+        let global = local_ms as f64 + self.offset_ms + self.skew * elapsed;
+        if global <= 0.0 {
+            0
+        } else {
+            (global + 0.5) as u64
+        }
+    }
+
+    /// Upper bound of the difference between `network_time_ms` and the root clock
+    ///
+    /// Made of the regression residuals, the timestamping error of every hop to the
+    /// root and the worst-case drift since the last update.
+    pub fn error_bound_ms(&self, local_ms: u64) -> u32 {
+        let since_update = local_ms.saturating_sub(self.updated_ms) as f64;
+        let drift = since_update * self.drift_ppm as f64 / 1_000_000.0;
+        let bound = self.base_error_ms + drift;
+        // Round up, the bound must never be optimistic
+        let whole = bound as u32;
+        if (whole as f64) < bound {
+            whole + 1
+        } else {
+            whole
+        }
+    }
+}
+
+/// Sync point used for the regression
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SyncPoint {
+    local_ms: u64,
+    /// Network time minus local time
+    offset_ms: i64,
+}
+
+/// Time synchronization state machine
+pub struct TimeSync {
+    node_id: u16,
+    interval_ms: u64,
+    root_id: u16,
+    round: u16,
+    depth: u8,
+    points: HistoryBuffer<SyncPoint, REGRESSION_POINTS>,
+    clock: Option<NetworkClock>,
+    last_root_heard_ms: u64,
+    last_sent_round: Option<u16>,
+    next_send_ms: u64,
+    publish: bool,
+}
+
+impl TimeSync {
+    /// Create the time sync state for a node
+    ///
+    /// The node starts as its own root candidate and defers to any node with a lower
+    /// ID. It has no network time until it is elected root, `ROOT_TIMEOUT_ROUNDS`
+    /// intervals after local time 0 without hearing a lower ID, or synchronized.
+    pub fn new(node_id: u16, interval_ms: u64) -> Self {
+        Self {
+            node_id,
+            interval_ms,
+            root_id: node_id,
+            round: 0,
+            depth: 0,
+            points: HistoryBuffer::new(),
+            clock: None,
+            last_root_heard_ms: 0,
+            last_sent_round: None,
+            next_send_ms: 0,
+            publish: false,
+        }
+    }
+
+    /// Publish every clock update to `NETWORK_CLOCK`, starting with the current one
+    pub fn with_published_clock(mut self) -> Self {
+        self.publish = true;
+        publish_clock(self.clock);
+        self
+    }
+
+    /// Node currently acting as time reference
+    pub fn root_id(&self) -> u16 {
+        self.root_id
+    }
+
+    /// Check whether this node is the time reference
+    pub fn is_root(&self) -> bool {
+        self.root_id == self.node_id
+    }
+
+    /// Hops between this node and the root
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Current clock estimate, `None` until the node was elected root or enough sync
+    /// messages arrived
+    pub fn clock(&self) -> Option<NetworkClock> {
+        self.clock
+    }
+
+    /// Convert a local timestamp to network time
+    pub fn network_time_ms(&self, local_ms: u64) -> Option<u64> {
+        self.clock.map(|clock| clock.network_time_ms(local_ms))
+    }
+
+    /// Bound of the network time error at the given local time
+    pub fn error_bound_ms(&self, local_ms: u64) -> Option<u32> {
+        self.clock.map(|clock| clock.error_bound_ms(local_ms))
+    }
+
+    /// Process a received sync message
+    ///
+    /// # Arguments
+    /// * `message` - Received sync message
+    /// * `local_rx_ms` - Local time at which the packet was received
+    ///
+    /// # Returns
+    /// `true` if the message was used to update the clock estimate
+    pub fn on_message(&mut self, message: &TimeSyncMessage, local_rx_ms: u64) -> bool {
+        self.check_root_timeout(local_rx_ms);
+
+        if message.root_id > self.root_id || message.root_id == self.node_id {
+            return false;
+        }
+        if message.root_id < self.root_id {
+            terminal_log!(info, "Time sync root is now {}", message.root_id);
+            self.root_id = message.root_id;
+            self.points.clear();
+            self.set_clock(None);
+            self.last_sent_round = None;
+        } else if message.round.wrapping_sub(self.round) as i16 <= 0 && !self.points.is_empty() {
+            // Already have this round, possibly through a shorter path
+            return false;
+        }
+
+        self.round = message.round;
+        self.depth = message.depth.saturating_add(1);
+        self.last_root_heard_ms = local_rx_ms;
+        self.points.write(SyncPoint {
+            local_ms: local_rx_ms,
+            offset_ms: message.global_time_ms as i64 - local_rx_ms as i64,
+        });
+        self.update_clock(local_rx_ms);
+        true
+    }
+
+    /// Produce the next sync broadcast if one is due
+    ///
+    /// The message carries the network time at `local_now_ms`, so it should be
+    /// transmitted right away. The latency until the frame is on air is part of
+    /// `PER_HOP_ERROR_MS`.
+    pub fn poll(&mut self, local_now_ms: u64) -> Option<TimeSyncMessage> {
+        self.check_root_timeout(local_now_ms);
+        self.check_election(local_now_ms);
+        if self.clock.is_none() || local_now_ms < self.next_send_ms {
+            return None;
+        }
+        self.next_send_ms = local_now_ms + self.interval_ms;
+
+        if self.is_root() {
+            self.round = self.round.wrapping_add(1);
+        } else if self.last_sent_round == Some(self.round) {
+            return None;
+        }
+        let global_time_ms = self.network_time_ms(local_now_ms)?;
+        self.last_sent_round = Some(self.round);
+
+        Some(TimeSyncMessage {
+            root_id: self.root_id,
+            round: self.round,
+            depth: self.depth,
+            global_time_ms,
+        })
+    }
+
+    /// Take over as root when the current root has gone silent
+    fn check_root_timeout(&mut self, local_now_ms: u64) {
+        let timeout = self.interval_ms * ROOT_TIMEOUT_ROUNDS;
+        if self.is_root() || local_now_ms.saturating_sub(self.last_root_heard_ms) <= timeout {
+            return;
+        }
+        terminal_log!(warn, "Time sync root {} lost, taking over", self.root_id);
+        self.root_id = self.node_id;
+        self.depth = 0;
+        self.points.clear();
+        // Keep the previous estimate as the new reference so network time stays continuous
+        let mut clock = self.clock.unwrap_or(NetworkClock::reference());
+        clock.base_error_ms = 0.0;
+        clock.drift_ppm = 0;
+        clock.updated_ms = local_now_ms;
+        self.set_clock(Some(clock));
+        self.last_sent_round = None;
+    }
+
+    /// Start acting as root once no node with a lower ID was heard for the root timeout
+    fn check_election(&mut self, local_now_ms: u64) {
+        let timeout = self.interval_ms * ROOT_TIMEOUT_ROUNDS;
+        if !self.is_root()
+            || self.clock.is_some()
+            || local_now_ms.saturating_sub(self.last_root_heard_ms) <= timeout
+        {
+            return;
+        }
+        terminal_log!(info, "No lower time sync root heard, acting as root");
+        self.set_clock(Some(NetworkClock {
+            updated_ms: local_now_ms,
+            ..NetworkClock::reference()
+        }));
+    }
+
+    /// Replace the clock estimate, publishing it if requested
+    fn set_clock(&mut self, clock: Option<NetworkClock>) {
+        self.clock = clock;
+        if self.publish {
+            publish_clock(clock);
+        }
+    }
+
+    /// Fit the regression over the stored sync points
+    fn update_clock(&mut self, local_now_ms: u64) {
+        let count = self.points.len();
+        if count < MIN_SYNC_POINTS {
+            return;
+        }
+
+        // Center on the first point to keep the floating point values small
+        let points = self.points.as_slice();
+        let base_local = points[0].local_ms as f64;
+        let base_offset = points[0].offset_ms as f64;
+        let n = count as f64;
+
+        let mean_x = points
+            .iter()
+            .map(|p| p.local_ms as f64 - base_local)
+            .sum::<f64>()
+            / n;
+        let mean_y = points
+            .iter()
+            .map(|p| p.offset_ms as f64 - base_offset)
+            .sum::<f64>()
+            / n;
+
+        let mut covariance = 0.0;
+        let mut variance = 0.0;
+        for point in points {
+            let dx = point.local_ms as f64 - base_local - mean_x;
+            let dy = point.offset_ms as f64 - base_offset - mean_y;
+            covariance += dx * dy;
+            variance += dx * dx;
+        }
+        let skew = if variance > 0.0 {
+            covariance / variance
+        } else {
+            0.0
+        };
+
+        let mut max_residual: f64 = 0.0;
+        for point in points {
+            let dx = point.local_ms as f64 - base_local - mean_x;
+            let dy = point.offset_ms as f64 - base_offset - mean_y;
+            let residual = dy - skew * dx;
+            let residual = if residual < 0.0 { -residual } else { residual };
+            max_residual = max_residual.max(residual);
+        }
+
+        let clock = NetworkClock {
+            local_ref_ms: (base_local + mean_x) as u64,
+            offset_ms: base_offset + mean_y,
+            skew,
+            base_error_ms: max_residual + (self.depth as u32 * PER_HOP_ERROR_MS) as f64,
+            drift_ppm: MAX_DRIFT_PPM,
+            updated_ms: local_now_ms,
+        };
+        terminal_log!(
+            debug,
+            "Time sync updated, skew {} ppm, error bound {} ms",
+            (skew * 1_000_000.0) as i32,
+            clock.error_bound_ms(local_now_ms)
+        );
+        self.set_clock(Some(clock));
+    }
+}
+
+/// Network clock of this node, published by the time sync task
+pub static NETWORK_CLOCK: Mutex<CriticalSectionRawMutex, Cell<Option<NetworkClock>>> =
+    Mutex::new(Cell::new(None));
+
+/// Publish the latest clock estimate for application code
+///
+/// Called by `TimeSync` instances created `with_published_clock`.
+pub fn publish_clock(clock: Option<NetworkClock>) {
+    NETWORK_CLOCK.lock(|shared| shared.set(clock));
+}
+
+/// Current network time, `None` while the node is not synchronized
+pub fn network_time_ms() -> Option<u64> {
+    let local_ms = Instant::now().as_millis();
+    NETWORK_CLOCK.lock(|shared| shared.get().map(|clock| clock.network_time_ms(local_ms)))
+}
+
+/// Timestamp a sensor reading taken at `local_ms`
+///
+/// Network time is used once the node is synchronized, local uptime otherwise.
+/// `validity.timestamp_synced()` tells which one was used.
+pub fn stamp_reading(data: &mut EnvironmentalData, local_ms: u64) {
+    match NETWORK_CLOCK.lock(|shared| shared.get()) {
+        Some(clock) => data.set_timestamp_ms(clock.network_time_ms(local_ms), true),
+        None => data.set_timestamp_ms(local_ms, false),
+    }
+}
@@ -2,12 +2,9 @@
 // This module defines generic, hardware-agnostic traits for radio communication
 // TODO: Implement concrete radio hardware drivers for 433MHz OOK communication
 // - Create implementation for specific 433MHz radio modules (e.g., RFM69, CC1101)
-// - Implement Manchester coding/decoding as per project requirements
-// - Add Reed-Solomon error correction integration
-// - Implement packet acknowledgment system
-// - Add network topology and routing functionality
+// - Integrate radio::manchester line coding and radio::fec error correction into drivers
 
-use super::protocol::Packet;
+use super::protocol::{DecodeError, Packet};
 use defmt::Format;
 
 /// Error types for radio communication operations
@@ -23,6 +20,12 @@ pub enum RadioError {
     NotReady,
     /// Invalid packet format or size
     InvalidPacket,
+    /// Frame integrity check failed (frame was corrupted on air)
+    ChecksumMismatch,
+    /// Forward error correction could not repair the frame
+    Uncorrectable,
+    /// Frame was rejected by the security layer (forged, replayed or not secured)
+    AuthenticationFailed,
     /// Timeout occurred during operation
     Timeout,
     /// Radio module is busy with another operation
@@ -35,6 +38,12 @@ pub enum RadioError {
     HardwareError,
 }
 
+impl From<DecodeError> for RadioError {
+    fn from(_: DecodeError) -> Self {
+        RadioError::InvalidPacket
+    }
+}
+
 /// Generic trait for radio transmission functionality
 ///
 /// This trait abstracts the hardware-specific details of radio transmission,
@@ -134,6 +143,19 @@ pub trait RadioReceiver {
     /// # Returns
     /// * Signal strength in dBm, or None if no packet has been received
     fn get_rssi(&self) -> Option<i16>;
+
+    /// Check if another node is currently transmitting (carrier sense)
+    ///
+    /// # Returns
+    /// * `true` if activity was detected on the channel
+    /// * `false` if the channel appears to be clear
+    ///
+    /// # Notes
+    /// The default implementation only treats a pending received packet as activity.
+    /// Radios that can measure the instantaneous signal level should override it.
+    fn is_channel_busy(&self) -> bool {
+        self.packet_available()
+    }
 }
 
 /// Combined trait for full-duplex radio communication
@@ -0,0 +1,392 @@
+/// Priority transmit queue
+/// Several producers (application, routing, command shell) hand outgoing packets to a
+/// single task owning the radio through an embassy `Channel`. The task keeps them in
+/// a fixed-capacity queue with two lanes: packets flagged with
+/// `PacketControl::emergency` are always sent before routine ones, each lane in
+/// arrival order.
+///
+/// Routine packets waiting longer than `TxQueueConfig::max_wait_ms` are handled by the
+/// `AgingPolicy`: kept, dropped, or promoted into the emergency lane behind the
+/// emergency packets already waiting, so a stream of alarms cannot starve them
+/// forever. When the queue is full an emergency packet replaces the oldest routine
+/// packet, a routine packet is dropped.
+///
+/// Queue depth, drops and wait times are published in `TX_QUEUE_STATS`.
+use super::protocol::Packet;
+use super::traits::RadioTransmitter;
+use crate::terminal_log;
+use core::cell::Cell;
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_time::Instant;
+use heapless::Deque;
+
+/// Default capacity of the channel between producers and the transmit task
+pub const TX_CHANNEL_DEPTH: usize = 8;
+
+/// Default time a routine packet may wait before the aging policy applies
+pub const DEFAULT_MAX_WAIT_MS: u64 = 30_000;
+
+/// Packet handed to the transmit task
+#[derive(Debug, Clone, PartialEq, Eq, Format)]
+pub struct TxRequest {
+    /// Packet to transmit, its emergency flag selects the lane
+    pub packet: Packet,
+    /// Time the packet was submitted, wait times are measured from here
+    pub submitted_ms: u64,
+}
+
+impl TxRequest {
+    /// Create a request submitted at `submitted_ms`
+    pub fn new(packet: Packet, submitted_ms: u64) -> Self {
+        Self {
+            packet,
+            submitted_ms,
+        }
+    }
+
+    /// Create a request submitted now
+    pub fn now(packet: Packet) -> Self {
+        Self::new(packet, Instant::now().as_millis())
+    }
+
+    /// Check whether the packet belongs into the emergency lane
+    pub fn is_emergency(&self) -> bool {
+        self.packet.header.control.is_emergency()
+    }
+}
+
+/// Channel producers use to hand packets to the transmit task
+pub type TxChannel<const N: usize> = Channel<CriticalSectionRawMutex, TxRequest, N>;
+
+/// Treatment of routine packets that waited longer than `TxQueueConfig::max_wait_ms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum AgingPolicy {
+    /// Keep waiting behind emergency packets
+    Keep,
+    /// Drop the packet, its data is outdated
+    Drop,
+    /// Move the packet into the emergency lane
+    Promote,
+}
+
+/// Transmit queue settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct TxQueueConfig {
+    /// Time a routine packet may wait before the aging policy applies
+    pub max_wait_ms: u64,
+    /// Treatment of routine packets that waited longer than `max_wait_ms`
+    pub aging: AgingPolicy,
+}
+
+impl Default for TxQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_wait_ms: DEFAULT_MAX_WAIT_MS,
+            aging: AgingPolicy::Drop,
+        }
+    }
+}
+
+/// Transmit queue counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct TxQueueStats {
+    /// Packets currently waiting
+    pub depth: u16,
+    /// Largest number of packets waiting at once
+    pub max_depth: u16,
+    /// Packets accepted into the queue
+    pub queued: u32,
+    /// Packets handed to the radio successfully
+    pub sent: u32,
+    /// Emergency packets handed to the radio successfully
+    pub emergency_sent: u32,
+    /// Packets the radio failed to send
+    pub failed: u32,
+    /// Packets dropped because the queue was full
+    pub dropped_full: u32,
+    /// Routine packets dropped by `AgingPolicy::Drop`
+    pub dropped_stale: u32,
+    /// Routine packets moved into the emergency lane by `AgingPolicy::Promote`
+    pub promoted: u32,
+    /// Sum of the wait times of all dequeued packets
+    pub total_wait_ms: u64,
+    /// Longest wait of a routine packet
+    pub max_wait_ms: u64,
+    /// Longest wait of an emergency packet
+    pub max_emergency_wait_ms: u64,
+}
+
+/// Counters before anything was queued, usable in `const` context
+const EMPTY_STATS: TxQueueStats = TxQueueStats {
+    depth: 0,
+    max_depth: 0,
+    queued: 0,
+    sent: 0,
+    emergency_sent: 0,
+    failed: 0,
+    dropped_full: 0,
+    dropped_stale: 0,
+    promoted: 0,
+    total_wait_ms: 0,
+    max_wait_ms: 0,
+    max_emergency_wait_ms: 0,
+};
+
+impl TxQueueStats {
+    /// Packets taken from the queue for transmission
+    pub fn dequeued(&self) -> u32 {
+        self.sent + self.failed
+    }
+
+    /// Average wait time of dequeued packets
+    pub fn mean_wait_ms(&self) -> u64 {
+        match self.dequeued() {
+            0 => 0,
+            count => self.total_wait_ms / count as u64,
+        }
+    }
+
+    /// Packets dropped for any reason
+    pub fn dropped(&self) -> u32 {
+        self.dropped_full + self.dropped_stale
+    }
+}
+
+/// Two-lane packet queue ordering emergency packets first
+///
+/// # Type Parameters
+/// * `N` - Number of packets both lanes hold together
+pub struct PriorityQueue<const N: usize> {
+    emergency: Deque<TxRequest, N>,
+    routine: Deque<TxRequest, N>,
+    config: TxQueueConfig,
+    stats: TxQueueStats,
+}
+
+impl<const N: usize> PriorityQueue<N> {
+    /// Create an empty queue
+    pub const fn new(config: TxQueueConfig) -> Self {
+        Self {
+            emergency: Deque::new(),
+            routine: Deque::new(),
+            config,
+            stats: EMPTY_STATS,
+        }
+    }
+
+    /// Active settings
+    pub fn config(&self) -> &TxQueueConfig {
+        &self.config
+    }
+
+    /// Queue counters
+    pub fn stats(&self) -> &TxQueueStats {
+        &self.stats
+    }
+
+    /// Number of waiting packets
+    pub fn len(&self) -> usize {
+        self.emergency.len() + self.routine.len()
+    }
+
+    /// Check whether no packet is waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of packets waiting in the emergency lane
+    pub fn emergency_len(&self) -> usize {
+        self.emergency.len()
+    }
+
+    /// Add a packet
+    ///
+    /// # Returns
+    /// `false` if the packet was dropped because the queue is full of packets at
+    /// least as important
+    pub fn push(&mut self, request: TxRequest) -> bool {
+        if self.len() >= N {
+            self.stats.dropped_full += 1;
+            if !request.is_emergency() || self.routine.pop_front().is_none() {
+                terminal_log!(warn, "TX queue full, dropping packet");
+                return false;
+            }
+            terminal_log!(warn, "TX queue full, dropping oldest routine packet");
+        }
+
+        let lane = if request.is_emergency() {
+            &mut self.emergency
+        } else {
+            &mut self.routine
+        };
+        // The combined length is below `N`, so is the length of each lane
+        let _ = lane.push_back(request);
+        self.stats.queued += 1;
+        self.update_depth();
+        true
+    }
+
+    /// Apply the aging policy to routine packets that waited too long
+    pub fn age(&mut self, now_ms: u64) {
+        while let Some(oldest) = self.routine.front() {
+            if now_ms.saturating_sub(oldest.submitted_ms) <= self.config.max_wait_ms {
+                break;
+            }
+            match self.config.aging {
+                AgingPolicy::Keep => break,
+                AgingPolicy::Drop => {
+                    if let Some(stale) = self.routine.pop_front() {
+                        terminal_log!(
+                            debug,
+                            "Dropping stale packet {}",
+                            stale.packet.header.sequence_number
+                        );
+                    }
+                    self.stats.dropped_stale += 1;
+                }
+                AgingPolicy::Promote => {
+                    if let Some(stale) = self.routine.pop_front() {
+                        // Both lanes together hold at most `N` packets
+                        let _ = self.emergency.push_back(stale);
+                    }
+                    self.stats.promoted += 1;
+                }
+            }
+        }
+        self.update_depth();
+    }
+
+    /// Take the next packet to transmit at `now_ms`
+    ///
+    /// The aging policy is applied first, the wait time of the returned packet is
+    /// recorded.
+    pub fn pop(&mut self, now_ms: u64) -> Option<TxRequest> {
+        self.age(now_ms);
+        let request = self
+            .emergency
+            .pop_front()
+            .or_else(|| self.routine.pop_front())?;
+
+        let wait_ms = now_ms.saturating_sub(request.submitted_ms);
+        self.stats.total_wait_ms = self.stats.total_wait_ms.saturating_add(wait_ms);
+        let max_wait_ms = if request.is_emergency() {
+            &mut self.stats.max_emergency_wait_ms
+        } else {
+            &mut self.stats.max_wait_ms
+        };
+        *max_wait_ms = (*max_wait_ms).max(wait_ms);
+        self.update_depth();
+        Some(request)
+    }
+
+    /// Record the outcome of transmitting a packet returned by `pop`
+    pub fn record_transmission(&mut self, request: &TxRequest, sent: bool) {
+        match (sent, request.is_emergency()) {
+            (true, true) => {
+                self.stats.sent += 1;
+                self.stats.emergency_sent += 1;
+            }
+            (true, false) => self.stats.sent += 1,
+            (false, _) => self.stats.failed += 1,
+        }
+    }
+
+    fn update_depth(&mut self) {
+        self.stats.depth = self.len() as u16;
+        self.stats.max_depth = self.stats.max_depth.max(self.stats.depth);
+    }
+}
+
+/// Task owning the radio and sending queued packets in priority order
+///
+/// # Type Parameters
+/// * `R` - Radio used for transmission
+/// * `N` - Capacity of the priority queue
+pub struct TransmitQueue<R, const N: usize> {
+    radio: R,
+    queue: PriorityQueue<N>,
+}
+
+impl<R: RadioTransmitter, const N: usize> TransmitQueue<R, N> {
+    /// Create the transmit task for an initialized radio
+    pub fn new(radio: R, config: TxQueueConfig) -> Self {
+        Self {
+            radio,
+            queue: PriorityQueue::new(config),
+        }
+    }
+
+    /// Access the owned radio
+    pub fn radio(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    /// Waiting packets
+    pub fn queue(&self) -> &PriorityQueue<N> {
+        &self.queue
+    }
+
+    /// Queue counters
+    pub fn stats(&self) -> &TxQueueStats {
+        self.queue.stats()
+    }
+
+    /// Move all submitted packets from the channel into the queue
+    pub fn collect<const C: usize>(&mut self, channel: &TxChannel<C>) {
+        while let Ok(request) = channel.try_receive() {
+            self.queue.push(request);
+        }
+    }
+
+    /// Collect submitted packets and transmit the most important one
+    ///
+    /// # Returns
+    /// `true` if a packet was taken from the queue
+    pub async fn step<const C: usize>(&mut self, channel: &TxChannel<C>, now_ms: u64) -> bool {
+        self.collect(channel);
+        let Some(request) = self.queue.pop(now_ms) else {
+            publish_stats(self.queue.stats());
+            return false;
+        };
+
+        let result = self.radio.transmit(&request.packet).await;
+        if let Err(e) = &result {
+            terminal_log!(
+                warn,
+                "Transmission of packet {} failed: {:?}",
+                request.packet.header.sequence_number,
+                e
+            );
+        }
+        self.queue.record_transmission(&request, result.is_ok());
+        publish_stats(self.queue.stats());
+        true
+    }
+
+    /// Send submitted packets forever
+    pub async fn run<const C: usize>(&mut self, channel: &TxChannel<C>) -> ! {
+        loop {
+            if self.queue.is_empty() {
+                let request = channel.receive().await;
+                self.queue.push(request);
+            }
+            self.step(channel, Instant::now().as_millis()).await;
+        }
+    }
+}
+
+/// Latest counters of the transmit task, read by the command shell
+pub static TX_QUEUE_STATS: Mutex<CriticalSectionRawMutex, Cell<TxQueueStats>> =
+    Mutex::new(Cell::new(EMPTY_STATS));
+
+/// Take a snapshot of the transmit task counters
+pub fn tx_queue_stats() -> TxQueueStats {
+    TX_QUEUE_STATS.lock(|stats| stats.get())
+}
+
+fn publish_stats(stats: &TxQueueStats) {
+    TX_QUEUE_STATS.lock(|shared| shared.set(*stats));
+}
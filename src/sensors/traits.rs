@@ -61,7 +61,8 @@ pub struct EnvironmentalData {
     /// Example: 1500 represents 150.0 lux
     pub light_lux_x10: u32,
 
-    /// Timestamp of the reading in milliseconds since system start
+    /// Timestamp of the reading in milliseconds since system start, or in swarm
+    /// network time when `validity.timestamp_synced()` is set
     pub timestamp_ms: u64,
 
     /// Validity flags indicating which readings are valid
@@ -80,8 +81,10 @@ pub struct DataValidity {
     pub pressure_valid: bool,
     /// Light reading is valid
     pub light_valid: bool,
+    /// Timestamp is network time from `radio::timesync` instead of local uptime
+    pub timestamp_synced: bool,
     /// Reserved bits (unused)
-    #[bits(4)]
+    #[bits(3)]
     _reserved: u8,
 }
 
@@ -163,6 +166,12 @@ impl EnvironmentalData {
         self.pressure_pa = pressure;
         self.validity = self.validity.with_pressure_valid(true);
     }
+
+    /// Set the reading timestamp, `synced` marks it as swarm network time
+    pub fn set_timestamp_ms(&mut self, timestamp_ms: u64, synced: bool) {
+        self.timestamp_ms = timestamp_ms;
+        self.validity = self.validity.with_timestamp_synced(synced);
+    }
 }
 
 impl Default for EnvironmentalData {
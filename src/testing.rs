@@ -2,5 +2,7 @@
 /// This module provides hardware-agnostic mock implementations for testing
 /// without requiring actual hardware peripherals.
 pub mod blackpill_f401;
+pub mod board_sim;
 #[cfg(feature = "hil")]
 pub mod hil;
+pub mod radio_sim;
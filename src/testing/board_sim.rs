@@ -0,0 +1,96 @@
+/// Simulated board resources for testing persistence and security code
+/// Provides flash storage backed by RAM, which can simulate a power loss before a
+/// write, and the unique IDs and network key of simulated boards.
+use crate::hw::traits::FlashStorage;
+use crate::radio::join::Uid;
+use crate::radio::security::NetworkKey;
+
+/// Size of one `RamFlash` sector
+pub const SIM_SECTOR_SIZE: usize = 64;
+
+/// Network key shared by simulated boards
+pub const SIM_NETWORK_KEY: NetworkKey = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+
+/// MCU unique ID of simulated board number `board`
+pub fn board_uid(board: u8) -> Uid {
+    [
+        0x30, 0x00, 0x41, 0x00, 0x0D, 0x51, 0x33, 0x36, 0x38, 0x39, 0x34, board,
+    ]
+}
+
+/// Flash storage backed by RAM, erased bytes read as 0xFF
+///
+/// # Type Parameters
+/// * `SECTORS` - Number of `SIM_SECTOR_SIZE` byte sectors
+#[derive(Debug, Clone)]
+pub struct RamFlash<const SECTORS: usize> {
+    sectors: [[u8; SIM_SECTOR_SIZE]; SECTORS],
+    /// Sector erases so far
+    pub erases: u32,
+    /// Writes fail as if the board lost power right before them
+    pub power_lost: bool,
+}
+
+impl<const SECTORS: usize> RamFlash<SECTORS> {
+    /// Create erased storage
+    pub fn new() -> Self {
+        Self {
+            sectors: [[0xFF; SIM_SECTOR_SIZE]; SECTORS],
+            erases: 0,
+            power_lost: false,
+        }
+    }
+}
+
+impl<const SECTORS: usize> Default for RamFlash<SECTORS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SECTORS: usize> FlashStorage for RamFlash<SECTORS> {
+    fn read(&self, address: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
+        let start = address as usize;
+        let bytes = self
+            .sectors
+            .as_flattened()
+            .get(start..start + buffer.len())
+            .ok_or("Address out of range")?;
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), &'static str> {
+        if self.power_lost {
+            return Err("Power lost");
+        }
+        let start = address as usize;
+        let bytes = self
+            .sectors
+            .as_flattened_mut()
+            .get_mut(start..start + data.len())
+            .ok_or("Address out of range")?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), &'static str> {
+        let sector = self
+            .sectors
+            .get_mut(address as usize / SIM_SECTOR_SIZE)
+            .ok_or("Address out of range")?;
+        sector.fill(0xFF);
+        self.erases += 1;
+        Ok(())
+    }
+
+    fn sector_size(&self) -> u32 {
+        SIM_SECTOR_SIZE as u32
+    }
+
+    fn total_size(&self) -> u32 {
+        (SECTORS * SIM_SECTOR_SIZE) as u32
+    }
+}
@@ -0,0 +1,454 @@
+/// Simulated radio medium for testing protocol code without hardware
+/// Any number of virtual nodes share one in-memory medium. Every node gets a
+/// `SimRadio` implementing the radio traits, and a packet transmitted by one node is
+/// delivered to every other node that is in range, listening and tuned close enough
+/// to the sender's frequency. Links can be configured with packet loss, bit errors,
+/// RSSI and propagation delay.
+///
+/// Frames travel as bytes: bit errors are applied to the encoded frame, which is
+/// delivered as it arrived. `receive` runs the frame through the CRC check, and the
+/// Reed-Solomon decoder when `fec_parity` is set, while `receive_frame` hands out the
+/// raw bytes for tests of the synchronization and line coding layers. Link loss
+/// scales inversely with the sender's power level.
+///
+/// The medium has its own clock which only moves when the test calls `advance_ms`,
+/// so delayed deliveries are deterministic.
+use crate::radio::fec::{FecFrame, ReedSolomon};
+use crate::radio::framing;
+use crate::radio::protocol::Packet;
+use crate::radio::random::XorShift32;
+use crate::radio::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+use core::cell::RefCell;
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use heapless::Deque;
+
+/// Frequency every simulated radio starts on
+pub const DEFAULT_SIM_FREQUENCY_HZ: u32 = 433_000_000;
+
+/// Power level every simulated radio starts with
+pub const DEFAULT_SIM_POWER_LEVEL: u8 = 128;
+
+/// Properties of the link from one node to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct LinkProfile {
+    /// Signal strength reported by the receiver
+    pub rssi_dbm: i16,
+    /// Chance that a packet is lost at `DEFAULT_SIM_POWER_LEVEL`, in percent
+    pub loss_percent: u8,
+    /// Chance that a transmitted bit is flipped, in bits per million
+    pub bit_error_ppm: u32,
+    /// Time between transmission and reception
+    pub delay_ms: u32,
+}
+
+impl Default for LinkProfile {
+    fn default() -> Self {
+        Self {
+            rssi_dbm: -60,
+            loss_percent: 0,
+            bit_error_ppm: 0,
+            delay_ms: 0,
+        }
+    }
+}
+
+/// Medium-wide parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SimConfig {
+    /// Link used between every pair of nodes until changed with `set_link`
+    pub default_link: LinkProfile,
+    /// Largest frequency difference at which a receiver still hears a transmitter
+    pub channel_bandwidth_hz: u32,
+    /// Time a transmission occupies the channel, used for carrier sense
+    pub airtime_ms: u32,
+    /// Reed-Solomon parity symbols appended to every frame, 0 sends plain CRC frames
+    pub fec_parity: usize,
+    /// Seed for loss and bit-error decisions
+    pub seed: u32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            default_link: LinkProfile::default(),
+            channel_bandwidth_hz: 100_000,
+            airtime_ms: 5,
+            fec_parity: 0,
+            seed: 1,
+        }
+    }
+}
+
+/// Medium counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format)]
+pub struct SimStats {
+    /// Packets transmitted by any node
+    pub transmitted: u32,
+    /// Copies of packets queued for a receiver
+    pub delivered: u32,
+    /// Copies dropped by link loss
+    pub lost: u32,
+    /// Copies that arrived with bit errors
+    pub corrupted: u32,
+    /// Copies received by a node but rejected by the CRC check or FEC decoder
+    pub rejected: u32,
+    /// Copies not received because the receiver was out of range, on another
+    /// frequency, disabled or asleep
+    pub unheard: u32,
+    /// Copies dropped because the receiver's queue was full
+    pub overflows: u32,
+}
+
+/// Frame travelling to one receiver
+struct InFlight {
+    frame: FecFrame,
+    rssi_dbm: i16,
+    arrival_ms: u64,
+}
+
+/// State of one virtual node
+struct SimNode<const QUEUE: usize> {
+    frequency_hz: u32,
+    power_level: u8,
+    enabled: bool,
+    asleep: bool,
+    last_rssi: Option<i16>,
+    busy_until_ms: u64,
+    queue: Deque<InFlight, QUEUE>,
+}
+
+impl<const QUEUE: usize> SimNode<QUEUE> {
+    fn new() -> Self {
+        Self {
+            frequency_hz: DEFAULT_SIM_FREQUENCY_HZ,
+            power_level: DEFAULT_SIM_POWER_LEVEL,
+            enabled: true,
+            asleep: false,
+            last_rssi: None,
+            busy_until_ms: 0,
+            queue: Deque::new(),
+        }
+    }
+
+    fn hears(&self) -> bool {
+        self.enabled && !self.asleep
+    }
+}
+
+struct MediumState<const NODES: usize, const QUEUE: usize> {
+    config: SimConfig,
+    fec: Option<ReedSolomon>,
+    now_ms: u64,
+    rng: XorShift32,
+    links: [[Option<LinkProfile>; NODES]; NODES],
+    nodes: [SimNode<QUEUE>; NODES],
+    stats: SimStats,
+}
+
+impl<const NODES: usize, const QUEUE: usize> MediumState<NODES, QUEUE> {
+    fn transmit(&mut self, from: usize, packet: &Packet) -> Result<(), RadioError> {
+        if self.nodes[from].asleep {
+            return Err(RadioError::NotReady);
+        }
+        self.stats.transmitted += 1;
+
+        let frame = match &self.fec {
+            Some(fec) => fec
+                .encode_packet(packet)
+                .map_err(|_| RadioError::InvalidPacket)?,
+            None => framing::encode(packet).into_iter().collect(),
+        };
+        let tx_frequency = self.nodes[from].frequency_hz;
+        let tx_power = self.nodes[from].power_level;
+        for to in 0..NODES {
+            if to == from {
+                continue;
+            }
+            let Some(link) = self.links[from][to] else {
+                self.stats.unheard += 1;
+                continue;
+            };
+            let receiver = &self.nodes[to];
+            let separation = receiver.frequency_hz.abs_diff(tx_frequency);
+            if !receiver.hears() || separation > self.config.channel_bandwidth_hz {
+                self.stats.unheard += 1;
+                continue;
+            }
+
+            let arrival_ms = self.now_ms + link.delay_ms as u64;
+            let receiver = &mut self.nodes[to];
+            receiver.busy_until_ms = receiver
+                .busy_until_ms
+                .max(arrival_ms + self.config.airtime_ms as u64);
+
+            if self.rng.below(100) < Self::loss_percent(&link, tx_power) {
+                self.stats.lost += 1;
+                continue;
+            }
+
+            let mut received = frame.clone();
+            let mut flipped = false;
+            if link.bit_error_ppm > 0 {
+                for byte in received.iter_mut() {
+                    for bit in 0..8 {
+                        if self.rng.below(1_000_000) < link.bit_error_ppm {
+                            *byte ^= 1 << bit;
+                            flipped = true;
+                        }
+                    }
+                }
+            }
+            if flipped {
+                self.stats.corrupted += 1;
+            }
+
+            let in_flight = InFlight {
+                frame: received,
+                rssi_dbm: link.rssi_dbm,
+                arrival_ms,
+            };
+            if self.nodes[to].queue.push_back(in_flight).is_err() {
+                self.stats.overflows += 1;
+                continue;
+            }
+            self.stats.delivered += 1;
+        }
+        Ok(())
+    }
+
+    /// Loss chance of `link` for a sender transmitting at `power_level`
+    fn loss_percent(link: &LinkProfile, power_level: u8) -> u32 {
+        if power_level == 0 {
+            return 100;
+        }
+        let scaled = link.loss_percent as u32 * DEFAULT_SIM_POWER_LEVEL as u32 / power_level as u32;
+        scaled.min(100)
+    }
+
+    /// Take the next arrived frame of `node` off its queue
+    fn take_frame(&mut self, node: usize) -> Result<FecFrame, RadioError> {
+        if !self.arrived(node) {
+            return Err(RadioError::ReceptionFailed);
+        }
+        let node = &mut self.nodes[node];
+        let in_flight = node.queue.pop_front().ok_or(RadioError::ReceptionFailed)?;
+        node.last_rssi = Some(in_flight.rssi_dbm);
+        Ok(in_flight.frame)
+    }
+
+    fn arrived(&self, node: usize) -> bool {
+        let node = &self.nodes[node];
+        node.hears()
+            && node
+                .queue
+                .front()
+                .is_some_and(|in_flight| in_flight.arrival_ms <= self.now_ms)
+    }
+}
+
+/// Shared in-memory radio medium
+///
+/// # Type Parameters
+/// * `NODES` - Number of virtual nodes
+/// * `QUEUE` - Number of packets each node can have waiting for reception
+pub struct SimMedium<const NODES: usize, const QUEUE: usize> {
+    state: Mutex<CriticalSectionRawMutex, RefCell<MediumState<NODES, QUEUE>>>,
+}
+
+impl<const NODES: usize, const QUEUE: usize> SimMedium<NODES, QUEUE> {
+    /// Create a medium in which every node can hear every other node
+    ///
+    /// # Panics
+    /// Panics if `fec_parity` is larger than `MAX_PARITY_SYMBOLS`
+    pub fn new(config: SimConfig) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(MediumState {
+                config,
+                fec: match config.fec_parity {
+                    0 => None,
+                    parity => Some(ReedSolomon::new(parity).expect("invalid FEC parity")),
+                },
+                now_ms: 0,
+                rng: XorShift32::new(config.seed),
+                links: [[Some(config.default_link); NODES]; NODES],
+                nodes: core::array::from_fn(|_| SimNode::new()),
+                stats: SimStats::default(),
+            })),
+        }
+    }
+
+    /// Get the radio of node `index`
+    ///
+    /// # Panics
+    /// Panics if `index` is not below `NODES`
+    pub fn radio(&self, index: usize) -> SimRadio<'_, NODES, QUEUE> {
+        assert!(index < NODES, "no such simulated node");
+        SimRadio {
+            medium: self,
+            index,
+        }
+    }
+
+    /// Configure the link from `from` to `to`, `None` puts `to` out of range
+    pub fn set_link(&self, from: usize, to: usize, link: Option<LinkProfile>) {
+        self.with_state(|state| state.links[from][to] = link);
+    }
+
+    /// Configure the links in both directions between two nodes
+    pub fn set_link_symmetric(&self, a: usize, b: usize, link: Option<LinkProfile>) {
+        self.set_link(a, b, link);
+        self.set_link(b, a, link);
+    }
+
+    /// Current simulated time
+    pub fn now_ms(&self) -> u64 {
+        self.with_state(|state| state.now_ms)
+    }
+
+    /// Move the simulated time forward, releasing delayed packets
+    pub fn advance_ms(&self, ms: u64) {
+        self.with_state(|state| state.now_ms += ms);
+    }
+
+    /// Medium counters
+    pub fn stats(&self) -> SimStats {
+        self.with_state(|state| state.stats)
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut MediumState<NODES, QUEUE>) -> T) -> T {
+        self.state.lock(|state| f(&mut state.borrow_mut()))
+    }
+}
+
+/// Radio of one virtual node attached to a `SimMedium`
+pub struct SimRadio<'a, const NODES: usize, const QUEUE: usize> {
+    medium: &'a SimMedium<NODES, QUEUE>,
+    index: usize,
+}
+
+impl<const NODES: usize, const QUEUE: usize> SimRadio<'_, NODES, QUEUE> {
+    /// Index of this node in the medium
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Check whether the radio is in sleep mode
+    pub fn is_asleep(&self) -> bool {
+        self.medium
+            .with_state(|state| state.nodes[self.index].asleep)
+    }
+
+    /// Number of packets queued for this node, including ones still in flight
+    pub fn pending(&self) -> usize {
+        self.medium
+            .with_state(|state| state.nodes[self.index].queue.len())
+    }
+
+    /// Receive the next frame as it came off the air, bit errors included
+    ///
+    /// The frame is not checked or decoded, so it carries the Reed-Solomon parity
+    /// when the medium uses FEC.
+    pub fn receive_frame(&mut self) -> Result<FecFrame, RadioError> {
+        self.medium.with_state(|state| state.take_frame(self.index))
+    }
+}
+
+impl<const NODES: usize, const QUEUE: usize> RadioTransmitter for SimRadio<'_, NODES, QUEUE> {
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.transmit(self.index, packet))
+    }
+
+    fn is_ready(&self) -> bool {
+        !self.is_asleep()
+    }
+
+    async fn set_power_level(&mut self, power_level: u8) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].power_level = power_level);
+        Ok(())
+    }
+
+    fn get_power_level(&self) -> u8 {
+        self.medium
+            .with_state(|state| state.nodes[self.index].power_level)
+    }
+}
+
+impl<const NODES: usize, const QUEUE: usize> RadioReceiver for SimRadio<'_, NODES, QUEUE> {
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        self.medium.with_state(|state| {
+            let mut frame = state.take_frame(self.index)?;
+            let decoded = match &state.fec {
+                Some(fec) => fec.decode_packet(&mut frame).map(|(packet, _)| packet),
+                None => framing::decode(&frame),
+            };
+            if decoded.is_err() {
+                state.stats.rejected += 1;
+            }
+            decoded
+        })
+    }
+
+    fn packet_available(&self) -> bool {
+        self.medium.with_state(|state| state.arrived(self.index))
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].enabled = enabled);
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.medium
+            .with_state(|state| state.nodes[self.index].enabled)
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].last_rssi)
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.medium.with_state(|state| {
+            state.now_ms < state.nodes[self.index].busy_until_ms || state.arrived(self.index)
+        })
+    }
+}
+
+impl<const NODES: usize, const QUEUE: usize> RadioTransceiver for SimRadio<'_, NODES, QUEUE> {
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        self.medium.with_state(|state| {
+            let node = &mut state.nodes[self.index];
+            node.enabled = true;
+            node.asleep = false;
+        });
+        Ok(())
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].asleep = true);
+        Ok(())
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].asleep = false);
+        Ok(())
+    }
+
+    fn get_frequency(&self) -> u32 {
+        self.medium
+            .with_state(|state| state.nodes[self.index].frequency_hz)
+    }
+
+    async fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), RadioError> {
+        self.medium
+            .with_state(|state| state.nodes[self.index].frequency_hz = frequency_hz);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use core::cell::Cell;
+use sensor_swarm::radio::csma::CsmaConfig;
+use sensor_swarm::radio::protocol::Packet;
+use sensor_swarm::radio::traits::{RadioError, RadioReceiver, RadioTransmitter};
+
+/// Radio whose channel is busy for the first `busy_samples` carrier-sense checks
+#[derive(Default)]
+struct BusyRadio {
+    busy_samples: Cell<u8>,
+    sensed: Cell<u8>,
+    sent: u32,
+}
+
+impl BusyRadio {
+    fn busy_for(samples: u8) -> Self {
+        Self {
+            busy_samples: Cell::new(samples),
+            ..Self::default()
+        }
+    }
+}
+
+impl RadioTransmitter for BusyRadio {
+    async fn transmit(&mut self, _packet: &Packet) -> Result<(), RadioError> {
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    async fn set_power_level(&mut self, _power_level: u8) -> Result<(), RadioError> {
+        Ok(())
+    }
+
+    fn get_power_level(&self) -> u8 {
+        0
+    }
+}
+
+impl RadioReceiver for BusyRadio {
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        Err(RadioError::ReceptionFailed)
+    }
+
+    fn packet_available(&self) -> bool {
+        false
+    }
+
+    async fn set_enabled(&mut self, _enabled: bool) -> Result<(), RadioError> {
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        None
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.sensed.set(self.sensed.get() + 1);
+        let busy = self.busy_samples.get();
+        self.busy_samples.set(busy.saturating_sub(1));
+        busy > 0
+    }
+}
+
+/// Parameters without backoff delay so tests do not depend on a time driver
+fn instant_config() -> CsmaConfig {
+    CsmaConfig {
+        max_attempts: 4,
+        backoff_unit_ms: 0,
+        ..CsmaConfig::default()
+    }
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::{instant_config, BusyRadio};
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::csma::*;
+    use sensor_swarm::radio::protocol::Packet;
+    use sensor_swarm::radio::random::XorShift32;
+    use sensor_swarm::radio::traits::{RadioError, RadioTransmitter};
+
+    #[test]
+    fn test_clear_channel_transmits_after_initial_backoff() {
+        let mut csma = CsmaRadio::new(BusyRadio::default(), instant_config(), 1);
+
+        defmt::unwrap!(block_on(csma.transmit(&Packet::new(1, 2, 3, b"hi"))));
+
+        defmt::assert!(csma.radio().sent == 1);
+        defmt::assert!(csma.radio().sensed.get() == 1);
+        defmt::assert!(csma.stats().transmitted == 1);
+        defmt::assert!(csma.stats().busy_samples == 0);
+        // The channel is only sampled after a random backoff
+        defmt::assert!(csma.stats().backoffs == 1);
+    }
+
+    #[test]
+    fn test_busy_channel_defers_transmission() {
+        let mut csma = CsmaRadio::new(BusyRadio::busy_for(2), instant_config(), 1);
+
+        defmt::unwrap!(block_on(csma.transmit(&Packet::new(1, 2, 3, b"hi"))));
+
+        defmt::assert!(csma.radio().sent == 1);
+        defmt::assert!(csma.radio().sensed.get() == 3);
+        defmt::assert!(csma.stats().busy_samples == 2);
+        defmt::assert!(csma.stats().backoffs == 3);
+        defmt::assert!(csma.stats().gave_up == 0);
+    }
+
+    #[test]
+    fn test_gives_up_with_busy_error() {
+        let mut csma = CsmaRadio::new(BusyRadio::busy_for(10), instant_config(), 1);
+
+        match block_on(csma.transmit(&Packet::new(1, 2, 3, b"hi"))) {
+            Err(RadioError::Busy) => {}
+            _ => defmt::panic!("expected the channel to stay busy"),
+        }
+
+        defmt::assert!(csma.radio().sent == 0);
+        defmt::assert!(csma.radio().sensed.get() == 4);
+        defmt::assert!(csma.stats().gave_up == 1);
+        defmt::assert!(csma.stats().transmitted == 0);
+    }
+
+    #[test]
+    fn test_zero_attempts_still_senses_the_channel() {
+        let config = CsmaConfig {
+            max_attempts: 0,
+            ..instant_config()
+        };
+
+        let mut clear = CsmaRadio::new(BusyRadio::default(), config, 1);
+        defmt::unwrap!(block_on(clear.transmit(&Packet::new(1, 2, 3, b"hi"))));
+        defmt::assert!(clear.radio().sent == 1);
+        defmt::assert!(clear.radio().sensed.get() == 1);
+
+        let mut busy = CsmaRadio::new(BusyRadio::busy_for(10), config, 1);
+        match block_on(busy.transmit(&Packet::new(1, 2, 3, b"hi"))) {
+            Err(RadioError::Busy) => {}
+            _ => defmt::panic!("expected the channel to stay busy"),
+        }
+        defmt::assert!(busy.radio().sensed.get() == 1);
+        defmt::assert!(busy.stats().busy_samples == 1);
+    }
+
+    #[test]
+    fn test_backoff_window_grows_and_is_capped() {
+        let config = CsmaConfig {
+            min_backoff_exponent: 2,
+            max_backoff_exponent: 4,
+            backoff_unit_ms: 3,
+            ..CsmaConfig::default()
+        };
+        let mut rng = XorShift32::new(7);
+        let mut longest = [0u32; 5];
+
+        for _ in 0..200 {
+            for (busy_count, longest) in (1u8..).zip(longest.iter_mut()) {
+                let backoff = config.backoff_ms(busy_count, &mut rng);
+                defmt::assert!(backoff.is_multiple_of(3));
+                *longest = (*longest).max(backoff);
+            }
+        }
+
+        // Windows of 4, 8 and then 16 backoff periods
+        defmt::assert!(longest[0] == 3 * 3);
+        defmt::assert!(longest[1] == 7 * 3);
+        defmt::assert!(longest[2] == 15 * 3);
+        defmt::assert!(longest[3] == 15 * 3);
+        defmt::assert!(longest[4] == 15 * 3);
+    }
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use sensor_swarm::radio::dedup::*;
+    use sensor_swarm::radio::protocol::Packet;
+
+    #[test]
+    fn test_repeated_packet_is_duplicate() {
+        let mut cache: DuplicateCache<4> = DuplicateCache::default();
+        let packet = Packet::new(0x0010, 0x0020, 7, b"x");
+
+        defmt::assert!(!cache.is_duplicate(&packet.header));
+        defmt::assert!(cache.is_duplicate(&packet.header));
+
+        // Same sequence number from another sender is unrelated
+        let other = Packet::new(0x0011, 0x0020, 7, b"x");
+        defmt::assert!(!cache.is_duplicate(&other.header));
+        defmt::assert!(cache.len() == 2);
+    }
+
+    #[test]
+    fn test_out_of_order_within_window() {
+        let mut cache: DuplicateCache<4> = DuplicateCache::default();
+
+        for seq in [10u16, 12, 11, 9] {
+            defmt::assert!(!cache.is_duplicate(&Packet::new(1, 0, seq, b"").header));
+        }
+        for seq in [9u16, 10, 11, 12] {
+            defmt::assert!(cache.is_duplicate(&Packet::new(1, 0, seq, b"").header));
+        }
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        let mut cache: DuplicateCache<4> = DuplicateCache::default();
+
+        for seq in [0xFFFEu16, 0xFFFF, 0, 1] {
+            defmt::assert!(!cache.is_duplicate(&Packet::new(1, 0, seq, b"").header));
+        }
+        // Packets from before the wrap are still recognised
+        defmt::assert!(cache.is_duplicate(&Packet::new(1, 0, 0xFFFF, b"").header));
+        defmt::assert!(cache.is_duplicate(&Packet::new(1, 0, 0, b"").header));
+    }
+
+    #[test]
+    fn test_retransmit_of_seen_packet_requests_reack() {
+        let mut cache: DuplicateCache<4> = DuplicateCache::default();
+        let mut packet = Packet::new(3, 4, 100, b"data");
+        packet.header.control.set_ack_request(true);
+
+        defmt::assert!(cache.check(&packet.header) == DuplicateStatus::New);
+
+        packet.header.control.set_retransmit(true);
+        defmt::assert!(cache.check(&packet.header) == DuplicateStatus::Duplicate { reack: true });
+
+        let broadcast = Packet::new(3, 0, 101, b"data");
+        defmt::assert!(cache.check(&broadcast.header) == DuplicateStatus::New);
+        defmt::assert!(
+            cache.check(&broadcast.header) == DuplicateStatus::Duplicate { reack: false }
+        );
+    }
+
+    #[test]
+    fn test_senders_age_out() {
+        let mut cache: DuplicateCache<4> = DuplicateCache::new(1_000);
+        let packet = Packet::new(1, 0, 5, b"");
+
+        cache.tick(100);
+        defmt::assert!(!cache.is_duplicate(&packet.header));
+        cache.tick(900);
+        defmt::assert!(cache.len() == 1);
+        cache.tick(1_200);
+        defmt::assert!(cache.is_empty());
+        defmt::assert!(!cache.is_duplicate(&packet.header));
+    }
+
+    #[test]
+    fn test_full_cache_evicts_least_recent_sender() {
+        let mut cache: DuplicateCache<2> = DuplicateCache::default();
+
+        cache.tick(10);
+        defmt::assert!(!cache.is_duplicate(&Packet::new(1, 0, 1, b"").header));
+        cache.tick(20);
+        defmt::assert!(!cache.is_duplicate(&Packet::new(2, 0, 1, b"").header));
+        cache.tick(30);
+        defmt::assert!(!cache.is_duplicate(&Packet::new(3, 0, 1, b"").header));
+
+        defmt::assert!(cache.len() == 2);
+        // Sender 2 is still tracked, sender 1 was evicted
+        defmt::assert!(cache.is_duplicate(&Packet::new(2, 0, 1, b"").header));
+        defmt::assert!(!cache.is_duplicate(&Packet::new(1, 0, 1, b"").header));
+    }
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use sensor_swarm::radio::fec::*;
+    use sensor_swarm::radio::protocol::*;
+    use sensor_swarm::radio::traits::RadioError;
+
+    #[test]
+    fn test_invalid_parity_rejected() {
+        defmt::assert!(ReedSolomon::new(0).is_err());
+        defmt::assert!(ReedSolomon::new(MAX_PARITY_SYMBOLS + 1).is_err());
+        defmt::assert!(defmt::unwrap!(ReedSolomon::new(8)).correction_capacity() == 4);
+    }
+
+    #[test]
+    fn test_encode_is_systematic_and_known() {
+        // Reference parity computed with an independent RS(255, 251) implementation
+        // over GF(256)/0x11D with first consecutive root alpha^0
+        let rs = defmt::unwrap!(ReedSolomon::new(4));
+        let mut out = [0u8; 16];
+        let len = defmt::unwrap!(rs.encode(b"hello", &mut out));
+
+        defmt::assert!(len == 9);
+        defmt::assert!(&out[..5] == b"hello");
+        defmt::assert!(out[5..9] == [0xCB, 0xBA, 0xA9, 0xBA]);
+    }
+
+    #[test]
+    fn test_clean_codeword_reports_no_corrections() {
+        let rs = defmt::unwrap!(ReedSolomon::new(6));
+        let mut out = [0u8; 32];
+        let len = defmt::unwrap!(rs.encode(b"sensor swarm", &mut out));
+
+        defmt::assert!(defmt::unwrap!(rs.decode(&mut out[..len])) == 0);
+        defmt::assert!(&out[..12] == b"sensor swarm");
+    }
+
+    #[test]
+    fn test_corrects_up_to_capacity() {
+        let rs = defmt::unwrap!(ReedSolomon::new(8));
+        let mut original = [0u8; 40];
+        let len = defmt::unwrap!(rs.encode(b"0123456789abcdefghijklmnopqrstu", &mut original));
+
+        // Corrupt 4 symbols spread over data and parity
+        let mut received = original;
+        received[0] ^= 0xFF;
+        received[10] ^= 0x01;
+        received[20] ^= 0x5A;
+        received[len - 1] ^= 0x80;
+
+        defmt::assert!(defmt::unwrap!(rs.decode(&mut received[..len])) == 4);
+        defmt::assert!(received[..len] == original[..len]);
+    }
+
+    #[test]
+    fn test_too_many_errors_detected() {
+        let rs = defmt::unwrap!(ReedSolomon::new(4));
+        let mut original = [0u8; 24];
+        let len = defmt::unwrap!(rs.encode(b"uncorrectable data", &mut original));
+
+        let mut received = original;
+        received[1] ^= 0x11;
+        received[5] ^= 0x22;
+        received[9] ^= 0x33;
+
+        defmt::assert!(rs.decode(&mut received[..len]) == Err(FecError::TooManyErrors));
+    }
+
+    #[test]
+    fn test_packet_roundtrip_reports_repairs() {
+        let rs = defmt::unwrap!(ReedSolomon::new(8));
+        let packet = Packet::new(0x0A0B, 0, 1234, b"T=21.50;H=45.00");
+
+        let mut clean = defmt::unwrap!(rs.encode_packet(&packet));
+        let (decoded, outcome) = defmt::unwrap!(rs.decode_packet(&mut clean));
+        defmt::assert!(decoded == packet);
+        defmt::assert!(outcome == FecOutcome::Clean);
+
+        let mut noisy = defmt::unwrap!(rs.encode_packet(&packet));
+        noisy[2] ^= 0x40;
+        noisy[HEADER_SIZE + 3] ^= 0x0F;
+        let (decoded, outcome) = defmt::unwrap!(rs.decode_packet(&mut noisy));
+        defmt::assert!(decoded == packet);
+        defmt::assert!(outcome == FecOutcome::Repaired { symbols: 2 });
+    }
+
+    #[test]
+    fn test_packet_beyond_capacity_is_uncorrectable() {
+        let rs = defmt::unwrap!(ReedSolomon::new(2));
+        let packet = Packet::new(1, 2, 3, b"abc");
+
+        let mut noisy = defmt::unwrap!(rs.encode_packet(&packet));
+        noisy[0] ^= 0x01;
+        noisy[4] ^= 0x02;
+
+        defmt::assert!(matches!(
+            rs.decode_packet(&mut noisy),
+            Err(RadioError::Uncorrectable) | Err(RadioError::ChecksumMismatch)
+        ));
+    }
+}
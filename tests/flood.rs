@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use sensor_swarm::radio::flood::*;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID, DEFAULT_TTL};
+
+    #[test]
+    fn test_broadcast_is_delivered_and_relayed() {
+        let config = FloodConfig::default();
+        let mut relay = FloodRelay::new(0x0002, config);
+        let packet = Packet::new(0x0001, BROADCAST_ID, 10, b"hello");
+
+        let decision = relay.on_receive(&packet, 0);
+        defmt::assert!(decision.deliver);
+        let forward = defmt::unwrap!(decision.forward);
+        defmt::assert!(forward.packet.header.ttl == DEFAULT_TTL - 1);
+        defmt::assert!(forward.packet.header.hop_count == 1);
+        defmt::assert!(forward.packet.header.sender_id == 0x0001);
+        defmt::assert!(forward.packet.header.relay_id == 0x0002);
+        defmt::assert!(forward.packet.payload_data() == b"hello");
+        defmt::assert!(forward.delay_ms >= config.min_relay_delay_ms);
+        defmt::assert!(forward.delay_ms <= config.max_relay_delay_ms);
+    }
+
+    #[test]
+    fn test_unicast_routing() {
+        let mut relay = FloodRelay::new(0x0002, FloodConfig::default());
+
+        // Addressed to this node: delivered, not relayed
+        let decision = relay.on_receive(&Packet::new(0x0001, 0x0002, 1, b"x"), 0);
+        defmt::assert!(decision.deliver);
+        defmt::assert!(decision.forward.is_none());
+
+        // Addressed to another node: relayed only
+        let decision = relay.on_receive(&Packet::new(0x0001, 0x0003, 2, b"x"), 0);
+        defmt::assert!(!decision.deliver);
+        defmt::assert!(decision.forward.is_some());
+        defmt::assert!(relay.stats().delivered == 1);
+        defmt::assert!(relay.stats().forwarded == 1);
+    }
+
+    #[test]
+    fn test_seen_packets_are_not_relayed_again() {
+        let mut relay = FloodRelay::new(0x0002, FloodConfig::default());
+        let packet = Packet::new(0x0001, 0x0003, 5, b"x");
+
+        defmt::assert!(relay.on_receive(&packet, 0).forward.is_some());
+
+        // The same packet relayed back by a neighbor one hop further
+        let mut echoed = packet.clone();
+        echoed.header.ttl -= 1;
+        echoed.header.hop_count += 1;
+        defmt::assert!(
+            relay.on_receive(&echoed, 50)
+                == FloodDecision {
+                    deliver: false,
+                    forward: None
+                }
+        );
+        defmt::assert!(relay.stats().duplicates == 1);
+
+        // Own packets are never relayed
+        let own = Packet::new(0x0002, BROADCAST_ID, 1, b"x");
+        defmt::assert!(relay.on_receive(&own, 60).forward.is_none());
+    }
+
+    #[test]
+    fn test_acks_do_not_shadow_data_packets() {
+        let mut relay = FloodRelay::new(0x0002, FloodConfig::default());
+
+        // Node 3 acknowledges seq 7 of node 1, then originates its own seq 7
+        let mut ack = Packet::new(0x0003, 0x0001, 7, &[]);
+        ack.header.control.set_ack_response(true);
+        defmt::assert!(
+            relay.on_receive(&ack, 0)
+                == FloodDecision {
+                    deliver: false,
+                    forward: None
+                }
+        );
+        let data = Packet::new(0x0003, 0x0002, 7, b"x");
+        defmt::assert!(relay.on_receive(&data, 10).deliver);
+        defmt::assert!(relay.stats().duplicates == 0);
+
+        // Acks for this node are delivered
+        let mut own_ack = Packet::new(0x0003, 0x0002, 8, &[]);
+        own_ack.header.control.set_ack_response(true);
+        defmt::assert!(relay.on_receive(&own_ack, 20).deliver);
+    }
+
+    #[test]
+    fn test_hop_limit_stops_relaying() {
+        let mut relay = FloodRelay::new(0x0002, FloodConfig::default());
+        let mut packet = Packet::new(0x0001, BROADCAST_ID, 9, b"x");
+        packet.header.ttl = 0;
+        packet.header.hop_count = DEFAULT_TTL;
+
+        let decision = relay.on_receive(&packet, 0);
+        defmt::assert!(decision.deliver);
+        defmt::assert!(decision.forward.is_none());
+        defmt::assert!(relay.stats().ttl_expired == 1);
+    }
+}
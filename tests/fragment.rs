@@ -0,0 +1,200 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+/// Build a message of `len` bytes with a recognisable pattern
+fn pattern<const N: usize>(len: usize) -> heapless::Vec<u8, N> {
+    (0..len).map(|i| (i * 7 + 3) as u8).collect()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use super::pattern;
+    use sensor_swarm::radio::fragment::*;
+    use sensor_swarm::radio::protocol::{Packet, MAX_DATA_SIZE};
+
+    #[test]
+    fn test_fragment_header_roundtrip() {
+        let header = FragmentHeader {
+            message_id: 0x1234,
+            index: 2,
+            count: 5,
+        };
+        let bytes = header.to_bytes();
+        defmt::assert!(bytes == [0x34, 0x12, 2, 5]);
+        defmt::assert!(defmt::unwrap!(FragmentHeader::from_bytes(&bytes)) == header);
+
+        defmt::assert!(
+            FragmentHeader::from_bytes(&[0, 0, 5, 5]) == Err(FragmentError::InvalidFragment)
+        );
+        defmt::assert!(
+            FragmentHeader::from_bytes(&[0, 0, 0, 0]) == Err(FragmentError::InvalidFragment)
+        );
+        defmt::assert!(
+            FragmentHeader::from_bytes(&[0, 0, 0]) == Err(FragmentError::InvalidFragment)
+        );
+    }
+
+    #[test]
+    fn test_large_message_roundtrip() {
+        let message: heapless::Vec<u8, 1000> = pattern(1000);
+        let mut template = Packet::new(0x0042, 0x0001, 100, &[]).header;
+        template.ttl = 3;
+        template.next_hop = 0x0007;
+        let fragments = defmt::unwrap!(Fragments::new(template, 9, &message));
+        defmt::assert!(fragments.total() == fragment_count(1000));
+
+        let mut reassembler: Reassembler<2> = Reassembler::default();
+        let mut result = None;
+        for (i, packet) in fragments.enumerate() {
+            defmt::assert!(packet.header.sequence_number == 100 + i as u16);
+            defmt::assert!(packet.header.ttl == 3);
+            defmt::assert!(packet.header.next_hop == 0x0007);
+            defmt::assert!(packet.payload_data().len() <= MAX_DATA_SIZE);
+            defmt::assert!(result.is_none());
+            result = defmt::unwrap!(reassembler.push(&packet, 0));
+        }
+
+        let result = defmt::unwrap!(result);
+        defmt::assert!(result.as_slice() == message.as_slice());
+        defmt::assert!(reassembler.in_progress() == 0);
+    }
+
+    #[test]
+    fn test_out_of_order_and_duplicate_fragments() {
+        let message: heapless::Vec<u8, 100> = pattern(100);
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let mut packets: heapless::Vec<Packet, 4> =
+            defmt::unwrap!(Fragments::new(template, 3, &message)).collect();
+        defmt::assert!(packets.len() == 4);
+        packets.reverse();
+
+        let mut reassembler: Reassembler<1> = Reassembler::default();
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[0], 0)).is_none());
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[0], 0)).is_none());
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[1], 0)).is_none());
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[2], 0)).is_none());
+        let result = defmt::unwrap!(defmt::unwrap!(reassembler.push(&packets[3], 0)));
+        defmt::assert!(result.as_slice() == message.as_slice());
+    }
+
+    #[test]
+    fn test_late_duplicate_of_completed_message_is_dropped() {
+        let message: heapless::Vec<u8, 100> = pattern(100);
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let packets: heapless::Vec<Packet, 4> =
+            defmt::unwrap!(Fragments::new(template, 3, &message)).collect();
+
+        let mut reassembler: Reassembler<1> = Reassembler::new(1_000);
+        for packet in &packets[..3] {
+            defmt::assert!(defmt::unwrap!(reassembler.push(packet, 0)).is_none());
+        }
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[3], 0)).is_some());
+
+        // A retransmitted fragment neither opens a slot nor delivers the message again
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[1], 500)).is_none());
+        defmt::assert!(reassembler.in_progress() == 0);
+
+        // After the timeout the message ID may be reused
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[1], 1_500)).is_none());
+        defmt::assert!(reassembler.in_progress() == 1);
+    }
+
+    #[test]
+    fn test_inconsistent_fragment_keeps_reassembly() {
+        let message: heapless::Vec<u8, 100> = pattern(100);
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let packets: heapless::Vec<Packet, 4> =
+            defmt::unwrap!(Fragments::new(template.clone(), 3, &message)).collect();
+        // Same sender and message ID, but a different fragment count
+        let stray: heapless::Vec<u8, 60> = pattern(60);
+        let stray = defmt::unwrap!(defmt::unwrap!(Fragments::new(template, 3, &stray)).next());
+
+        let mut reassembler: Reassembler<1> = Reassembler::default();
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[0], 0)).is_none());
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[1], 0)).is_none());
+        defmt::assert!(reassembler.push(&stray, 0) == Err(FragmentError::InconsistentFragment));
+        defmt::assert!(reassembler.in_progress() == 1);
+
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[2], 0)).is_none());
+        let result = defmt::unwrap!(defmt::unwrap!(reassembler.push(&packets[3], 0)));
+        defmt::assert!(result.as_slice() == message.as_slice());
+    }
+
+    #[test]
+    fn test_small_message_is_single_fragment() {
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let mut fragments = defmt::unwrap!(Fragments::new(template, 1, b"tiny"));
+        defmt::assert!(fragments.total() == 1);
+
+        let mut reassembler: Reassembler<1> = Reassembler::default();
+        let packet = defmt::unwrap!(fragments.next());
+        defmt::assert!(fragments.next().is_none());
+        defmt::assert!(packet.header.control.is_fragment());
+
+        // The same payload without the fragment flag is an ordinary message
+        let mut plain = packet.clone();
+        plain.header.control.set_fragment(false);
+        defmt::assert!(reassembler.push(&plain, 0) == Err(FragmentError::InvalidFragment));
+
+        let result = defmt::unwrap!(defmt::unwrap!(reassembler.push(&packet, 0)));
+        defmt::assert!(result.as_slice() == b"tiny");
+        defmt::assert!(reassembler.in_progress() == 0);
+    }
+
+    #[test]
+    fn test_incomplete_message_times_out() {
+        let message: heapless::Vec<u8, 60> = pattern(60);
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let packets: heapless::Vec<Packet, 3> =
+            defmt::unwrap!(Fragments::new(template, 7, &message)).collect();
+
+        let mut reassembler: Reassembler<1> = Reassembler::new(1_000);
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[0], 0)).is_none());
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[1], 500)).is_none());
+        defmt::assert!(reassembler.expire(1_500) == 1);
+
+        // The last fragment alone cannot complete the message any more
+        defmt::assert!(defmt::unwrap!(reassembler.push(&packets[2], 1_600)).is_none());
+        defmt::assert!(reassembler.in_progress() == 1);
+    }
+
+    #[test]
+    fn test_concurrent_reassembly_limit() {
+        let message: heapless::Vec<u8, 60> = pattern(60);
+        let mut reassembler: Reassembler<1> = Reassembler::default();
+
+        let mut from_first = defmt::unwrap!(Fragments::new(
+            Packet::new(1, 0, 0, &[]).header,
+            1,
+            &message
+        ));
+        let mut from_second = defmt::unwrap!(Fragments::new(
+            Packet::new(2, 0, 0, &[]).header,
+            1,
+            &message
+        ));
+        let first = defmt::unwrap!(from_first.next());
+        let second = defmt::unwrap!(from_second.next());
+
+        defmt::assert!(defmt::unwrap!(reassembler.push(&first, 0)).is_none());
+        defmt::assert!(reassembler.push(&second, 0) == Err(FragmentError::TooManyReassemblies));
+    }
+
+    #[test]
+    fn test_oversized_message_is_rejected() {
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let message = [0u8; MAX_MESSAGE_SIZE + 1];
+        defmt::assert!(Fragments::new(template, 1, &message).is_err());
+    }
+}
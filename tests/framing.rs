@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use sensor_swarm::radio::framing::*;
+    use sensor_swarm::radio::protocol::*;
+    use sensor_swarm::radio::traits::RadioError;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let packet = Packet::new(0x0102, 0x0304, 7, b"temperature=21.5");
+        let frame = encode(&packet);
+
+        defmt::assert!(frame.len() == frame_len(&packet.header));
+        defmt::assert!(frame.len() == packet.encoded_len() + CRC_SIZE);
+
+        let decoded = defmt::unwrap!(decode(&frame));
+        defmt::assert!(decoded == packet);
+    }
+
+    #[test]
+    fn test_frame_crc_known_value() {
+        // Reference value computed with an independent CRC-16/CCITT-FALSE implementation
+        let packet = Packet::new(1, 2, 3, b"AB");
+        let frame = encode(&packet);
+
+        defmt::assert!(
+            frame[..HEADER_SIZE + 2]
+                == [
+                    1,
+                    0,
+                    2,
+                    0,
+                    3,
+                    0,
+                    0,
+                    DEFAULT_TTL,
+                    0,
+                    1,
+                    0,
+                    0,
+                    0,
+                    2,
+                    0x41,
+                    0x42
+                ]
+        );
+        defmt::assert!(frame[HEADER_SIZE + 2..] == 0x9C4Du16.to_le_bytes());
+    }
+
+    #[test]
+    fn test_corrupted_frame_is_rejected() {
+        let packet = Packet::new(1, 2, 3, b"payload");
+        let frame = encode(&packet);
+
+        // Flip every single bit of the frame in turn, CRC-16 detects all single-bit errors
+        for byte in 0..frame.len() {
+            for bit in 0..8 {
+                let mut corrupted = frame.clone();
+                corrupted[byte] ^= 1 << bit;
+                defmt::assert!(decode(&corrupted) == Err(RadioError::ChecksumMismatch));
+            }
+        }
+    }
+
+    #[test]
+    fn test_short_frame_is_invalid() {
+        let packet = Packet::new(1, 2, 3, b"abc");
+        let frame = encode(&packet);
+
+        defmt::assert!(decode(&frame[..HEADER_SIZE + 1]) == Err(RadioError::InvalidPacket));
+        defmt::assert!(decode(&[]) == Err(RadioError::InvalidPacket));
+    }
+}
@@ -0,0 +1,305 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::hopping::{ChannelPlan, HopConfig, HoppingSchedule};
+
+fn schedule(seed: u32) -> HoppingSchedule {
+    let config = HopConfig {
+        seed,
+        ..HopConfig::default()
+    };
+    HoppingSchedule::new(ChannelPlan::default(), config)
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::schedule;
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::hopping::*;
+    use sensor_swarm::radio::join::{JoinCoordinator, NetworkParams};
+    use sensor_swarm::radio::message::Message;
+    use sensor_swarm::radio::protocol::Packet;
+    use sensor_swarm::radio::traits::{RadioReceiver, RadioTransceiver, RadioTransmitter};
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    #[test]
+    fn test_channel_plan() {
+        let plan = ChannelPlan::default();
+        defmt::assert!(plan.len() == DEFAULT_CHANNEL_COUNT as usize);
+        defmt::assert!(plan.rendezvous_frequency() == 433_920_000);
+        defmt::assert!(plan.frequency(7) == Some(434_520_000));
+        defmt::assert!(plan.frequency(8).is_none());
+        defmt::assert!(plan.channel_of(433_320_000) == Some(1));
+        defmt::assert!(plan.channel_of(433_330_000).is_none());
+
+        let custom = defmt::unwrap!(ChannelPlan::new(&[433_050_000, 434_000_000], 1));
+        defmt::assert!(custom.rendezvous_frequency() == 434_000_000);
+        defmt::assert!(ChannelPlan::new(&[], 0).is_none());
+        defmt::assert!(ChannelPlan::new(&[433_050_000], 1).is_none());
+        defmt::assert!(ChannelPlan::uniform(433_000_000, 100_000, 17, 0).is_none());
+        defmt::assert!(ChannelPlan::uniform(433_120_000, 200_000, 8, 4) == Some(plan));
+    }
+
+    #[test]
+    fn test_hop_sequence_is_shared() {
+        let a = schedule(0x1234);
+        let b = schedule(0x1234);
+        let other = schedule(0x4321);
+        let mut visits = [0u32; DEFAULT_CHANNEL_COUNT as usize];
+        let mut differences = 0;
+        for hop in 0..400u64 {
+            let channel = a.channel_for_hop(hop);
+            defmt::assert!(b.channel_for_hop(hop) == channel);
+            differences += (other.channel_for_hop(hop) != channel) as u32;
+            visits[channel as usize] += 1;
+            if hop % DEFAULT_RENDEZVOUS_INTERVAL as u64 == 0 {
+                defmt::assert!(channel == DEFAULT_RENDEZVOUS_CHANNEL);
+            }
+        }
+        // Every channel is used, none dominates apart from the rendezvous channel
+        defmt::assert!(visits.iter().all(|&count| (25..=110).contains(&count)));
+        defmt::assert!(differences > 200);
+
+        defmt::assert!(a.hop_at(12_999) == 12);
+        defmt::assert!(a.hop_start_ms(13) == 13_000);
+        defmt::assert!(a.channel_at(12_999) == a.channel_for_hop(12));
+        let frequency = a.plan().frequency(a.channel_for_hop(12));
+        defmt::assert!(Some(a.frequency_at(12_000)) == frequency);
+
+        let joined = HopConfig::for_network(0x1234);
+        defmt::assert!(joined == *a.config());
+    }
+
+    #[test]
+    fn test_channel_map_limits_hops() {
+        let mut schedule = schedule(7);
+        let mut map = ChannelMap::all(DEFAULT_CHANNEL_COUNT as usize);
+        map.block(1);
+        map.block(6);
+        defmt::assert!(map.allowed_count() == 6 && !map.is_allowed(6));
+        schedule.schedule_map(ChannelMapMessage { map, from_hop: 100 });
+
+        let used_before = (0..100).any(|hop| schedule.channel_for_hop(hop) == 6);
+        defmt::assert!(used_before);
+        for hop in 100..500 {
+            let channel = schedule.channel_for_hop(hop);
+            defmt::assert!(channel != 1 && channel != 6);
+        }
+        defmt::assert!(schedule.map_for_hop(99) == schedule.map());
+
+        // Without usable channels the swarm stays on the rendezvous channel
+        let mut nothing = schedule.clone();
+        nothing.schedule_map(ChannelMapMessage {
+            map: ChannelMap::from_bits(0),
+            from_hop: 0,
+        });
+        defmt::assert!((1..50).all(|hop| nothing.channel_for_hop(hop) == 4));
+
+        let message = ChannelMapMessage {
+            map,
+            from_hop: 1 << 40,
+        };
+        let packet = message.to_packet(1, 9);
+        defmt::assert!(ChannelMapMessage::from_packet(&packet) == Some(message));
+        defmt::assert!(Message::from_packet(&packet) == Ok(Message::ChannelMap(message)));
+        defmt::assert!(ChannelMapMessage::from_bytes(&[0xFF; CHANNEL_MAP_SIZE - 1]).is_none());
+        defmt::assert!(ChannelMapMessage::from_packet(&Packet::new(1, 2, 3, b"x")).is_none());
+    }
+
+    #[test]
+    fn test_poor_channels_are_blacklisted() {
+        let plan = ChannelPlan::default();
+        let config = BlacklistConfig {
+            min_allowed: 6,
+            retry_after_ms: 60_000,
+            ..BlacklistConfig::default()
+        };
+        let mut ratings = ChannelRatings::default();
+        for packet in 0..16 {
+            for channel in 0..DEFAULT_CHANNEL_COUNT {
+                // Channel 2 is jammed, 3 and the rendezvous channel are noisy
+                let delivered = match channel {
+                    2 => false,
+                    3 | 4 => packet % 4 == 0,
+                    _ => true,
+                };
+                ratings.record(channel, delivered);
+            }
+        }
+        defmt::assert!(ratings.delivery_permille(2) == Some(0));
+
+        let all = ChannelMap::all(plan.len());
+        let map = ratings.evaluate(&plan, all, &config, 1_000);
+        // The worst channels go first, but enough channels remain to hop over
+        defmt::assert!(!map.is_allowed(2) && !map.is_allowed(3));
+        defmt::assert!(map.is_allowed(DEFAULT_RENDEZVOUS_CHANNEL));
+        defmt::assert!(map.allowed_count() == 6);
+
+        // Blocked channels stay blocked until they get another chance
+        defmt::assert!(ratings.evaluate(&plan, map, &config, 30_000) == map);
+        defmt::assert!(ratings.evaluate(&plan, map, &config, 61_000) == all);
+        defmt::assert!(ratings.delivery_permille(2).is_none());
+    }
+
+    #[test]
+    fn test_hoppers_stay_together() {
+        let medium: SimMedium<3, 8> = SimMedium::new(SimConfig::default());
+        let mut coordinator_radio = medium.radio(0);
+        let mut node_radio = medium.radio(1);
+        let mut joining_radio = medium.radio(2);
+        let config = BlacklistConfig {
+            min_allowed: 2,
+            ..BlacklistConfig::default()
+        };
+        let mut coordinator = ChannelHopper::new(schedule(0xBEEF), config);
+        let mut node = ChannelHopper::new(schedule(0xBEEF), config);
+        let mut joining = ChannelHopper::new(schedule(0xBEEF), config);
+        node.set_coordinator(Some(1));
+
+        // Packets get through whatever channel the hop selects
+        let mut rendezvous_hops = 0;
+        for hop in 0..24u64 {
+            let now_ms = hop * DEFAULT_DWELL_MS as u64 + 10;
+            let next = defmt::unwrap!(block_on(
+                coordinator.tune(&mut coordinator_radio, Some(now_ms))
+            ));
+            defmt::assert!(next == Some((hop + 1) * DEFAULT_DWELL_MS as u64));
+            defmt::unwrap!(block_on(node.tune(&mut node_radio, Some(now_ms))));
+            defmt::assert!(
+                defmt::unwrap!(block_on(joining.tune(&mut joining_radio, None))).is_none()
+            );
+            defmt::assert!(node_radio.get_frequency() == coordinator_radio.get_frequency());
+
+            defmt::unwrap!(block_on(
+                coordinator_radio.transmit(&Packet::new(1, 2, hop as u16, b"x"))
+            ));
+            defmt::assert!(block_on(node_radio.receive()).is_ok());
+            // The joining node only hears the swarm on the rendezvous channel
+            if block_on(joining_radio.receive()).is_ok() {
+                defmt::assert!(coordinator.channel() == Some(DEFAULT_RENDEZVOUS_CHANNEL));
+                rendezvous_hops += 1;
+            }
+            for _ in 0..4 {
+                coordinator.record_delivery(coordinator.channel() != Some(5));
+            }
+        }
+        defmt::assert!(rendezvous_hops >= 3);
+        defmt::assert!(joining.stats().hops == 1);
+
+        // The coordinator blocks the failing channel, the node follows the announcement
+        let message = defmt::unwrap!(coordinator.evaluate(24_000));
+        defmt::assert!(!message.map.is_allowed(5));
+        defmt::assert!(message.from_hop == 24 + config.switch_delay_hops as u64);
+        defmt::assert!(coordinator.evaluate(24_000).is_none());
+
+        // Maps from other nodes are ignored, they would split the swarm
+        let rogue = ChannelMapMessage {
+            map: ChannelMap::from_bits(0b11),
+            ..message
+        };
+        node.on_channel_map(3, &rogue);
+        joining.on_channel_map(1, &message);
+        defmt::assert!(node.schedule().pending().is_none());
+        defmt::assert!(joining.schedule().pending().is_none());
+        defmt::assert!(node.stats().rejected_maps == 1 && joining.stats().rejected_maps == 1);
+
+        node.on_channel_map(1, &message);
+        for hop in message.from_hop..message.from_hop + 64 {
+            let now_ms = hop * DEFAULT_DWELL_MS as u64;
+            defmt::unwrap!(block_on(
+                coordinator.tune(&mut coordinator_radio, Some(now_ms))
+            ));
+            defmt::unwrap!(block_on(node.tune(&mut node_radio, Some(now_ms))));
+            defmt::assert!(node.channel() == coordinator.channel());
+            defmt::assert!(node.channel() != Some(5));
+        }
+        defmt::assert!(node.schedule().map() == message.map);
+        defmt::assert!(node.schedule().pending().is_none());
+
+        // Losing network time sends the node back to the rendezvous channel
+        defmt::unwrap!(block_on(node.tune(&mut node_radio, None)));
+        defmt::assert!(node_radio.get_frequency() == ChannelPlan::default().rendezvous_frequency());
+        defmt::assert!(node.stats().rendezvous_fallbacks == 1 && node.stats().map_updates == 1);
+    }
+
+    #[test]
+    fn test_map_is_repeated_on_rendezvous_hops() {
+        const DWELL_MS: u64 = DEFAULT_DWELL_MS as u64;
+        const INTERVAL: u64 = DEFAULT_RENDEZVOUS_INTERVAL as u64;
+        let medium: SimMedium<3, 4> = SimMedium::new(SimConfig::default());
+        let mut coordinator_radio = medium.radio(0);
+        let mut node_radio = medium.radio(1);
+        let mut late_radio = medium.radio(2);
+        let config = BlacklistConfig::default();
+        let mut coordinator = ChannelHopper::new(schedule(0xBEEF), config);
+        let mut node = ChannelHopper::new(schedule(0xBEEF), config);
+        let mut late = ChannelHopper::new(schedule(0xBEEF), config);
+        for hopper in [&mut coordinator, &mut node, &mut late] {
+            hopper.set_coordinator(Some(1));
+        }
+
+        let mut map = ChannelMap::all(DEFAULT_CHANNEL_COUNT as usize);
+        map.block(0);
+        map.block(7);
+        let scheduled = ChannelMapMessage {
+            map,
+            from_hop: 2 * INTERVAL - 2,
+        };
+        coordinator.on_channel_map(1, &scheduled);
+
+        // Only rendezvous hops repeat the announcement, once each
+        defmt::assert!(coordinator.announcement(3 * DWELL_MS).is_none());
+        let repeated = defmt::unwrap!(coordinator.announcement(INTERVAL * DWELL_MS + 10));
+        defmt::assert!(repeated == scheduled);
+        defmt::assert!(coordinator
+            .announcement(INTERVAL * DWELL_MS + 500)
+            .is_none());
+
+        // A node that missed the first announcement picks up the repetition
+        node.on_channel_map(1, &repeated);
+        node.on_channel_map(1, &repeated);
+        defmt::assert!(node.schedule().pending() == Some(&scheduled));
+        defmt::assert!(node.stats().map_updates == 1);
+
+        // Once active, the map is announced from the current hop on
+        let now_ms = 2 * INTERVAL * DWELL_MS;
+        defmt::unwrap!(block_on(
+            coordinator.tune(&mut coordinator_radio, Some(now_ms))
+        ));
+        let active = defmt::unwrap!(coordinator.announcement(now_ms));
+        defmt::assert!(active.map == map && active.from_hop == 2 * INTERVAL);
+        late.on_channel_map(1, &active);
+        for hop in 2 * INTERVAL + 1..4 * INTERVAL {
+            let now_ms = hop * DWELL_MS;
+            defmt::unwrap!(block_on(
+                coordinator.tune(&mut coordinator_radio, Some(now_ms))
+            ));
+            defmt::unwrap!(block_on(node.tune(&mut node_radio, Some(now_ms))));
+            defmt::unwrap!(block_on(late.tune(&mut late_radio, Some(now_ms))));
+            defmt::assert!(node.channel() == coordinator.channel());
+            defmt::assert!(late.channel() == coordinator.channel());
+        }
+        defmt::assert!(late.schedule().map() == map);
+
+        // Joining nodes get the active map with their network parameters
+        let mut join: JoinCoordinator<4> = JoinCoordinator::new(NetworkParams {
+            network_id: 0xBEEF,
+            gateway_id: 1,
+            channel: DEFAULT_RENDEZVOUS_CHANNEL,
+            report_interval_s: 60,
+            channel_map: ChannelMap::all(DEFAULT_CHANNEL_COUNT as usize),
+        });
+        join.set_channel_map(coordinator.schedule().map());
+        defmt::assert!(join.params().channel_map == map);
+    }
+}
@@ -0,0 +1,327 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::hopping::ChannelMap;
+use sensor_swarm::radio::join::NetworkParams;
+
+fn params() -> NetworkParams {
+    NetworkParams {
+        network_id: 0x5157,
+        gateway_id: 1,
+        channel: 3,
+        report_interval_s: 60,
+        channel_map: ChannelMap::from_bits(0x00DF),
+    }
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::params;
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::join::*;
+    use sensor_swarm::radio::message::{Message, MessageError, MessageType, MESSAGE_VERSION};
+    use sensor_swarm::radio::neighbor::NodeRole;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+    use sensor_swarm::radio::security::{PacketSecurity, SecureRadio};
+    use sensor_swarm::radio::traits::{RadioReceiver, RadioTransmitter};
+    use sensor_swarm::testing::board_sim::{board_uid, RamFlash, SIM_NETWORK_KEY};
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    #[test]
+    fn test_join_messages_roundtrip() {
+        let request = JoinRequest {
+            uid: board_uid(1),
+            previous_id: UNASSIGNED_NODE_ID,
+            role: NodeRole::Relay,
+        };
+        let packet = request.to_packet(1);
+        defmt::assert!(packet.header.sender_id == UNASSIGNED_NODE_ID);
+        defmt::assert!(packet.header.target_id == BROADCAST_ID);
+        defmt::assert!(Message::from_packet(&packet) == Ok(Message::JoinRequest(request)));
+
+        let accept = JoinAccept {
+            uid: board_uid(1),
+            node_id: 17,
+            params: params(),
+            key_follows: true,
+        };
+        let packet = accept.to_packet(1, 2);
+        defmt::assert!(Message::from_packet(&packet) == Ok(Message::JoinAccept(accept)));
+
+        let key = SessionKey {
+            key_id: 2,
+            key: [0xA5; 16],
+        };
+        let packet = Message::SessionKey(key).to_packet(1, 17, 3);
+        defmt::assert!(Message::from_packet(&packet) == Ok(Message::SessionKey(key)));
+
+        let mut bad_role = [0u8; 2 + JOIN_REQUEST_SIZE];
+        bad_role[0] = MESSAGE_VERSION;
+        bad_role[1] = MessageType::JoinRequest as u8;
+        bad_role[2 + JOIN_REQUEST_SIZE - 1] = 9;
+        defmt::assert!(
+            Message::from_packet(&Packet::new(UNASSIGNED_NODE_ID, 0, 1, &bad_role))
+                == Err(MessageError::Malformed(MessageType::JoinRequest))
+        );
+    }
+
+    #[test]
+    fn test_handshake_assigns_id_and_key() {
+        let mut coordinator: JoinCoordinator<8> = JoinCoordinator::new(params());
+        coordinator.set_session_key(Some(SessionKey {
+            key_id: 1,
+            key: [0x3C; 16],
+        }));
+        let mut client = JoinClient::new(board_uid(1), NodeRole::Sensor, None);
+        defmt::assert!(!client.is_joined());
+
+        let request_packet = defmt::unwrap!(client.poll(0));
+        // Requests are repeated with a growing delay until an accept arrives
+        defmt::assert!(client.poll(DEFAULT_JOIN_RETRY_MS - 1).is_none());
+        let retry = defmt::unwrap!(client.poll(2 * DEFAULT_JOIN_RETRY_MS));
+        defmt::assert!(retry.header.sequence_number != request_packet.header.sequence_number);
+
+        let Ok(Message::JoinRequest(request)) = Message::from_packet(&request_packet) else {
+            defmt::panic!("expected a join request");
+        };
+        let accept = defmt::unwrap!(coordinator.handle_request(&request));
+        // The gateway's own ID is never handed out
+        defmt::assert!(accept.node_id == 2);
+        defmt::assert!(accept.key_follows);
+
+        let packet = accept.to_packet(1, 1);
+        let message = defmt::unwrap!(Message::from_packet(&packet));
+        defmt::assert!(client.handle(&packet.header, &message, 10) == JoinEvent::Joined);
+        defmt::assert!(client.node_id() == Some(2));
+        defmt::assert!(client.awaiting_key());
+        defmt::assert!(client.poll(1_000_000).is_none());
+
+        // A duplicate accept for a repeated request changes nothing
+        defmt::assert!(client.handle(&packet.header, &message, 11) == JoinEvent::Ignored);
+
+        let mut key_packet = defmt::unwrap!(coordinator.session_key_packet(2, 2));
+        defmt::assert!(key_packet.header.target_id == 2);
+        let message = defmt::unwrap!(Message::from_packet(&key_packet));
+        // A key sent in clear is never installed, even while unsecured packets pass
+        defmt::assert!(client.handle(&key_packet.header, &message, 12) == JoinEvent::Ignored);
+        defmt::assert!(client.awaiting_key());
+
+        // `SecureRadio` keeps the flag set on packets it opened
+        key_packet.header.control.set_secured(true);
+        defmt::assert!(client.handle(&key_packet.header, &message, 12) == JoinEvent::KeyReceived);
+        defmt::assert!(!client.awaiting_key());
+        let membership = defmt::unwrap!(client.membership().copied());
+        defmt::assert!(defmt::unwrap!(membership.session_key).key == [0x3C; 16]);
+        defmt::assert!(membership.params == params());
+    }
+
+    #[test]
+    fn test_boards_join_over_secure_radio() {
+        let medium: SimMedium<3, 8> = SimMedium::new(SimConfig::default());
+        let security = PacketSecurity::<RamFlash<2>, 4>::new(
+            1,
+            &board_uid(0),
+            &SIM_NETWORK_KEY,
+            RamFlash::new(),
+            0,
+        );
+        let mut gateway = SecureRadio::new(medium.radio(0), security);
+        let mut coordinator: JoinCoordinator<8> = JoinCoordinator::new(params());
+        coordinator.set_session_key(Some(SessionKey {
+            key_id: 1,
+            key: [0x3C; 16],
+        }));
+        let mut boards = [1u8, 2].map(|last| {
+            let security = PacketSecurity::<RamFlash<2>, 4>::new(
+                UNASSIGNED_NODE_ID,
+                &board_uid(last),
+                &SIM_NETWORK_KEY,
+                RamFlash::new(),
+                0,
+            );
+            let radio = SecureRadio::new(medium.radio(last as usize), security);
+            (
+                radio,
+                JoinClient::new(board_uid(last), NodeRole::Sensor, None),
+            )
+        });
+
+        // Both boards seal their first request as the unassigned ID with counter 0
+        for (radio, client) in boards.iter_mut() {
+            let request = defmt::unwrap!(client.poll(0));
+            defmt::unwrap!(block_on(radio.transmit(&request)));
+        }
+
+        let mut sequence_number = 0;
+        for _ in 0..2 {
+            let packet = defmt::unwrap!(block_on(gateway.receive()));
+            let Ok(Message::JoinRequest(request)) = Message::from_packet(&packet) else {
+                defmt::panic!("expected a join request");
+            };
+            let accept = defmt::unwrap!(coordinator.handle_request(&request));
+            sequence_number += 1;
+            defmt::unwrap!(block_on(
+                gateway.transmit(&accept.to_packet(1, sequence_number))
+            ));
+            sequence_number += 1;
+            let key_packet =
+                defmt::unwrap!(coordinator.session_key_packet(accept.node_id, sequence_number));
+            defmt::unwrap!(block_on(gateway.transmit(&key_packet)));
+        }
+        defmt::assert!(gateway.stats().replays == 0);
+        defmt::assert!(coordinator.len() == 2);
+
+        for (radio, client) in boards.iter_mut() {
+            while radio.packet_available() {
+                let packet = defmt::unwrap!(block_on(radio.receive()));
+                let message = defmt::unwrap!(Message::from_packet(&packet));
+                if client.handle(&packet.header, &message, 0) == JoinEvent::Joined {
+                    radio
+                        .security()
+                        .set_node_id(defmt::unwrap!(client.node_id()));
+                }
+            }
+            // The key arrived sealed, `SecureRadio` kept the flag for the client
+            defmt::assert!(client.is_joined());
+            defmt::assert!(!client.awaiting_key());
+            let membership = defmt::unwrap!(client.membership().copied());
+            defmt::assert!(defmt::unwrap!(membership.session_key).key == [0x3C; 16]);
+        }
+        let ids = boards.each_ref().map(|(_, client)| client.node_id());
+        defmt::assert!(ids == [Some(2), Some(3)] || ids == [Some(3), Some(2)]);
+
+        // Joined boards transmit with their assigned IDs
+        let (radio, client) = &mut boards[0];
+        let node_id = defmt::unwrap!(client.node_id());
+        defmt::unwrap!(block_on(radio.transmit(&Packet::new(node_id, 1, 1, b"hi"))));
+        let packet = defmt::unwrap!(block_on(gateway.receive()));
+        defmt::assert!(packet.header.relay_id == node_id);
+        defmt::assert!(packet.payload_data() == b"hi");
+    }
+
+    #[test]
+    fn test_membership_survives_reboot() {
+        let membership = Membership {
+            node_id: 42,
+            params: params(),
+            session_key: Some(SessionKey {
+                key_id: 7,
+                key: [0x11; 16],
+            }),
+        };
+        let mut flash: RamFlash<1> = RamFlash::new();
+        defmt::assert!(Membership::load(&flash, 0).is_none());
+        defmt::unwrap!(membership.store(&mut flash, 0));
+        defmt::assert!(Membership::load(&flash, 0) == Some(membership));
+
+        let client = JoinClient::new(board_uid(1), NodeRole::Sensor, Membership::load(&flash, 0));
+        defmt::assert!(client.node_id() == Some(42));
+
+        // Corrupted and forgotten records are not used
+        let mut record = membership.to_bytes();
+        record[2] ^= 0x01;
+        defmt::assert!(Membership::from_bytes(&record).is_none());
+        defmt::unwrap!(Membership::forget(&mut flash, 0));
+        defmt::assert!(Membership::load(&flash, 0).is_none());
+    }
+
+    #[test]
+    fn test_rejoin_keeps_previous_id() {
+        let mut coordinator: JoinCoordinator<8> = JoinCoordinator::new(params());
+        let first = JoinRequest {
+            uid: board_uid(1),
+            previous_id: UNASSIGNED_NODE_ID,
+            role: NodeRole::Sensor,
+        };
+        defmt::assert!(defmt::unwrap!(coordinator.handle_request(&first)).node_id == 2);
+        // A known board gets the same ID again
+        defmt::assert!(defmt::unwrap!(coordinator.handle_request(&first)).node_id == 2);
+        defmt::assert!(coordinator.stats().rejoins == 1);
+
+        // After a gateway restart the board asks for its previous ID
+        let mut restarted: JoinCoordinator<8> = JoinCoordinator::new(params());
+        let rejoin = JoinRequest {
+            previous_id: 9,
+            ..first
+        };
+        defmt::assert!(defmt::unwrap!(restarted.handle_request(&rejoin)).node_id == 9);
+
+        // Another board claiming the same ID gets a free one instead
+        let other = JoinRequest {
+            uid: board_uid(2),
+            ..rejoin
+        };
+        defmt::assert!(defmt::unwrap!(restarted.handle_request(&other)).node_id == 2);
+        defmt::assert!(restarted.stats().conflicts == 1);
+        defmt::assert!(restarted.node_id_of(&board_uid(2)) == Some(2));
+        defmt::assert!(restarted.len() == 2);
+    }
+
+    #[test]
+    fn test_client_rejoins_on_id_conflict() {
+        let stored = Membership {
+            node_id: 5,
+            params: params(),
+            session_key: None,
+        };
+        let mut client = JoinClient::new(board_uid(1), NodeRole::Sensor, Some(stored));
+
+        // Accepts for other boards with other IDs do not matter
+        let unrelated = JoinAccept {
+            uid: board_uid(2),
+            node_id: 6,
+            params: params(),
+            key_follows: false,
+        };
+        let packet = unrelated.to_packet(1, 1);
+        let message = Message::JoinAccept(unrelated);
+        defmt::assert!(client.handle(&packet.header, &message, 0) == JoinEvent::Ignored);
+
+        let conflicting = JoinAccept {
+            node_id: 5,
+            ..unrelated
+        };
+        let message = Message::JoinAccept(conflicting);
+        defmt::assert!(client.handle(&packet.header, &message, 100) == JoinEvent::Conflict);
+        defmt::assert!(!client.is_joined());
+
+        let request = defmt::unwrap!(client.poll(100));
+        let Ok(Message::JoinRequest(request)) = Message::from_packet(&request) else {
+            defmt::panic!("expected a join request");
+        };
+        defmt::assert!(request.previous_id == UNASSIGNED_NODE_ID);
+    }
+
+    #[test]
+    fn test_full_table_ignores_new_boards() {
+        let mut coordinator: JoinCoordinator<2> = JoinCoordinator::new(params());
+        for last in 0..2 {
+            let request = JoinRequest {
+                uid: board_uid(last),
+                previous_id: UNASSIGNED_NODE_ID,
+                role: NodeRole::Sensor,
+            };
+            defmt::unwrap!(coordinator.handle_request(&request));
+        }
+        let late = JoinRequest {
+            uid: board_uid(9),
+            previous_id: UNASSIGNED_NODE_ID,
+            role: NodeRole::Sensor,
+        };
+        defmt::assert!(coordinator.handle_request(&late).is_none());
+        defmt::assert!(coordinator.stats().table_full == 1);
+
+        defmt::assert!(coordinator.release(2));
+        defmt::assert!(defmt::unwrap!(coordinator.handle_request(&late)).node_id == 2);
+    }
+}
@@ -0,0 +1,199 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::link_quality::LinkTable;
+use sensor_swarm::radio::protocol::{Header, Packet};
+
+/// Header of a packet the neighbor originated itself
+fn header(neighbor: u16, sequence_number: u16) -> Header {
+    Packet::new(neighbor, 1, sequence_number, b"x").header
+}
+
+/// Let `neighbor` send one packet per pattern entry, `false` entries are lost
+///
+/// # Returns
+/// The sequence number following the last packet
+fn replay<const N: usize>(
+    table: &mut LinkTable<N>,
+    neighbor: u16,
+    first_sequence: u16,
+    pattern: &[bool],
+    rounds: usize,
+) -> u16 {
+    let mut sequence_number = first_sequence;
+    for _ in 0..rounds {
+        for &received in pattern {
+            if received {
+                table.observe(&header(neighbor, sequence_number), 0);
+            }
+            sequence_number = sequence_number.wrapping_add(1);
+        }
+    }
+    sequence_number
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::{header, replay};
+    use sensor_swarm::radio::link_quality::*;
+    use sensor_swarm::radio::neighbor::NodeRole;
+    use sensor_swarm::radio::protocol::Packet;
+    use sensor_swarm::radio::reliable::DeliveryOutcome;
+    use sensor_swarm::radio::routing::{self, Router, PERFECT_LINK_COST};
+    use sensor_swarm::radio::traits::RadioError;
+
+    #[test]
+    fn test_lossless_link() {
+        let mut table: LinkTable<4> = LinkTable::new(10_000);
+        replay(&mut table, 7, 100, &[true], MIN_LINK_SAMPLES as usize - 1);
+        let link = defmt::unwrap!(table.get(7));
+        // Too few packets to judge the link yet
+        defmt::assert!(link.etx_centi().is_none());
+        defmt::assert!(table.link_cost(7).is_none());
+
+        replay(&mut table, 7, 103, &[true], 20);
+        let link = defmt::unwrap!(table.get(7));
+        defmt::assert!(link.reception_permille() == Some(1_000));
+        defmt::assert!(link.delivery_permille().is_none());
+        defmt::assert!(link.etx_centi() == Some(100));
+        defmt::assert!(table.link_cost(7) == Some(PERFECT_LINK_COST));
+    }
+
+    #[test]
+    fn test_scripted_loss_patterns() {
+        // Every fourth packet lost
+        let mut table: LinkTable<4> = LinkTable::new(10_000);
+        replay(&mut table, 7, 0, &[true, true, true, false], 16);
+        let link = defmt::unwrap!(table.get(7));
+        let prr = defmt::unwrap!(link.reception_permille());
+        defmt::assert!((650..=850).contains(&prr));
+        // Symmetric link assumed: ETX = 1 / PRR^2
+        let etx = defmt::unwrap!(link.etx_centi());
+        defmt::assert!((140..=240).contains(&etx));
+
+        // Bursts of loss hurt as much as spread loss of the same rate
+        let mut bursty: LinkTable<4> = LinkTable::new(10_000);
+        let pattern = [true, true, true, true, true, true, false, false];
+        replay(&mut bursty, 7, 0, &pattern, 16);
+        let prr = defmt::unwrap!(defmt::unwrap!(bursty.get(7)).reception_permille());
+        defmt::assert!((600..=900).contains(&prr));
+
+        // A link that goes dead is quickly rated as such
+        let mut dying: LinkTable<4> = LinkTable::new(10_000);
+        let next = replay(&mut dying, 7, 0, &[true], 32);
+        replay(
+            &mut dying,
+            7,
+            next,
+            &[false, false, false, false, false, true],
+            4,
+        );
+        let link = defmt::unwrap!(dying.get(7));
+        defmt::assert!(defmt::unwrap!(link.reception_permille()) < 400);
+        defmt::assert!(link.etx_centi() == Some(MAX_ETX_CENTI));
+
+        // Estimates are deterministic for the same loss pattern
+        let mut again: LinkTable<4> = LinkTable::new(10_000);
+        replay(&mut again, 7, 0, &[true, true, true, false], 16);
+        defmt::assert!(again.get(7) == table.get(7));
+    }
+
+    #[test]
+    fn test_ack_outcomes_drive_etx() {
+        let mut table: LinkTable<4> = LinkTable::new(10_000);
+        replay(&mut table, 7, 0, &[true], 16);
+
+        // Half of the transmissions need a retry
+        for _ in 0..16 {
+            table.record_delivery(7, &DeliveryOutcome::Delivered { attempts: 1 }, 0);
+            table.record_delivery(7, &DeliveryOutcome::Delivered { attempts: 2 }, 0);
+        }
+        let link = defmt::unwrap!(table.get(7));
+        let delivery = defmt::unwrap!(link.delivery_permille());
+        defmt::assert!((600..=720).contains(&delivery));
+        // The ack-based ratio wins over the perfect reception ratio
+        defmt::assert!(link.reception_permille() == Some(1_000));
+        let etx = defmt::unwrap!(link.etx_centi());
+        defmt::assert!((140..=165).contains(&etx));
+
+        // Radio errors say nothing about the link, timeouts do
+        let before = link.clone();
+        table.record_delivery(7, &DeliveryOutcome::Failed(RadioError::NotReady), 0);
+        defmt::assert!(table.get(7) == Some(&before));
+        table.record_delivery(7, &DeliveryOutcome::TimedOut { attempts: 4 }, 0);
+        defmt::assert!(defmt::unwrap!(table.get(7)).etx_centi() > Some(etx));
+    }
+
+    #[test]
+    fn test_only_own_sequence_numbers_count() {
+        let mut table: LinkTable<4> = LinkTable::new(10_000);
+        replay(&mut table, 7, 10, &[true], 8);
+
+        // Repeats, acks, relayed packets and counter restarts are not losses
+        table.observe(&header(7, 17), 0);
+        let mut ack = header(7, 40);
+        ack.control.set_ack_response(true);
+        table.observe(&ack, 0);
+        let mut relayed = header(9, 60);
+        relayed.relay_id = 7;
+        table.observe(&relayed, 0);
+        table.observe(&header(7, 1_000), 0);
+        table.observe(&header(7, 995), 0);
+        defmt::assert!(defmt::unwrap!(table.get(7)).reception_permille() == Some(1_000));
+
+        // Losses are still counted while the counter wraps around
+        let next = replay(&mut table, 8, u16::MAX - 3, &[true, false], 4);
+        defmt::assert!(next == 4);
+        defmt::assert!(defmt::unwrap!(table.get(8)).reception_permille() < Some(600));
+    }
+
+    #[test]
+    fn test_table_capacity_and_expiry() {
+        let mut table: LinkTable<2> = LinkTable::new(1_000);
+        table.observe(&header(1, 0), 10);
+        table.observe(&header(2, 0), 20);
+        table.observe(&header(1, 1), 30);
+        // The least recently updated link makes room
+        table.record_ack(3, true, 40);
+        defmt::assert!(table.len() == 2);
+        defmt::assert!(table.get(2).is_none());
+
+        defmt::assert!(table.expire(1_035) == 1);
+        defmt::assert!(table.get(3).is_some());
+
+        let summary = defmt::unwrap!(table.get(3)).summary(540);
+        defmt::assert!(summary.node_id == 3 && summary.age_ms == 500);
+        defmt::assert!(summary.delivery_permille.is_none());
+    }
+
+    #[test]
+    fn test_shared_table_serves_routing() {
+        const NEIGHBOR: u16 = 0x0077;
+        for sequence_number in 0..32u16 {
+            if sequence_number % 2 == 0 {
+                observe_link(&LINKS, &header(NEIGHBOR, sequence_number), 0);
+            }
+        }
+        let cost = defmt::unwrap!(link_cost(&LINKS, NEIGHBOR));
+        defmt::assert!(cost > 2 * PERFECT_LINK_COST);
+        // A strong signal does not hide the measured loss
+        defmt::assert!(routing::link_cost(&LINKS, NEIGHBOR, Some(-40)) == cost);
+
+        let mut router: Router<4> = Router::new(1, NodeRole::Sensor, 10_000, &LINKS);
+        router.learn(&Packet::new(NEIGHBOR, 1, 32, b"x"), Some(-40), 0);
+        defmt::assert!(defmt::unwrap!(router.table().get(NEIGHBOR)).cost == cost);
+
+        let snapshot = links_snapshot(0);
+        defmt::assert!(snapshot.iter().any(|link| link.node_id == NEIGHBOR));
+    }
+}
@@ -0,0 +1,170 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::manchester::bits_msb_first;
+
+/// Expand a packed chip stream into `samples_per_chip` samples per chip
+fn oversample(chips: &[u8], samples_per_chip: usize, out: &mut [bool]) -> usize {
+    let mut len = 0;
+    for chip in bits_msb_first(chips) {
+        for _ in 0..samples_per_chip {
+            out[len] = chip;
+            len += 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use super::oversample;
+    use sensor_swarm::radio::manchester::*;
+
+    #[test]
+    fn test_encode_ieee_convention() {
+        let codec = ManchesterCodec::default();
+        let mut chips = [0u8; 4];
+
+        // 0xF0 -> 1111 0000 -> 01010101 10101010
+        let len = defmt::unwrap!(codec.encode(&[0xF0, 0x0F], &mut chips));
+        defmt::assert!(len == 4);
+        defmt::assert!(chips == [0x55, 0xAA, 0xAA, 0x55]);
+    }
+
+    #[test]
+    fn test_encode_thomas_convention() {
+        let codec = ManchesterCodec::new(ManchesterConvention::Thomas);
+        let mut chips = [0u8; 2];
+
+        defmt::unwrap!(codec.encode(&[0xF0], &mut chips));
+        defmt::assert!(chips == [0xAA, 0x55]);
+    }
+
+    #[test]
+    fn test_packed_roundtrip() {
+        let codec = ManchesterCodec::default();
+        let data = [0x00, 0xFF, 0xA5, 0x3C, 0x81];
+        let mut chips = [0u8; 10];
+        let mut decoded = [0u8; 5];
+
+        defmt::unwrap!(codec.encode(&data, &mut chips));
+        let len = defmt::unwrap!(codec.decode(&chips, &mut decoded));
+        defmt::assert!(len == data.len());
+        defmt::assert!(decoded == data);
+    }
+
+    #[test]
+    fn test_invalid_symbol_reports_bit_index() {
+        let codec = ManchesterCodec::default();
+        let mut decoded = [0u8; 1];
+
+        // Third bit is encoded as `11`
+        let chips = [0b0101_1101, 0b0101_0101];
+        defmt::assert!(
+            codec.decode(&chips, &mut decoded)
+                == Err(ManchesterError::InvalidSymbol { bit_index: 2 })
+        );
+    }
+
+    #[test]
+    fn test_buffer_errors() {
+        let codec = ManchesterCodec::default();
+        let mut small = [0u8; 1];
+
+        defmt::assert!(codec.encode(&[1, 2], &mut small) == Err(ManchesterError::BufferTooSmall));
+        defmt::assert!(codec.decode(&[0x55], &mut small) == Err(ManchesterError::Truncated));
+        defmt::assert!(
+            codec.decode(&[0x55, 0x55, 0x55, 0x55], &mut small)
+                == Err(ManchesterError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_oversampled_roundtrip() {
+        let codec = ManchesterCodec::default();
+        let data = [0xC3, 0x5A, 0x00];
+        let mut chips = [0u8; 6];
+        defmt::unwrap!(codec.encode(&data, &mut chips));
+
+        let mut samples = [false; 6 * 8 * 4];
+        let len = oversample(&chips, 4, &mut samples);
+
+        let mut decoded = [0u8; 3];
+        let count =
+            defmt::unwrap!(codec.decode_samples(samples[..len].iter().copied(), 4, &mut decoded));
+        defmt::assert!(count == 3);
+        defmt::assert!(decoded == data);
+    }
+
+    #[test]
+    fn test_oversampled_tolerates_jitter_and_idle_tail() {
+        let codec = ManchesterCodec::default();
+        let mut chips = [0u8; 2];
+        defmt::unwrap!(codec.encode(&[0x96], &mut chips));
+
+        let mut samples = [false; 160];
+        let mut len = oversample(&chips, 8, &mut samples);
+
+        // Stretch the first run by 3 samples and shorten a later one by 3 samples
+        samples.copy_within(0..len, 3);
+        len += 3;
+        let second_edge = (3..len).find(|&i| samples[i] != samples[0]).unwrap_or(len);
+        let third_edge = (second_edge..len)
+            .find(|&i| samples[i] != samples[second_edge])
+            .unwrap_or(len);
+        samples.copy_within(third_edge.., third_edge - 3);
+        len -= 3;
+
+        // Idle line after the frame merges with the last chip
+        let last = samples[len - 1];
+        for sample in samples[len..len + 20].iter_mut() {
+            *sample = last;
+        }
+        len += 20;
+
+        let mut decoded = [0u8; 2];
+        let count =
+            defmt::unwrap!(codec.decode_samples(samples[..len].iter().copied(), 8, &mut decoded));
+        defmt::assert!(count == 1);
+        defmt::assert!(decoded[0] == 0x96);
+    }
+
+    #[test]
+    fn test_oversampled_clock_recovery_errors() {
+        let codec = ManchesterCodec::default();
+        let mut decoded = [0u8; 4];
+
+        // Glitch of a single sample with 8 samples per chip
+        let mut samples = [false; 40];
+        samples[8..16].fill(true);
+        samples[20] = true;
+        defmt::assert!(
+            codec.decode_samples(samples.iter().copied(), 8, &mut decoded)
+                == Err(ManchesterError::ClockRecovery { sample_index: 20 })
+        );
+
+        // Missing transition: three chip periods at the same level
+        let mut samples = [false; 48];
+        samples[0..24].fill(true);
+        defmt::assert!(
+            codec.decode_samples(samples.iter().copied(), 8, &mut decoded)
+                == Err(ManchesterError::ClockRecovery { sample_index: 0 })
+        );
+
+        defmt::assert!(
+            codec.decode_samples(core::iter::empty(), 8, &mut decoded)
+                == Err(ManchesterError::ClockRecovery { sample_index: 0 })
+        );
+    }
+}
@@ -0,0 +1,169 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use sensor_swarm::radio::fragment::Fragments;
+    use sensor_swarm::radio::message::*;
+    use sensor_swarm::radio::neighbor::{Beacon, NodeRole};
+    use sensor_swarm::radio::protocol::{Header, Packet, BROADCAST_ID};
+    use sensor_swarm::radio::report::SensorReport;
+    use sensor_swarm::radio::timesync::TimeSyncMessage;
+    use sensor_swarm::sensors::traits::{DataValidity, EnvironmentalData};
+
+    #[test]
+    fn test_every_message_type_roundtrips() {
+        let mut reading = EnvironmentalData::new();
+        reading.temperature_celsius_x100 = -1250;
+        reading.humidity_percent_x100 = 6525;
+        reading.pressure_pa = 101_325;
+        reading.timestamp_ms = 0x0102_0304_0506;
+        reading.validity = DataValidity::all_valid();
+
+        let messages = [
+            Message::SensorReport(defmt::unwrap!(SensorReport::single(&reading))),
+            Message::Beacon(Beacon::new(7, NodeRole::Relay)),
+            Message::TimeSync(TimeSyncMessage {
+                root_id: 1,
+                round: 9,
+                depth: 2,
+                global_time_ms: 123_456,
+            }),
+            Message::CommandRequest {
+                request_id: 3,
+                command: defmt::unwrap!(MessageText::try_from("sensors")),
+            },
+            Message::CommandResponse {
+                request_id: 3,
+                output: defmt::unwrap!(MessageText::try_from("T=-12.50C")),
+            },
+            Message::Ack {
+                sequence_number: 0xBEEF,
+            },
+            Message::ConfigUpdate {
+                key: 4,
+                value: 30_000,
+            },
+            Message::LogLine {
+                level: LogLevel::Warn,
+                text: defmt::unwrap!(MessageText::try_from("battery low")),
+            },
+        ];
+
+        for message in messages.iter() {
+            let packet = message.to_packet(7, BROADCAST_ID, 1);
+            let payload = packet.payload_data();
+            defmt::assert!(payload[0] == MESSAGE_VERSION);
+            defmt::assert!(payload[1] == message.message_type() as u8);
+            defmt::assert!(defmt::unwrap!(Message::from_packet(&packet)) == *message);
+        }
+    }
+
+    #[test]
+    fn test_newer_and_unknown_messages_are_rejected() {
+        let unknown = Packet::new(1, 0, 0, &[MESSAGE_VERSION, 0xEE, 1, 2, 3]);
+        defmt::assert!(Message::from_packet(&unknown) == Err(MessageError::UnknownType(0xEE)));
+
+        let newer = Packet::new(
+            1,
+            0,
+            0,
+            &[MESSAGE_VERSION + 1, MessageType::Ack as u8, 1, 0],
+        );
+        defmt::assert!(
+            Message::from_packet(&newer)
+                == Err(MessageError::UnsupportedVersion(MESSAGE_VERSION + 1))
+        );
+
+        defmt::assert!(Message::decode(1, &[MESSAGE_VERSION]) == Err(MessageError::Truncated));
+        defmt::assert!(
+            Message::decode(1, &[MESSAGE_VERSION, MessageType::Ack as u8, 1])
+                == Err(MessageError::Malformed(MessageType::Ack))
+        );
+        defmt::assert!(
+            Message::decode(1, &[MESSAGE_VERSION, MessageType::LogLine as u8, 9, b'x'])
+                == Err(MessageError::Malformed(MessageType::LogLine))
+        );
+    }
+
+    #[test]
+    fn test_trailing_body_bytes_are_ignored() {
+        // A later version may append fields to an existing message
+        let payload = [
+            MESSAGE_VERSION,
+            MessageType::Ack as u8,
+            0x34,
+            0x12,
+            0xFF,
+            0xFF,
+        ];
+        defmt::assert!(
+            Message::decode(1, &payload)
+                == Ok(Message::Ack {
+                    sequence_number: 0x1234
+                })
+        );
+    }
+
+    #[test]
+    fn test_dispatcher_routes_by_type() {
+        let mut acks = 0;
+        let mut any = 0;
+        let mut on_ack = |_: &Header, message: &Message| {
+            defmt::assert!(message.message_type() == MessageType::Ack);
+            acks += 1;
+        };
+        let mut on_ack_too = |_: &Header, _: &Message| any += 1;
+
+        let mut dispatcher: Dispatcher<4> = Dispatcher::new();
+        defmt::assert!(dispatcher.register(MessageType::Ack, &mut on_ack));
+        defmt::assert!(dispatcher.register(MessageType::Ack, &mut on_ack_too));
+
+        let ack = Message::Ack { sequence_number: 5 }.to_packet(2, 1, 10);
+        defmt::assert!(dispatcher.dispatch(&ack) == DispatchOutcome::Handled(2));
+
+        let config = Message::ConfigUpdate { key: 1, value: 2 }.to_packet(2, 1, 11);
+        defmt::assert!(
+            dispatcher.dispatch(&config) == DispatchOutcome::Unhandled(MessageType::ConfigUpdate)
+        );
+
+        let garbage = Packet::new(2, 1, 12, &[0xFF, 0xFF]);
+        defmt::assert!(
+            dispatcher.dispatch(&garbage)
+                == DispatchOutcome::Skipped(MessageError::UnsupportedVersion(0xFF))
+        );
+
+        let stats = *dispatcher.stats();
+        defmt::assert!(stats.handled == 1);
+        defmt::assert!(stats.unhandled == 1);
+        defmt::assert!(stats.skipped == 1);
+        drop(dispatcher);
+        defmt::assert!(acks == 1);
+        defmt::assert!(any == 1);
+    }
+
+    #[test]
+    fn test_fragments_are_not_decoded_as_messages() {
+        let mut dispatcher: Dispatcher<1> = Dispatcher::new();
+        let message = Message::Ack { sequence_number: 1 }.encode();
+        let template = Packet::new(1, 2, 0, &[]).header;
+        let mut fragments = defmt::unwrap!(Fragments::new(template, 1, &message));
+        let fragment = defmt::unwrap!(fragments.next());
+
+        defmt::assert!(dispatcher.dispatch(&fragment) == DispatchOutcome::Fragment);
+        defmt::assert!(
+            dispatcher.dispatch_payload(&fragment.header, &message)
+                == DispatchOutcome::Unhandled(MessageType::Ack)
+        );
+    }
+}
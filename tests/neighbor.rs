@@ -0,0 +1,160 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use sensor_swarm::radio::message::{MessageType, MESSAGE_VERSION};
+    use sensor_swarm::radio::neighbor::*;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+
+    #[test]
+    fn test_beacon_packet_roundtrip() {
+        let beacon = Beacon::new(0x0042, NodeRole::Relay);
+        let packet = beacon.to_packet(7);
+        defmt::assert!(packet.header.target_id == BROADCAST_ID);
+        defmt::assert!(packet.header.ttl == 0);
+        defmt::assert!(defmt::unwrap!(Beacon::from_packet(&packet)) == beacon);
+        defmt::assert!(beacon.firmware == FirmwareVersion::current());
+        defmt::assert!(beacon.uid_tag.is_none());
+
+        let tagged = beacon.with_uid_tag(Some(0xA1B2_C3D4));
+        defmt::assert!(defmt::unwrap!(Beacon::from_packet(&tagged.to_packet(8))) == tagged);
+
+        // Beacons of older firmware end after the route fields
+        let legacy = Packet::new(
+            1,
+            0,
+            0,
+            &[
+                MESSAGE_VERSION,
+                MessageType::Beacon as u8,
+                1,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0xFF,
+                0xFF,
+            ],
+        );
+        let legacy = defmt::unwrap!(Beacon::from_packet(&legacy));
+        defmt::assert!(legacy.role == NodeRole::Relay && legacy.uid_tag.is_none());
+
+        // Ordinary data and unknown roles are not beacons
+        defmt::assert!(Beacon::from_packet(&Packet::new(1, 0, 0, b"hello")).is_none());
+        let bad_role = Packet::new(
+            1,
+            0,
+            0,
+            &[
+                MESSAGE_VERSION,
+                MessageType::Beacon as u8,
+                9,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0xFF,
+                0xFF,
+            ],
+        );
+        defmt::assert!(Beacon::from_packet(&bad_role).is_none());
+    }
+
+    #[test]
+    fn test_beacon_scheduler_interval_and_jitter() {
+        let mut scheduler = BeaconScheduler::new(1_000, 100, 5);
+        defmt::assert!(scheduler.poll(0));
+        defmt::assert!(!scheduler.poll(999));
+
+        let due = scheduler.next_due_ms();
+        defmt::assert!((1_000..=1_100).contains(&due));
+        defmt::assert!(scheduler.poll(due));
+        defmt::assert!(!scheduler.poll(due));
+    }
+
+    #[test]
+    fn test_table_tracks_packets_and_smoothed_rssi() {
+        let mut table: NeighborTable<4> = NeighborTable::new(10_000);
+        let beacon = Beacon::new(5, NodeRole::Sensor).to_packet(0);
+
+        table.observe(&beacon, Some(-60), 100);
+        table.observe(&Packet::new(5, 1, 1, b"data"), Some(-80), 200);
+        // Data from an unknown node does not create an entry
+        table.observe(&Packet::new(6, 1, 1, b"data"), Some(-50), 200);
+        // A packet relayed by another node says nothing about its originator
+        let mut relayed = Packet::new(5, 1, 2, b"data");
+        relayed.header.relay_id = 6;
+        table.observe(&relayed, Some(-40), 300);
+
+        defmt::assert!(table.len() == 1);
+        let entry = defmt::unwrap!(table.get(5));
+        defmt::assert!(entry.role == NodeRole::Sensor);
+        defmt::assert!(entry.packet_count == 2);
+        defmt::assert!(entry.last_seen_ms == 200);
+        defmt::assert!(entry.rssi_dbm() == Some(-65));
+
+        let mut history = entry.rssi_history();
+        defmt::assert!(history.next() == Some(-60));
+        defmt::assert!(history.next() == Some(-80));
+        defmt::assert!(history.next().is_none());
+    }
+
+    #[test]
+    fn test_silent_neighbors_age_out() {
+        let mut table: NeighborTable<4> = NeighborTable::new(1_000);
+        table.observe(&Beacon::new(1, NodeRole::Relay).to_packet(0), None, 0);
+        table.observe(&Beacon::new(2, NodeRole::Gateway).to_packet(0), None, 800);
+
+        defmt::assert!(table.expire(1_500) == 1);
+        defmt::assert!(table.get(1).is_none());
+        defmt::assert!(table.get(2).is_some());
+        defmt::assert!(defmt::unwrap!(table.get(2)).rssi_dbm().is_none());
+    }
+
+    #[test]
+    fn test_full_table_replaces_least_recent_neighbor() {
+        let mut table: NeighborTable<2> = NeighborTable::default();
+        table.observe(&Beacon::new(1, NodeRole::Sensor).to_packet(0), None, 10);
+        table.observe(&Beacon::new(2, NodeRole::Sensor).to_packet(0), None, 20);
+        table.observe(&Beacon::new(1, NodeRole::Sensor).to_packet(1), None, 30);
+        table.observe(&Beacon::new(3, NodeRole::Sensor).to_packet(0), None, 40);
+
+        defmt::assert!(table.len() == 2);
+        defmt::assert!(table.get(2).is_none());
+        defmt::assert!(table.get(1).is_some());
+        defmt::assert!(table.get(3).is_some());
+    }
+
+    #[test]
+    fn test_shared_table_snapshot() {
+        observe_neighbor(
+            &NEIGHBORS,
+            &Beacon::new(9, NodeRole::Gateway).to_packet(0),
+            Some(-40),
+            0,
+        );
+
+        let snapshot = neighbors_snapshot(1);
+        defmt::assert!(snapshot.len() == 1);
+        defmt::assert!(snapshot[0].node_id == 9);
+        defmt::assert!(snapshot[0].age_ms == 1);
+        defmt::assert!(snapshot[0].rssi_dbm == Some(-40));
+
+        let snapshot = neighbors_snapshot(DEFAULT_NEIGHBOR_TIMEOUT_MS + 1);
+        defmt::assert!(snapshot.is_empty());
+    }
+}
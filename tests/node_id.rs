@@ -0,0 +1,190 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use sensor_swarm::radio::join::UNASSIGNED_NODE_ID;
+    use sensor_swarm::radio::neighbor::{Beacon, NeighborTable, NodeRole};
+    use sensor_swarm::radio::node_id::*;
+    use sensor_swarm::radio::protocol::BROADCAST_ID;
+    use sensor_swarm::testing::board_sim::board_uid;
+
+    #[test]
+    fn test_derived_id_is_stable() {
+        let first = NodeIdentity::new(board_uid(1), None, None);
+        defmt::assert!(first.source() == NodeIdSource::Derived);
+        defmt::assert!(first.node_id() == derive_node_id(&board_uid(1), 0));
+        defmt::assert!(NodeIdentity::new(board_uid(1), None, None).node_id() == first.node_id());
+        defmt::assert!(NodeIdentity::new(board_uid(2), None, None).node_id() != first.node_id());
+        defmt::assert!(derive_node_id(&board_uid(1), 1) != first.node_id());
+
+        for last in 0..=255 {
+            let node_id = derive_node_id(&board_uid(last), 0);
+            defmt::assert!(node_id != BROADCAST_ID && node_id != UNASSIGNED_NODE_ID);
+            defmt::assert!(uid_tag(&board_uid(last)) != 0);
+        }
+    }
+
+    #[test]
+    fn test_configured_override_takes_precedence() {
+        let configured = NodeIdOverride { node_id: 42 };
+        let record = configured.to_bytes();
+        defmt::assert!(NodeIdOverride::from_bytes(&record) == Some(configured));
+        defmt::assert!(NodeIdOverride::from_bytes(&[0xFF; NODE_ID_OVERRIDE_RECORD_SIZE]).is_none());
+        let mut corrupted = record;
+        corrupted[2] ^= 0x01;
+        defmt::assert!(NodeIdOverride::from_bytes(&corrupted).is_none());
+
+        let mut identity =
+            NodeIdentity::new(board_uid(2), NodeIdOverride::from_bytes(&record), None);
+        defmt::assert!(identity.node_id() == 42);
+        defmt::assert!(identity.source() == NodeIdSource::Configured);
+        defmt::assert!(!identity.assign(7));
+
+        // A colliding board is reported but the configured ID is kept
+        let other = NodeIdentity::new(board_uid(1), Some(configured), None);
+        defmt::assert!(other.uid_tag() < identity.uid_tag());
+        let event = identity.observe_beacon(&other.beacon(NodeRole::Sensor));
+        defmt::assert!(event == IdentityEvent::ConfiguredConflict);
+        defmt::assert!(identity.node_id() == 42);
+        defmt::assert!(identity.conflicts() == 1);
+    }
+
+    #[test]
+    fn test_collision_rederives_id() {
+        let mut identity = NodeIdentity::new(board_uid(1), None, None);
+        let node_id = identity.node_id();
+
+        // Our own beacon, other IDs and untagged beacons are not collisions
+        let own = identity.beacon(NodeRole::Relay);
+        defmt::assert!(own.uid_tag == Some(identity.uid_tag()));
+        defmt::assert!(identity.observe_beacon(&own) == IdentityEvent::Ignored);
+        let other_id = Beacon::new(node_id.wrapping_add(1), NodeRole::Relay)
+            .with_uid_tag(Some(uid_tag(&board_uid(2))));
+        defmt::assert!(identity.observe_beacon(&other_id) == IdentityEvent::Ignored);
+        let untagged = Beacon::new(node_id, NodeRole::Relay);
+        defmt::assert!(identity.observe_beacon(&untagged) == IdentityEvent::Ignored);
+        defmt::assert!(identity.conflicts() == 0);
+
+        // A board with a higher UID tag gives way itself
+        let keeping = untagged.with_uid_tag(Some(uid_tag(&board_uid(2))));
+        defmt::assert!(uid_tag(&board_uid(2)) > identity.uid_tag());
+        defmt::assert!(identity.observe_beacon(&keeping) == IdentityEvent::Ignored);
+        defmt::assert!(identity.conflicts() == 0);
+
+        let colliding = untagged.with_uid_tag(Some(uid_tag(&board_uid(4))));
+        defmt::assert!(uid_tag(&board_uid(4)) < identity.uid_tag());
+        defmt::assert!(identity.observe_beacon(&colliding) == IdentityEvent::Rederived);
+        defmt::assert!(identity.node_id() == derive_node_id(&board_uid(1), 1));
+        defmt::assert!(identity.source() == NodeIdSource::Derived);
+        // The old ID no longer collides
+        defmt::assert!(identity.observe_beacon(&colliding) == IdentityEvent::Ignored);
+    }
+
+    #[test]
+    fn test_rederived_id_survives_reboot() {
+        let record = DerivedIdRecord { attempt: 3 };
+        defmt::assert!(DerivedIdRecord::from_bytes(&record.to_bytes()) == Some(record));
+        defmt::assert!(DerivedIdRecord::from_bytes(&[0xFF; DERIVED_ID_RECORD_SIZE]).is_none());
+        let mut corrupted = record.to_bytes();
+        corrupted[2] ^= 0x01;
+        defmt::assert!(DerivedIdRecord::from_bytes(&corrupted).is_none());
+
+        let mut identity = NodeIdentity::new(board_uid(1), None, None);
+        let colliding = Beacon::new(identity.node_id(), NodeRole::Relay)
+            .with_uid_tag(Some(uid_tag(&board_uid(4))));
+        defmt::assert!(identity.observe_beacon(&colliding) == IdentityEvent::Rederived);
+        defmt::assert!(identity.derived_record().attempt == 1);
+
+        let stored = identity.derived_record().to_bytes();
+        let rebooted = NodeIdentity::new(board_uid(1), None, DerivedIdRecord::from_bytes(&stored));
+        defmt::assert!(rebooted.node_id() == identity.node_id());
+        defmt::assert!(rebooted.derived_record() == identity.derived_record());
+    }
+
+    #[test]
+    fn test_reported_conflict_moves_one_board() {
+        let mut first = NodeIdentity::new(board_uid(1), None, None);
+        let mut second = NodeIdentity::new(board_uid(2), None, None);
+        defmt::assert!(first.assign(5) && second.assign(5));
+
+        // A third node hears both boards
+        let mut table: NeighborTable<4> = NeighborTable::default();
+        let beacon = first.beacon(NodeRole::Sensor).to_packet(0);
+        defmt::assert!(table.observe(&beacon, None, 0).is_none());
+        defmt::assert!(table.observe(&beacon, None, 10).is_none());
+        let beacon = second.beacon(NodeRole::Sensor).to_packet(0);
+        let conflict = defmt::unwrap!(table.observe(&beacon, None, 20));
+        defmt::assert!(conflict.node_id == 5);
+        defmt::assert!(conflict.uid_tags == [first.uid_tag(), second.uid_tag()]);
+
+        let packet = conflict.to_packet(9, 1);
+        defmt::assert!(packet.header.target_id == 5 && packet.header.ttl == 0);
+        let received = defmt::unwrap!(IdConflict::from_packet(&packet));
+        defmt::assert!(received == conflict);
+
+        // Only the board with the higher UID tag gives way
+        let events = [
+            first.observe_conflict(&received),
+            second.observe_conflict(&received),
+        ];
+        defmt::assert!(events.contains(&IdentityEvent::RejoinRequired));
+        defmt::assert!(events.contains(&IdentityEvent::Ignored));
+        let keeper = if first.uid_tag() < second.uid_tag() {
+            &first
+        } else {
+            &second
+        };
+        defmt::assert!(keeper.node_id() == 5);
+
+        // Any further board with that ID moves as well
+        let mut third = NodeIdentity::new(board_uid(3), None, None);
+        defmt::assert!(third.assign(5));
+        defmt::assert!(third.observe_conflict(&received) == IdentityEvent::RejoinRequired);
+        defmt::assert!(third.observe_conflict(&received) == IdentityEvent::Ignored);
+    }
+
+    #[test]
+    fn test_boards_hearing_each_other_move_only_one() {
+        let mut first = NodeIdentity::new(board_uid(1), None, None);
+        let mut second = NodeIdentity::new(board_uid(2), None, None);
+        defmt::assert!(first.assign(5) && second.assign(5));
+
+        let events = [
+            first.observe_beacon(&second.beacon(NodeRole::Sensor)),
+            second.observe_beacon(&first.beacon(NodeRole::Sensor)),
+        ];
+        defmt::assert!(events.contains(&IdentityEvent::RejoinRequired));
+        defmt::assert!(events.contains(&IdentityEvent::Ignored));
+        let keeper = if first.uid_tag() < second.uid_tag() {
+            &first
+        } else {
+            &second
+        };
+        defmt::assert!(keeper.node_id() == 5);
+        defmt::assert!(keeper.source() == NodeIdSource::Assigned);
+    }
+
+    #[test]
+    fn test_collision_of_assigned_id_requires_rejoin() {
+        let mut identity = NodeIdentity::new(board_uid(1), None, None);
+        defmt::assert!(!identity.assign(BROADCAST_ID));
+        defmt::assert!(identity.assign(5));
+        defmt::assert!(identity.source() == NodeIdSource::Assigned);
+
+        let colliding = Beacon::new(5, NodeRole::Sensor).with_uid_tag(Some(uid_tag(&board_uid(4))));
+        defmt::assert!(identity.observe_beacon(&colliding) == IdentityEvent::RejoinRequired);
+        defmt::assert!(identity.source() == NodeIdSource::Derived);
+        defmt::assert!(identity.node_id() == derive_node_id(&board_uid(1), 0));
+    }
+}
@@ -100,6 +100,36 @@ mod tests {
         defmt::assert!(result == Command::Version);
     }
 
+    #[test]
+    fn test_parse_neighbors_command() {
+        let parser = CommandParser::new();
+
+        let result = parser.parse("neighbors");
+        defmt::assert!(result == Command::Neighbors);
+
+        let result = parser.parse("NBR");
+        defmt::assert!(result == Command::Neighbors);
+    }
+
+    #[test]
+    fn test_parse_txqueue_command() {
+        let parser = CommandParser::new();
+
+        let result = parser.parse("txqueue");
+        defmt::assert!(result == Command::TxQueue);
+
+        let result = parser.parse("TXQ");
+        defmt::assert!(result == Command::TxQueue);
+    }
+
+    #[test]
+    fn test_parse_links_command() {
+        let parser = CommandParser::new();
+
+        let result = parser.parse("links");
+        defmt::assert!(result == Command::Links);
+    }
+
     #[test]
     fn test_parse_reboot_commands() {
         let parser = CommandParser::new();
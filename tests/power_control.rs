@@ -0,0 +1,212 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::protocol::{Header, Packet};
+
+/// Unicast packet from node 1 requesting an ack
+fn request(target_id: u16, sequence_number: u16) -> Packet {
+    let mut packet = Packet::new(1, target_id, sequence_number, b"data");
+    packet.header.control.set_ack_request(true);
+    packet
+}
+
+/// Ack sent by `sender_id` for `sequence_number`
+fn ack(sender_id: u16, sequence_number: u16) -> Header {
+    let mut packet = Packet::new(sender_id, 1, sequence_number, &[]);
+    packet.header.control.set_ack_response(true);
+    packet.header
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::{ack, request};
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::power_control::*;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+    use sensor_swarm::radio::traits::{RadioReceiver, RadioTransmitter};
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    const CONFIG: PowerConfig = PowerConfig {
+        min_level: 40,
+        max_level: 200,
+        initial_level: 200,
+        step_down: 50,
+        step_up: 100,
+        acks_to_lower: 4,
+        losses_to_raise: 2,
+    };
+
+    #[test]
+    fn test_acks_lower_power_to_minimum() {
+        let mut controller: PowerController<4> = PowerController::new(1, CONFIG);
+        for _ in 0..3 {
+            defmt::assert!(controller.record_ack(7, true, 0).is_none());
+        }
+        let decision = defmt::unwrap!(controller.record_ack(7, true, 0));
+        defmt::assert!(decision.from == 200 && decision.to == 150);
+        defmt::assert!(decision.change == PowerChange::Lowered);
+
+        let mut decisions = 0;
+        for _ in 0..32 {
+            decisions += controller.record_ack(7, true, 0).is_some() as u32;
+        }
+        // 150 -> 100 -> 50 -> 40, then the lower bound holds
+        defmt::assert!(decisions == 3);
+        defmt::assert!(controller.level_for(7) == CONFIG.min_level);
+        // Other destinations keep their own level
+        defmt::assert!(controller.level_for(8) == CONFIG.initial_level);
+        defmt::assert!(controller.stats().lowered == 4);
+    }
+
+    #[test]
+    fn test_losses_raise_power_to_maximum() {
+        let config = PowerConfig {
+            initial_level: 40,
+            ..CONFIG
+        };
+        let mut controller: PowerController<4> = PowerController::new(1, config);
+        // A single loss is tolerated, acks in between reset the streak
+        defmt::assert!(controller.record_ack(7, false, 0).is_none());
+        defmt::assert!(controller.record_ack(7, true, 0).is_none());
+        defmt::assert!(controller.record_ack(7, false, 0).is_none());
+
+        let decision = defmt::unwrap!(controller.record_ack(7, false, 0));
+        defmt::assert!(decision.from == 40 && decision.to == 140);
+        defmt::assert!(decision.change == PowerChange::Raised);
+        controller.record_ack(7, false, 0);
+        let decision = defmt::unwrap!(controller.record_ack(7, false, 0));
+        defmt::assert!(decision.to == CONFIG.max_level);
+        controller.record_ack(7, false, 0);
+        defmt::assert!(controller.record_ack(7, false, 0).is_none());
+
+        let stats = controller.stats();
+        defmt::assert!(stats.raised == 2 && stats.losses == 7 && stats.acks == 1);
+    }
+
+    #[test]
+    fn test_unanswered_ack_requests_count_as_lost() {
+        let config = PowerConfig {
+            acks_to_lower: 1,
+            ..CONFIG
+        };
+        let mut controller: PowerController<4> = PowerController::new(1, config);
+        defmt::assert!(controller.on_transmit(&request(7, 1).header, 0) == 200);
+        // Acks for other packets or from other nodes are no feedback
+        controller.on_receive(&ack(7, 2), 10);
+        controller.on_receive(&ack(8, 1), 10);
+        defmt::assert!(controller.stats().acks == 0);
+        controller.on_receive(&ack(7, 1), 10);
+        defmt::assert!(controller.level_for(7) == 150);
+
+        // Packets without ack request give no feedback either
+        let plain = Packet::new(1, 7, 2, b"data");
+        defmt::assert!(controller.on_transmit(&plain.header, 20) == 150);
+        defmt::assert!(controller.on_transmit(&plain.header, 20) == 150);
+
+        // Two retransmissions of an unanswered packet raise the level
+        controller.on_transmit(&request(7, 3).header, 30);
+        controller.on_transmit(&request(7, 3).header, 40);
+        defmt::assert!(controller.on_transmit(&request(7, 3).header, 50) == 200);
+        defmt::assert!(controller.stats().losses == 2);
+
+        // Broadcasts always use the upper bound
+        let broadcast = Packet::new(1, BROADCAST_ID, 4, b"data");
+        defmt::assert!(controller.on_transmit(&broadcast.header, 60) == 200);
+    }
+
+    #[test]
+    fn test_levels_follow_the_next_hop() {
+        let config = PowerConfig {
+            acks_to_lower: 1,
+            ..CONFIG
+        };
+        let mut controller: PowerController<4> = PowerController::new(1, config);
+
+        // Own packet to node 9 routed through node 7, the ack of node 7 credits it
+        let mut routed = request(9, 1);
+        routed.header.next_hop = 7;
+        defmt::assert!(controller.on_transmit(&routed.header, 0) == 200);
+        controller.on_receive(&ack(9, 1), 10);
+        defmt::assert!(controller.stats().acks == 0);
+        controller.on_receive(&ack(7, 1), 10);
+        defmt::assert!(controller.level_for(7) == 150);
+        defmt::assert!(controller.level_for(9) == CONFIG.initial_level);
+
+        // Relayed ack requests are acknowledged by the next hop as well
+        let mut relayed = Packet::new(5, 9, 2, b"data");
+        relayed.header.control.set_ack_request(true);
+        relayed.header.relay_id = 1;
+        relayed.header.next_hop = 7;
+        defmt::assert!(controller.on_transmit(&relayed.header, 20) == 150);
+        controller.on_receive(&ack(7, 2), 30);
+        defmt::assert!(controller.level_for(7) == 100);
+        defmt::assert!(controller.stats().losses == 0);
+
+        // Acks for other nodes are ignored
+        let mut foreign = Packet::new(7, 5, 2, &[]);
+        foreign.header.control.set_ack_response(true);
+        controller.on_receive(&foreign.header, 40);
+        defmt::assert!(controller.stats().acks == 2);
+    }
+
+    #[test]
+    fn test_table_capacity() {
+        let mut controller: PowerController<2> = PowerController::new(1, CONFIG);
+        for _ in 0..4 {
+            controller.record_ack(1, true, 10);
+        }
+        controller.record_ack(2, true, 20);
+        controller.record_ack(3, true, 30);
+        // The least recently used destination was forgotten
+        defmt::assert!(controller.level_for(1) == CONFIG.initial_level);
+
+        controller.set_max_level(100);
+        defmt::assert!(controller.level_for(2) == 100);
+        defmt::assert!(controller.level_for(BROADCAST_ID) == 100);
+    }
+
+    #[test]
+    fn test_wrapper_applies_levels() {
+        let medium: SimMedium<2, 8> = SimMedium::new(SimConfig::default());
+        let mut peer = medium.radio(1);
+        let config = PowerConfig {
+            acks_to_lower: 1,
+            ..CONFIG
+        };
+        let mut radio: PowerControlledRadio<_, 4> =
+            PowerControlledRadio::new(medium.radio(0), 1, config);
+
+        defmt::unwrap!(block_on(radio.transmit(&request(2, 1))));
+        defmt::assert!(radio.get_power_level() == 200);
+        defmt::unwrap!(block_on(peer.receive()));
+
+        let mut response = Packet::new(2, 1, 1, &[]);
+        response.header.control.set_ack_response(true);
+        defmt::unwrap!(block_on(peer.transmit(&response)));
+        defmt::unwrap!(block_on(radio.receive()));
+        defmt::unwrap!(block_on(radio.transmit(&request(2, 2))));
+        defmt::assert!(radio.get_power_level() == 150);
+        defmt::unwrap!(block_on(peer.receive()));
+
+        let broadcast = Packet::new(1, BROADCAST_ID, 3, b"x");
+        defmt::unwrap!(block_on(radio.transmit(&broadcast)));
+        defmt::assert!(radio.get_power_level() == 200);
+
+        // Setting the power level bounds the controller instead
+        defmt::unwrap!(block_on(radio.set_power_level(120)));
+        defmt::assert!(radio.controller().config().max_level == 120);
+        defmt::unwrap!(block_on(radio.transmit(&broadcast)));
+        defmt::assert!(radio.get_power_level() == 120);
+        defmt::assert!(radio.stats().lowered == 1);
+    }
+}
@@ -0,0 +1,267 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::csma::{CsmaConfig, CsmaRadio};
+    use sensor_swarm::radio::manchester::bits_msb_first;
+    use sensor_swarm::radio::protocol::Packet;
+    use sensor_swarm::radio::sync::{FrameSynchronizer, SyncConfig};
+    use sensor_swarm::radio::traits::{
+        RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter,
+    };
+    use sensor_swarm::testing::radio_sim::*;
+
+    #[test]
+    fn test_broadcast_reaches_every_other_node() {
+        let medium: SimMedium<3, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+        let mut c = medium.radio(2);
+        medium.set_link(
+            0,
+            2,
+            Some(LinkProfile {
+                rssi_dbm: -90,
+                ..LinkProfile::default()
+            }),
+        );
+
+        let packet = Packet::new(1, 0, 7, b"hello");
+        defmt::unwrap!(block_on(a.transmit(&packet)));
+
+        defmt::assert!(!a.packet_available());
+        defmt::assert!(defmt::unwrap!(block_on(b.receive())) == packet);
+        defmt::assert!(defmt::unwrap!(block_on(c.receive())) == packet);
+        defmt::assert!(b.get_rssi() == Some(-60));
+        defmt::assert!(c.get_rssi() == Some(-90));
+        defmt::assert!(medium.stats().delivered == 2);
+    }
+
+    #[test]
+    fn test_range_and_frequency_separation() {
+        let medium: SimMedium<3, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let b = medium.radio(1);
+        let mut c = medium.radio(2);
+        medium.set_link(0, 1, None);
+        defmt::unwrap!(block_on(
+            c.set_frequency(DEFAULT_SIM_FREQUENCY_HZ + 500_000)
+        ));
+
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 0, 1, b"x"))));
+        defmt::assert!(!b.packet_available());
+        defmt::assert!(!c.packet_available());
+        defmt::assert!(medium.stats().unheard == 2);
+
+        // A small offset stays within the channel bandwidth
+        defmt::unwrap!(block_on(c.set_frequency(DEFAULT_SIM_FREQUENCY_HZ + 20_000)));
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 0, 2, b"x"))));
+        defmt::assert!(c.packet_available());
+    }
+
+    #[test]
+    fn test_loss_and_bit_errors() {
+        let medium: SimMedium<3, 8> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let b = medium.radio(1);
+        let mut c = medium.radio(2);
+        medium.set_link(
+            0,
+            1,
+            Some(LinkProfile {
+                loss_percent: 100,
+                ..LinkProfile::default()
+            }),
+        );
+        medium.set_link(
+            0,
+            2,
+            Some(LinkProfile {
+                bit_error_ppm: 200_000,
+                ..LinkProfile::default()
+            }),
+        );
+
+        for seq in 0..4 {
+            defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 0, seq, b"data"))));
+        }
+
+        // Corrupted frames reach the receiver and fail its CRC check
+        defmt::assert!(!b.packet_available());
+        defmt::assert!(medium.stats().lost == 4);
+        defmt::assert!(medium.stats().corrupted == 4);
+        for _ in 0..4 {
+            match block_on(c.receive()) {
+                Err(RadioError::ChecksumMismatch) | Err(RadioError::InvalidPacket) => {}
+                _ => defmt::panic!("expected a corrupted frame"),
+            }
+        }
+        defmt::assert!(medium.stats().rejected == 4);
+    }
+
+    #[test]
+    fn test_fec_repairs_bit_errors() {
+        let medium: SimMedium<2, 16> = SimMedium::new(SimConfig {
+            fec_parity: 16,
+            ..SimConfig::default()
+        });
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+        medium.set_link(
+            0,
+            1,
+            Some(LinkProfile {
+                bit_error_ppm: 2_000,
+                ..LinkProfile::default()
+            }),
+        );
+
+        let packet = Packet::new(1, 2, 1, b"repair me");
+        for _ in 0..16 {
+            defmt::unwrap!(block_on(a.transmit(&packet)));
+        }
+        for _ in 0..16 {
+            defmt::assert!(defmt::unwrap!(block_on(b.receive())) == packet);
+        }
+        defmt::assert!(medium.stats().corrupted > 0);
+        defmt::assert!(medium.stats().rejected == 0);
+    }
+
+    #[test]
+    fn test_raw_frames_feed_the_synchronizer() {
+        let medium: SimMedium<2, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+
+        let packet = Packet::new(1, 2, 1, b"raw");
+        defmt::unwrap!(block_on(a.transmit(&packet)));
+        let frame = defmt::unwrap!(b.receive_frame());
+
+        let mut sync = FrameSynchronizer::new(SyncConfig::default());
+        sync.push_bits(bits_msb_first(&[0x00, 0xAA, 0xAA, 0x2D, 0xD4]));
+        let synced = defmt::unwrap!(sync.push_bits(bits_msb_first(&frame)));
+        defmt::assert!(synced[..] == frame[..]);
+    }
+
+    #[test]
+    fn test_loss_scales_with_power_level() {
+        let medium: SimMedium<2, 64> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+        medium.set_link(
+            0,
+            1,
+            Some(LinkProfile {
+                loss_percent: 50,
+                ..LinkProfile::default()
+            }),
+        );
+
+        // Twice the default power halves the loss, half of it doubles the loss
+        defmt::unwrap!(block_on(a.set_power_level(DEFAULT_SIM_POWER_LEVEL / 2)));
+        for seq in 0..20 {
+            defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 2, seq, b"x"))));
+        }
+        defmt::assert!(medium.stats().lost == 20);
+
+        defmt::unwrap!(block_on(a.set_power_level(0)));
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 2, 20, b"x"))));
+        defmt::assert!(medium.stats().lost == 21);
+
+        defmt::unwrap!(block_on(a.set_power_level(255)));
+        for seq in 0..40 {
+            defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 2, seq, b"x"))));
+        }
+        let delivered = medium.stats().delivered;
+        defmt::assert!(delivered > 20 && delivered < 40);
+        defmt::assert!(b.pending() == delivered as usize);
+        while b.packet_available() {
+            defmt::unwrap!(block_on(b.receive()));
+        }
+    }
+
+    #[test]
+    fn test_propagation_delay() {
+        let medium: SimMedium<2, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+        medium.set_link(
+            0,
+            1,
+            Some(LinkProfile {
+                delay_ms: 20,
+                ..LinkProfile::default()
+            }),
+        );
+
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 2, 1, b"late"))));
+        defmt::assert!(!b.packet_available());
+        defmt::assert!(b.pending() == 1);
+
+        medium.advance_ms(19);
+        defmt::assert!(!b.packet_available());
+        medium.advance_ms(1);
+        defmt::assert!(defmt::unwrap!(block_on(b.receive())).payload_data() == b"late");
+    }
+
+    #[test]
+    fn test_sleeping_and_disabled_nodes_hear_nothing() {
+        let medium: SimMedium<3, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let mut b = medium.radio(1);
+        let mut c = medium.radio(2);
+        defmt::unwrap!(block_on(b.sleep()));
+        defmt::unwrap!(block_on(c.set_enabled(false)));
+
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 0, 1, b"x"))));
+        defmt::unwrap!(block_on(b.wake()));
+        defmt::unwrap!(block_on(c.set_enabled(true)));
+        defmt::assert!(!b.packet_available());
+        defmt::assert!(!c.packet_available());
+
+        // A sleeping radio cannot transmit either
+        defmt::unwrap!(block_on(a.sleep()));
+        match block_on(a.transmit(&Packet::new(1, 0, 2, b"x"))) {
+            Err(RadioError::NotReady) => {}
+            _ => defmt::panic!("expected a sleeping radio to refuse"),
+        }
+    }
+
+    #[test]
+    fn test_carrier_sense_drives_csma() {
+        let medium: SimMedium<2, 4> = SimMedium::new(SimConfig::default());
+        let mut a = medium.radio(0);
+        let config = CsmaConfig {
+            max_attempts: 3,
+            backoff_unit_ms: 0,
+            ..CsmaConfig::default()
+        };
+        let mut b = CsmaRadio::new(medium.radio(1), config, 2);
+
+        defmt::unwrap!(block_on(a.transmit(&Packet::new(1, 0, 1, b"x"))));
+        defmt::unwrap!(block_on(b.receive()));
+
+        // The channel stays occupied for the airtime of the frame
+        defmt::assert!(b.is_channel_busy());
+        match block_on(b.transmit(&Packet::new(2, 0, 1, b"y"))) {
+            Err(RadioError::Busy) => {}
+            _ => defmt::panic!("expected the channel to be busy"),
+        }
+
+        medium.advance_ms(SimConfig::default().airtime_ms as u64);
+        defmt::unwrap!(block_on(b.transmit(&Packet::new(2, 0, 1, b"y"))));
+        defmt::assert!(a.packet_available());
+    }
+}
@@ -0,0 +1,432 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use heapless::Vec;
+use sensor_swarm::radio::link_quality::{LinkTable, SharedLinkTable};
+use sensor_swarm::radio::neighbor::{NeighborTable, SharedNeighborTable};
+use sensor_swarm::radio::protocol::Packet;
+use sensor_swarm::radio::reliable::{ReliableLink, RetryPolicy};
+use sensor_swarm::radio::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+use sensor_swarm::testing::radio_sim::{LinkProfile, SimMedium, SimRadio};
+
+type Medium = SimMedium<2, 8>;
+
+fn link_table() -> SharedLinkTable {
+    Mutex::new(RefCell::new(LinkTable::default()))
+}
+
+fn neighbor_table() -> SharedNeighborTable {
+    Mutex::new(RefCell::new(NeighborTable::default()))
+}
+
+/// Short timeouts without backoff, so lost packets cost only a few milliseconds
+const POLICY: RetryPolicy = RetryPolicy {
+    max_retries: 3,
+    ack_timeout_ms: 10,
+    initial_backoff_ms: 0,
+    max_backoff_ms: 0,
+    jitter_ms: 0,
+};
+
+/// Radio of node 0 that lets the reliable link of node 1 answer every transmission
+/// right away
+///
+/// Bit `n` of `lost_packets` drops transmission `n + 1` before it reaches node 1,
+/// bit `n` of `lost_acks` drops node 1's answer to it.
+struct ScriptedRadio<'a> {
+    radio: SimRadio<'a, 2, 8>,
+    medium: &'a Medium,
+    peer: ReliableLink<'a, SimRadio<'a, 2, 8>, 4>,
+    lost_packets: u8,
+    lost_acks: u8,
+    transmissions: u8,
+    delivered: Vec<Packet, 8>,
+}
+
+impl<'a> ScriptedRadio<'a> {
+    fn new(medium: &'a Medium, peer: ReliableLink<'a, SimRadio<'a, 2, 8>, 4>) -> Self {
+        Self {
+            radio: medium.radio(0),
+            medium,
+            peer,
+            lost_packets: 0,
+            lost_acks: 0,
+            transmissions: 0,
+            delivered: Vec::new(),
+        }
+    }
+}
+
+impl RadioTransmitter for ScriptedRadio<'_> {
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        let bit = 1u8.checked_shl(self.transmissions as u32).unwrap_or(0);
+        self.transmissions += 1;
+        if self.lost_packets & bit != 0 {
+            return Ok(());
+        }
+        self.radio.transmit(packet).await?;
+
+        if self.lost_acks & bit != 0 {
+            self.medium.set_link(1, 0, None);
+        }
+        if let Some(received) = self.peer.receive().await {
+            let _ = self.delivered.push(received);
+        }
+        self.medium.set_link(1, 0, Some(LinkProfile::default()));
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.radio.is_ready()
+    }
+
+    async fn set_power_level(&mut self, power_level: u8) -> Result<(), RadioError> {
+        self.radio.set_power_level(power_level).await
+    }
+
+    fn get_power_level(&self) -> u8 {
+        self.radio.get_power_level()
+    }
+}
+
+impl RadioReceiver for ScriptedRadio<'_> {
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        self.radio.receive().await
+    }
+
+    fn packet_available(&self) -> bool {
+        self.radio.packet_available()
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.radio.set_enabled(enabled).await
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.radio.is_enabled()
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        self.radio.get_rssi()
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        self.radio.is_channel_busy()
+    }
+}
+
+impl RadioTransceiver for ScriptedRadio<'_> {
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        self.radio.initialize().await
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.radio.sleep().await
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.radio.wake().await
+    }
+
+    fn get_frequency(&self) -> u32 {
+        self.radio.get_frequency()
+    }
+
+    async fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), RadioError> {
+        self.radio.set_frequency(frequency_hz).await
+    }
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::{link_table, neighbor_table, ScriptedRadio, POLICY};
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::neighbor::{Beacon, NodeRole};
+    use sensor_swarm::radio::node_id::IdConflict;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+    use sensor_swarm::radio::random::XorShift32;
+    use sensor_swarm::radio::reliable::*;
+    use sensor_swarm::radio::traits::{RadioReceiver, RadioTransmitter};
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    #[test]
+    fn test_backoff_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 8,
+            ack_timeout_ms: 100,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            jitter_ms: 0,
+        };
+        let mut rng = XorShift32::new(1);
+
+        defmt::assert!(policy.backoff_ms(1, &mut rng) == 100);
+        defmt::assert!(policy.backoff_ms(2, &mut rng) == 200);
+        defmt::assert!(policy.backoff_ms(3, &mut rng) == 400);
+        defmt::assert!(policy.backoff_ms(4, &mut rng) == 800);
+        defmt::assert!(policy.backoff_ms(5, &mut rng) == 1_000);
+        defmt::assert!(policy.backoff_ms(40, &mut rng) == 1_000);
+    }
+
+    #[test]
+    fn test_backoff_saturates_at_extreme_policies() {
+        let policy = RetryPolicy {
+            max_retries: 8,
+            ack_timeout_ms: 100,
+            initial_backoff_ms: u32::MAX,
+            max_backoff_ms: u32::MAX,
+            jitter_ms: u32::MAX,
+        };
+        let mut rng = XorShift32::new(1);
+        for retry in 1..=8 {
+            defmt::assert!(policy.backoff_ms(retry, &mut rng) == u32::MAX);
+        }
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let policy = RetryPolicy::default();
+        let mut rng = XorShift32::new(0x1234);
+        let mut saw_jitter = false;
+
+        for _ in 0..200 {
+            let backoff = policy.backoff_ms(1, &mut rng);
+            defmt::assert!(backoff >= policy.initial_backoff_ms);
+            defmt::assert!(backoff <= policy.initial_backoff_ms + policy.jitter_ms);
+            saw_jitter |= backoff != policy.initial_backoff_ms;
+        }
+        defmt::assert!(saw_jitter);
+    }
+
+    #[test]
+    fn test_ack_matches_sender_and_sequence() {
+        let mut sent = Packet::new(0x0010, 0x0020, 42, b"data");
+        sent.header.control.set_ack_request(true);
+
+        let ack = ack_for(&sent.header, 0x0020);
+        defmt::assert!(ack.header.control.is_ack());
+        defmt::assert!(ack.header.target_id == 0x0010);
+        defmt::assert!(ack.payload_data().is_empty());
+        defmt::assert!(is_matching_ack(&sent.header, &ack.header));
+
+        // Wrong sequence number
+        let stale = ack_for(&Packet::new(0x0010, 0x0020, 41, b"").header, 0x0020);
+        defmt::assert!(!is_matching_ack(&sent.header, &stale.header));
+
+        // Ack from a node other than the target
+        let foreign = ack_for(&sent.header, 0x0030);
+        defmt::assert!(!is_matching_ack(&sent.header, &foreign.header));
+
+        // Data packet with the same addressing is not an ack
+        let data = Packet::new(0x0020, 0x0010, 42, b"");
+        defmt::assert!(!is_matching_ack(&sent.header, &data.header));
+    }
+
+    #[test]
+    fn test_routed_packets_are_acked_by_the_next_hop() {
+        let mut sent = Packet::new(0x0010, 0x0040, 42, b"data");
+        sent.header.next_hop = 0x0020;
+        sent.header.control.set_ack_request(true);
+
+        // The next hop answers, the final target is not expected to
+        defmt::assert!(is_matching_ack(
+            &sent.header,
+            &ack_for(&sent.header, 0x0020).header
+        ));
+        defmt::assert!(!is_matching_ack(
+            &sent.header,
+            &ack_for(&sent.header, 0x0040).header
+        ));
+
+        // A relay forwarding the packet is acked itself, not the originator
+        let mut forwarded = sent.clone();
+        forwarded.header.relay_id = 0x0020;
+        forwarded.header.next_hop = 0x0030;
+        let ack = ack_for(&forwarded.header, 0x0030);
+        defmt::assert!(ack.header.target_id == 0x0020);
+        defmt::assert!(is_matching_ack(&forwarded.header, &ack.header));
+        defmt::assert!(!is_matching_ack(&sent.header, &ack.header));
+    }
+
+    #[test]
+    fn test_send_is_acknowledged_automatically() {
+        let medium = SimMedium::new(SimConfig::default());
+        let (links, peer_links, neighbors) = (link_table(), link_table(), neighbor_table());
+        let peer = ReliableLink::new(medium.radio(1), 2, POLICY, &peer_links, &neighbors);
+        let mut link: ReliableLink<_, 4> = ReliableLink::new(
+            ScriptedRadio::new(&medium, peer),
+            1,
+            POLICY,
+            &links,
+            &neighbors,
+        );
+
+        let outcome = block_on(link.send(Packet::new(1, 2, 7, b"data")));
+        defmt::assert!(outcome == DeliveryOutcome::Delivered { attempts: 1 });
+
+        let delivered = &link.radio().delivered;
+        defmt::assert!(delivered.len() == 1);
+        defmt::assert!(delivered[0].payload_data() == b"data");
+        defmt::assert!(delivered[0].header.control.is_ack_request());
+        // The ack was consumed by the sender and not handed out as data
+        defmt::assert!(block_on(link.receive()).is_none());
+
+        // Both ends learned about each other
+        links.lock(|table| defmt::assert!(table.borrow().get(2).is_some()));
+        peer_links.lock(|table| defmt::assert!(table.borrow().get(1).is_some()));
+    }
+
+    #[test]
+    fn test_next_hop_acknowledges_routed_packets() {
+        let medium = SimMedium::new(SimConfig::default());
+        let (links, peer_links, neighbors) = (link_table(), link_table(), neighbor_table());
+        let peer = ReliableLink::new(medium.radio(1), 2, POLICY, &peer_links, &neighbors);
+        let mut link: ReliableLink<_, 4> = ReliableLink::new(
+            ScriptedRadio::new(&medium, peer),
+            1,
+            POLICY,
+            &links,
+            &neighbors,
+        );
+
+        // Node 9 is out of range, node 2 relays
+        let mut packet = Packet::new(1, 9, 11, b"data");
+        packet.header.next_hop = 2;
+        let outcome = block_on(link.send(packet));
+        defmt::assert!(outcome == DeliveryOutcome::Delivered { attempts: 1 });
+        defmt::assert!(link.radio().transmissions == 1);
+
+        // The relay gets the packet to forward it, the next hop link is credited
+        let delivered = &link.radio().delivered;
+        defmt::assert!(delivered.len() == 1 && delivered[0].header.target_id == 9);
+        links.lock(|table| {
+            let table = table.borrow();
+            defmt::assert!(table.get(2).is_some());
+            defmt::assert!(table.get(9).is_none());
+        });
+    }
+
+    #[test]
+    fn test_lost_packets_are_retransmitted() {
+        let medium = SimMedium::new(SimConfig::default());
+        let (links, peer_links, neighbors) = (link_table(), link_table(), neighbor_table());
+        let peer = ReliableLink::new(medium.radio(1), 2, POLICY, &peer_links, &neighbors);
+        let mut radio = ScriptedRadio::new(&medium, peer);
+        radio.lost_packets = 0b011;
+        let mut link: ReliableLink<_, 4> = ReliableLink::new(radio, 1, POLICY, &links, &neighbors);
+
+        let outcome = block_on(link.send(Packet::new(1, 2, 8, b"data")));
+        defmt::assert!(outcome == DeliveryOutcome::Delivered { attempts: 3 });
+        defmt::assert!(link.radio().transmissions == 3);
+        let delivered = &link.radio().delivered;
+        defmt::assert!(delivered.len() == 1);
+        defmt::assert!(delivered[0].header.control.is_retransmit());
+    }
+
+    #[test]
+    fn test_lost_ack_is_answered_again() {
+        let medium = SimMedium::new(SimConfig::default());
+        let (links, peer_links, neighbors) = (link_table(), link_table(), neighbor_table());
+        let peer = ReliableLink::new(medium.radio(1), 2, POLICY, &peer_links, &neighbors);
+        let mut radio = ScriptedRadio::new(&medium, peer);
+        radio.lost_acks = 0b001;
+        let mut link: ReliableLink<_, 4> = ReliableLink::new(radio, 1, POLICY, &links, &neighbors);
+
+        let outcome = block_on(link.send(Packet::new(1, 2, 9, b"data")));
+        defmt::assert!(outcome == DeliveryOutcome::Delivered { attempts: 2 });
+        // The retransmission was acknowledged but not delivered twice
+        defmt::assert!(link.radio().delivered.len() == 1);
+        defmt::assert!(medium.stats().transmitted == 4);
+    }
+
+    #[test]
+    fn test_unanswered_send_times_out() {
+        let medium = SimMedium::new(SimConfig::default());
+        let (links, peer_links, neighbors) = (link_table(), link_table(), neighbor_table());
+        let peer = ReliableLink::new(medium.radio(1), 2, POLICY, &peer_links, &neighbors);
+        let mut radio = ScriptedRadio::new(&medium, peer);
+        radio.lost_packets = u8::MAX;
+        let mut link: ReliableLink<_, 4> = ReliableLink::new(radio, 1, POLICY, &links, &neighbors);
+
+        let outcome = block_on(link.send(Packet::new(1, 2, 10, b"data")));
+        defmt::assert!(outcome == DeliveryOutcome::TimedOut { attempts: 4 });
+        defmt::assert!(link.radio().delivered.is_empty());
+        links.lock(|table| {
+            let table = table.borrow();
+            let estimate = defmt::unwrap!(table.get(2));
+            defmt::assert!(estimate.delivery_permille() == Some(0));
+        });
+
+        // Broadcasts are sent once without waiting for an ack
+        let outcome = block_on(link.send(Packet::new(1, BROADCAST_ID, 11, b"all")));
+        defmt::assert!(outcome == DeliveryOutcome::Delivered { attempts: 1 });
+    }
+
+    #[test]
+    fn test_traffic_during_ack_wait_is_kept() {
+        let medium: SimMedium<2, 8> = SimMedium::new(SimConfig::default());
+        let (links, neighbors) = (link_table(), neighbor_table());
+        let mut other = medium.radio(1);
+        let mut link: ReliableLink<_, 4> =
+            ReliableLink::new(medium.radio(0), 1, POLICY, &links, &neighbors);
+
+        // Node 2 sends its own request before answering
+        let mut request = Packet::new(2, 1, 5, b"ping");
+        request.header.control.set_ack_request(true);
+        defmt::unwrap!(block_on(other.transmit(&request)));
+        let outcome = block_on(link.send(Packet::new(1, 2, 12, b"data")));
+        defmt::assert!(outcome == DeliveryOutcome::TimedOut { attempts: 4 });
+
+        // The request was answered and is handed out afterwards
+        let mut acked = false;
+        while let Ok(packet) = block_on(other.receive()) {
+            acked |= packet.header.control.is_ack() && packet.header.sequence_number == 5;
+        }
+        defmt::assert!(acked);
+        let received = defmt::unwrap!(block_on(link.receive()));
+        defmt::assert!(received.payload_data() == b"ping");
+        defmt::assert!(block_on(link.receive()).is_none());
+    }
+
+    #[test]
+    fn test_received_beacons_feed_the_neighbor_table() {
+        let medium: SimMedium<2, 8> = SimMedium::new(SimConfig::default());
+        let (links, neighbors) = (link_table(), neighbor_table());
+        let mut other = medium.radio(1);
+        let mut link: ReliableLink<_, 4> =
+            ReliableLink::new(medium.radio(0), 1, POLICY, &links, &neighbors);
+
+        // Two boards beaconing as node 5 with different UID tags
+        for (sequence_number, uid_tag) in [(1, 0xA1), (2, 0xB2)] {
+            let beacon = Beacon::new(5, NodeRole::Relay).with_uid_tag(Some(uid_tag));
+            defmt::unwrap!(block_on(other.transmit(&beacon.to_packet(sequence_number))));
+            defmt::assert!(block_on(link.receive()).is_some());
+        }
+
+        neighbors.lock(|table| {
+            let table = table.borrow();
+            let entry = defmt::unwrap!(table.get(5));
+            defmt::assert!(entry.role == NodeRole::Relay);
+            defmt::assert!(entry.packet_count == 2);
+            defmt::assert!(entry.rssi_dbm().is_some());
+        });
+        let conflict = IdConflict {
+            node_id: 5,
+            uid_tags: [0xA1, 0xB2],
+        };
+        defmt::assert!(link.take_id_conflict() == Some(conflict));
+        defmt::assert!(link.take_id_conflict().is_none());
+    }
+}
@@ -0,0 +1,153 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::sensors::traits::{DataValidity, EnvironmentalData};
+
+/// Reading with all four channels valid, taken `minute` minutes after boot
+fn full_reading(minute: u64, temperature: i32) -> EnvironmentalData {
+    let mut reading = EnvironmentalData::new();
+    reading.temperature_celsius_x100 = temperature;
+    reading.humidity_percent_x100 = 6525;
+    reading.pressure_pa = 101_325;
+    reading.light_lux_x10 = 1500;
+    reading.timestamp_ms = 3_600_000 + minute * 60_000;
+    reading.validity = DataValidity::all_valid();
+    reading
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::full_reading;
+    use sensor_swarm::radio::message::{Message, MAX_BODY_SIZE};
+    use sensor_swarm::radio::protocol::BROADCAST_ID;
+    use sensor_swarm::radio::report::*;
+    use sensor_swarm::sensors::traits::{DataValidity, EnvironmentalData};
+
+    #[test]
+    fn test_varint_and_zigzag_roundtrip() {
+        for value in [0i64, 1, -1, 63, -64, 1 << 40, i64::MAX, i64::MIN] {
+            defmt::assert!(zigzag_decode(zigzag_encode(value)) == value);
+        }
+        defmt::assert!(zigzag_encode(-1) == 1);
+        defmt::assert!(zigzag_encode(1) == 2);
+
+        let mut buf = [0u8; MAX_VARINT_SIZE];
+        for value in [0u64, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let len = encode_varint(value, &mut buf);
+            defmt::assert!(decode_varint(&buf[..len]) == Some(value));
+        }
+        defmt::assert!(encode_varint(300, &mut buf) == 2);
+        defmt::assert!(buf[..2] == [0xAC, 0x02]);
+
+        // Unterminated, overlong and over-wide varints are rejected
+        defmt::assert!(decode_varint(&[0x80]).is_none());
+        defmt::assert!(decode_varint(&[0x01, 0x01]).is_none());
+        let too_wide = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F];
+        defmt::assert!(decode_varint(&too_wide).is_none());
+    }
+
+    #[test]
+    fn test_batch_roundtrip() {
+        let readings = [full_reading(0, 2150), full_reading(1, 2162)];
+        let mut report = SensorReport::new();
+        for reading in readings.iter() {
+            defmt::unwrap!(report.push(reading));
+        }
+
+        // Both readings fit into a single message body
+        defmt::assert!(report.len() == 2);
+        defmt::assert!(report.as_bytes().len() <= MAX_BODY_SIZE);
+        defmt::assert!(report.readings().eq(readings.iter().copied()));
+    }
+
+    #[test]
+    fn test_only_valid_channels_are_sent() {
+        let mut report = SensorReport::new();
+        for minute in 0..4 {
+            let mut reading = EnvironmentalData::new();
+            reading.set_timestamp_ms(minute * 10_000, true);
+            reading.temperature_celsius_x100 = 2000 - minute as i32;
+            reading.validity = reading.validity.with_temperature_valid(true);
+            defmt::unwrap!(report.push(&reading));
+        }
+
+        // Small deltas after the first reading take one byte per field
+        defmt::assert!(report.len() == 4);
+        defmt::assert!(report.as_bytes().len() <= 2 + 3 + 1 + 3 * (3 + 2 + 1));
+
+        let decoded = defmt::unwrap!(report.readings().last());
+        defmt::assert!(decoded.temperature_celsius_x100 == 1997);
+        defmt::assert!(decoded.timestamp_ms == 30_000);
+        defmt::assert!(decoded.validity.temperature_valid());
+        defmt::assert!(decoded.validity.timestamp_synced());
+        defmt::assert!(!decoded.validity.humidity_valid());
+        defmt::assert!(decoded.pressure_pa == 0);
+    }
+
+    #[test]
+    fn test_full_and_out_of_order_batches() {
+        let mut report = SensorReport::new();
+        let mut minute = 0;
+        while report.push(&full_reading(minute, 2000)).is_ok() {
+            minute += 1;
+        }
+        let before = report.clone();
+        defmt::assert!(report.push(&full_reading(minute, 2000)) == Err(ReportError::Full));
+        defmt::assert!(report == before);
+
+        let mut report = SensorReport::new();
+        defmt::unwrap!(report.push(&full_reading(5, 2000)));
+        defmt::assert!(report.push(&full_reading(4, 2000)) == Err(ReportError::OutOfOrder));
+    }
+
+    #[test]
+    fn test_malformed_batches_and_unknown_fields() {
+        // Channel field before any timestamp
+        defmt::assert!(SensorReport::from_bytes(&[0x11, 0x02]) == Err(ReportError::Malformed));
+        // Length nibble pointing past the end
+        defmt::assert!(SensorReport::from_bytes(&[0x02, 0x01]) == Err(ReportError::Malformed));
+        // Humidity below zero
+        defmt::assert!(
+            SensorReport::from_bytes(&[0x01, 0x00, 0x21, 0x01]) == Err(ReportError::Malformed)
+        );
+
+        // A field type added by a newer encoder is skipped
+        let report = defmt::unwrap!(SensorReport::from_bytes(&[
+            0x01, 0x05, 0xE2, 0xAA, 0xBB, 0x11, 0x04
+        ]));
+        defmt::assert!(report.len() == 1);
+        let reading = defmt::unwrap!(report.readings().next());
+        defmt::assert!(reading.timestamp_ms == 5);
+        defmt::assert!(reading.temperature_celsius_x100 == 2);
+        defmt::assert!(reading.validity == DataValidity::new().with_temperature_valid(true));
+    }
+
+    #[test]
+    fn test_gateway_rebuilds_readings_from_packet() {
+        let mut report = SensorReport::new();
+        defmt::unwrap!(report.push(&full_reading(0, -550)));
+        let packet = Message::SensorReport(report.clone()).to_packet(9, BROADCAST_ID, 3);
+
+        let Ok(Message::SensorReport(mut received)) = Message::from_packet(&packet) else {
+            defmt::panic!("expected a sensor report");
+        };
+        let mut readings = received.readings();
+        defmt::assert!(readings.next() == Some(full_reading(0, -550)));
+        defmt::assert!(readings.next().is_none());
+
+        // A decoded batch continues from the same delta state as the original
+        defmt::unwrap!(report.push(&full_reading(2, -540)));
+        defmt::unwrap!(received.push(&full_reading(2, -540)));
+        defmt::assert!(received == report);
+    }
+}
@@ -0,0 +1,224 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use sensor_swarm::radio::link_quality::{LinkTable, SharedLinkTable};
+
+/// Empty link table, link costs fall back to signal strength
+fn link_table() -> SharedLinkTable {
+    Mutex::new(RefCell::new(LinkTable::default()))
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::link_table;
+    use sensor_swarm::radio::neighbor::{Beacon, NodeRole};
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+    use sensor_swarm::radio::routing::*;
+
+    const GATEWAY: u16 = 0x0001;
+    const RELAY: u16 = 0x0002;
+    const NODE: u16 = 0x0003;
+
+    #[test]
+    fn test_beacon_advert_builds_gateway_route() {
+        let links = link_table();
+        let mut gateway: Router<4> = Router::new(GATEWAY, NodeRole::Gateway, 10_000, &links);
+        let mut relay: Router<4> = Router::new(RELAY, NodeRole::Relay, 10_000, &links);
+        let mut node: Router<4> = Router::new(NODE, NodeRole::Sensor, 10_000, &links);
+
+        let advert = defmt::unwrap!(gateway.advertisement());
+        defmt::assert!(advert.cost == 0);
+
+        let beacon = Beacon::new(GATEWAY, NodeRole::Gateway).with_route(gateway.advertisement());
+        relay.learn(&beacon.to_packet(0), Some(-60), 0);
+        let route = defmt::unwrap!(relay.table().best_gateway());
+        defmt::assert!(route.destination == GATEWAY);
+        defmt::assert!(route.next_hop == GATEWAY);
+        defmt::assert!(route.cost == PERFECT_LINK_COST);
+
+        let beacon = Beacon::new(RELAY, NodeRole::Relay).with_route(relay.advertisement());
+        node.learn(&beacon.to_packet(0), Some(-60), 0);
+        let route = defmt::unwrap!(node.table().get(GATEWAY));
+        defmt::assert!(route.next_hop == RELAY);
+        defmt::assert!(route.cost == 2 * PERFECT_LINK_COST);
+        defmt::assert!(route.is_gateway);
+
+        // The gateway learns the relay as a neighbor from its beacon
+        gateway.learn(&beacon.to_packet(0), Some(-60), 0);
+        defmt::assert!(gateway.table().get(RELAY).is_some());
+    }
+
+    #[test]
+    fn test_link_quality_beats_hop_count() {
+        let links = link_table();
+        let mut node: Router<4> = Router::new(NODE, NodeRole::Sensor, 10_000, &links);
+
+        // Weak direct link to the gateway
+        let gateway_beacon =
+            Beacon::new(GATEWAY, NodeRole::Gateway).with_route(Some(RouteAdvert {
+                gateway_id: GATEWAY,
+                cost: 0,
+                via: BROADCAST_ID,
+            }));
+        node.learn(&gateway_beacon.to_packet(0), Some(-100), 0);
+        defmt::assert!(defmt::unwrap!(node.table().get(GATEWAY)).next_hop == GATEWAY);
+
+        // Two strong links through the relay are cheaper
+        let relay_beacon = Beacon::new(RELAY, NodeRole::Relay).with_route(Some(RouteAdvert {
+            gateway_id: GATEWAY,
+            cost: PERFECT_LINK_COST,
+            via: GATEWAY,
+        }));
+        node.learn(&relay_beacon.to_packet(0), Some(-50), 10);
+        let route = defmt::unwrap!(node.table().get(GATEWAY));
+        defmt::assert!(route.next_hop == RELAY);
+        defmt::assert!(route.cost == 2 * PERFECT_LINK_COST);
+    }
+
+    #[test]
+    fn test_unicast_forwarded_hop_by_hop() {
+        let links = link_table();
+        let mut relay: Router<4> = Router::new(RELAY, NodeRole::Relay, 10_000, &links);
+        relay.learn(
+            &Beacon::new(GATEWAY, NodeRole::Gateway).to_packet(0),
+            Some(-60),
+            0,
+        );
+
+        let mut node: Router<4> = Router::new(NODE, NodeRole::Sensor, 10_000, &links);
+        node.learn(
+            &Beacon::new(RELAY, NodeRole::Relay).to_packet(0),
+            Some(-60),
+            0,
+        );
+        let mut packet = Packet::new(NODE, RELAY, 1, b"direct");
+        defmt::assert!(node.prepare_outgoing(&mut packet));
+        defmt::assert!(packet.header.next_hop == RELAY);
+
+        // The node only knows the relay, so a packet to the gateway is flooded
+        let mut packet = Packet::new(NODE, GATEWAY, 2, b"reading");
+        defmt::assert!(!node.prepare_outgoing(&mut packet));
+        defmt::assert!(packet.header.next_hop == BROADCAST_ID);
+        defmt::assert!(relay.on_receive(&packet, Some(-60), 5) == RouteDecision::Flood);
+
+        // Routed through the relay, which knows the gateway
+        packet.header.next_hop = RELAY;
+        let RouteDecision::Forward(forwarded) = relay.on_receive(&packet, Some(-60), 5) else {
+            defmt::panic!("Packet was not forwarded");
+        };
+        defmt::assert!(forwarded.header.next_hop == GATEWAY);
+        defmt::assert!(forwarded.header.relay_id == RELAY);
+        defmt::assert!(forwarded.header.hop_count == 1);
+        defmt::assert!(forwarded.header.ttl == packet.header.ttl - 1);
+
+        // The relay learned the reverse path to the node
+        defmt::assert!(defmt::unwrap!(relay.table().get(NODE)).next_hop == NODE);
+
+        let mut gateway: Router<4> = Router::new(GATEWAY, NodeRole::Gateway, 10_000, &links);
+        defmt::assert!(gateway.on_receive(&forwarded, Some(-60), 6) == RouteDecision::Deliver);
+        let reverse = defmt::unwrap!(gateway.table().get(NODE));
+        defmt::assert!(reverse.next_hop == RELAY);
+        defmt::assert!(reverse.cost == 2 * PERFECT_LINK_COST);
+
+        // Overheard packets routed through someone else are not forwarded
+        let mut other: Router<4> = Router::new(0x0009, NodeRole::Relay, 10_000, &links);
+        defmt::assert!(other.on_receive(&packet, Some(-60), 5) == RouteDecision::Drop);
+    }
+
+    #[test]
+    fn test_stale_routes_expire() {
+        let mut table: RoutingTable<4> = RoutingTable::new(1_000);
+        let route = Route {
+            destination: GATEWAY,
+            next_hop: RELAY,
+            cost: 32,
+            is_gateway: true,
+            updated_ms: 0,
+        };
+        defmt::assert!(table.update(route));
+        defmt::assert!(table.expire(500) == 0);
+        defmt::assert!(table.expire(1_001) == 1);
+        defmt::assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_lost_gateway_is_poisoned() {
+        let links = link_table();
+        let gateway: Router<4> = Router::new(GATEWAY, NodeRole::Gateway, 1_000, &links);
+        let mut relay: Router<4> = Router::new(RELAY, NodeRole::Relay, 1_000, &links);
+        let mut node: Router<4> = Router::new(NODE, NodeRole::Sensor, 1_000, &links);
+        let beacon = |id, role, router: &Router<4>| {
+            Beacon::new(id, role)
+                .with_route(router.advertisement())
+                .to_packet(0)
+        };
+
+        relay.learn(&beacon(GATEWAY, NodeRole::Gateway, &gateway), Some(-60), 0);
+        node.learn(&beacon(RELAY, NodeRole::Relay, &relay), Some(-60), 900);
+        let advert = defmt::unwrap!(node.advertisement());
+        defmt::assert!(advert.via == RELAY && advert.cost == 2 * PERFECT_LINK_COST);
+
+        // The gateway went silent, the relay must not fall back to the node's route
+        relay.learn(&beacon(NODE, NodeRole::Sensor, &node), Some(-60), 1_500);
+        defmt::assert!(relay.table().get(GATEWAY).is_none());
+        let advert = defmt::unwrap!(relay.advertisement());
+        defmt::assert!(advert.gateway_id == GATEWAY && advert.cost == INFINITE_COST);
+        defmt::assert!(!advert.is_reachable());
+
+        // The node drops its route at the relay's next beacon, before it expires
+        node.learn(&beacon(RELAY, NodeRole::Relay, &relay), Some(-60), 1_600);
+        defmt::assert!(node.table().get(GATEWAY).is_none());
+        defmt::assert!(node.table().get(RELAY).is_some());
+        defmt::assert!(!defmt::unwrap!(node.advertisement()).is_reachable());
+
+        // A returning gateway is picked up again
+        relay.learn(
+            &beacon(GATEWAY, NodeRole::Gateway, &gateway),
+            Some(-60),
+            1_700,
+        );
+        node.learn(&beacon(RELAY, NodeRole::Relay, &relay), Some(-60), 1_700);
+        defmt::assert!(defmt::unwrap!(node.table().get(GATEWAY)).next_hop == RELAY);
+    }
+
+    #[test]
+    fn test_table_update_rules() {
+        let mut table: RoutingTable<2> = RoutingTable::default();
+        let route = |destination, next_hop, cost| Route {
+            destination,
+            next_hop,
+            cost,
+            is_gateway: false,
+            updated_ms: 0,
+        };
+
+        defmt::assert!(table.update(route(10, 1, 40)));
+        // More expensive alternative is ignored, current next hop may raise the cost
+        defmt::assert!(!table.update(route(10, 2, 50)));
+        defmt::assert!(table.update(route(10, 1, 60)));
+        defmt::assert!(defmt::unwrap!(table.get(10)).cost == 60);
+        // Current next hop lost its route
+        defmt::assert!(table.update(route(10, 1, INFINITE_COST)));
+        defmt::assert!(table.get(10).is_none());
+
+        // A full table only replaces its most expensive route
+        defmt::assert!(table.update(route(10, 1, 40)));
+        defmt::assert!(table.update(route(11, 1, 80)));
+        defmt::assert!(!table.update(route(12, 1, 90)));
+        defmt::assert!(table.update(route(12, 1, 20)));
+        defmt::assert!(table.get(11).is_none());
+        defmt::assert!(table.len() == 2);
+    }
+}
@@ -0,0 +1,342 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::security::PacketSecurity;
+use sensor_swarm::testing::board_sim::{board_uid, RamFlash, SIM_NETWORK_KEY};
+
+/// Security context of a board with empty flash
+fn context(node_id: u16, board: u8) -> PacketSecurity<RamFlash<2>, 4> {
+    PacketSecurity::new(
+        node_id,
+        &board_uid(board),
+        &SIM_NETWORK_KEY,
+        RamFlash::new(),
+        0,
+    )
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::context;
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::fragment::{Fragments, Reassembler, FRAGMENT_DATA_SIZE};
+    use sensor_swarm::radio::hopping::ChannelMap;
+    use sensor_swarm::radio::join::{JoinAccept, NetworkParams, UID_SIZE};
+    use sensor_swarm::radio::message::Message;
+    use sensor_swarm::radio::protocol::{
+        Packet, BROADCAST_ID, EXTENSION_PREFIX_SIZE, MAX_DATA_SIZE, OPTION_LIFETIME,
+        OPTION_PREFIX_SIZE,
+    };
+    use sensor_swarm::radio::security::*;
+    use sensor_swarm::radio::traits::{RadioError, RadioReceiver, RadioTransmitter};
+    use sensor_swarm::testing::board_sim::{board_uid, RamFlash, SIM_NETWORK_KEY, SIM_SECTOR_SIZE};
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    #[test]
+    fn test_seal_and_open_roundtrip() {
+        let mut alice: PacketSecurity<RamFlash<2>, 4> = context(1, 1);
+        let mut bob: PacketSecurity<RamFlash<2>, 4> = context(2, 2);
+        let packet = Packet::new(1, 2, 40, b"T=21.50C");
+
+        let sealed = defmt::unwrap!(alice.seal(&packet));
+        defmt::assert!(sealed.header.control.is_secured());
+        defmt::assert!(sealed.header.payload_len as usize == 8 + SECURITY_OVERHEAD);
+        defmt::assert!(
+            &sealed.payload_data()[SECURITY_HEADER_SIZE..SECURITY_HEADER_SIZE + 8] != b"T=21.50C"
+        );
+        defmt::assert!(alice.frame_counter() == 1);
+
+        let opened = defmt::unwrap!(bob.open(&sealed));
+        defmt::assert!(opened.payload_data() == b"T=21.50C");
+        defmt::assert!(opened.header.sequence_number == 40);
+        defmt::assert!(opened.header.control.is_secured());
+        defmt::assert!(bob.stats().opened == 1);
+
+        // Every transmission uses a fresh frame counter, even for the same packet
+        let again = defmt::unwrap!(alice.seal(&packet));
+        defmt::assert!(again.payload_data() != sealed.payload_data());
+        defmt::assert!(defmt::unwrap!(bob.open(&again)).payload_data() == b"T=21.50C");
+    }
+
+    #[test]
+    fn test_tampered_frames_fail_authentication() {
+        let mut alice: PacketSecurity<RamFlash<2>, 4> = context(1, 1);
+        let mut bob: PacketSecurity<RamFlash<2>, 4> = context(2, 2);
+        let mut mallory: PacketSecurity<RamFlash<2>, 4> = PacketSecurity::new(
+            3,
+            &board_uid(3),
+            &[0x55; NETWORK_KEY_SIZE],
+            RamFlash::new(),
+            0,
+        );
+        let sealed = defmt::unwrap!(alice.seal(&Packet::new(1, 2, 1, b"open valve")));
+
+        // Redirected packet: the header is authenticated
+        let mut redirected = sealed.clone();
+        redirected.header.target_id = BROADCAST_ID;
+        defmt::assert!(bob.open(&redirected) == Err(SecurityError::AuthenticationFailed));
+
+        // Flipped ciphertext bit
+        let mut flipped = sealed.clone();
+        flipped.payload[SECURITY_HEADER_SIZE] ^= 0x01;
+        defmt::assert!(bob.open(&flipped) == Err(SecurityError::AuthenticationFailed));
+
+        // Packet sealed with another key
+        let forged = defmt::unwrap!(mallory.seal(&Packet::new(1, 2, 1, b"open valve")));
+        defmt::assert!(bob.open(&forged) == Err(SecurityError::AuthenticationFailed));
+        defmt::assert!(bob.stats().auth_failures == 3);
+
+        // Failed frames do not consume the frame counter of the genuine one
+        defmt::assert!(defmt::unwrap!(bob.open(&sealed)).payload_data() == b"open valve");
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut alice: PacketSecurity<RamFlash<2>, 4> = context(1, 1);
+        let mut bob: PacketSecurity<RamFlash<2>, 4> = context(2, 2);
+        let packet = Packet::new(1, 2, 1, b"x");
+        let first = defmt::unwrap!(alice.seal(&packet));
+        let second = defmt::unwrap!(alice.seal(&packet));
+        let third = defmt::unwrap!(alice.seal(&packet));
+
+        // Out of order delivery inside the window is fine, repeats are not
+        defmt::unwrap!(bob.open(&third));
+        defmt::unwrap!(bob.open(&first));
+        defmt::assert!(bob.open(&first) == Err(SecurityError::Replay));
+        defmt::unwrap!(bob.open(&second));
+
+        // Frames older than the window are rejected
+        alice.set_frame_counter(1000);
+        defmt::unwrap!(bob.open(&defmt::unwrap!(alice.seal(&packet))));
+        defmt::assert!(bob.open(&third) == Err(SecurityError::Replay));
+        defmt::assert!(bob.stats().replays == 2);
+
+        // The frame counter never moves backwards
+        alice.set_frame_counter(5);
+        defmt::assert!(alice.frame_counter() == 1001);
+        alice.set_frame_counter(u32::MAX);
+        defmt::assert!(alice.seal(&packet) == Err(SecurityError::CounterExhausted));
+    }
+
+    #[test]
+    fn test_frame_counter_survives_reboot() {
+        let mut alice = context(1, 1);
+        let mut bob = context(2, 2);
+        let packet = Packet::new(1, 2, 1, b"x");
+
+        // The first seal reserves a block of counters before using one
+        defmt::unwrap!(bob.open(&defmt::unwrap!(alice.seal(&packet))));
+        let reserved = FrameCounterRecord {
+            next: FRAME_COUNTER_RESERVATION,
+        };
+        defmt::assert!(FrameCounterRecord::load(alice.storage(), 0) == Some(reserved));
+
+        // After a reboot the node continues behind the reserved block
+        let mut rebooted: PacketSecurity<RamFlash<2>, 4> = PacketSecurity::new(
+            1,
+            &board_uid(1),
+            &SIM_NETWORK_KEY,
+            alice.storage().clone(),
+            0,
+        );
+        defmt::assert!(rebooted.frame_counter() == FRAME_COUNTER_RESERVATION);
+        let sealed = defmt::unwrap!(rebooted.seal(&packet));
+        defmt::assert!(defmt::unwrap!(bob.open(&sealed)).payload_data() == b"x");
+        defmt::assert!(bob.stats().replays == 0);
+
+        // The next block is reserved once the current one is used up
+        rebooted.set_frame_counter(2 * FRAME_COUNTER_RESERVATION - 1);
+        defmt::unwrap!(rebooted.seal(&packet));
+        defmt::assert!(
+            FrameCounterRecord::load(rebooted.storage(), 0).map(|record| record.next)
+                == Some(2 * FRAME_COUNTER_RESERVATION)
+        );
+        defmt::unwrap!(rebooted.seal(&packet));
+        defmt::assert!(
+            FrameCounterRecord::load(rebooted.storage(), 0).map(|record| record.next)
+                == Some(3 * FRAME_COUNTER_RESERVATION)
+        );
+
+        let mut record = reserved.to_bytes();
+        record[2] ^= 0x01;
+        defmt::assert!(FrameCounterRecord::from_bytes(&record).is_none());
+    }
+
+    #[test]
+    fn test_power_loss_during_sector_erase_keeps_the_counter() {
+        let mut alice = context(1, 1);
+        let packet = Packet::new(1, 2, 1, b"x");
+        let records_per_sector = (SIM_SECTOR_SIZE / FRAME_COUNTER_RECORD_SIZE) as u32;
+
+        // Reservations are appended, the first sector is filled without erasing
+        for block in 0..records_per_sector {
+            alice.set_frame_counter(block * FRAME_COUNTER_RESERVATION);
+            defmt::unwrap!(alice.seal(&packet));
+        }
+        defmt::assert!(alice.storage().erases == 0);
+        let highest_used = (records_per_sector - 1) * FRAME_COUNTER_RESERVATION;
+
+        // Power fails after the second sector was erased, before the record is written
+        alice.storage().power_lost = true;
+        alice.set_frame_counter(records_per_sector * FRAME_COUNTER_RESERVATION);
+        defmt::assert!(alice.seal(&packet).err() == Some(SecurityError::StorageFailed));
+        defmt::assert!(alice.storage().erases == 1);
+
+        let mut flash = alice.storage().clone();
+        flash.power_lost = false;
+        let mut rebooted: PacketSecurity<RamFlash<2>, 4> =
+            PacketSecurity::new(1, &board_uid(1), &SIM_NETWORK_KEY, flash, 0);
+        defmt::assert!(rebooted.frame_counter() > highest_used);
+
+        // The next reservation goes to the erased sector
+        defmt::unwrap!(rebooted.seal(&packet));
+        defmt::assert!(rebooted.storage().erases == 2);
+        defmt::assert!(
+            FrameCounterRecord::load(rebooted.storage(), 0).map(|record| record.next)
+                == Some((records_per_sector + 1) * FRAME_COUNTER_RESERVATION)
+        );
+    }
+
+    #[test]
+    fn test_colliding_node_ids_use_distinct_nonces() {
+        // Two boards sharing node ID 7, both starting at frame counter 0
+        let mut first = context(7, 1);
+        let mut second = context(7, 2);
+        let mut bob = context(2, 3);
+        let packet = Packet::new(7, 2, 1, b"same plaintext");
+
+        let from_first = defmt::unwrap!(first.seal(&packet));
+        let from_second = defmt::unwrap!(second.seal(&packet));
+        defmt::assert!(from_first.payload_data() != from_second.payload_data());
+
+        // Each board has its own replay window
+        defmt::unwrap!(bob.open(&from_first));
+        defmt::unwrap!(bob.open(&from_second));
+        defmt::assert!(bob.open(&from_first) == Err(SecurityError::Replay));
+        defmt::assert!(bob.open(&from_second) == Err(SecurityError::Replay));
+
+        // The UID tag is authenticated through the nonce
+        let mut carol = context(3, 4);
+        let mut swapped = from_first.clone();
+        swapped.payload[FRAME_COUNTER_SIZE..SECURITY_HEADER_SIZE]
+            .copy_from_slice(&from_second.payload[FRAME_COUNTER_SIZE..SECURITY_HEADER_SIZE]);
+        defmt::assert!(carol.open(&swapped) == Err(SecurityError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn test_header_options_stay_readable_and_authenticated() {
+        let mut alice: PacketSecurity<RamFlash<2>, 4> = context(1, 1);
+        let mut bob: PacketSecurity<RamFlash<2>, 4> = context(2, 2);
+        let mut packet = Packet::new(1, 2, 1, b"payload");
+        defmt::assert!(packet.push_option(OPTION_LIFETIME, &60u16.to_le_bytes()));
+
+        let sealed = defmt::unwrap!(alice.seal(&packet));
+        defmt::assert!(sealed.option(OPTION_LIFETIME) == Some(&[60, 0][..]));
+
+        let mut tampered = sealed.clone();
+        tampered.payload[EXTENSION_PREFIX_SIZE + OPTION_PREFIX_SIZE] = 0xFF;
+        defmt::assert!(bob.open(&tampered) == Err(SecurityError::AuthenticationFailed));
+
+        let opened = defmt::unwrap!(bob.open(&sealed));
+        defmt::assert!(opened.payload_data() == b"payload");
+        defmt::assert!(opened.option(OPTION_LIFETIME) == Some(&[60, 0][..]));
+    }
+
+    #[test]
+    fn test_unsecured_and_oversized_packets() {
+        let mut bob: PacketSecurity<RamFlash<2>, 4> = context(2, 2);
+        let plain = Packet::new(1, 2, 1, b"hello");
+        defmt::assert!(bob.open(&plain) == Err(SecurityError::NotSecured));
+
+        bob.set_accept_unsecured(true);
+        defmt::assert!(defmt::unwrap!(bob.open(&plain)) == plain);
+        defmt::assert!(bob.stats().unsecured == 2);
+
+        // A secured flag without room for the security fields
+        let mut truncated = plain.clone();
+        truncated.header.control.set_secured(true);
+        defmt::assert!(bob.open(&truncated) == Err(SecurityError::Malformed));
+
+        let large = Packet::new(2, 1, 1, &[0xAA; MAX_SECURED_PAYLOAD + 1]);
+        defmt::assert!(bob.seal(&large) == Err(SecurityError::PayloadTooLarge));
+        let fits = Packet::new(2, 1, 1, &[0xAA; MAX_SECURED_PAYLOAD]);
+        defmt::unwrap!(bob.seal(&fits));
+    }
+
+    #[test]
+    fn test_secure_radio_drops_injected_frames() {
+        let medium: SimMedium<3, 4> = SimMedium::new(SimConfig::default());
+        let mut alice = SecureRadio::new(medium.radio(0), context(1, 1));
+        let mut bob = SecureRadio::new(medium.radio(1), context(2, 2));
+        let mut intruder = medium.radio(2);
+
+        defmt::unwrap!(block_on(intruder.transmit(&Packet::new(1, 2, 7, b"fake"))));
+        match block_on(bob.receive()) {
+            Err(RadioError::AuthenticationFailed) => {}
+            _ => defmt::panic!("expected the injected frame to be dropped"),
+        }
+
+        defmt::unwrap!(block_on(alice.transmit(&Packet::new(1, 2, 8, b"real"))));
+        let received = defmt::unwrap!(block_on(bob.receive()));
+        defmt::assert!(received.payload_data() == b"real");
+
+        // The intruder only hears ciphertext
+        let overheard = defmt::unwrap!(block_on(intruder.receive()));
+        defmt::assert!(overheard.header.control.is_secured());
+        defmt::assert!(
+            &overheard.payload_data()[SECURITY_HEADER_SIZE..SECURITY_HEADER_SIZE + 4] != b"real"
+        );
+        defmt::assert!(bob.stats().unsecured == 1);
+        defmt::assert!(bob.stats().opened == 1);
+    }
+
+    #[test]
+    fn test_full_messages_and_fragments_fit_sealed() {
+        let medium: SimMedium<2, 4> = SimMedium::new(SimConfig::default());
+        let mut alice = SecureRadio::new(medium.radio(0), context(1, 1));
+        let mut bob = SecureRadio::new(medium.radio(1), context(2, 2));
+
+        let accept = JoinAccept {
+            uid: [0x5A; UID_SIZE],
+            node_id: 0x0102,
+            params: NetworkParams {
+                network_id: 0xBEEF,
+                gateway_id: 1,
+                channel: 4,
+                report_interval_s: 60,
+                channel_map: ChannelMap::all(8),
+            },
+            key_follows: true,
+        };
+        defmt::unwrap!(block_on(alice.transmit(&accept.to_packet(1, 1))));
+        let received = defmt::unwrap!(block_on(bob.receive()));
+        defmt::assert!(Message::from_packet(&received) == Ok(Message::JoinAccept(accept)));
+
+        let mut message = [0u8; 3 * FRAGMENT_DATA_SIZE];
+        for (i, byte) in message.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let template = Packet::new(1, 2, 10, &[]).header;
+        let mut reassembler: Reassembler<1> = Reassembler::default();
+        let mut result = None;
+        for packet in defmt::unwrap!(Fragments::new(template, 5, &message)) {
+            defmt::assert!(packet.payload_data().len() == MAX_DATA_SIZE);
+            defmt::unwrap!(block_on(alice.transmit(&packet)));
+            let received = defmt::unwrap!(block_on(bob.receive()));
+            defmt::assert!(received.header.control.is_fragment());
+            result = defmt::unwrap!(reassembler.push(&received, 0));
+        }
+        defmt::assert!(defmt::unwrap!(result).as_slice() == message.as_slice());
+        defmt::assert!(bob.stats().opened == 4);
+    }
+}
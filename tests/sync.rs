@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+/// Bit capture recorded from a receiver: noise, a preamble with one flipped bit,
+/// sync word and a frame for `Packet::new(1, 2, 3, b"hi")`, more noise, then a
+/// clean preamble + sync word and an emergency frame `Packet::new(5, 0, 16, b"!")`.
+const RECORDED_CAPTURE: [u8; 52] = [
+    0x3B, 0xC6, 0x19, 0xF0, 0x5D, 0xAA, 0xA2, 0x2D, 0xD4, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00,
+    0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x68, 0x69, 0x3A, 0xB5, 0x91, 0x4E, 0xAA, 0xAA, 0x2D,
+    0xD4, 0x05, 0x00, 0x00, 0x00, 0x10, 0x00, 0x04, 0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x01, 0x21,
+    0x59, 0x3E, 0x00, 0xFF,
+];
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use super::RECORDED_CAPTURE;
+    use sensor_swarm::radio::fec::ReedSolomon;
+    use sensor_swarm::radio::framing;
+    use sensor_swarm::radio::manchester::bits_msb_first;
+    use sensor_swarm::radio::protocol::{Packet, HEADER_SIZE};
+    use sensor_swarm::radio::sync::*;
+
+    #[test]
+    fn test_recorded_capture_yields_frames() {
+        let mut sync = FrameSynchronizer::new(SyncConfig::default());
+        let mut frames = 0;
+
+        for bit in bits_msb_first(&RECORDED_CAPTURE) {
+            if let Some(frame) = sync.push_bit(bit) {
+                let packet = defmt::unwrap!(framing::decode(&frame));
+                match frames {
+                    0 => defmt::assert!(packet == Packet::new(1, 2, 3, b"hi")),
+                    _ => {
+                        defmt::assert!(packet.header.sender_id == 5);
+                        defmt::assert!(packet.header.control.is_emergency());
+                        defmt::assert!(packet.payload_data() == b"!");
+                    }
+                }
+                frames += 1;
+            }
+        }
+
+        defmt::assert!(frames == 2);
+        defmt::assert!(sync.stats().frames == 2);
+        defmt::assert!(!sync.is_locked());
+    }
+
+    #[test]
+    fn test_bit_errors_beyond_tolerance_are_ignored() {
+        let config = SyncConfig {
+            max_bit_errors: 0,
+            ..SyncConfig::default()
+        };
+        let mut sync = FrameSynchronizer::new(config);
+
+        // The first preamble carries one bit error, only the second frame is found
+        let mut frames = 0;
+        for bit in bits_msb_first(&RECORDED_CAPTURE) {
+            if let Some(frame) = sync.push_bit(bit) {
+                let packet = defmt::unwrap!(framing::decode(&frame));
+                defmt::assert!(packet.header.sender_id == 5);
+                frames += 1;
+            }
+        }
+        defmt::assert!(frames == 1);
+    }
+
+    #[test]
+    fn test_impossible_length_is_a_false_sync() {
+        let mut sync = FrameSynchronizer::new(SyncConfig::default());
+        let capture = [
+            0xAA, 0xAA, 0x2D, 0xD4, 1, 0, 2, 0, 3, 0, 0, 3, 0, 1, 0, 0, 0, 0xFF, 0x00,
+        ];
+
+        defmt::assert!(sync.push_bits(bits_msb_first(&capture)).is_none());
+        defmt::assert!(sync.stats().sync_detections == 1);
+        defmt::assert!(sync.stats().false_syncs == 1);
+        defmt::assert!(!sync.is_locked());
+    }
+
+    #[test]
+    fn test_trailer_bytes_collect_fec_parity() {
+        let rs = defmt::unwrap!(ReedSolomon::new(8));
+        let packet = Packet::new(0x0102, 0, 77, b"fec");
+        let encoded = defmt::unwrap!(rs.encode_packet(&packet));
+
+        let config = SyncConfig {
+            trailer_bytes: rs.parity_symbols(),
+            ..SyncConfig::default()
+        };
+        let mut sync = FrameSynchronizer::new(config);
+
+        defmt::assert!(sync
+            .push_bits(bits_msb_first(&[0x00, 0xAA, 0xAA, 0x2D, 0xD4]))
+            .is_none());
+        defmt::assert!(sync.is_locked());
+
+        let mut frame = defmt::unwrap!(sync.push_bits(bits_msb_first(&encoded)));
+        defmt::assert!(frame.len() == encoded.len());
+        let (decoded, _) = defmt::unwrap!(rs.decode_packet(&mut frame));
+        defmt::assert!(decoded == packet);
+    }
+
+    #[test]
+    fn test_protected_length_survives_corruption() {
+        let rs = defmt::unwrap!(ReedSolomon::new(8));
+        let packet = Packet::new(0x0102, 0, 78, b"fec");
+        let mut encoded = defmt::unwrap!(rs.encode_packet(&packet));
+        // The header length byte is only repaired by the decoder
+        encoded[HEADER_SIZE - 1] ^= 0x40;
+        let config = SyncConfig {
+            trailer_bytes: rs.parity_symbols(),
+            ..SyncConfig::default()
+        };
+
+        let mut plain = FrameSynchronizer::new(config);
+        plain.push_bits(bits_msb_first(&[0x00, 0xAA, 0xAA, 0x2D, 0xD4]));
+        defmt::assert!(plain.push_bits(bits_msb_first(&encoded)).is_none());
+        defmt::assert!(plain.stats().false_syncs == 1);
+
+        let mut sync = FrameSynchronizer::new(SyncConfig {
+            protected_length: true,
+            ..config
+        });
+        sync.push_bits(bits_msb_first(&[0x00, 0xAA, 0xAA, 0x2D, 0xD4]));
+        let mut prefix = length_prefix(3);
+        prefix[1] ^= 0x41;
+        defmt::assert!(sync.push_bits(bits_msb_first(&prefix)).is_none());
+        let mut frame = defmt::unwrap!(sync.push_bits(bits_msb_first(&encoded)));
+        defmt::assert!(frame.len() == encoded.len());
+        let (decoded, _) = defmt::unwrap!(rs.decode_packet(&mut frame));
+        defmt::assert!(decoded == packet);
+    }
+
+    #[test]
+    fn test_pattern_lengths_are_clamped() {
+        let sync = FrameSynchronizer::new(SyncConfig {
+            preamble_bits: 200,
+            sync_bits: 64,
+            ..SyncConfig::default()
+        });
+        defmt::assert!(sync.config().preamble_bits == 32);
+        defmt::assert!(sync.config().sync_bits == 32);
+
+        let mut sync = FrameSynchronizer::new(SyncConfig {
+            sync_bits: 0,
+            ..SyncConfig::default()
+        });
+        defmt::assert!(sync.config().sync_bits == 1);
+        defmt::assert!(sync.push_bits(bits_msb_first(&[0xAA; 8])).is_none());
+    }
+}
@@ -0,0 +1,351 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use core::cell::Cell;
+use heapless::{Deque, Vec};
+use sensor_swarm::radio::protocol::Packet;
+use sensor_swarm::radio::tdma::SuperframeConfig;
+use sensor_swarm::radio::traits::{RadioError, RadioReceiver, RadioTransceiver, RadioTransmitter};
+
+/// Radio that records what the MAC asks it to do
+#[derive(Default)]
+struct MockRadio {
+    enabled: bool,
+    asleep: bool,
+    sleeps: u32,
+    sent: Vec<Packet, 8>,
+    incoming: Deque<Packet, 4>,
+    /// Carrier-sense checks that still find the channel busy
+    busy_samples: Cell<u8>,
+}
+
+impl RadioTransmitter for MockRadio {
+    async fn transmit(&mut self, packet: &Packet) -> Result<(), RadioError> {
+        if self.asleep {
+            return Err(RadioError::NotReady);
+        }
+        self.sent
+            .push(packet.clone())
+            .map_err(|_| RadioError::BufferError)
+    }
+
+    fn is_ready(&self) -> bool {
+        !self.asleep
+    }
+
+    async fn set_power_level(&mut self, _power_level: u8) -> Result<(), RadioError> {
+        Ok(())
+    }
+
+    fn get_power_level(&self) -> u8 {
+        0
+    }
+}
+
+impl RadioReceiver for MockRadio {
+    async fn receive(&mut self) -> Result<Packet, RadioError> {
+        self.incoming.pop_front().ok_or(RadioError::ReceptionFailed)
+    }
+
+    fn packet_available(&self) -> bool {
+        self.enabled && !self.asleep && !self.incoming.is_empty()
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> Result<(), RadioError> {
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn get_rssi(&self) -> Option<i16> {
+        None
+    }
+
+    fn is_channel_busy(&self) -> bool {
+        let busy = self.busy_samples.get();
+        self.busy_samples.set(busy.saturating_sub(1));
+        busy > 0
+    }
+}
+
+impl RadioTransceiver for MockRadio {
+    async fn initialize(&mut self) -> Result<(), RadioError> {
+        Ok(())
+    }
+
+    async fn sleep(&mut self) -> Result<(), RadioError> {
+        self.asleep = true;
+        self.sleeps += 1;
+        Ok(())
+    }
+
+    async fn wake(&mut self) -> Result<(), RadioError> {
+        self.asleep = false;
+        Ok(())
+    }
+
+    fn get_frequency(&self) -> u32 {
+        433_000_000
+    }
+
+    async fn set_frequency(&mut self, _frequency_hz: u32) -> Result<(), RadioError> {
+        Ok(())
+    }
+}
+
+/// Superframe of 8 slots of 100 ms, at most two packets per slot
+fn config() -> SuperframeConfig {
+    SuperframeConfig {
+        slot_count: 8,
+        slot_duration_ms: 100,
+        guard_ms: 10,
+        max_packets_per_slot: 2,
+    }
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::{config, MockRadio};
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::protocol::{Packet, BROADCAST_ID};
+    use sensor_swarm::radio::tdma::*;
+    use sensor_swarm::radio::traits::RadioError;
+
+    #[test]
+    fn test_default_slot_derived_from_node_id() {
+        let a = SlotAssignment::from_node_id(3, &config());
+        let b = SlotAssignment::from_node_id(4, &config());
+
+        defmt::assert!(a.tx_slot == 4);
+        defmt::assert!(b.tx_slot == 5);
+        defmt::assert!(a.listens_in(SHARED_SLOT));
+        defmt::assert!(!a.listens_in(b.tx_slot));
+
+        // Node IDs wrap around the slots but never land on the shared slot
+        for node_id in 0..64 {
+            let slot = SlotAssignment::from_node_id(node_id, &config()).tx_slot;
+            defmt::assert!(slot != SHARED_SLOT && slot < 8);
+        }
+        defmt::assert!(a
+            .with_listen_slot(b.tx_slot, &config())
+            .listens_in(b.tx_slot));
+
+        // Slots beyond the superframe are ignored
+        defmt::assert!(a.with_listen_slot(8, &config()) == a);
+        defmt::assert!(a.with_listen_slot(MAX_SLOTS, &config()) == a);
+
+        // Listening to a neighbor covers its default transmit slot
+        let parent = SlotAssignment::default_tx_slot(4, &config());
+        defmt::assert!(parent == b.tx_slot);
+        defmt::assert!(a.with_neighbor(4, &config()).listens_in(parent));
+    }
+
+    #[test]
+    fn test_schedule_actions_over_superframe() {
+        // Node 3 transmits in slot 4 (400..500 ms)
+        let schedule = TdmaSchedule::for_node(3, config());
+
+        // The shared slot is open to every node after the guard interval
+        let shared = schedule.action_at(800 + 20);
+        defmt::assert!(shared.activity == SlotActivity::Contend);
+        defmt::assert!(shared.slot == SHARED_SLOT && shared.until_ms == 890);
+        defmt::assert!(schedule.action_at(805).activity == SlotActivity::Listen);
+        defmt::assert!(schedule.action_at(895).activity == SlotActivity::Listen);
+
+        let other = schedule.action_at(150);
+        defmt::assert!(other.activity == SlotActivity::Sleep);
+        defmt::assert!(other.slot == 1);
+        defmt::assert!(other.until_ms == 200);
+
+        let guard = schedule.action_at(405);
+        defmt::assert!(guard.activity == SlotActivity::Listen);
+        defmt::assert!(guard.until_ms == 410);
+
+        let send = schedule.action_at(410);
+        defmt::assert!(send.activity == SlotActivity::Transmit);
+        defmt::assert!(send.until_ms == 490);
+
+        defmt::assert!(schedule.action_at(495).activity == SlotActivity::Listen);
+
+        // The pattern repeats every superframe
+        defmt::assert!(schedule.action_at(800 + 450).activity == SlotActivity::Transmit);
+        defmt::assert!(schedule.next_transmit_ms(150) == 410);
+        defmt::assert!(schedule.next_transmit_ms(450) == 450);
+        defmt::assert!(schedule.next_transmit_ms(600) == 1210);
+    }
+
+    #[test]
+    fn test_unicast_slots_are_opt_in() {
+        // Node 4 transmits in slot 5 (500..600 ms)
+        let schedule = TdmaSchedule::for_node(3, config());
+        defmt::assert!(schedule.action_at(550).activity == SlotActivity::Sleep);
+
+        let assignment = SlotAssignment::from_node_id(3, &config()).with_neighbor(4, &config());
+        let schedule = TdmaSchedule::new(config(), assignment);
+        let listen = schedule.action_at(550);
+        defmt::assert!(listen.activity == SlotActivity::Listen);
+        defmt::assert!(listen.until_ms == 600);
+    }
+
+    #[test]
+    fn test_single_slot_config_keeps_a_transmit_slot() {
+        let single = SuperframeConfig {
+            slot_count: 1,
+            ..config()
+        };
+        let schedule = TdmaSchedule::for_node(3, single);
+        defmt::assert!(schedule.config().slot_count == MIN_SLOTS);
+        defmt::assert!(schedule.assignment().tx_slot == 1);
+        defmt::assert!(schedule.action_at(150).activity == SlotActivity::Transmit);
+
+        // Queued packets go out in the owned slot
+        let mut mac: TdmaMac<MockRadio, 4> = TdmaMac::new(MockRadio::default(), schedule, 3);
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 1, b"a")));
+        let mut now = 0;
+        while now < 200 {
+            now = defmt::unwrap!(block_on(mac.step(now)));
+        }
+        defmt::assert!(mac.radio().sent.len() == 1);
+        defmt::assert!(mac.queued() == 0);
+    }
+
+    #[test]
+    fn test_packets_wait_for_owned_slot() {
+        let mut mac: TdmaMac<MockRadio, 4> =
+            TdmaMac::new(MockRadio::default(), TdmaSchedule::for_node(3, config()), 3);
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 1, b"a")));
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 2, b"b")));
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 3, b"c")));
+
+        // Drive the MAC with a simulated clock through one superframe
+        let mut now = 100;
+        while now < 400 {
+            now = defmt::unwrap!(block_on(mac.step(now)));
+            defmt::assert!(mac.radio().sent.is_empty());
+        }
+        defmt::assert!(mac.radio().asleep);
+        defmt::assert!(!mac.radio().enabled);
+        defmt::assert!(mac.radio().sleeps == 1);
+
+        while now < 500 {
+            now = defmt::unwrap!(block_on(mac.step(now)));
+        }
+
+        // Two packets fit into the slot, the third waits for the next superframe
+        defmt::assert!(mac.radio().sent.len() == 2);
+        defmt::assert!(mac.queued() == 1);
+        defmt::assert!(mac.stats().transmitted == 2);
+        defmt::assert!(mac.stats().wakeups == 1);
+
+        now = defmt::unwrap!(block_on(mac.step(now)));
+        defmt::assert!(now == 600);
+        defmt::assert!(mac.radio().asleep);
+
+        while now < 800 + 500 {
+            now = defmt::unwrap!(block_on(mac.step(now)));
+        }
+        defmt::assert!(mac.radio().sent.len() == 3);
+        defmt::assert!(mac.queued() == 0);
+    }
+
+    #[test]
+    fn test_listens_in_shared_slot() {
+        let mut mac: TdmaMac<MockRadio, 4> =
+            TdmaMac::new(MockRadio::default(), TdmaSchedule::for_node(3, config()), 3);
+
+        // Asleep in slot 7, a packet arriving now is not picked up
+        defmt::unwrap!(block_on(mac.step(750)));
+        defmt::unwrap!(mac.radio().incoming.push_back(Packet::new(9, 0, 1, b"x")));
+        defmt::unwrap!(block_on(mac.step(760)));
+        defmt::assert!(mac.receive().is_none());
+
+        // Woken for the shared slot
+        let next = defmt::unwrap!(block_on(mac.step(800)));
+        defmt::assert!(next < 900);
+        defmt::assert!(mac.radio().enabled && !mac.radio().asleep);
+        let packet = defmt::unwrap!(mac.receive());
+        defmt::assert!(packet.header.sender_id == 9);
+        defmt::assert!(mac.stats().received == 1);
+    }
+
+    #[test]
+    fn test_broadcasts_contend_in_shared_slot() {
+        let mut mac: TdmaMac<MockRadio, 4> =
+            TdmaMac::new(MockRadio::default(), TdmaSchedule::for_node(3, config()), 3);
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 1, b"unicast")));
+        for sequence_number in 2..5 {
+            defmt::unwrap!(mac.enqueue(Packet::new(3, BROADCAST_ID, sequence_number, b"b")));
+        }
+        defmt::assert!(mac.queued() == 1 && mac.queued_broadcasts() == 3);
+        mac.radio().busy_samples.set(1);
+
+        // Broadcasts go out at random points of the send window of the shared slot
+        let mut now = 0;
+        let mut send_times: heapless::Vec<u64, 4> = heapless::Vec::new();
+        while now < 100 {
+            let sent = mac.radio().sent.len();
+            let next = defmt::unwrap!(block_on(mac.step(now)));
+            if mac.radio().sent.len() > sent {
+                defmt::unwrap!(send_times.push(now));
+            }
+            defmt::assert!(next > now);
+            now = next;
+        }
+        defmt::assert!(send_times.iter().all(|&at| (10..90).contains(&at)));
+        defmt::assert!(mac
+            .radio()
+            .sent
+            .iter()
+            .all(|p| p.header.target_id == BROADCAST_ID));
+
+        // At most two per slot, and one attempt found the channel busy
+        defmt::assert!(mac.stats().broadcasts == 2);
+        defmt::assert!(mac.stats().busy_deferrals == 1);
+        defmt::assert!(mac.queued() == 1 && mac.queued_broadcasts() == 1);
+
+        while now < 800 + 100 {
+            now = defmt::unwrap!(block_on(mac.step(now)));
+        }
+        defmt::assert!(mac.queued() == 0 && mac.queued_broadcasts() == 0);
+        defmt::assert!(mac.stats().transmitted == 1 && mac.stats().broadcasts == 3);
+    }
+
+    #[test]
+    fn test_zero_slot_duration_is_clamped() {
+        let zero = SuperframeConfig {
+            slot_duration_ms: 0,
+            ..config()
+        };
+        let schedule = TdmaSchedule::for_node(3, zero);
+        defmt::assert!(schedule.config().slot_duration_ms == 1);
+        defmt::assert!(schedule.action_at(4).activity == SlotActivity::Transmit);
+        defmt::assert!(schedule.next_transmit_ms(5) == 12);
+    }
+
+    #[test]
+    fn test_full_queue_rejects_packets() {
+        let mut mac: TdmaMac<MockRadio, 2> =
+            TdmaMac::new(MockRadio::default(), TdmaSchedule::for_node(3, config()), 3);
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 1, b"a")));
+        defmt::unwrap!(mac.enqueue(Packet::new(3, 1, 2, b"b")));
+
+        match mac.enqueue(Packet::new(3, 1, 3, b"c")) {
+            Err(RadioError::BufferError) => {}
+            _ => defmt::panic!("expected a full queue"),
+        }
+        defmt::assert!(mac.stats().queue_overflows == 1);
+    }
+}
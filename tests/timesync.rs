@@ -0,0 +1,166 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+/// Local clock of a simulated node: `offset_ms` ahead of the root and running
+/// `drift_ppm` faster
+fn local_time(root_ms: u64, offset_ms: u64, drift_ppm: i64) -> u64 {
+    let drift = root_ms as i64 * drift_ppm / 1_000_000;
+    (root_ms as i64 + drift) as u64 + offset_ms
+}
+
+/// Local time at which a node with a 1 s interval that heard no lower ID elects itself
+const ELECTED_MS: u64 = sensor_swarm::radio::timesync::ROOT_TIMEOUT_ROUNDS * 1_000 + 1;
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+
+    use super::{local_time, ELECTED_MS};
+    use sensor_swarm::radio::protocol::Packet;
+    use sensor_swarm::radio::timesync::*;
+    use sensor_swarm::sensors::traits::EnvironmentalData;
+
+    #[test]
+    fn test_message_packet_roundtrip() {
+        let message = TimeSyncMessage {
+            root_id: 1,
+            round: 513,
+            depth: 2,
+            global_time_ms: 0x0102_0304_0506_0708,
+        };
+        let packet = message.to_packet(7, 99);
+        defmt::assert!(packet.header.ttl == 0);
+        defmt::assert!(TimeSyncMessage::from_packet(&packet) == Some(message));
+        defmt::assert!(TimeSyncMessage::from_packet(&Packet::new(7, 0, 1, b"x")).is_none());
+    }
+
+    #[test]
+    fn test_lowest_id_becomes_root() {
+        let mut root = TimeSync::new(1, 1_000);
+        let mut node = TimeSync::new(5, 1_000);
+        defmt::assert!(root.is_root());
+        defmt::assert!(node.is_root());
+
+        let message = defmt::unwrap!(root.poll(ELECTED_MS));
+        defmt::assert!(node.on_message(&message, 10));
+        defmt::assert!(!node.is_root());
+        defmt::assert!(node.root_id() == 1);
+        defmt::assert!(node.depth() == 1);
+
+        // The root ignores nodes with higher IDs
+        let mut other = TimeSync::new(9, 1_000);
+        let foreign = defmt::unwrap!(other.poll(ELECTED_MS));
+        defmt::assert!(!root.on_message(&foreign, 20));
+        defmt::assert!(root.is_root());
+    }
+
+    #[test]
+    fn test_offset_and_drift_are_tracked_over_two_hops() {
+        let interval = 10_000;
+        let mut root = TimeSync::new(1, interval);
+        let mut relay = TimeSync::new(2, interval);
+        let mut leaf = TimeSync::new(3, interval);
+
+        // Relay clock is 500 s ahead and 50 ppm fast, leaf 80 s ahead and 80 ppm slow
+        let relay_clock = |root_ms| local_time(root_ms, 500_000, 50);
+        let leaf_clock = |root_ms| local_time(root_ms, 80_000, -80);
+
+        for round in 0..10u64 {
+            let root_ms = 1_000_000 + round * interval;
+            let message = defmt::unwrap!(root.poll(root_ms));
+            relay.on_message(&message, relay_clock(root_ms + 3));
+
+            let relay_tx = root_ms + 40;
+            if let Some(message) = relay.poll(relay_clock(relay_tx)) {
+                leaf.on_message(&message, leaf_clock(relay_tx + 3));
+            }
+        }
+
+        let root_ms = 1_000_000 + 10 * interval + 5_000;
+        let relay_estimate = defmt::unwrap!(relay.network_time_ms(relay_clock(root_ms)));
+        let leaf_estimate = defmt::unwrap!(leaf.network_time_ms(leaf_clock(root_ms)));
+        let relay_bound = defmt::unwrap!(relay.error_bound_ms(relay_clock(root_ms)));
+        let leaf_bound = defmt::unwrap!(leaf.error_bound_ms(leaf_clock(root_ms)));
+
+        defmt::assert!(leaf.depth() == 2);
+        defmt::assert!(relay_estimate.abs_diff(root_ms) <= relay_bound as u64 + 3);
+        defmt::assert!(leaf_estimate.abs_diff(root_ms) <= leaf_bound as u64 + 6);
+        defmt::assert!(leaf_bound >= relay_bound);
+        defmt::assert!(leaf_bound < 20);
+    }
+
+    #[test]
+    fn test_unsynchronized_node_stays_silent() {
+        let mut root = TimeSync::new(1, 1_000);
+        let mut node = TimeSync::new(4, 1_000);
+
+        node.on_message(&defmt::unwrap!(root.poll(ELECTED_MS)), 0);
+        defmt::assert!(node.network_time_ms(0).is_none());
+        defmt::assert!(node.poll(100).is_none());
+    }
+
+    #[test]
+    fn test_node_takes_over_when_root_is_lost() {
+        let mut root = TimeSync::new(1, 1_000);
+        let mut node = TimeSync::new(4, 1_000);
+        for round in 0..4u64 {
+            let message = defmt::unwrap!(root.poll(ELECTED_MS + round * 1_000));
+            node.on_message(&message, round * 1_000 + 7);
+        }
+        defmt::assert!(!node.is_root());
+        let before = defmt::unwrap!(node.network_time_ms(10_000));
+
+        defmt::assert!(node.poll(20_000).is_some());
+        defmt::assert!(node.is_root());
+        defmt::assert!(defmt::unwrap!(node.network_time_ms(10_000)) == before);
+        defmt::assert!(node.error_bound_ms(20_000) == Some(0));
+    }
+
+    #[test]
+    fn test_root_is_elected_after_hearing_no_lower_id() {
+        let mut node = TimeSync::new(3, 1_000).with_published_clock();
+        defmt::assert!(node.network_time_ms(0).is_none());
+        defmt::assert!(NETWORK_CLOCK.lock(|clock| clock.get()).is_none());
+        defmt::assert!(node.poll(1_000).is_none());
+        defmt::assert!(node.poll(ELECTED_MS - 1).is_none());
+
+        let message = defmt::unwrap!(node.poll(ELECTED_MS));
+        defmt::assert!(message.root_id == 3 && message.depth == 0);
+        defmt::assert!(message.global_time_ms == ELECTED_MS);
+        defmt::assert!(NETWORK_CLOCK.lock(|clock| clock.get()).is_some());
+        publish_clock(None);
+    }
+
+    #[test]
+    fn test_readings_use_network_time_once_published() {
+        let mut data = EnvironmentalData::new();
+        publish_clock(None);
+        stamp_reading(&mut data, 1_234);
+        defmt::assert!(data.timestamp_ms == 1_234);
+        defmt::assert!(!data.validity.timestamp_synced());
+
+        let mut root = TimeSync::new(1, 1_000);
+        let mut node = TimeSync::new(2, 1_000).with_published_clock();
+        for round in 0..4u64 {
+            let message = defmt::unwrap!(root.poll(50_000 + round * 1_000));
+            if round < 3 {
+                // Uptime is not network time before the root was heard often enough
+                stamp_reading(&mut data, 1_234);
+                defmt::assert!(!data.validity.timestamp_synced());
+            }
+            node.on_message(&message, round * 1_000);
+        }
+        stamp_reading(&mut data, 5_000);
+        defmt::assert!(data.timestamp_ms == 55_000);
+        defmt::assert!(data.validity.timestamp_synced());
+    }
+}
@@ -0,0 +1,135 @@
+#![no_std]
+#![no_main]
+
+use defmt_semihosting as _;
+use panic_probe as _;
+
+// Custom defmt panic handler for tests
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
+
+use sensor_swarm::radio::protocol::Packet;
+use sensor_swarm::radio::tx_queue::TxRequest;
+
+fn request(sequence_number: u16, emergency: bool, submitted_ms: u64) -> TxRequest {
+    let mut packet = Packet::new(1, 2, sequence_number, b"data");
+    packet.header.control.set_emergency(emergency);
+    TxRequest::new(packet, submitted_ms)
+}
+
+#[cfg(test)]
+#[defmt_test::tests]
+mod tests {
+    use super::request;
+    use embassy_futures::block_on;
+    use sensor_swarm::radio::traits::RadioReceiver;
+    use sensor_swarm::radio::tx_queue::*;
+    use sensor_swarm::testing::radio_sim::{SimConfig, SimMedium};
+
+    #[test]
+    fn test_emergency_packets_go_first() {
+        let mut queue: PriorityQueue<8> = PriorityQueue::new(TxQueueConfig::default());
+        defmt::assert!(queue.push(request(1, false, 0)));
+        defmt::assert!(queue.push(request(2, false, 0)));
+        defmt::assert!(queue.push(request(3, true, 10)));
+        defmt::assert!(queue.push(request(4, true, 20)));
+        defmt::assert!(queue.emergency_len() == 2);
+
+        let order = [3, 4, 1, 2];
+        for sequence_number in order {
+            let next = defmt::unwrap!(queue.pop(100));
+            defmt::assert!(next.packet.header.sequence_number == sequence_number);
+        }
+        defmt::assert!(queue.pop(100).is_none());
+
+        let stats = queue.stats();
+        defmt::assert!(stats.max_depth == 4 && stats.depth == 0);
+        defmt::assert!(stats.max_emergency_wait_ms == 90);
+        defmt::assert!(stats.max_wait_ms == 100);
+        defmt::assert!(stats.total_wait_ms == 90 + 80 + 100 + 100);
+    }
+
+    #[test]
+    fn test_full_queue_keeps_emergency_packets() {
+        let mut queue: PriorityQueue<2> = PriorityQueue::new(TxQueueConfig::default());
+        defmt::assert!(queue.push(request(1, false, 0)));
+        defmt::assert!(queue.push(request(2, false, 0)));
+        defmt::assert!(!queue.push(request(3, false, 0)));
+
+        // The oldest routine packet makes room for an emergency packet
+        defmt::assert!(queue.push(request(4, true, 0)));
+        defmt::assert!(queue.push(request(5, true, 0)));
+        defmt::assert!(!queue.push(request(6, true, 0)));
+        defmt::assert!(queue.emergency_len() == 2);
+        defmt::assert!(queue.stats().dropped_full == 4);
+        defmt::assert!(queue.stats().queued == 4);
+    }
+
+    #[test]
+    fn test_aging_policies() {
+        let config = TxQueueConfig {
+            max_wait_ms: 1_000,
+            aging: AgingPolicy::Drop,
+        };
+        let mut queue: PriorityQueue<4> = PriorityQueue::new(config);
+        defmt::assert!(queue.push(request(1, false, 0)));
+        defmt::assert!(queue.push(request(2, false, 500)));
+        let next = defmt::unwrap!(queue.pop(1_200));
+        defmt::assert!(next.packet.header.sequence_number == 2);
+        defmt::assert!(queue.stats().dropped_stale == 1);
+
+        let config = TxQueueConfig {
+            aging: AgingPolicy::Promote,
+            ..config
+        };
+        let mut queue: PriorityQueue<4> = PriorityQueue::new(config);
+        defmt::assert!(queue.push(request(1, false, 0)));
+        defmt::assert!(queue.push(request(2, true, 1_100)));
+        defmt::assert!(queue.push(request(3, true, 1_150)));
+        // The starved packet is sent behind the emergency packets already waiting
+        queue.age(1_200);
+        defmt::assert!(queue.push(request(4, true, 1_200)));
+        let order = [2, 3, 1, 4];
+        for sequence_number in order {
+            let next = defmt::unwrap!(queue.pop(1_300));
+            defmt::assert!(next.packet.header.sequence_number == sequence_number);
+        }
+        defmt::assert!(queue.stats().promoted == 1);
+
+        let config = TxQueueConfig {
+            aging: AgingPolicy::Keep,
+            ..config
+        };
+        let mut queue: PriorityQueue<4> = PriorityQueue::new(config);
+        defmt::assert!(queue.push(request(1, false, 0)));
+        defmt::assert!(queue.pop(1_000_000).is_some());
+    }
+
+    #[test]
+    fn test_transmit_task_drains_channel_by_priority() {
+        let medium: SimMedium<2, 8> = SimMedium::new(SimConfig::default());
+        let mut listener = medium.radio(1);
+        let channel: TxChannel<TX_CHANNEL_DEPTH> = TxChannel::new();
+        let mut task: TransmitQueue<_, 8> =
+            TransmitQueue::new(medium.radio(0), TxQueueConfig::default());
+
+        defmt::assert!(!block_on(task.step(&channel, 0)));
+        defmt::unwrap!(channel.try_send(request(1, false, 0)));
+        defmt::unwrap!(channel.try_send(request(2, true, 5)));
+
+        defmt::assert!(block_on(task.step(&channel, 10)));
+        defmt::assert!(block_on(task.step(&channel, 20)));
+        let first = defmt::unwrap!(block_on(listener.receive()));
+        let second = defmt::unwrap!(block_on(listener.receive()));
+        defmt::assert!(first.header.sequence_number == 2);
+        defmt::assert!(second.header.sequence_number == 1);
+
+        let stats = tx_queue_stats();
+        defmt::assert!(stats == *task.stats());
+        defmt::assert!(stats.sent == 2 && stats.emergency_sent == 1);
+        defmt::assert!(stats.mean_wait_ms() == (5 + 20) / 2);
+        defmt::assert!(stats.dropped() == 0);
+    }
+}